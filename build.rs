@@ -0,0 +1,8 @@
+//! Generates the gRPC service of `proto/esp_vision.proto` for builds with the
+//! `grpc` feature, which needs `protoc`.
+fn main() {
+    println!("cargo:rerun-if-changed=proto/esp_vision.proto");
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/esp_vision.proto")
+        .expect("cannot compile proto/esp_vision.proto; is protoc installed?");
+}
@@ -0,0 +1,258 @@
+//! An HTTP control API, with `--api ADDR`.
+//!
+//! Where a show-control system rather than someone at the keyboard runs the
+//! tracker, it does so with plain HTTP requests:
+//!
+//! | Request         | Body      | Effect                              |
+//! |-----------------|-----------|-------------------------------------|
+//! | `GET /status`   |           | the status as JSON                  |
+//! | `POST /start`   |           | start tracking again after a `stop` |
+//! | `POST /stop`    |           | stop tracking all targets           |
+//! | `POST /roi`     | `x,y,w,h` | start tracking from this region     |
+//! | `POST /tracker` | `NAME`    | switch to another tracking backend  |
+//! | `POST /pause`   |           | pause sending to ESP                |
+//! | `POST /resume`  |           | resume sending to ESP               |
+//!
+//! Requests that are accepted are answered with `204 No Content` and take
+//! effect on the next frame; invalid ones with `400 Bad Request` and the
+//! reason, e.g. for a backend the config can't track with, such as `dnn`
+//! without `--dnn-model`. After a `stop`, no target starts by itself, e.g.
+//! from a face or a tracker that needs no selection, until `start` or
+//! `roi`. Switching the backend starts the first target again from where it
+//! was. The status is the same as the
+//! [`dashboard`](../dashboard/index.html)'s, e.g.
+//!
+//! ```text
+//! $ curl -d 200,150,80,80 http://localhost:8003/roi
+//! $ curl http://localhost:8003/status
+//! {"fps":29.8,"sent_per_second":30,"esp":"connected","paused":false,
+//! "tracker":"camshift","stopped":false,"targets":[{"x":240.0,"y":190.0}]}
+//! ```
+//!
+//! Builds without the `net` feature have no API.
+use crate::config::TrackerRules;
+use crate::dashboard::Status;
+use crate::net::Net;
+use crate::tracker::Backend;
+use rust_vision::Rect;
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::mpsc::{self, Receiver, TryIter};
+use std::sync::{Arc, Mutex};
+
+/// What a request asks the main loop for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    Start,
+    Stop,
+    Roi(Rect),
+    Tracker(Backend),
+}
+
+/// `Api` is shared between the main loop, which takes the requests and
+/// publishes the status, and the HTTP server.
+pub struct Api {
+    status: Arc<Mutex<Status>>,
+    requests: Receiver<Request>,
+}
+
+impl Api {
+    /// Serves the API on `addr` from `net`. `paused` is set and cleared by
+    /// `pause` and `resume`, as by the control channel; `rules` refuse the
+    /// backends the config can't track with.
+    pub fn serve(net: &Net,
+                 addr: &str,
+                 paused: Arc<AtomicBool>,
+                 rules: TrackerRules)
+                 -> io::Result<Api> {
+        let status = Arc::new(Mutex::new(Status::default()));
+        let (sender, requests) = mpsc::channel();
+        server::spawn(net, addr, status.clone(), sender, paused, rules)?;
+        Ok(Api { status, requests })
+    }
+
+    /// The requests that came since the last call.
+    pub fn requests(&self) -> TryIter<Request> {
+        self.requests.try_iter()
+    }
+
+    /// Replaces the status that `GET /status` returns.
+    pub fn publish(&self, status: Status) {
+        *self.status.lock().unwrap() = status;
+    }
+}
+
+#[cfg(not(feature = "net"))]
+mod server {
+    use crate::config::TrackerRules;
+    use crate::dashboard::Status;
+    use crate::net::{self, Net};
+    use std::io;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc::Sender;
+    use std::sync::{Arc, Mutex};
+    use super::Request;
+
+    pub fn spawn(_net: &Net,
+                 _addr: &str,
+                 _status: Arc<Mutex<Status>>,
+                 _sender: Sender<Request>,
+                 _paused: Arc<AtomicBool>,
+                 _rules: TrackerRules)
+                 -> io::Result<()> {
+        Err(net::unavailable("HTTP API"))
+    }
+}
+
+#[cfg(feature = "net")]
+mod server {
+    use crate::config::{self, TrackerRules};
+    use crate::dashboard::Status;
+    use crate::net::Net;
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::Sender;
+    use std::sync::{Arc, Mutex};
+    use super::Request;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Largest request read, headers and body.
+    const MAX_REQUEST: usize = 4096;
+
+    pub fn spawn(net: &Net,
+                 addr: &str,
+                 status: Arc<Mutex<Status>>,
+                 sender: Sender<Request>,
+                 paused: Arc<AtomicBool>,
+                 rules: TrackerRules)
+                 -> io::Result<()> {
+        let listener = net.block_on(TcpListener::bind(addr))?;
+        net.spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        debug!("API request from {}", peer);
+                        let status = status.clone();
+                        let sender = sender.clone();
+                        let paused = paused.clone();
+                        let rules = rules.clone();
+                        tokio::spawn(async move {
+                            respond(stream, &status, sender, &paused, &rules)
+                                .await
+                                .ok()
+                        });
+                    }
+                    Err(e) => warn!("API error: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Reads one request, applies it and answers.
+    async fn respond(mut stream: TcpStream,
+                     status: &Mutex<Status>,
+                     sender: Sender<Request>,
+                     paused: &AtomicBool,
+                     rules: &TrackerRules)
+                     -> io::Result<()> {
+        let (head, body) = read_request(&mut stream).await?;
+        let mut words = head.split_whitespace();
+        let (method, path) = (words.next().unwrap_or(""),
+                              words.next().unwrap_or(""));
+        let response = match (method, path) {
+            ("GET", "/status") => {
+                let json = status.lock().unwrap().to_json();
+                Ok(Some(json))
+            }
+            ("POST", _) => {
+                apply(path, body.trim(), &sender, paused, rules).map(|()| None)
+            }
+            _ => Err((404, "Not Found".to_string())),
+        };
+        let response = match response {
+            Ok(Some(json)) => {
+                format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        json.len(),
+                        json)
+            }
+            Ok(None) => {
+                "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            }
+            Err((code, reason)) => {
+                let reason = format!("{}\n", reason);
+                format!("HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        code,
+                        if code == 404 { "Not Found" } else { "Bad Request" },
+                        reason.len(),
+                        reason)
+            }
+        };
+        stream.write_all(response.as_bytes()).await
+    }
+
+    /// Applies `POST path` with `body`, or says why not with an HTTP status.
+    fn apply(path: &str,
+             body: &str,
+             sender: &Sender<Request>,
+             paused: &AtomicBool,
+             rules: &TrackerRules)
+             -> Result<(), (u16, String)> {
+        let request = match path {
+            "/start" => Request::Start,
+            "/stop" => Request::Stop,
+            "/roi" => Request::Roi(config::rect(body).map_err(|e| (400, e))?),
+            "/tracker" => {
+                let backend = body.parse().map_err(|e| (400, e))?;
+                rules.check(backend).map_err(|e| (400, e))?;
+                Request::Tracker(backend)
+            }
+            "/pause" | "/resume" => {
+                paused.store(path == "/pause", Ordering::SeqCst);
+                info!("API: {}", &path[1..]);
+                return Ok(());
+            }
+            _ => return Err((404, "Not Found".to_string())),
+        };
+        info!("API: {:?}", request);
+        // The main loop only goes away on exit.
+        sender.send(request).ok();
+        Ok(())
+    }
+
+    /// Reads the request line and headers, and the body that follows as given
+    /// by `Content-Length`.
+    async fn read_request(stream: &mut TcpStream)
+                          -> io::Result<(String, String)> {
+        let mut buf = Vec::new();
+        let mut chunk = [0; 1024];
+        loop {
+            let n = stream.read(&mut chunk).await?;
+            buf.extend_from_slice(&chunk[..n]);
+            let text = String::from_utf8_lossy(&buf).into_owned();
+            if let Some(end) = text.find("\r\n\r\n") {
+                let (head, body) = (&text[..end], &text[end + 4..]);
+                let length = head.lines()
+                    .filter_map(|l| {
+                        let (name, value) = l.split_at(l.find(':')?);
+                        if name.eq_ignore_ascii_case("content-length") {
+                            value[1..].trim().parse::<usize>().ok()
+                        } else {
+                            None
+                        }
+                    })
+                    .next()
+                    .unwrap_or(0);
+                if body.len() >= length || n == 0 || buf.len() >= MAX_REQUEST {
+                    return Ok((head.to_string(), body.to_string()));
+                }
+            } else if n == 0 || buf.len() >= MAX_REQUEST {
+                return Ok((text, String::new()));
+            }
+        }
+    }
+}
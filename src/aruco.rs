@@ -0,0 +1,120 @@
+//! ArUco markers: the 6-DoF pose of a fiducial marker.
+use crate::camera::{self, Intrinsics};
+use rust_vision::*;
+use std::str::FromStr;
+
+/// Which marker to follow.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArucoOptions {
+    pub dictionary: Dictionary,
+    pub id: i32,
+    /// Side of the printed marker, in meters.
+    pub marker_length: f64,
+}
+
+impl Default for ArucoOptions {
+    fn default() -> ArucoOptions {
+        ArucoOptions {
+            dictionary: Dictionary(PredefinedDictionary::Dict4x4_50),
+            id: 0,
+            marker_length: 0.05,
+        }
+    }
+}
+
+/// A predefined ArUco dictionary.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dictionary(pub PredefinedDictionary);
+
+impl FromStr for Dictionary {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Dictionary, String> {
+        let d = match s {
+            "4x4_50" => PredefinedDictionary::Dict4x4_50,
+            "5x5_100" => PredefinedDictionary::Dict5x5_100,
+            "6x6_250" => PredefinedDictionary::Dict6x6_250,
+            "original" => PredefinedDictionary::ArucoOriginal,
+            _ => return Err(format!("unknown ArUco dictionary `{}`", s)),
+        };
+        Ok(Dictionary(d))
+    }
+}
+
+/// Names of the dimensions produced by `Aruco::update`.
+pub const NAMES: [&str; 6] = ["x", "y", "z", "roll", "pitch", "yaw"];
+
+/// A detected marker. The pose is in OpenCV's camera frame (x right, y
+/// down, z forward, as seen by the camera rather than on the mirrored
+/// screen).
+pub struct Detection {
+    /// Corners of the marker in the displayed (mirrored) frame.
+    pub corners: Vec<Point2f>,
+    /// `x y z` in meters, then `roll pitch yaw` in degrees.
+    pub pose: [f64; 6],
+}
+
+/// `Aruco` finds one marker and estimates its pose.
+pub struct Aruco {
+    options: ArucoOptions,
+    camera_matrix: Mat,
+    distortion: Mat,
+}
+
+impl Aruco {
+    pub fn new(options: ArucoOptions, intrinsics: &Intrinsics) -> Aruco {
+        Aruco {
+            options,
+            camera_matrix: intrinsics.camera_matrix(),
+            distortion: intrinsics.distortion(),
+        }
+    }
+
+    /// Looks for the marker in the displayed `frame`.
+    pub fn update(&self, frame: &Mat) -> Option<Detection> {
+        let raw = camera::unmirrored(frame);
+        let (corners, ids) = aruco_detect_markers(&raw,
+                                                  self.options.dictionary.0);
+        let i = ids.iter().position(|&id| id == self.options.id)?;
+        let found = [corners[i].clone()];
+        let length = self.options.marker_length as f32;
+        let (rvecs, tvecs) =
+            aruco_estimate_pose_single_markers(&found,
+                                               length,
+                                               &self.camera_matrix,
+                                               &self.distortion);
+        let (r, t) = (rvecs[0], tvecs[0]);
+        let [roll, pitch, yaw] = euler(&rodrigues(r));
+        let mirror = (frame.cols - 1) as f32;
+        Some(Detection {
+            corners: corners[i]
+                .iter()
+                .map(|p| Point2f::new(mirror - p.x, p.y))
+                .collect(),
+            pose: [t[0], t[1], t[2], roll, pitch, yaw],
+        })
+    }
+}
+
+/// The rotation matrix of the rotation vector `r`.
+fn rodrigues(r: [f64; 3]) -> [[f64; 3]; 3] {
+    let theta = (r[0] * r[0] + r[1] * r[1] + r[2] * r[2]).sqrt();
+    if theta < 1e-12 {
+        return [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    }
+    let (x, y, z) = (r[0] / theta, r[1] / theta, r[2] / theta);
+    let (s, c) = theta.sin_cos();
+    let v = 1.0 - c;
+    [[c + x * x * v, x * y * v - z * s, x * z * v + y * s],
+     [y * x * v + z * s, c + y * y * v, y * z * v - x * s],
+     [z * x * v - y * s, z * y * v + x * s, c + z * z * v]]
+}
+
+/// `roll pitch yaw` (about x, y and z, applied in z-y-x order) of the
+/// rotation matrix `m`, in degrees.
+fn euler(m: &[[f64; 3]; 3]) -> [f64; 3] {
+    let roll = m[2][1].atan2(m[2][2]);
+    let pitch = (-m[2][0]).atan2(m[2][1].hypot(m[2][2]));
+    let yaw = m[1][0].atan2(m[0][0]);
+    [roll.to_degrees(), pitch.to_degrees(), yaw.to_degrees()]
+}
@@ -0,0 +1,193 @@
+//! `esp-vision bench [options]`: runs the tracking pipeline on recorded
+//! input as fast as it goes, without a window, and reports how long every
+//! stage took, so that machines, trackers and changes can be compared.
+//!
+//! The options are those of the application, e.g.
+//!
+//! ```text
+//! esp-vision bench --input video:gesture.mp4 --tracker kcf --roi 200,150,80,80
+//! ```
+//!
+//! Every frame goes through the stages `capture` (decoding), `convert`
+//! (undistortion, mirroring and preprocessing), `track`, `serialize` and
+//! `send`, the first three with the code the application runs live. The
+//! samples are sent to `--server` if ESP is on, over a blocking
+//! connection so that the time of the write is measured; the `send` stage
+//! is left out otherwise. For every stage the mean, the median, the 90th
+//! and 99th percentiles and the maximum are printed.
+use crate::config::{Config, Mode};
+use crate::input::Input;
+use crate::preprocess::Converter;
+use crate::profile::{self, Profile};
+use crate::sample::Sample;
+use crate::target::Target;
+use rust_vision::{Mat, Rect};
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// Name of the subcommand.
+pub const COMMAND: &str = "bench";
+/// The stages, in the order they are run and reported.
+const STAGES: [&str; 5] = ["capture", "convert", "track", "serialize", "send"];
+
+/// Runs the subcommand; `args` is the whole command line.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut rest = vec![format!("{} {}", args[0], COMMAND)];
+    rest.extend_from_slice(&args[2..]);
+    let mut config = Config::from_args(&rest)?;
+    if config.mode != Mode::Track {
+        return Err(format!("{} measures the track mode, not --mode {}",
+                           COMMAND,
+                           config.mode));
+    }
+    if config.inputs.len() != 1 {
+        return Err(format!("{} reads a single --input", COMMAND));
+    }
+    // As fast as it goes, rather than at the recorded frame rate.
+    config.playback.paced = false;
+    let mut input = Input::open(&config.inputs[0],
+                                &config.playback,
+                                &config.capture)?;
+    if input.is_live() {
+        return Err(format!("{} needs recorded --input, e.g. a video",
+                           COMMAND));
+    }
+    let (mut profile, params) =
+        profile::initial(&config).map_err(|e| e.to_string())?;
+    // A profile starts where it was saved, as it does live.
+    let window = profile.as_ref().map(Profile::window);
+    let selection = match window.or(config.start_region) {
+        Some(region) => Some(region),
+        None if !config.tracker.needs_selection() => None,
+        None => {
+            return Err(format!("--tracker {} needs a selection; give --roi \
+                                or --region",
+                               config.tracker))
+        }
+    };
+    let mut stream = match TcpStream::connect(config.server.as_str()) {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            warn!("ESP is not on at {} ({}); the send stage is left out",
+                  config.server,
+                  e);
+            None
+        }
+    };
+    let serializer = config.format.serializer(config.dimension_names(),
+                                              config.dimension_labels(),
+                                              config.ascii_stamps);
+    let mut header = Vec::new();
+    serializer.header(&mut header);
+    if let Some(Err(e)) = stream.as_mut().map(|s| s.write_all(&header)) {
+        warn!("Lost the connection to ESP ({}); the send stage is left out",
+              e);
+        stream = None;
+    }
+    let mut converter = Converter::new(&config)?;
+
+    let mut times: Vec<Vec<Duration>> =
+        STAGES.iter().map(|_| Vec::new()).collect();
+    let mut target: Option<Target> = None;
+    let mut buf = Vec::new();
+    let start = Instant::now();
+    let mut seq = 0;
+    loop {
+        let mut stage = Instant::now();
+        let mut lap = |times: &mut Vec<Vec<Duration>>, i: usize| {
+            let now = Instant::now();
+            times[i].push(now - stage);
+            stage = now;
+        };
+
+        let mut frame = Mat::new();
+        if !input.read(&frame) {
+            break;
+        }
+        let captured = Instant::now();
+        lap(&mut times, 0);
+
+        converter.convert(&mut frame);
+        lap(&mut times, 1);
+
+        let values = match target {
+            Some(ref mut target) => {
+                let o = target.update(&config, &frame, &params, captured, true);
+                o.values
+            }
+            None => {
+                let region = selection.unwrap_or_else(|| {
+                    Rect::new(0, 0, frame.cols, frame.rows)
+                });
+                let mut started = Target::new(&config, &frame, region, &params);
+                if let Some(profile) = profile.take() {
+                    started.restore(&profile.model)?;
+                }
+                target = Some(started);
+                continue;
+            }
+        };
+        lap(&mut times, 2);
+
+        let time = (captured - start).as_secs_f64();
+        buf.clear();
+        serializer.serialize(&Sample::new(seq, time, values), &mut buf);
+        seq += 1;
+        lap(&mut times, 3);
+
+        if let Some(ref mut s) = stream {
+            if let Err(e) = s.write_all(&buf) {
+                warn!("Lost the connection to ESP ({}); the send stage \
+                       ends here",
+                      e);
+                stream = None;
+            } else {
+                lap(&mut times, 4);
+            }
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let frames = times[0].len();
+    if frames == 0 {
+        return Err("the input has no frames".to_string());
+    }
+    println!("{} frames in {:.2} s, {:.1} fps, tracker {}",
+             frames,
+             elapsed,
+             frames as f64 / elapsed,
+             config.tracker);
+    println!("{:>10} {:>9} {:>9} {:>9} {:>9} {:>9}",
+             "stage",
+             "mean",
+             "p50",
+             "p90",
+             "p99",
+             "max");
+    for (name, stage) in STAGES.iter().zip(&mut times) {
+        if stage.is_empty() {
+            continue;
+        }
+        stage.sort();
+        let mean = stage.iter().sum::<Duration>() / stage.len() as u32;
+        println!("{:>10} {:>9} {:>9} {:>9} {:>9} {:>9}",
+                 name,
+                 ms(mean),
+                 ms(percentile(stage, 0.5)),
+                 ms(percentile(stage, 0.9)),
+                 ms(percentile(stage, 0.99)),
+                 ms(stage[stage.len() - 1]));
+    }
+    Ok(())
+}
+
+/// The `p` quantile of the `sorted` times, by the nearest rank.
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1).min(sorted.len()) - 1]
+}
+
+fn ms(d: Duration) -> String {
+    format!("{:.2} ms", d.as_secs_f64() * 1000.0)
+}
@@ -0,0 +1,167 @@
+//! `esp-vision calibrate`: measures the intrinsics of a camera from views of
+//! a printed checkerboard, for `--undistort` and the ArUco pose.
+//!
+//! Hold the checkerboard in front of the camera; its inner corners are drawn
+//! once found. Press space to keep a view, varying the distance, the angle
+//! and the part of the frame the board covers between views. Once `--views`
+//! views are kept, or on `c` after a few, the camera is calibrated and the
+//! intrinsics are written to `--output`. `ESC` or `q` gives up.
+use crate::camera::Intrinsics;
+use crate::input::{CaptureSettings, Input, InputSpec, Playback};
+use crate::keys;
+use crate::window;
+use getopts::Options;
+use rust_vision::*;
+
+/// Name of the subcommand.
+pub const COMMAND: &str = "calibrate";
+/// Views needed before `c` calibrates.
+const MIN_VIEWS: usize = 5;
+const WINDOW: &str = "Calibration";
+
+/// Runs the subcommand; `args` is the whole command line.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optopt("",
+                "input",
+                "the camera to calibrate (camera:0)",
+                "SOURCE");
+    opts.optopt("",
+                "board",
+                "inner corners of the checkerboard, per row and per column \
+                 (9x6)",
+                "COLSxROWS");
+    opts.optopt("", "views", "views to calibrate from (15)", "N");
+    opts.optopt("",
+                "output",
+                "file to write the intrinsics to (intrinsics.toml)",
+                "FILE");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[2..]).map_err(|e| e.to_string())?;
+    if matches.opt_present("h") {
+        let brief = format!("Usage: {} {} [options]", args[0], COMMAND);
+        print!("{}", opts.usage(&brief));
+        return Ok(());
+    }
+    if !window::BUILT {
+        return Err("calibrating needs a window; build with `--features gui`"
+            .to_string());
+    }
+    let spec: InputSpec = match matches.opt_str("input") {
+        Some(input) => input.parse()?,
+        None => InputSpec::default(),
+    };
+    let board = match matches.opt_str("board") {
+        Some(board) => pattern(&board)?,
+        None => Size2i::new(9, 6),
+    };
+    let views = match matches.opt_str("views") {
+        Some(views) => {
+            match views.parse::<usize>() {
+                Ok(n) if n >= MIN_VIEWS => n,
+                _ => {
+                    return Err(format!("--views must be a number of at \
+                                        least {}",
+                                       MIN_VIEWS))
+                }
+            }
+        }
+        None => 15,
+    };
+    let output = matches.opt_str("output")
+        .unwrap_or_else(|| "intrinsics.toml".to_string());
+
+    let mut input = Input::open(&spec,
+                                &Playback::default(),
+                                &CaptureSettings::default())?;
+    window::open(WINDOW);
+    let corners_of_board = board_points(board);
+    let mut image_points = Vec::new();
+    let frame = Mat::new();
+    let mut size = Size2i::new(0, 0);
+    while image_points.len() < views {
+        if !input.read(&frame) {
+            return Err("the input ended before calibrating".to_string());
+        }
+        // Unlike the main window, the frames are not mirrored: the
+        // intrinsics are those of the camera.
+        size = Size2i::new(frame.cols, frame.rows);
+        let gray = frame.cvt_color(ColorConversionCodes::BGR2GRAY);
+        let corners = gray.find_chessboard_corners(board);
+        let view = frame.clone();
+        if let Some(ref corners) = corners {
+            view.draw_chessboard_corners(board, corners, true);
+        }
+        view.put_text(&format!("{}/{} views: space keeps this one, c \
+                                calibrates",
+                               image_points.len(),
+                               views),
+                      Point2i::new(10, 30),
+                      HersheyFonts::HersheySimplex,
+                      0.6,
+                      Scalar::new(0, 255, 0, 0));
+        let key = window::show_and_wait(WINDOW, &view, 30);
+        let key = match keys::character(key) {
+            Some(key) => key,
+            None => continue,
+        };
+        match key {
+            '\x1b' | 'q' => return Err("calibration aborted".to_string()),
+            ' ' => {
+                match corners {
+                    Some(corners) => {
+                        image_points.push(corners);
+                        info!("Kept view {}", image_points.len());
+                    }
+                    None => info!("The checkerboard is not in view"),
+                }
+            }
+            'c' if image_points.len() >= MIN_VIEWS => break,
+            'c' => info!("Keep at least {} views first", MIN_VIEWS),
+            _ => {}
+        }
+    }
+    window::close(WINDOW);
+
+    let object_points = vec![corners_of_board; image_points.len()];
+    let (rms, camera_matrix, distortion) =
+        calibrate_camera(&object_points, &image_points, size);
+    let k = camera_matrix.data_f64();
+    let intrinsics = Intrinsics {
+        fx: k[0],
+        fy: k[4],
+        cx: k[2],
+        cy: k[5],
+        distortion: distortion.data_f64(),
+    };
+    info!("Calibrated from {} views, with a reprojection error of {:.3} \
+           pixels",
+          image_points.len(),
+          rms);
+    intrinsics.save(&output)?;
+    info!("Wrote the intrinsics to {}", output);
+    Ok(())
+}
+
+/// The size of the board as `COLSxROWS` inner corners.
+fn pattern(s: &str) -> Result<Size2i, String> {
+    let parts: Vec<&str> = s.split('x').collect();
+    match (parts.len(), parts[0].parse(), parts.last().unwrap().parse()) {
+        (2, Ok(cols), Ok(rows)) if cols > 1 && rows > 1 => {
+            Ok(Size2i::new(cols, rows))
+        }
+        _ => Err(format!("board `{}` is not COLSxROWS inner corners", s)),
+    }
+}
+
+/// The corners of the board in its own plane, one square apart. The size of
+/// the squares doesn't matter for the intrinsics.
+fn board_points(board: Size2i) -> Vec<Point3f> {
+    let mut points = Vec::new();
+    for row in 0..board.height {
+        for col in 0..board.width {
+            points.push(Point3f::new(col as f32, row as f32, 0.0));
+        }
+    }
+    points
+}
@@ -0,0 +1,158 @@
+//! Camera intrinsics.
+//!
+//! Intrinsics are read from a TOML file:
+//!
+//! ```toml
+//! fx = 612.3
+//! fy = 611.8
+//! cx = 321.4
+//! cy = 238.9
+//! distortion = [0.12, -0.25, 0.0, 0.0, 0.1]
+//! ```
+//!
+//! Files ending in `.yml` or `.yaml` are read as written by OpenCV's
+//! calibration sample instead, from their `camera_matrix` and
+//! `distortion_coefficients` matrices. `esp-vision calibrate` writes the
+//! TOML file; see the [`calibrate`](../calibrate/index.html) module.
+use rust_vision::{FlipCode, Mat, Size2i, init_undistort_rectify_map};
+use std::fs::File;
+use std::io::prelude::*;
+
+/// `Intrinsics` describes the pinhole model of the camera, in pixels.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Intrinsics {
+    pub fx: f64,
+    pub fy: f64,
+    pub cx: f64,
+    pub cy: f64,
+    /// Distortion coefficients `k1 k2 p1 p2 [k3]`; none for an ideal lens.
+    #[serde(default)]
+    pub distortion: Vec<f64>,
+}
+
+impl Intrinsics {
+    pub fn load(path: &str) -> Result<Intrinsics, String> {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| format!("cannot read intrinsics {}: {}", path, e))?;
+        if path.ends_with(".yml") || path.ends_with(".yaml") {
+            return Intrinsics::from_opencv_yaml(&text)
+                .ok_or_else(|| format!("invalid intrinsics {}: no \
+                                        camera_matrix of 3x3 values",
+                                       path));
+        }
+        toml::from_str(&text)
+            .map_err(|e| format!("invalid intrinsics {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = toml::to_string(self)
+            .map_err(|e| format!("cannot serialize intrinsics: {}", e))?;
+        File::create(path)
+            .and_then(|mut f| f.write_all(text.as_bytes()))
+            .map_err(|e| format!("cannot write intrinsics {}: {}", path, e))
+    }
+
+    fn from_opencv_yaml(text: &str) -> Option<Intrinsics> {
+        let k = opencv_matrix(text, "camera_matrix")?;
+        if k.len() != 9 {
+            return None;
+        }
+        Some(Intrinsics {
+            fx: k[0],
+            fy: k[4],
+            cx: k[2],
+            cy: k[5],
+            distortion: opencv_matrix(text, "distortion_coefficients")
+                .unwrap_or_default(),
+        })
+    }
+
+    /// The same camera without distortion, as seen through `Undistorter`.
+    pub fn undistorted(&self) -> Intrinsics {
+        Intrinsics {
+            distortion: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// The 3x3 camera matrix.
+    pub fn camera_matrix(&self) -> Mat {
+        Mat::from_slice(3,
+                        3,
+                        &[self.fx, 0.0, self.cx, 0.0, self.fy, self.cy, 0.0,
+                          0.0, 1.0])
+    }
+
+    /// The distortion coefficients as a row vector.
+    pub fn distortion(&self) -> Mat {
+        let mut d = self.distortion.clone();
+        if d.is_empty() {
+            d = vec![0.0; 4];
+        }
+        Mat::from_slice(1, d.len() as i32, &d)
+    }
+}
+
+/// The `data` of the matrix called `name` at the top level of an OpenCV
+/// YAML file.
+fn opencv_matrix(text: &str, name: &str) -> Option<Vec<f64>> {
+    let key = format!("{}:", name);
+    let start = text.match_indices(&key)
+        .map(|(i, _)| i)
+        .find(|&i| i == 0 || text[..i].ends_with('\n'))?;
+    let rest = &text[start..];
+    let rest = &rest[rest.find("data:")?..];
+    let values = &rest[rest.find('[')? + 1..rest.find(']')?];
+    values.split(',')
+        .map(|v| v.trim().parse().ok())
+        .collect()
+}
+
+/// `Undistorter` removes the lens distortion from frames, so that straight
+/// lines stay straight up to the edges of wide-angle lenses.
+pub struct Undistorter {
+    camera_matrix: Mat,
+    distortion: Mat,
+    /// The remapping for frames of the given width and height.
+    maps: Option<((i32, i32), Mat, Mat)>,
+}
+
+impl Undistorter {
+    pub fn new(intrinsics: &Intrinsics) -> Undistorter {
+        Undistorter {
+            camera_matrix: intrinsics.camera_matrix(),
+            distortion: intrinsics.distortion(),
+            maps: None,
+        }
+    }
+
+    /// The undistorted `frame`, which must be as the camera saw it.
+    pub fn apply(&mut self, frame: &Mat) -> Mat {
+        let size = (frame.cols, frame.rows);
+        if let Some((s, ref map1, ref map2)) = self.maps {
+            if s == size {
+                return frame.remap(map1, map2);
+            }
+        }
+        // Computing the maps once makes every frame a cheap remap.
+        let (map1, map2) = init_undistort_rectify_map(&self.camera_matrix,
+                                                      &self.distortion,
+                                                      Size2i::new(size.0,
+                                                                  size.1));
+        let undistorted = frame.remap(&map1, &map2);
+        self.maps = Some((size, map1, map2));
+        undistorted
+    }
+}
+
+/// The frame as the camera saw it. Displayed frames are mirrored, which
+/// breaks anything that depends on handedness: codes don't decode, the
+/// intrinsics don't apply, and left and right are swapped.
+pub fn unmirrored(frame: &Mat) -> Mat {
+    let mut raw = frame.clone();
+    raw.flip(FlipCode::YAxis);
+    raw
+}
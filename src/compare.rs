@@ -0,0 +1,249 @@
+//! `esp-vision compare REFERENCE FILE... [options]`: compares
+//! `--log-features` files of the same gestures recorded in different
+//! sessions, to tell when the camera or the lighting changed enough for the
+//! ESP model to need retraining.
+//!
+//! ```text
+//! esp-vision compare monday.csv tuesday.csv friday.csv --dims x,y
+//! ```
+//!
+//! Every file is compared with the first, the reference, over the
+//! dimensions they share, or those of `--dims`. Samples of lost targets are
+//! left out. For every dimension, the mean offset is the difference between
+//! the means of the session and of the reference, also given in standard
+//! deviations of the reference, and the scale is the ratio of their standard
+//! deviations. The DTW distance is the mean distance between the samples of
+//! both sessions once aligned by dynamic time warping, which tolerates
+//! gestures performed at another speed; long sessions are subsampled to
+//! `MAX_DTW_SAMPLES` first.
+//!
+//! A session is marked as drifted once a dimension moved by more than
+//! `MAX_OFFSET` standard deviations or changed scale by more than
+//! `MAX_SCALE` either way: what the model learnt no longer matches what it
+//! is sent.
+use crate::feature_replay;
+use crate::sample::Sample;
+use getopts::Options;
+
+/// Name of the subcommand.
+pub const COMMAND: &str = "compare";
+/// Offset, in standard deviations of the reference, above which a session
+/// drifted.
+const MAX_OFFSET: f64 = 0.5;
+/// Ratio of the standard deviations above which, or below whose inverse, a
+/// session drifted.
+const MAX_SCALE: f64 = 1.25;
+/// Samples of each session dynamic time warping aligns at most.
+const MAX_DTW_SAMPLES: usize = 2000;
+
+/// A session: the names of its dimensions and its samples.
+struct Session {
+    path: String,
+    names: Vec<String>,
+    samples: Vec<Sample>,
+}
+
+impl Session {
+    fn load(path: &str) -> Result<Session, String> {
+        let (names, samples) = feature_replay::load(path)?;
+        Ok(Session {
+            path: path.to_string(),
+            names,
+            samples,
+        })
+    }
+
+    /// The values of the dimensions `columns`, in the samples where all of
+    /// them are finite.
+    fn rows(&self, columns: &[usize]) -> Vec<Vec<f64>> {
+        self.samples
+            .iter()
+            .map(|s| columns.iter().map(|&c| s.values[c]).collect::<Vec<_>>())
+            .filter(|row| row.iter().all(|v| v.is_finite()))
+            .collect()
+    }
+}
+
+/// Runs the subcommand; `args` is the whole command line.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optopt("",
+                "dims",
+                "comma-separated dimensions to compare (all that the files \
+                 share)",
+                "LIST");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[2..]).map_err(|e| e.to_string())?;
+    let brief = format!("Usage: {} {} REFERENCE FILE... [options]",
+                        args[0],
+                        COMMAND);
+    if matches.opt_present("h") {
+        print!("{}", opts.usage(&brief));
+        return Ok(());
+    }
+    if matches.free.len() < 2 {
+        return Err(opts.usage(&brief));
+    }
+    let sessions = matches.free
+        .iter()
+        .map(|path| Session::load(path))
+        .collect::<Result<Vec<_>, String>>()?;
+    let reference = &sessions[0];
+    let dims: Vec<String> = match matches.opt_str("dims") {
+        Some(list) => list.split(',').map(|d| d.trim().to_string()).collect(),
+        None => {
+            reference.names
+                .iter()
+                .filter(|n| sessions.iter().all(|s| s.names.contains(n)))
+                .cloned()
+                .collect()
+        }
+    };
+    if dims.is_empty() {
+        return Err("the files have no dimension in common".to_string());
+    }
+    let mut columns = Vec::with_capacity(sessions.len());
+    for session in &sessions {
+        let c = dims.iter()
+            .map(|d| session.names.iter().position(|n| n == d))
+            .collect::<Option<Vec<usize>>>()
+            .ok_or_else(|| {
+                format!("{} doesn't have all of the dimensions {}",
+                        session.path,
+                        dims.join(","))
+            })?;
+        columns.push(c);
+    }
+    let reference_rows = reference.rows(&columns[0]);
+    let reference_stats = (0..dims.len())
+        .map(|d| stats(reference_rows.iter().map(|r| r[d])))
+        .collect::<Option<Vec<(f64, f64)>>>()
+        .ok_or_else(|| format!("{} has no tracked samples", reference.path))?;
+
+    println!("Reference {}: {} samples",
+             reference.path,
+             reference_rows.len());
+    for (session, columns) in sessions.iter().zip(&columns).skip(1) {
+        let rows = session.rows(columns);
+        println!();
+        println!("{}: {} samples", session.path, rows.len());
+        if rows.is_empty() {
+            println!("  no tracked samples");
+            continue;
+        }
+        println!("{:>16} {:>12} {:>9} {:>8}",
+                 "dimension",
+                 "offset",
+                 "(sd)",
+                 "scale");
+        let mut drifted = false;
+        for (d, name) in dims.iter().enumerate() {
+            let (mean, sd) = stats(rows.iter().map(|r| r[d])).unwrap();
+            let (reference_mean, reference_sd) = reference_stats[d];
+            let offset = mean - reference_mean;
+            let (in_sds, scale) = if reference_sd > 0.0 {
+                (offset / reference_sd, sd / reference_sd)
+            } else {
+                (f64::NAN, f64::NAN)
+            };
+            let moved = in_sds.abs() > MAX_OFFSET || scale > MAX_SCALE ||
+                        scale < 1.0 / MAX_SCALE;
+            drifted |= moved;
+            println!("{:>16} {:>12.4} {:>9.2} {:>8.3}{}",
+                     name,
+                     offset,
+                     in_sds,
+                     scale,
+                     if moved { "  *" } else { "" });
+        }
+        println!("DTW distance {:.4}",
+                 dtw(&subsample(&reference_rows), &subsample(&rows)));
+        if drifted {
+            println!("Drifted (*): consider retraining the ESP model");
+        } else {
+            println!("No drift beyond {} sd or a scale of {}",
+                     MAX_OFFSET,
+                     MAX_SCALE);
+        }
+    }
+    Ok(())
+}
+
+/// The mean and the standard deviation of `values`, if there are any.
+fn stats<I: Iterator<Item = f64>>(values: I) -> Option<(f64, f64)> {
+    let (mut n, mut sum, mut squares) = (0.0, 0.0, 0.0);
+    for v in values {
+        n += 1.0;
+        sum += v;
+        squares += v * v;
+    }
+    if n == 0.0 {
+        return None;
+    }
+    let mean = sum / n;
+    Some((mean, (squares / n - mean * mean).max(0.0).sqrt()))
+}
+
+/// Every so many of `rows`, to keep `MAX_DTW_SAMPLES` of them at most.
+fn subsample(rows: &[Vec<f64>]) -> Vec<&[f64]> {
+    let step = rows.len().div_ceil(MAX_DTW_SAMPLES);
+    rows.iter().step_by(step.max(1)).map(Vec::as_slice).collect()
+}
+
+/// The mean Euclidean distance between the rows of `a` and `b` along their
+/// dynamic time warping alignment.
+fn dtw(a: &[&[f64]], b: &[&[f64]]) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return f64::NAN;
+    }
+    let distance = |x: &[f64], y: &[f64]| {
+        x.iter().zip(y).map(|(p, q)| (p - q) * (p - q)).sum::<f64>().sqrt()
+    };
+    // The cost and length of the best path to every cell of the previous
+    // and the current row.
+    let mut previous = vec![(f64::INFINITY, 0); b.len() + 1];
+    previous[0] = (0.0, 0);
+    for x in a {
+        let mut current = vec![(f64::INFINITY, 0); b.len() + 1];
+        for (j, y) in b.iter().enumerate() {
+            let best = [previous[j], previous[j + 1], current[j]]
+                .iter()
+                .cloned()
+                .min_by(|p, q| p.0.partial_cmp(&q.0).unwrap())
+                .unwrap();
+            current[j + 1] = (best.0 + distance(x, y), best.1 + 1);
+        }
+        previous = current;
+    }
+    let (cost, steps) = previous[b.len()];
+    cost / steps as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dtw, stats};
+
+    #[test]
+    fn computes_mean_and_deviation() {
+        let (mean, sd) = stats(vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]
+                .into_iter())
+            .unwrap();
+        assert_eq!((mean, sd), (5.0, 2.0));
+        assert_eq!(stats(Vec::new().into_iter()), None);
+    }
+
+    #[test]
+    fn aligns_gestures_performed_at_another_speed() {
+        let slow: Vec<Vec<f64>> =
+            [0.0, 0.0, 1.0, 1.0, 2.0, 2.0].iter().map(|&v| vec![v]).collect();
+        let fast: Vec<Vec<f64>> =
+            [0.0, 1.0, 2.0].iter().map(|&v| vec![v]).collect();
+        let shifted: Vec<Vec<f64>> =
+            [1.0, 2.0, 3.0].iter().map(|&v| vec![v]).collect();
+        let rows = |r: &[Vec<f64>]| {
+            r.iter().map(Vec::as_slice).collect::<Vec<_>>()
+        };
+        assert_eq!(dtw(&rows(&slow), &rows(&fast)), 0.0);
+        assert!(dtw(&rows(&fast), &rows(&shifted)) > 0.0);
+    }
+}
@@ -0,0 +1,3075 @@
+//! Configuration from the command line, the environment and an optional
+//! config file.
+//!
+//! `--config FILE` reads a TOML file whose keys mirror the long command line
+//! options, with underscores instead of dashes:
+//!
+//! ```toml
+//! server = "192.168.1.10:8001"
+//! features = "x,y,size"
+//! normalize = "unit"
+//! send_rate = 30
+//!
+//! [[filters]]
+//! type = "median"
+//! window = 5
+//! ```
+//!
+//! Every setting can also be given as an environment variable named after
+//! it, in capitals with the prefix `ESP_VISION_`, which is easier in a
+//! container. The value is read as TOML, or as a string if it isn't valid
+//! TOML, so tables such as filters are written inline:
+//!
+//! ```text
+//! ESP_VISION_SERVER=192.168.1.10:8001
+//! ESP_VISION_SEND_RATE=30
+//! ESP_VISION_FILTERS='[{ type = "median", window = 5 }]'
+//! ```
+//!
+//! `ESP_VISION_CONFIG` names the config file when `--config` doesn't.
+//! Options given on the command line take precedence over environment
+//! variables, and both take precedence over the file. See the
+//! [`filter`](../filter/index.html) module for the available filters, and
+//! the [`preprocess`](../preprocess/index.html) module for the
+//! `[[preprocess]]` steps applied to every frame.
+//! Colored markers are listed the same way:
+//!
+//! ```toml
+//! mode = "markers"
+//!
+//! [[markers]]
+//! name = "red"
+//! hsv_low = [170, 120, 70]
+//! hsv_high = [10, 255, 255]
+//! ```
+//!
+//! So are named regions, which start tracking without the mouse, either
+//! with `--region NAME` or with the keys `1` to `9`:
+//!
+//! ```toml
+//! [[regions]]
+//! name = "left-pad"
+//! rect = [40, 300, 120, 120]
+//! ```
+//!
+//! And `[[sinks]]`, the outputs of `--sink`; see the
+//! [`outputs`](../outputs/index.html) module. `[[labels]]` name and color
+//! the classes ESP predicts; see the [`labels`](../labels/index.html)
+//! module. `[[zones]]` are the zones of `--zone-dims` and `--zone-events`;
+//! see the [`zones`](../zones/index.html) module. `[params]` sets the
+//! vision parameters that the control channel tunes, such as the bounds of
+//! the HSV mask:
+//!
+//! ```toml
+//! [params]
+//! h_min = 100
+//! h_max = 130
+//! ```
+//!
+//! The file is watched while running, and changes to `[params]`,
+//! `send_rate` and `[[filters]]` are applied right away; see the
+//! [`reload`](../reload/index.html) module.
+use crate::aruco::{self, ArucoOptions};
+use crate::bench;
+use crate::calibrate;
+use crate::camera::Intrinsics;
+use crate::compare;
+use crate::coordinate;
+use crate::cuda;
+use crate::discover;
+use crate::grpc;
+use crate::derive::Order;
+use crate::devices;
+use crate::evaluate;
+use crate::feature_replay;
+use crate::features::FeatureSet;
+use crate::feedback;
+use crate::filter::FilterSpec;
+use crate::flow;
+use crate::gaze;
+use crate::input::{self, CaptureSettings, InputSpec, Playback};
+use crate::kalman;
+use crate::keepalive::Idle;
+use crate::labels::LabelSpec;
+use crate::latency;
+use crate::landmarks::{self, LandmarkSpec};
+use crate::logging::{LogFilter, LogFormat};
+use crate::loss::LossConfig;
+use crate::markers::MarkerSpec;
+use crate::net;
+use crate::normalize::{Convention, Normalization, Unit};
+use crate::offline;
+use crate::outputs::SinkSpec;
+use crate::params::VisionParams;
+use crate::pipeline;
+use crate::plot;
+use crate::pose;
+use crate::preprocess::{Pipeline, StepSpec};
+use crate::privacy::Privacy;
+use crate::rate;
+use crate::realsense;
+use crate::segment::{self, Segmentation};
+use crate::serialize::Format;
+use crate::session;
+use crate::standby;
+use crate::stereo::Rig;
+use crate::sync;
+use crate::synthetic;
+use crate::tracker::{Backend, DNN_BUILT, OPENCL_BUILT, TrackerOptions,
+                     VOC_LABELS};
+use crate::v4l2;
+use crate::window;
+use crate::zones::ZoneSpec;
+use getopts::{Matches, Options};
+use rust_vision::Rect;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::time::Duration;
+use toml::Value;
+use toml::value::Table;
+
+/// The cascade of the face profile, shipped with OpenCV.
+const FACE_CASCADE: &str = "haarcascade_frontalface_default.xml";
+
+/// The prefix of environment variables that set settings.
+const ENV_PREFIX: &str = "ESP_VISION_";
+/// The environment variable naming the config file.
+const CONFIG_VAR: &str = "ESP_VISION_CONFIG";
+
+/// `RegionPreset` is a named region that tracking can be started from.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RegionPreset {
+    pub name: String,
+    /// The region, as `[x, y, width, height]`.
+    pub rect: [i32; 4],
+}
+
+impl RegionPreset {
+    pub fn rect(&self) -> Rect {
+        let r = self.rect;
+        Rect::new(r[0], r[1], r[2], r[3])
+    }
+}
+
+/// What is streamed to ESP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Features of a target selected with the mouse.
+    Track,
+    /// Global motion from dense optical flow; nothing needs to be selected.
+    Flow,
+    /// Features of every configured colored marker.
+    Markers,
+    /// Facial landmarks of the largest face.
+    Landmarks,
+    /// The pose of an ArUco marker.
+    Aruco,
+    /// Centroids of QR codes, by payload.
+    Qr,
+    /// Joints of a human pose network.
+    Pose,
+    /// Position of the pupils within the eyes.
+    Gaze,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Mode, String> {
+        match s {
+            "track" => Ok(Mode::Track),
+            "flow" => Ok(Mode::Flow),
+            "markers" => Ok(Mode::Markers),
+            "landmarks" => Ok(Mode::Landmarks),
+            "aruco" => Ok(Mode::Aruco),
+            "qr" => Ok(Mode::Qr),
+            "pose" => Ok(Mode::Pose),
+            "gaze" => Ok(Mode::Gaze),
+            _ => Err(format!("unknown mode `{}`", s)),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mode::Track => write!(f, "track"),
+            Mode::Flow => write!(f, "flow"),
+            Mode::Markers => write!(f, "markers"),
+            Mode::Landmarks => write!(f, "landmarks"),
+            Mode::Aruco => write!(f, "aruco"),
+            Mode::Qr => write!(f, "qr"),
+            Mode::Pose => write!(f, "pose"),
+            Mode::Gaze => write!(f, "gaze"),
+        }
+    }
+}
+
+/// `Config` collects everything that can be configured.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Address of ESP's `TcpInputStream`.
+    pub server: String,
+    /// The mDNS service to look for ESP as, before trying `server`.
+    pub discover: Option<String>,
+    /// What is streamed.
+    pub mode: Mode,
+    /// The tracking backend.
+    pub tracker: Backend,
+    /// Settings of the tracking backends.
+    pub tracking: TrackerOptions,
+    /// Run the networks and the dense flow on CUDA.
+    pub cuda: bool,
+    /// Address we listen on for predictions coming back from ESP.
+    pub prediction: String,
+    /// Address we listen on for parameter updates.
+    pub control: String,
+    /// Address of the Prometheus metrics endpoint, if any.
+    pub metrics: Option<String>,
+    /// Address of the web dashboard, if any.
+    pub dashboard: Option<String>,
+    /// Address of the HTTP control API, if any.
+    pub api: Option<String>,
+    /// Address of the gRPC service, if any.
+    pub grpc: Option<String>,
+    /// More outputs for the samples.
+    pub sinks: Vec<SinkSpec>,
+    /// Which messages are logged.
+    pub log_filter: LogFilter,
+    /// How messages are logged.
+    pub log_format: LogFormat,
+    /// Features sent to ESP for every frame.
+    pub features: FeatureSet,
+    /// Cascade used to start tracking the largest face, if any.
+    pub face_cascade: Option<String>,
+    /// Intrinsics of the camera, if known.
+    pub intrinsics: Option<Intrinsics>,
+    /// Remove the lens distortion of the intrinsics from every frame.
+    pub undistort: bool,
+    /// The stereo rig of the two inputs, to send the depth of the target.
+    pub stereo: Option<Rig>,
+    /// Send the depth of every target, read from the RealSense input.
+    pub depth: bool,
+    /// The marker of the `aruco` mode.
+    pub aruco: ArucoOptions,
+    /// Weights and description of the network of the `pose` mode.
+    pub pose_model: Option<(String, String)>,
+    /// The joints sent in the `pose` mode, as indices into `pose::JOINTS`.
+    pub joints: Vec<usize>,
+    /// Joints whose confidence is lower are sent as NaN.
+    pub joint_confidence: f64,
+    /// Payloads of the QR codes sent in the `qr` mode, in order.
+    pub qr_channels: Vec<String>,
+    /// Eye cascade of the `gaze` mode.
+    pub eye_cascade: Option<String>,
+    /// Facemark model of the `landmarks` mode.
+    pub landmark_model: Option<String>,
+    /// The landmarks sent in the `landmarks` mode.
+    pub landmarks: Vec<LandmarkSpec>,
+    /// Start tracking a skin-colored blob entering this zone.
+    pub skin_zone: Option<Rect>,
+    /// The patch `c` recalibrates the colors from, rather than the first
+    /// target.
+    pub reference_patch: Option<Rect>,
+    /// Wait for motion in this zone before tracking.
+    pub arm_zone: Option<Rect>,
+    /// How long nothing has to move in the arm zone to stop tracking.
+    pub idle_after: Duration,
+    /// Named regions to start tracking from, selected by the keys `1` to
+    /// `9` in order.
+    pub regions: Vec<RegionPreset>,
+    /// Zones whose occupancy is followed in the track mode.
+    pub zones: Vec<ZoneSpec>,
+    /// Send the occupancy of every zone as a dimension.
+    pub zone_dims: bool,
+    /// Send the zones entered and left to this address.
+    pub zone_events: Option<String>,
+    /// The names and colors of the classes ESP predicts.
+    pub labels: Vec<LabelSpec>,
+    /// Vision parameters set by `[params]` in the config file, by name.
+    pub params: Vec<(String, f64)>,
+    /// The config file, watched for changes while running.
+    pub config_file: Option<String>,
+    /// The live settings that the command line or environment set, as
+    /// named in `Live::KEYS`; changes to them in the config file are ignored.
+    pub overridden: Vec<&'static str>,
+    /// Show a desktop notification for every prediction.
+    pub notify: bool,
+    /// The command playing the sounds of the labels.
+    pub sound_player: String,
+    /// Start tracking from this region, given by `--region` or `--roi`.
+    pub start_region: Option<Rect>,
+    /// Keep the profile of the first target in this file.
+    pub save_profile: Option<String>,
+    /// Start tracking from the profile in this file.
+    pub load_profile: Option<String>,
+    /// The colored markers of the `markers` mode.
+    pub markers: Vec<MarkerSpec>,
+    /// Number of targets tracked at once.
+    pub targets: usize,
+    /// The two targets are hands, sent as left and right.
+    pub hands: bool,
+    /// Names of the targets, before the names of their dimensions; empty to
+    /// number them.
+    pub target_names: Vec<String>,
+    /// Send this many tracked points instead of `features`.
+    pub points: Option<usize>,
+    /// Derivatives of the centroid appended after `features`.
+    pub derive: Order,
+    /// Coordinate space of the spatial dimensions.
+    pub normalization: Normalization,
+    /// Origin and orientation of the axes in that space.
+    pub convention: Convention,
+    /// File of the plane positions are mapped onto, written by calibrating
+    /// it with `p`.
+    pub plane: Option<String>,
+    /// Width and height of the plane when calibrating it.
+    pub plane_size: [f64; 2],
+    /// Wire format of the ESP sink.
+    pub format: Format,
+    /// Append time and sequence number in the ASCII format.
+    pub ascii_stamps: bool,
+    /// Measure the latency of the samples from capture to ESP.
+    pub measure_latency: bool,
+    /// Print the samples rather than connecting to ESP.
+    pub dry_run: bool,
+    /// The dimensions ESP expects, if declared.
+    pub expect_dims: Option<usize>,
+    /// Print the dimensions and their ESP labels, and exit.
+    pub print_manifest: bool,
+    /// Start the stream to ESP with a line describing the dimensions.
+    pub send_manifest: bool,
+    /// Where frames come from; one target is tracked in each when there are
+    /// several.
+    pub inputs: Vec<InputSpec>,
+    pub playback: Playback,
+    /// What is asked of cameras.
+    pub capture: CaptureSettings,
+    /// Live frames older than this when tracking gets to them are dropped.
+    pub latency_budget: Option<Duration>,
+    /// Directory to record the session to.
+    pub session: Option<String>,
+    /// Directory of the session to replay.
+    pub replay: Option<String>,
+    /// Run without any window.
+    pub headless: bool,
+    /// Mask the frames shown, recorded and published.
+    pub privacy: Option<Privacy>,
+    /// Record the displayed frames to this video file.
+    pub record: Option<String>,
+    /// Log the samples to this CSV file.
+    pub log_features: Option<String>,
+    /// Write the samples, labeled with the class tagged by the number keys,
+    /// to this CSV file.
+    pub dataset: Option<String>,
+    /// Log the capture time and frame of the samples to this CSV file.
+    pub sync_log: Option<String>,
+    /// Send a beacon in the `sync` dimension at this interval.
+    pub sync_beacons: Option<Duration>,
+    /// Write the quality report of the session to this file.
+    pub report: Option<String>,
+    /// Show trackbars for the mask thresholds and the histogram.
+    pub trackbars: bool,
+    /// Maximum number of samples per second sent to ESP, if limited.
+    pub send_rate: Option<f64>,
+    /// Samples per second on the fixed clock of the resampler, if any.
+    pub resample: Option<f64>,
+    /// Keepalive samples per second while nothing else is sent, if any.
+    pub keepalive: Option<f64>,
+    /// What keepalive samples carry.
+    pub keepalive_values: Idle,
+    /// Seconds of samples the plot shows.
+    pub plot_seconds: f64,
+    /// Centroids of every target drawn as a trail; 0 draws none.
+    pub trail: usize,
+    /// How samples are split into gestures, if they are.
+    pub segment: Option<Segmentation>,
+    /// Smoothing filters applied to the tracked region, in order.
+    pub filters: Vec<FilterSpec>,
+    /// Preprocessing steps applied to every frame, in order.
+    pub preprocess: Vec<StepSpec>,
+    /// When to consider the target lost, and what to send then.
+    pub loss: LossConfig,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            server: "127.0.0.1:8001".to_string(),
+            discover: None,
+            mode: Mode::Track,
+            tracker: Backend::Camshift,
+            tracking: TrackerOptions::default(),
+            cuda: false,
+            prediction: "127.0.0.1:5204".to_string(),
+            control: "127.0.0.1:8002".to_string(),
+            metrics: None,
+            dashboard: None,
+            api: None,
+            grpc: None,
+            sinks: Vec::new(),
+            log_filter: LogFilter::default(),
+            log_format: LogFormat::Text,
+            features: FeatureSet::default(),
+            face_cascade: None,
+            intrinsics: None,
+            stereo: None,
+            depth: false,
+            undistort: false,
+            aruco: ArucoOptions::default(),
+            pose_model: None,
+            joints: pose::parse_joints("left_wrist,right_wrist").unwrap(),
+            joint_confidence: 0.1,
+            qr_channels: Vec::new(),
+            eye_cascade: None,
+            landmark_model: None,
+            landmarks: landmarks::parse_list("nose,chin").unwrap(),
+            skin_zone: None,
+            reference_patch: None,
+            arm_zone: None,
+            idle_after: standby::DEFAULT_IDLE_AFTER,
+            regions: Vec::new(),
+            zones: Vec::new(),
+            zone_dims: false,
+            zone_events: None,
+            labels: Vec::new(),
+            params: Vec::new(),
+            config_file: None,
+            overridden: Vec::new(),
+            notify: false,
+            sound_player: feedback::DEFAULT_PLAYER.to_string(),
+            start_region: None,
+            save_profile: None,
+            load_profile: None,
+            markers: Vec::new(),
+            targets: 1,
+            hands: false,
+            target_names: Vec::new(),
+            points: None,
+            derive: Order::None,
+            normalization: Normalization::Pixel,
+            convention: Convention::default(),
+            plane: None,
+            plane_size: [1.0, 1.0],
+            format: Format::Ascii,
+            ascii_stamps: false,
+            measure_latency: false,
+            dry_run: false,
+            expect_dims: None,
+            print_manifest: false,
+            send_manifest: false,
+            inputs: vec![InputSpec::default()],
+            playback: Playback::default(),
+            capture: CaptureSettings::default(),
+            latency_budget: Some(pipeline::DEFAULT_LATENCY_BUDGET),
+            session: None,
+            replay: None,
+            headless: false,
+            privacy: None,
+            record: None,
+            log_features: None,
+            dataset: None,
+            sync_log: None,
+            sync_beacons: None,
+            report: None,
+            trackbars: false,
+            send_rate: None,
+            resample: None,
+            keepalive: None,
+            keepalive_values: Idle::Last,
+            plot_seconds: plot::DEFAULT_SECONDS,
+            trail: 0,
+            segment: None,
+            filters: Vec::new(),
+            preprocess: Vec::new(),
+            loss: LossConfig::default(),
+        }
+    }
+}
+
+fn options() -> Options {
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help message");
+    opts.optopt("c",
+                "config",
+                "read settings from a TOML file (or $ESP_VISION_CONFIG)",
+                "FILE");
+    opts.optopt("",
+                "mode",
+                "what to stream: track (a selected target), flow (global \
+                 motion), markers (colored markers), landmarks (facial \
+                 landmarks), aruco (pose of an ArUco marker), qr (QR codes), \
+                 pose (human pose) or gaze (gaze direction) (track)",
+                "MODE");
+    opts.optopt("",
+                "pose-model",
+                "weights of the network of the pose mode",
+                "FILE");
+    opts.optopt("",
+                "pose-config",
+                "description of the network of the pose mode",
+                "FILE");
+    opts.optopt("",
+                "joints",
+                "comma-separated joints to send, e.g. left_wrist, \
+                 right_elbow, neck (left_wrist,right_wrist)",
+                "LIST");
+    opts.optopt("",
+                "joint-confidence",
+                "joints with a lower confidence are sent as NaN (0.1)",
+                "SCORE");
+    opts.optopt("",
+                "qr-channels",
+                "comma-separated payloads of the QR codes to send",
+                "LIST");
+    opts.optopt("",
+                "intrinsics",
+                "TOML file with the camera intrinsics, or OpenCV's YAML",
+                "FILE");
+    opts.optflag("",
+                 "undistort",
+                 "remove the lens distortion of the --intrinsics from every \
+                  frame");
+    opts.optopt("",
+                "stereo",
+                "TOML file describing the stereo rig of two --input, to send \
+                 the depth of the target",
+                "FILE");
+    opts.optopt("",
+                "aruco-dictionary",
+                "ArUco dictionary: 4x4_50, 5x5_100, 6x6_250 or original \
+                 (4x4_50)",
+                "NAME");
+    opts.optopt("", "aruco-id", "id of the ArUco marker to follow (0)", "ID");
+    opts.optopt("",
+                "marker-length",
+                "side of the printed ArUco marker in meters (0.05)",
+                "M");
+    opts.optopt("",
+                "eye-cascade",
+                "Haar cascade finding eyes for the gaze mode",
+                "FILE");
+    opts.optopt("",
+                "landmark-model",
+                "LBF facemark model for the landmarks mode",
+                "FILE");
+    opts.optopt("",
+                "landmarks",
+                "comma-separated landmarks to send: chin, nose, left_eye, \
+                 right_eye, left_brow, right_brow, mouth or a point index \
+                 (nose,chin)",
+                "LIST");
+    opts.optmulti("",
+                  "marker",
+                  "a colored marker for the markers mode, e.g. \
+                   red:170,120,70:10,255,255; repeat for more",
+                  "NAME:HSV:HSV");
+    opts.optopt("",
+                "tracker",
+                "tracking backend: camshift, kcf, csrt, mosse, lk, mog2, \
+                 knn, template, dnn, ensemble or a registered one \
+                 (camshift)",
+                "NAME");
+    opts.optopt("",
+                "dnn-model",
+                "weights of the dnn tracker's detector",
+                "FILE");
+    opts.optopt("",
+                "dnn-config",
+                "network description of the dnn tracker's detector",
+                "FILE");
+    opts.optopt("",
+                "dnn-kind",
+                "kind of the detector: ssd or yolo (ssd)",
+                "KIND");
+    opts.optopt("",
+                "dnn-labels",
+                "class names of the detector, one per line (the VOC classes \
+                 of MobileNet-SSD)",
+                "FILE");
+    opts.optopt("",
+                "dnn-class",
+                "class to follow, by name or id (person)",
+                "CLASS");
+    opts.optopt("",
+                "dnn-confidence",
+                "minimal detection score (0.5)",
+                "SCORE");
+    opts.optflag("",
+                 "cuda",
+                 "run the dnn tracker, the pose network and the dense flow \
+                  on CUDA (needs a build with the cuda feature)");
+    opts.optopt("",
+                "detect-every",
+                "frames between detections of the dnn tracker (10)",
+                "N");
+    opts.optopt("",
+                "face-cascade",
+                "start tracking the largest face found with this Haar or LBP \
+                 cascade",
+                "FILE");
+    opts.optopt("",
+                "skin-zone",
+                "start tracking a hand (or other skin) entering this zone",
+                "X,Y,W,H");
+    opts.optopt("",
+                "reference-patch",
+                "region whose colors `c` recalibrates the HSV mask from, \
+                 rather than the first target",
+                "X,Y,W,H");
+    opts.optopt("",
+                "arm-zone",
+                "track and send nothing until something moves in this zone",
+                "X,Y,W,H");
+    opts.optopt("",
+                "idle-after",
+                "stop tracking and arm --arm-zone again after SECS without \
+                 motion in it (30)",
+                "SECS");
+    opts.optopt("",
+                "region",
+                "start tracking from this region of the config file",
+                "NAME");
+    opts.optopt("", "roi", "start tracking from this region", "X,Y,W,H");
+    opts.optopt("",
+                "save-profile",
+                "keep the histogram, window and thresholds of the first \
+                 target in this file",
+                "FILE");
+    opts.optopt("",
+                "load-profile",
+                "start tracking from a profile kept by --save-profile",
+                "FILE");
+    opts.optopt("",
+                "histogram",
+                "what the camshift tracker follows: hue, or hs for hue and \
+                 saturation (hue)",
+                "KIND");
+    opts.optopt("",
+                "saturation-bins",
+                "saturation bins of the hs histogram (8)",
+                "N");
+    opts.optopt("",
+                "hist-adapt",
+                "blend the histogram of the tracked window into the camshift \
+                 histogram at this rate per frame (0)",
+                "RATE");
+    opts.optopt("",
+                "hist-adapt-confidence",
+                "confidence a frame needs to be blended in (0.5)",
+                "SCORE");
+    opts.optopt("",
+                "min-window",
+                "the camshift search window never shrinks below PX pixels \
+                 (8)",
+                "PX");
+    opts.optflag("",
+                 "opencl",
+                 "run the color conversion, mask and backprojection of the \
+                  camshift tracker through OpenCL, if a device is found \
+                  (opencl feature)");
+    opts.optopt("",
+                "search-expansion",
+                "template search window size relative to the template (2)",
+                "FACTOR");
+    opts.optopt("",
+                "template-update",
+                "when to replace the template: never, always or confident \
+                 (confident)",
+                "POLICY");
+    opts.optopt("",
+                "update-confidence",
+                "match score that counts as confident (0.8)",
+                "SCORE");
+    opts.optopt("",
+                "server",
+                "address of ESP's TcpInputStream (127.0.0.1:8001)",
+                "ADDR");
+    opts.optflagopt("",
+                    "discover",
+                    "look for ESP on the local network as an mDNS service, \
+                     given as --discover=SERVICE (_esp._tcp), then try \
+                     --server",
+                    "SERVICE");
+    opts.optopt("",
+                "prediction",
+                "address to receive ESP predictions on (127.0.0.1:5204)",
+                "ADDR");
+    opts.optflag("",
+                 "notify",
+                 "show a desktop notification for every prediction");
+    opts.optopt("",
+                "sound-player",
+                &format!("command playing the sounds of the labels ({})",
+                         feedback::DEFAULT_PLAYER),
+                "CMD");
+    opts.optopt("",
+                "control",
+                "address of the control channel (127.0.0.1:8002)",
+                "ADDR");
+    opts.optopt("",
+                "metrics",
+                "serve Prometheus metrics on http://ADDR/metrics",
+                "ADDR");
+    opts.optopt("",
+                "dashboard",
+                "serve a status page with the annotated video on \
+                 http://ADDR/",
+                "ADDR");
+    opts.optopt("",
+                "api",
+                "serve the HTTP control API on http://ADDR/",
+                "ADDR");
+    opts.optopt("",
+                "grpc",
+                "serve the samples and control over gRPC on ADDR (needs a \
+                 build with the grpc feature)",
+                "ADDR");
+    opts.optmulti("",
+                  "sink",
+                  "also send the samples to an output, e.g. \
+                   osc:127.0.0.1:9000,rate=30 or csv:FILE; repeat for more",
+                  "KIND:TARGET");
+    opts.optflag("v", "verbose", "log debug messages too");
+    opts.optopt("",
+                "log",
+                "log levels, overall and per module, e.g. \
+                 warn,loss=debug (info)",
+                "FILTER");
+    opts.optopt("",
+                "log-format",
+                "format of the log on stderr: text or json (text)",
+                "FORMAT");
+    opts.optopt("",
+                "features",
+                "comma-separated features to send: x, y, area, size, angle, \
+                 aspect, ellipse_width, ellipse_height, or ellipse for x, y, \
+                 both axes and the angle (x,y)",
+                "LIST");
+    opts.optopt("",
+                "profile",
+                "working settings for a use case: hand (a skin-colored \
+                 hand), face (the largest face) or marker (red and blue \
+                 markers)",
+                "NAME");
+    opts.optopt("",
+                "preset",
+                "defaults for a use case: hands (two hands, sent as left and \
+                 right)",
+                "NAME");
+    opts.optopt("",
+                "targets",
+                "number of targets to select and track at once (1)",
+                "N");
+    opts.optopt("",
+                "target-names",
+                "comma-separated names of the targets, which their \
+                 dimensions start with, e.g. hand,ball",
+                "NAMES");
+    opts.optopt("",
+                "points",
+                "send the first N points of the lk tracker instead of the \
+                 features",
+                "N");
+    opts.optopt("",
+                "dims",
+                "number of dimensions sent; picks x, x,y or x,y,size unless \
+                 --features is given",
+                "N");
+    opts.optflag("",
+                 "velocity",
+                 "append the velocity of the centroid (dx dy)");
+    opts.optflag("",
+                 "acceleration",
+                 "append velocity and acceleration (dx dy ddx ddy)");
+    opts.optopt("",
+                "normalize",
+                "coordinate space: pixel, unit ([0,1]), signed ([-1,1]), \
+                 aspect or letterbox (pixel)",
+                "MODE");
+    opts.optopt("",
+                "origin",
+                "move the origin of the positions to the corner or the \
+                 center of the frame (that of --normalize)",
+                "ORIGIN");
+    opts.optflag("", "y-up", "point the y axis up rather than down");
+    opts.optflag("",
+                 "unmirror",
+                 "send x as the camera sees it rather than as the mirrored \
+                  preview shows it");
+    opts.optopt("",
+                "plane",
+                "map positions onto the plane calibrated in this file; press \
+                 `p` and click its four corners to calibrate it",
+                "FILE");
+    opts.optopt("",
+                "plane-size",
+                "width and height of the plane when calibrating it (1x1)",
+                "WxH");
+    opts.optopt("",
+                "format",
+                "wire format of the ESP sink: ascii, json, binary or framed \
+                 (ascii)",
+                "FORMAT");
+    opts.optflag("",
+                 "ascii-stamps",
+                 "append the time and sequence number to ASCII samples \
+                  (two extra dimensions for ESP)");
+    opts.optflag("",
+                 "measure-latency",
+                 "stamp samples with their capture time and print the \
+                  latency until they are written, and echoed back if the \
+                  server echoes (see `echo`), on exit");
+    opts.optflag("",
+                 "dry-run",
+                 "print the samples instead of connecting to the server, to \
+                  check the features");
+    opts.optopt("",
+                "expect-dims",
+                "refuse to start unless the samples have the N dimensions \
+                 the ESP pipeline declares",
+                "N");
+    opts.optflag("",
+                 "print-manifest",
+                 "print the dimensions, their units and the lines declaring \
+                  them in ESP, and exit");
+    opts.optflag("",
+                 "send-manifest",
+                 "start the stream with a line describing the dimensions \
+                  (ascii and json formats)");
+    opts.optflag("",
+                 "headless",
+                 "open no window; tracking has to be started by --roi, \
+                  --region, --load-profile, --face-cascade, --skin-zone, \
+                  --arm-zone or a tracker that needs no selection");
+    opts.optopt("",
+                "privacy",
+                "mask the video wherever it is shown, recorded or published, \
+                 keeping the overlays: blur or black",
+                "MODE");
+    opts.optmulti("",
+                  "input",
+                  "where frames come from: camera:N, video:PATH, images:DIR, \
+                   mjpeg:URL, synthetic:TRAJECTORY, v4l2:DEVICE, \
+                   realsense[:SERIAL] or an rtsp:// or http:// URL \
+                   (camera:0); repeat to track one target in each",
+                  "SOURCE");
+    opts.optflag("",
+                 "demo",
+                 "track a ball bouncing in a synthetic scene instead of a \
+                  camera");
+    opts.optopt("",
+                "speed",
+                "playback speed of recorded input, relative to its frame \
+                 rate (1)",
+                "FACTOR");
+    opts.optopt("",
+                "frame-delay",
+                "milliseconds between the frames of images:DIR (33)",
+                "MS");
+    opts.optflag("", "loop", "start recorded input over at its end");
+    opts.optopt("",
+                "resolution",
+                "frame size to ask cameras for (the camera's default)",
+                "WIDTHxHEIGHT");
+    opts.optopt("",
+                "fps",
+                "frame rate to ask cameras for (the camera's default)",
+                "N");
+    opts.optopt("",
+                "pixel-format",
+                "pixel format to ask cameras for, e.g. MJPG, which many \
+                 cameras need for high frame rates (the camera's default)",
+                "FOURCC");
+    opts.optopt("",
+                "exposure",
+                "fixed camera exposure, in the units of the backend (auto)",
+                "VALUE");
+    opts.optopt("", "gain", "fixed camera gain (auto)", "VALUE");
+    opts.optopt("",
+                "white-balance",
+                "fixed camera white balance (auto)",
+                "KELVIN");
+    opts.optopt("",
+                "latency-budget",
+                "drop live frames older than MS by the time tracking gets to \
+                 them; 0 keeps them (100)",
+                "MS");
+    opts.optopt("",
+                "camera-timeout",
+                "give up on a camera that fails and can't be reopened for \
+                 SECS (never)",
+                "SECS");
+    opts.optflag("",
+                 "lock-camera",
+                 "lock exposure, gain and white balance once the camera \
+                  settled; `l` locks them again");
+    opts.optopt("",
+                "session",
+                "record the frames, selections, keys and parameter changes \
+                 to this directory, for `replay`",
+                "DIR");
+    opts.optopt("",
+                "replay",
+                "replay the session recorded to this directory; `replay \
+                 DIR` also restores its options",
+                "DIR");
+    opts.optopt("",
+                "record",
+                "record the displayed frames, annotations included, to this \
+                 video file (.mp4 or .avi)",
+                "FILE");
+    opts.optopt("",
+                "log-features",
+                "log every sample, with the confidence and state of the \
+                 targets, to this CSV file",
+                "FILE");
+    opts.optopt("",
+                "dataset",
+                "write every sample, labeled with the class tagged by the \
+                 keys 0 to 9, to this CSV file",
+                "FILE");
+    opts.optopt("",
+                "sync-log",
+                "log the sequence number, capture time and frame of every \
+                 sample to this CSV file",
+                "FILE");
+    opts.optopt("",
+                "sync-beacons",
+                "send a `sync` dimension numbering a sample every SECS \
+                 seconds, to align what ESP records with the sync log",
+                "SECS");
+    opts.optopt("",
+                "report",
+                "write the quality report of the session to this file when \
+                 stopping, as JSON if it ends with .json",
+                "FILE");
+    opts.optflag("",
+                 "trackbars",
+                 "tune the HSV mask and the histogram bins with trackbars \
+                  (press `t` to print the values)");
+    opts.optopt("",
+                "send-rate",
+                "send at most HZ samples per second, independent of the \
+                 camera frame rate",
+                "HZ");
+    opts.optopt("",
+                "resample",
+                "send exactly HZ samples per second, interpolated between \
+                 frames",
+                "HZ");
+    opts.optopt("",
+                "keepalive",
+                "send HZ keepalive samples per second while no other sample \
+                 is sent",
+                "HZ");
+    opts.optopt("",
+                "keepalive-values",
+                "what keepalive samples carry: last or nan (last)",
+                "VALUES");
+    opts.optopt("",
+                "plot-seconds",
+                "seconds of samples the plot shown with `g` spans (10)",
+                "SECS");
+    opts.optopt("",
+                "trail",
+                "draw the last N centroids of every target as a fading line, \
+                 to see the shape of gestures",
+                "N");
+    opts.optopt("",
+                "segment",
+                "only send gestures, which start when the positions move at \
+                 START units per second and end below STOP",
+                "START:STOP");
+    opts.optflag("",
+                 "segment-markers",
+                 "send all samples with a segment dimension marking the \
+                  start (1) and end (-1) of gestures");
+    opts.optflag("",
+                 "zone-dims",
+                 "send a dimension per zone of the config file, 1 while a \
+                  target is in it");
+    opts.optopt("",
+                "zone-events",
+                "send the zones targets enter and leave to this UDP address",
+                "ADDR");
+    opts.optflag("",
+                 "kalman",
+                 "smooth the centroid and size with a Kalman filter instead \
+                  of the filters from the config file (press `k` to compare \
+                  with the raw output)");
+    opts.optopt("",
+                "process-noise",
+                &format!("Kalman process noise in px²/s³ ({})",
+                         kalman::DEFAULT_PROCESS_NOISE),
+                "Q");
+    opts.optopt("",
+                "measurement-noise",
+                &format!("Kalman measurement noise in px² ({})",
+                         kalman::DEFAULT_MEASUREMENT_NOISE),
+                "R");
+    opts.optopt("",
+                "min-confidence",
+                "fraction of the search window the target must cover to \
+                 count as tracked (0.1)",
+                "C");
+    opts.optopt("",
+                "lost-frames",
+                "number of low-confidence frames before the target is lost \
+                 (5)",
+                "N");
+    opts.optopt("",
+                "min-size",
+                "search windows smaller than PX pixels are lost (4)",
+                "PX");
+    opts.optopt("",
+                "on-lost",
+                "what to send while the target is lost: skip or nan (skip)",
+                "MODE");
+    opts.optflag("",
+                 "reacquire",
+                 "search the frame for the target while it is lost");
+    opts.optopt("",
+                "reacquire-confidence",
+                "confidence a candidate needs to be re-acquired (0.3)",
+                "C");
+    opts
+}
+
+impl Config {
+    /// Parses the command line (including the program name). Prints the
+    /// usage and exits if `--help` is given.
+    pub fn from_args(args: &[String]) -> Result<Config, String> {
+        let opts = options();
+        let matches = opts.parse(&args[1..]).map_err(|e| e.to_string())?;
+        if matches.opt_present("h") {
+            let brief = format!("Usage: {0} [options]\n       {0} {1}\n       \
+                                 {0} {2} DIR [options]\n       \
+                                 {0} {3} FILE [options]\n       \
+                                 {0} {4} [options]\n       \
+                                 {0} {5} [options]\n       \
+                                 {0} {6} TRUTH --input SOURCE \
+                                 [options]\n       \
+                                 {0} {7} [ADDR]\n       \
+                                 {0} {8} --input SOURCE --out FILE \
+                                 [options]\n       \
+                                 {0} {9} REFERENCE FILE... [options]\n       \
+                                 {0} {10} --peer NAME=ADDR... [options]",
+                                args[0],
+                                devices::COMMAND,
+                                session::REPLAY_COMMAND,
+                                feature_replay::COMMAND,
+                                calibrate::COMMAND,
+                                bench::COMMAND,
+                                evaluate::COMMAND,
+                                latency::ECHO_COMMAND,
+                                offline::COMMAND,
+                                compare::COMMAND,
+                                coordinate::COMMAND);
+            print!("{}", opts.usage(&brief));
+            process::exit(0);
+        }
+        if let Some(arg) = matches.free.first() {
+            // `--discover SERVICE` leaves the service behind.
+            let hint = if matches.opt_present("discover") {
+                "; give the service as --discover=SERVICE"
+            } else {
+                ""
+            };
+            return Err(format!("unexpected argument `{}`{}", arg, hint));
+        }
+        Config::from_matches(&matches)
+    }
+
+    fn from_matches(matches: &Matches) -> Result<Config, String> {
+        let vars = env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        });
+        let mut settings = Settings::from_matches(matches)?
+            .or(Settings::from_env(vars)?);
+        let overridden = settings.live_keys();
+        let path = matches.opt_str("config")
+            .or_else(|| env::var(CONFIG_VAR).ok());
+        if let Some(ref path) = path {
+            settings = settings.or(Settings::from_file(path)?);
+        }
+        let mut config = settings.defaults()?.resolve()?;
+        config.config_file = path;
+        config.overridden = overridden;
+        Ok(config)
+    }
+
+    /// Total number of dimensions sent to ESP.
+    pub fn dims(&self) -> usize {
+        self.tracked_dims() + self.segment_markers() as usize +
+        self.sync_beacons.is_some() as usize
+    }
+
+    /// Number of dimensions ESP receives: the samples, and the time and
+    /// sequence number of `--ascii-stamps`.
+    pub fn esp_dims(&self) -> usize {
+        let stamps = self.ascii_stamps && self.format == Format::Ascii;
+        self.dims() + 2 * stamps as usize
+    }
+
+    /// Whether the samples end with the markers of the segmentation.
+    fn segment_markers(&self) -> bool {
+        matches!(self.segment, Some(s) if s.markers)
+    }
+
+    /// Number of dimensions of the samples, before segmentation.
+    pub fn tracked_dims(&self) -> usize {
+        match self.mode {
+            Mode::Flow => flow::NAMES.len(),
+            Mode::Markers => self.markers.len() * self.features.dims(),
+            Mode::Landmarks => 2 * self.landmarks.len(),
+            Mode::Aruco => aruco::NAMES.len(),
+            Mode::Qr => 2 * self.qr_channels.len(),
+            Mode::Pose => 2 * self.joints.len(),
+            Mode::Gaze => gaze::NAMES.len(),
+            // The right view only adds the depth.
+            Mode::Track if self.stereo.is_some() => {
+                self.target_dims() + 1 + self.zone_dims()
+            }
+            Mode::Track => self.targets * self.target_dims() + self.zone_dims(),
+        }
+    }
+
+    /// Number of dimensions of the zones, sent after those of the targets.
+    pub fn zone_dims(&self) -> usize {
+        if self.zone_dims { self.zones.len() } else { 0 }
+    }
+
+    /// Number of dimensions of each tracked target.
+    pub fn target_dims(&self) -> usize {
+        let spatial = match self.points {
+            Some(n) => 2 * n,
+            None => self.features.dims(),
+        };
+        spatial + self.derive.dims() + self.depth as usize
+    }
+
+    /// The index of the `sync` dimension in the samples sent, with
+    /// `--sync-beacons`.
+    pub fn beacon_index(&self) -> Option<usize> {
+        self.sync_beacons
+            .map(|_| self.tracked_dims() + self.segment_markers() as usize)
+    }
+
+    /// Names of all dimensions, in the order they are sent.
+    pub fn dimension_names(&self) -> Vec<String> {
+        let mut names = self.tracked_names();
+        if self.segment_markers() {
+            names.push(segment::NAME.to_string());
+        }
+        if self.sync_beacons.is_some() {
+            names.push(sync::NAME.to_string());
+        }
+        names
+    }
+
+    /// Names of the dimensions of the samples, before segmentation.
+    pub fn tracked_names(&self) -> Vec<String> {
+        match self.mode {
+            Mode::Flow => {
+                return flow::NAMES.iter().map(|n| n.to_string()).collect();
+            }
+            Mode::Gaze => {
+                return gaze::NAMES.iter().map(|n| n.to_string()).collect();
+            }
+            Mode::Markers => {
+                return self.markers
+                    .iter()
+                    .flat_map(|m| {
+                        self.features
+                            .features()
+                            .iter()
+                            .map(move |f| format!("{}_{}", m.name, f.name()))
+                    })
+                    .collect();
+            }
+            Mode::Aruco => {
+                return aruco::NAMES.iter().map(|n| n.to_string()).collect();
+            }
+            Mode::Qr => {
+                return self.qr_channels
+                    .iter()
+                    .flat_map(|c| vec![format!("{}_x", c), format!("{}_y", c)])
+                    .collect();
+            }
+            Mode::Pose => {
+                return self.joints
+                    .iter()
+                    .map(|&j| pose::JOINTS[j])
+                    .flat_map(|j| vec![format!("{}_x", j), format!("{}_y", j)])
+                    .collect();
+            }
+            Mode::Landmarks => {
+                return self.landmarks
+                    .iter()
+                    .flat_map(|l| {
+                        vec![format!("{}_x", l.name), format!("{}_y", l.name)]
+                    })
+                    .collect();
+            }
+            Mode::Track => {}
+        }
+        let mut names = self.track_names();
+        if self.zone_dims {
+            names.extend(self.zones.iter().map(|z| format!("zone_{}", z.name)));
+        }
+        names
+    }
+
+    /// Names of the dimensions of the targets, before those of the zones.
+    fn track_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = match self.points {
+            Some(n) => {
+                (0..n)
+                    .flat_map(|i| vec![format!("x{}", i), format!("y{}", i)])
+                    .collect()
+            }
+            None => {
+                self.features
+                    .features()
+                    .iter()
+                    .map(|f| f.name().to_string())
+                    .collect()
+            }
+        };
+        names.extend(self.derive.names().iter().map(|n| n.to_string()));
+        if self.depth {
+            names.push("z".to_string());
+        }
+        if self.stereo.is_some() {
+            names.push("z".to_string());
+            return names;
+        }
+        if self.hands {
+            return ["left", "right"]
+                .iter()
+                .flat_map(|h| names.iter().map(move |n| format!("{}_{}", h, n)))
+                .collect();
+        }
+        if !self.target_names.is_empty() {
+            return self.target_names
+                .iter()
+                .flat_map(|t| names.iter().map(move |n| format!("{}_{}", t, n)))
+                .collect();
+        }
+        if self.targets == 1 {
+            return names;
+        }
+        // `x1 y1 x2 y2 ...`
+        (1..self.targets + 1)
+            .flat_map(|i| names.iter().map(move |n| format!("{}{}", n, i)))
+            .collect()
+    }
+
+    /// What all dimensions are measured in, in the order they are sent;
+    /// see `Normalization::label`.
+    pub fn dimension_labels(&self) -> Vec<String> {
+        self.dimension_units()
+            .into_iter()
+            .map(|unit| self.normalization.label(unit))
+            .collect()
+    }
+
+    /// Units of all dimensions, in the order they are sent.
+    pub fn dimension_units(&self) -> Vec<Unit> {
+        let mut units = self.tracked_units();
+        if self.segment_markers() {
+            units.push(Unit::None);
+        }
+        if self.sync_beacons.is_some() {
+            units.push(Unit::None);
+        }
+        units
+    }
+
+    /// Units of the dimensions of the samples, before segmentation.
+    pub fn tracked_units(&self) -> Vec<Unit> {
+        match self.mode {
+            Mode::Flow => return flow::UNITS.to_vec(),
+            // Already relative to the size of the eyes.
+            Mode::Gaze => return vec![Unit::OffsetX, Unit::OffsetY],
+            Mode::Markers => {
+                return self.markers
+                    .iter()
+                    .flat_map(|_| self.features.features().iter())
+                    .map(|f| f.unit())
+                    .collect();
+            }
+            Mode::Landmarks => {
+                return self.landmarks
+                    .iter()
+                    .flat_map(|_| vec![Unit::X, Unit::Y])
+                    .collect();
+            }
+            // Meters and degrees don't depend on the frame.
+            Mode::Aruco => return vec![Unit::None; aruco::NAMES.len()],
+            Mode::Qr => {
+                return self.qr_channels
+                    .iter()
+                    .flat_map(|_| vec![Unit::X, Unit::Y])
+                    .collect();
+            }
+            Mode::Pose => {
+                return self.joints
+                    .iter()
+                    .flat_map(|_| vec![Unit::X, Unit::Y])
+                    .collect();
+            }
+            Mode::Track => {}
+        }
+        let mut units = self.track_units();
+        if self.zone_dims {
+            units.extend(self.zones.iter().map(|_| Unit::None));
+        }
+        units
+    }
+
+    /// Units of the dimensions of the targets, before those of the zones.
+    fn track_units(&self) -> Vec<Unit> {
+        let mut units: Vec<Unit> = match self.points {
+            Some(n) => {
+                (0..n).flat_map(|_| vec![Unit::X, Unit::Y]).collect()
+            }
+            None => self.features.features().iter().map(|f| f.unit()).collect(),
+        };
+        units.extend_from_slice(self.derive.units());
+        if self.depth {
+            // Millimeters don't depend on the frame.
+            units.push(Unit::None);
+        }
+        if self.stereo.is_some() {
+            // The depth is in the unit of the rig.
+            units.push(Unit::None);
+            return units;
+        }
+        let target = units.len();
+        units.into_iter().cycle().take(self.targets * target).collect()
+    }
+
+    /// What the rest of the config allows of the tracking backend.
+    pub fn tracker_rules(&self) -> TrackerRules {
+        TrackerRules {
+            dnn_model: !self.tracking.dnn.model.is_empty(),
+            opencl: self.tracking.camshift.opencl,
+            cuda: self.cuda,
+            mode: self.mode,
+            points: self.points.is_some(),
+        }
+    }
+}
+
+/// `TrackerRules` checks a tracking backend against the rest of the config,
+/// at startup and when the API or gRPC switch to another one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackerRules {
+    dnn_model: bool,
+    opencl: bool,
+    cuda: bool,
+    mode: Mode,
+    points: bool,
+}
+
+impl TrackerRules {
+    pub fn check(&self, backend: Backend) -> Result<(), String> {
+        if backend == Backend::Dnn {
+            if !DNN_BUILT {
+                return Err("the dnn tracker needs a build with `--features \
+                            dnn`"
+                    .to_string());
+            }
+            if !self.dnn_model {
+                return Err("the dnn tracker needs --dnn-model".to_string());
+            }
+        }
+        if self.opencl &&
+           !matches!(backend, Backend::Camshift | Backend::Ensemble) {
+            return Err(format!("--opencl accelerates the camshift and \
+                                ensemble trackers, not {}",
+                               backend));
+        }
+        let accelerated = backend == Backend::Dnn || self.mode == Mode::Pose ||
+                          self.mode == Mode::Flow;
+        if self.cuda && !accelerated {
+            return Err("--cuda accelerates the dnn tracker and the pose and \
+                        flow modes"
+                .to_string());
+        }
+        if self.points && backend != Backend::Lk {
+            return Err(format!("--points needs a point tracker (lk), not {}",
+                               backend));
+        }
+        Ok(())
+    }
+}
+
+/// `Settings` is one layer of configuration, e.g. the command line, the
+/// environment or the config file. Unset values fall through to the next layer.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    profile: Option<String>,
+    preset: Option<String>,
+    mode: Option<String>,
+    tracker: Option<String>,
+    dnn_model: Option<String>,
+    dnn_config: Option<String>,
+    dnn_kind: Option<String>,
+    dnn_labels: Option<String>,
+    dnn_class: Option<String>,
+    dnn_confidence: Option<f32>,
+    detect_every: Option<u32>,
+    cuda: Option<bool>,
+    face_cascade: Option<String>,
+    skin_zone: Option<String>,
+    reference_patch: Option<String>,
+    arm_zone: Option<String>,
+    idle_after: Option<f64>,
+    regions: Option<Vec<RegionPreset>>,
+    zones: Option<Vec<ZoneSpec>>,
+    zone_dims: Option<bool>,
+    zone_events: Option<String>,
+    labels: Option<Vec<LabelSpec>>,
+    params: Option<BTreeMap<String, f64>>,
+    notify: Option<bool>,
+    sound_player: Option<String>,
+    region: Option<String>,
+    roi: Option<String>,
+    save_profile: Option<String>,
+    load_profile: Option<String>,
+    histogram: Option<String>,
+    saturation_bins: Option<i32>,
+    hist_adapt: Option<f64>,
+    hist_adapt_confidence: Option<f64>,
+    min_window: Option<i32>,
+    opencl: Option<bool>,
+    search_expansion: Option<f64>,
+    template_update: Option<String>,
+    update_confidence: Option<f64>,
+    server: Option<String>,
+    discover: Option<String>,
+    prediction: Option<String>,
+    control: Option<String>,
+    metrics: Option<String>,
+    dashboard: Option<String>,
+    api: Option<String>,
+    grpc: Option<String>,
+    sinks: Option<Vec<SinkSpec>>,
+    verbose: Option<bool>,
+    log: Option<String>,
+    log_format: Option<String>,
+    features: Option<String>,
+    markers: Option<Vec<MarkerSpec>>,
+    intrinsics: Option<String>,
+    undistort: Option<bool>,
+    stereo: Option<String>,
+    aruco_dictionary: Option<String>,
+    aruco_id: Option<i32>,
+    marker_length: Option<f64>,
+    pose_model: Option<String>,
+    pose_config: Option<String>,
+    joints: Option<String>,
+    joint_confidence: Option<f64>,
+    qr_channels: Option<String>,
+    eye_cascade: Option<String>,
+    landmark_model: Option<String>,
+    landmarks: Option<String>,
+    targets: Option<usize>,
+    target_names: Option<String>,
+    points: Option<usize>,
+    dims: Option<usize>,
+    velocity: Option<bool>,
+    acceleration: Option<bool>,
+    normalize: Option<String>,
+    origin: Option<String>,
+    y_up: Option<bool>,
+    unmirror: Option<bool>,
+    plane: Option<String>,
+    plane_size: Option<String>,
+    format: Option<String>,
+    ascii_stamps: Option<bool>,
+    measure_latency: Option<bool>,
+    dry_run: Option<bool>,
+    expect_dims: Option<usize>,
+    print_manifest: Option<bool>,
+    send_manifest: Option<bool>,
+    input: Option<Vec<String>>,
+    demo: Option<bool>,
+    speed: Option<f64>,
+    frame_delay: Option<u64>,
+    #[serde(rename = "loop")]
+    looping: Option<bool>,
+    resolution: Option<String>,
+    fps: Option<f64>,
+    pixel_format: Option<String>,
+    exposure: Option<f64>,
+    gain: Option<f64>,
+    white_balance: Option<f64>,
+    lock_camera: Option<bool>,
+    camera_timeout: Option<f64>,
+    latency_budget: Option<u64>,
+    headless: Option<bool>,
+    privacy: Option<String>,
+    session: Option<String>,
+    replay: Option<String>,
+    record: Option<String>,
+    log_features: Option<String>,
+    dataset: Option<String>,
+    sync_log: Option<String>,
+    sync_beacons: Option<f64>,
+    report: Option<String>,
+    trackbars: Option<bool>,
+    send_rate: Option<f64>,
+    resample: Option<f64>,
+    keepalive: Option<f64>,
+    keepalive_values: Option<String>,
+    plot_seconds: Option<f64>,
+    trail: Option<usize>,
+    segment: Option<String>,
+    segment_markers: Option<bool>,
+    filters: Option<Vec<FilterSpec>>,
+    preprocess: Option<Vec<StepSpec>>,
+    min_confidence: Option<f64>,
+    lost_frames: Option<u32>,
+    min_size: Option<i32>,
+    on_lost: Option<String>,
+    reacquire: Option<bool>,
+    reacquire_confidence: Option<f64>,
+}
+
+impl Settings {
+    fn from_file(path: &str) -> Result<Settings, String> {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| format!("cannot read config file {}: {}", path, e))?;
+        toml::from_str(&text)
+            .map_err(|e| format!("invalid config file {}: {}", path, e))
+    }
+
+    /// The settings of the `ESP_VISION_*` variables among `vars`, except
+    /// `ESP_VISION_CONFIG`.
+    fn from_env<I>(vars: I) -> Result<Settings, String>
+        where I: Iterator<Item = (String, String)>
+    {
+        let mut settings = Settings::default();
+        for (name, value) in vars {
+            if !name.starts_with(ENV_PREFIX) || name == CONFIG_VAR {
+                continue;
+            }
+            let key = name[ENV_PREFIX.len()..].to_lowercase();
+            let var = Settings::from_var(&key, &value)
+                .map_err(|e| format!("invalid {}: {}", name, e))?;
+            settings = settings.or(var);
+        }
+        Ok(settings)
+    }
+
+    /// The setting `key` with the value of an environment variable, as TOML
+    /// or else as a string.
+    fn from_var(key: &str, value: &str) -> Result<Settings, String> {
+        if let Ok(settings) = toml::from_str(&format!("{} = {}", key, value)) {
+            return Ok(settings);
+        }
+        let mut table = Table::new();
+        table.insert(key.to_string(), Value::String(value.to_string()));
+        Value::Table(table).try_into().map_err(|e| e.to_string())
+    }
+
+    /// Fills the values missing in `self` from its preset, then from its
+    /// profile.
+    fn defaults(self) -> Result<Settings, String> {
+        let mut settings = self;
+        if let Some(preset) = settings.preset.clone() {
+            settings = settings.or(Settings::preset(&preset)?);
+        }
+        if let Some(name) = settings.profile.clone() {
+            let profile = Settings::profile(&name)?;
+            if settings.face_cascade.is_none() {
+                if let Some(ref cascade) = profile.face_cascade {
+                    if !Path::new(cascade).is_file() {
+                        return Err(format!("the {} profile needs {} in the \
+                                            working directory, or \
+                                            --face-cascade",
+                                           name,
+                                           cascade));
+                    }
+                }
+            }
+            settings = settings.or(profile);
+        }
+        Ok(settings)
+    }
+
+    /// The settings of the preset `name`, to be layered below the command
+    /// line and the config file.
+    fn preset(name: &str) -> Result<Settings, String> {
+        match name {
+            "hands" => {
+                Ok(Settings {
+                    targets: Some(2),
+                    ..Settings::default()
+                })
+            }
+            _ => Err(format!("unknown preset `{}`", name)),
+        }
+    }
+
+    /// The settings of the profile `name`: a tracker, its parameters, the
+    /// smoothing and the dimensions for a common use case, to be layered
+    /// below everything else.
+    fn profile(name: &str) -> Result<Settings, String> {
+        let text = |s: &str| Some(s.to_string());
+        match name {
+            "hand" => {
+                // Skin tones, with the hue range of red to orange.
+                let params = [("h_min", 0.0),
+                              ("h_max", 20.0),
+                              ("s_min", 48.0),
+                              ("v_min", 80.0)];
+                let smoothing = FilterSpec::Kalman {
+                    process_noise: kalman::DEFAULT_PROCESS_NOISE,
+                    measurement_noise: kalman::DEFAULT_MEASUREMENT_NOISE,
+                };
+                Ok(Settings {
+                    tracker: text("camshift"),
+                    params: Some(params.iter()
+                        .map(|&(name, value)| (name.to_string(), value))
+                        .collect()),
+                    features: text("x,y,size"),
+                    normalize: text("unit"),
+                    filters: Some(vec![smoothing]),
+                    ..Settings::default()
+                })
+            }
+            "face" => {
+                Ok(Settings {
+                    tracker: text("kcf"),
+                    face_cascade: text(FACE_CASCADE),
+                    features: text("x,y,size"),
+                    normalize: text("unit"),
+                    filters: Some(vec![FilterSpec::Ema { alpha: 0.5 }]),
+                    ..Settings::default()
+                })
+            }
+            "marker" => {
+                let markers = ["red:170,120,70:10,255,255",
+                               "blue:100,150,50:130,255,255"];
+                Ok(Settings {
+                    mode: text("markers"),
+                    markers: Some(markers.iter()
+                        .map(|m| m.parse().unwrap())
+                        .collect()),
+                    normalize: text("unit"),
+                    ..Settings::default()
+                })
+            }
+            _ => Err(format!("unknown profile `{}`", name)),
+        }
+    }
+
+    fn from_matches(matches: &Matches) -> Result<Settings, String> {
+        let flag = |name: &str| if matches.opt_present(name) {
+            Some(true)
+        } else {
+            None
+        };
+        let filters = if matches.opt_present("kalman") {
+            Some(vec![FilterSpec::Kalman {
+                          process_noise:
+                              number(matches, "process-noise")?
+                              .unwrap_or(kalman::DEFAULT_PROCESS_NOISE),
+                          measurement_noise:
+                              number(matches, "measurement-noise")?
+                              .unwrap_or(kalman::DEFAULT_MEASUREMENT_NOISE),
+                      }])
+        } else {
+            None
+        };
+        let inputs = matches.opt_strs("input");
+        let markers = matches.opt_strs("marker")
+            .iter()
+            .map(|m| m.parse())
+            .collect::<Result<Vec<MarkerSpec>, String>>()?;
+        let sinks = matches.opt_strs("sink")
+            .iter()
+            .map(|s| s.parse())
+            .collect::<Result<Vec<SinkSpec>, String>>()?;
+        Ok(Settings {
+            profile: matches.opt_str("profile"),
+            preset: matches.opt_str("preset"),
+            mode: matches.opt_str("mode"),
+            tracker: matches.opt_str("tracker"),
+            dnn_model: matches.opt_str("dnn-model"),
+            dnn_config: matches.opt_str("dnn-config"),
+            dnn_kind: matches.opt_str("dnn-kind"),
+            dnn_labels: matches.opt_str("dnn-labels"),
+            dnn_class: matches.opt_str("dnn-class"),
+            dnn_confidence: number(matches, "dnn-confidence")?,
+            detect_every: number(matches, "detect-every")?,
+            cuda: flag("cuda"),
+            face_cascade: matches.opt_str("face-cascade"),
+            skin_zone: matches.opt_str("skin-zone"),
+            reference_patch: matches.opt_str("reference-patch"),
+            arm_zone: matches.opt_str("arm-zone"),
+            idle_after: number(matches, "idle-after")?,
+            // Regions, labels and parameters can only be defined in the
+            // config file.
+            regions: None,
+            zones: None,
+            zone_dims: flag("zone-dims"),
+            zone_events: matches.opt_str("zone-events"),
+            labels: None,
+            params: None,
+            notify: flag("notify"),
+            sound_player: matches.opt_str("sound-player"),
+            region: matches.opt_str("region"),
+            roi: matches.opt_str("roi"),
+            save_profile: matches.opt_str("save-profile"),
+            load_profile: matches.opt_str("load-profile"),
+            histogram: matches.opt_str("histogram"),
+            saturation_bins: number(matches, "saturation-bins")?,
+            hist_adapt: number(matches, "hist-adapt")?,
+            hist_adapt_confidence: number(matches, "hist-adapt-confidence")?,
+            min_window: number(matches, "min-window")?,
+            opencl: flag("opencl"),
+            search_expansion: number(matches, "search-expansion")?,
+            template_update: matches.opt_str("template-update"),
+            update_confidence: number(matches, "update-confidence")?,
+            server: matches.opt_str("server"),
+            discover: if matches.opt_present("discover") {
+                Some(matches.opt_str("discover")
+                    .unwrap_or_else(|| discover::DEFAULT_SERVICE.to_string()))
+            } else {
+                None
+            },
+            prediction: matches.opt_str("prediction"),
+            control: matches.opt_str("control"),
+            metrics: matches.opt_str("metrics"),
+            dashboard: matches.opt_str("dashboard"),
+            api: matches.opt_str("api"),
+            grpc: matches.opt_str("grpc"),
+            sinks: if sinks.is_empty() {
+                None
+            } else {
+                Some(sinks)
+            },
+            verbose: flag("verbose"),
+            log: matches.opt_str("log"),
+            log_format: matches.opt_str("log-format"),
+            features: matches.opt_str("features"),
+            markers: if markers.is_empty() {
+                None
+            } else {
+                Some(markers)
+            },
+            intrinsics: matches.opt_str("intrinsics"),
+            undistort: flag("undistort"),
+            stereo: matches.opt_str("stereo"),
+            aruco_dictionary: matches.opt_str("aruco-dictionary"),
+            aruco_id: number(matches, "aruco-id")?,
+            marker_length: number(matches, "marker-length")?,
+            pose_model: matches.opt_str("pose-model"),
+            pose_config: matches.opt_str("pose-config"),
+            joints: matches.opt_str("joints"),
+            joint_confidence: number(matches, "joint-confidence")?,
+            qr_channels: matches.opt_str("qr-channels"),
+            eye_cascade: matches.opt_str("eye-cascade"),
+            landmark_model: matches.opt_str("landmark-model"),
+            landmarks: matches.opt_str("landmarks"),
+            targets: number(matches, "targets")?,
+            target_names: matches.opt_str("target-names"),
+            points: number(matches, "points")?,
+            dims: number(matches, "dims")?,
+            velocity: flag("velocity"),
+            acceleration: flag("acceleration"),
+            normalize: matches.opt_str("normalize"),
+            origin: matches.opt_str("origin"),
+            y_up: flag("y-up"),
+            unmirror: flag("unmirror"),
+            plane: matches.opt_str("plane"),
+            plane_size: matches.opt_str("plane-size"),
+            format: matches.opt_str("format"),
+            ascii_stamps: flag("ascii-stamps"),
+            measure_latency: flag("measure-latency"),
+            dry_run: flag("dry-run"),
+            expect_dims: number(matches, "expect-dims")?,
+            print_manifest: flag("print-manifest"),
+            send_manifest: flag("send-manifest"),
+            input: if inputs.is_empty() {
+                None
+            } else {
+                Some(inputs)
+            },
+            demo: flag("demo"),
+            speed: number(matches, "speed")?,
+            frame_delay: number(matches, "frame-delay")?,
+            looping: flag("loop"),
+            resolution: matches.opt_str("resolution"),
+            fps: number(matches, "fps")?,
+            pixel_format: matches.opt_str("pixel-format"),
+            exposure: number(matches, "exposure")?,
+            gain: number(matches, "gain")?,
+            white_balance: number(matches, "white-balance")?,
+            lock_camera: flag("lock-camera"),
+            camera_timeout: number(matches, "camera-timeout")?,
+            latency_budget: number(matches, "latency-budget")?,
+            headless: flag("headless"),
+            privacy: matches.opt_str("privacy"),
+            session: matches.opt_str("session"),
+            replay: matches.opt_str("replay"),
+            record: matches.opt_str("record"),
+            log_features: matches.opt_str("log-features"),
+            dataset: matches.opt_str("dataset"),
+            sync_log: matches.opt_str("sync-log"),
+            sync_beacons: number(matches, "sync-beacons")?,
+            report: matches.opt_str("report"),
+            trackbars: flag("trackbars"),
+            send_rate: number(matches, "send-rate")?,
+            resample: number(matches, "resample")?,
+            keepalive: number(matches, "keepalive")?,
+            keepalive_values: matches.opt_str("keepalive-values"),
+            plot_seconds: number(matches, "plot-seconds")?,
+            trail: number(matches, "trail")?,
+            segment: matches.opt_str("segment"),
+            segment_markers: flag("segment-markers"),
+            filters,
+            // Preprocessing can only be configured in the config file.
+            preprocess: None,
+            min_confidence: number(matches, "min-confidence")?,
+            lost_frames: number(matches, "lost-frames")?,
+            min_size: number(matches, "min-size")?,
+            on_lost: matches.opt_str("on-lost"),
+            reacquire: flag("reacquire"),
+            reacquire_confidence: number(matches, "reacquire-confidence")?,
+        })
+    }
+
+    /// Fills the values missing in `self` from `lower`.
+    fn or(self, lower: Settings) -> Settings {
+        Settings {
+            profile: self.profile.or(lower.profile),
+            preset: self.preset.or(lower.preset),
+            mode: self.mode.or(lower.mode),
+            tracker: self.tracker.or(lower.tracker),
+            dnn_model: self.dnn_model.or(lower.dnn_model),
+            dnn_config: self.dnn_config.or(lower.dnn_config),
+            dnn_kind: self.dnn_kind.or(lower.dnn_kind),
+            dnn_labels: self.dnn_labels.or(lower.dnn_labels),
+            dnn_class: self.dnn_class.or(lower.dnn_class),
+            dnn_confidence: self.dnn_confidence.or(lower.dnn_confidence),
+            detect_every: self.detect_every.or(lower.detect_every),
+            cuda: self.cuda.or(lower.cuda),
+            face_cascade: self.face_cascade.or(lower.face_cascade),
+            skin_zone: self.skin_zone.or(lower.skin_zone),
+            reference_patch: self.reference_patch.or(lower.reference_patch),
+            arm_zone: self.arm_zone.or(lower.arm_zone),
+            idle_after: self.idle_after.or(lower.idle_after),
+            regions: self.regions.or(lower.regions),
+            zones: self.zones.or(lower.zones),
+            zone_dims: self.zone_dims.or(lower.zone_dims),
+            zone_events: self.zone_events.or(lower.zone_events),
+            labels: self.labels.or(lower.labels),
+            params: merge(self.params, lower.params),
+            notify: self.notify.or(lower.notify),
+            sound_player: self.sound_player.or(lower.sound_player),
+            region: self.region.or(lower.region),
+            roi: self.roi.or(lower.roi),
+            save_profile: self.save_profile.or(lower.save_profile),
+            load_profile: self.load_profile.or(lower.load_profile),
+            histogram: self.histogram.or(lower.histogram),
+            saturation_bins: self.saturation_bins.or(lower.saturation_bins),
+            hist_adapt: self.hist_adapt.or(lower.hist_adapt),
+            hist_adapt_confidence: self.hist_adapt_confidence
+                .or(lower.hist_adapt_confidence),
+            min_window: self.min_window.or(lower.min_window),
+            opencl: self.opencl.or(lower.opencl),
+            search_expansion: self.search_expansion.or(lower.search_expansion),
+            template_update: self.template_update.or(lower.template_update),
+            update_confidence: self.update_confidence
+                .or(lower.update_confidence),
+            server: self.server.or(lower.server),
+            discover: self.discover.or(lower.discover),
+            prediction: self.prediction.or(lower.prediction),
+            control: self.control.or(lower.control),
+            metrics: self.metrics.or(lower.metrics),
+            dashboard: self.dashboard.or(lower.dashboard),
+            api: self.api.or(lower.api),
+            grpc: self.grpc.or(lower.grpc),
+            sinks: self.sinks.or(lower.sinks),
+            verbose: self.verbose.or(lower.verbose),
+            log: self.log.or(lower.log),
+            log_format: self.log_format.or(lower.log_format),
+            features: self.features.or(lower.features),
+            markers: self.markers.or(lower.markers),
+            intrinsics: self.intrinsics.or(lower.intrinsics),
+            undistort: self.undistort.or(lower.undistort),
+            stereo: self.stereo.or(lower.stereo),
+            aruco_dictionary: self.aruco_dictionary.or(lower.aruco_dictionary),
+            aruco_id: self.aruco_id.or(lower.aruco_id),
+            marker_length: self.marker_length.or(lower.marker_length),
+            pose_model: self.pose_model.or(lower.pose_model),
+            pose_config: self.pose_config.or(lower.pose_config),
+            joints: self.joints.or(lower.joints),
+            joint_confidence: self.joint_confidence.or(lower.joint_confidence),
+            qr_channels: self.qr_channels.or(lower.qr_channels),
+            eye_cascade: self.eye_cascade.or(lower.eye_cascade),
+            landmark_model: self.landmark_model.or(lower.landmark_model),
+            landmarks: self.landmarks.or(lower.landmarks),
+            targets: self.targets.or(lower.targets),
+            target_names: self.target_names.or(lower.target_names),
+            points: self.points.or(lower.points),
+            dims: self.dims.or(lower.dims),
+            velocity: self.velocity.or(lower.velocity),
+            acceleration: self.acceleration.or(lower.acceleration),
+            normalize: self.normalize.or(lower.normalize),
+            origin: self.origin.or(lower.origin),
+            y_up: self.y_up.or(lower.y_up),
+            unmirror: self.unmirror.or(lower.unmirror),
+            plane: self.plane.or(lower.plane),
+            plane_size: self.plane_size.or(lower.plane_size),
+            format: self.format.or(lower.format),
+            ascii_stamps: self.ascii_stamps.or(lower.ascii_stamps),
+            measure_latency: self.measure_latency.or(lower.measure_latency),
+            dry_run: self.dry_run.or(lower.dry_run),
+            expect_dims: self.expect_dims.or(lower.expect_dims),
+            print_manifest: self.print_manifest.or(lower.print_manifest),
+            send_manifest: self.send_manifest.or(lower.send_manifest),
+            input: self.input.or(lower.input),
+            demo: self.demo.or(lower.demo),
+            speed: self.speed.or(lower.speed),
+            frame_delay: self.frame_delay.or(lower.frame_delay),
+            looping: self.looping.or(lower.looping),
+            resolution: self.resolution.or(lower.resolution),
+            fps: self.fps.or(lower.fps),
+            pixel_format: self.pixel_format.or(lower.pixel_format),
+            exposure: self.exposure.or(lower.exposure),
+            gain: self.gain.or(lower.gain),
+            white_balance: self.white_balance.or(lower.white_balance),
+            lock_camera: self.lock_camera.or(lower.lock_camera),
+            camera_timeout: self.camera_timeout.or(lower.camera_timeout),
+            latency_budget: self.latency_budget.or(lower.latency_budget),
+            headless: self.headless.or(lower.headless),
+            privacy: self.privacy.or(lower.privacy),
+            session: self.session.or(lower.session),
+            replay: self.replay.or(lower.replay),
+            record: self.record.or(lower.record),
+            log_features: self.log_features.or(lower.log_features),
+            dataset: self.dataset.or(lower.dataset),
+            sync_log: self.sync_log.or(lower.sync_log),
+            sync_beacons: self.sync_beacons.or(lower.sync_beacons),
+            report: self.report.or(lower.report),
+            trackbars: self.trackbars.or(lower.trackbars),
+            send_rate: self.send_rate.or(lower.send_rate),
+            resample: self.resample.or(lower.resample),
+            keepalive: self.keepalive.or(lower.keepalive),
+            keepalive_values: self.keepalive_values.or(lower.keepalive_values),
+            plot_seconds: self.plot_seconds.or(lower.plot_seconds),
+            trail: self.trail.or(lower.trail),
+            segment: self.segment.or(lower.segment),
+            segment_markers: self.segment_markers.or(lower.segment_markers),
+            filters: self.filters.or(lower.filters),
+            preprocess: self.preprocess.or(lower.preprocess),
+            min_confidence: self.min_confidence.or(lower.min_confidence),
+            lost_frames: self.lost_frames.or(lower.lost_frames),
+            min_size: self.min_size.or(lower.min_size),
+            on_lost: self.on_lost.or(lower.on_lost),
+            reacquire: self.reacquire.or(lower.reacquire),
+            reacquire_confidence: self.reacquire_confidence
+                .or(lower.reacquire_confidence),
+        }
+    }
+
+    /// Whether any option that shapes the tracked features is set. Modes
+    /// with their own dimensions reject them.
+    fn shapes_features(&self) -> bool {
+        self.features.is_some() || self.dims.is_some() ||
+        self.points.is_some() || self.velocity.unwrap_or(false) ||
+        self.acceleration.unwrap_or(false)
+    }
+
+    /// The live settings these settings set, as named in `Live::KEYS`.
+    fn live_keys(&self) -> Vec<&'static str> {
+        let mut keys = Vec::new();
+        if self.params.is_some() {
+            keys.push("params");
+        }
+        if self.send_rate.is_some() {
+            keys.push("send_rate");
+        }
+        if self.filters.is_some() {
+            keys.push("filters");
+        }
+        keys
+    }
+
+    /// Validates the settings and fills in defaults.
+    fn resolve(self) -> Result<Config, String> {
+        let shaped = self.shapes_features();
+        let mut config = Config::default();
+        if let Some(mode) = self.mode {
+            config.mode = mode.parse()?;
+        }
+        if let Some(tracker) = self.tracker {
+            config.tracker = tracker.parse()?;
+        }
+        config.cuda = self.cuda.unwrap_or(false);
+        if config.cuda && !cuda::BUILT {
+            return Err("--cuda needs a build with `--features cuda`"
+                .to_string());
+        }
+        // With a model, the dnn tracker can also be switched to later.
+        if config.tracker == Backend::Dnn || self.dnn_model.is_some() {
+            config.tracking.dnn.cuda = config.cuda;
+            let dnn = &mut config.tracking.dnn;
+            dnn.model = self.dnn_model.unwrap_or_default();
+            dnn.config = self.dnn_config.unwrap_or_default();
+            for path in &[&dnn.model, &dnn.config] {
+                if !path.is_empty() {
+                    File::open(path)
+                        .map_err(|e| format!("cannot open {}: {}", path, e))?;
+                }
+            }
+            if let Some(kind) = self.dnn_kind {
+                dnn.kind = kind.parse()?;
+            }
+            if let Some(class) = self.dnn_class {
+                dnn.class = class_id(&class, self.dnn_labels.as_ref())?;
+            }
+            if let Some(c) = self.dnn_confidence {
+                if !(0.0..=1.0).contains(&c) {
+                    return Err(format!("dnn confidence must be between 0 and \
+                                        1, got {}",
+                                       c));
+                }
+                dnn.confidence = c;
+            }
+            if let Some(n) = self.detect_every {
+                dnn.every = n.max(1);
+            }
+        }
+        config.face_cascade = self.face_cascade;
+        if let Some(path) = self.intrinsics {
+            config.intrinsics = Some(Intrinsics::load(&path)?);
+        }
+        config.undistort = self.undistort.unwrap_or(false);
+        if config.undistort && config.intrinsics.is_none() {
+            return Err("--undistort needs --intrinsics".to_string());
+        }
+        if let Some(d) = self.aruco_dictionary {
+            config.aruco.dictionary = d.parse()?;
+        }
+        if let Some(id) = self.aruco_id {
+            config.aruco.id = id;
+        }
+        if let Some(length) = self.marker_length {
+            if length.is_nan() || length <= 0.0 {
+                return Err(format!("marker length must be positive, got {}",
+                                   length));
+            }
+            config.aruco.marker_length = length;
+        }
+        if let Some(zone) = self.skin_zone {
+            config.skin_zone = Some(rect(&zone)?);
+        }
+        if let Some(patch) = self.reference_patch {
+            config.reference_patch = Some(rect(&patch)?);
+        }
+        if let Some(zone) = self.arm_zone {
+            if config.mode != Mode::Track {
+                return Err("--arm-zone starts tracking and only applies to \
+                            --mode track"
+                    .to_string());
+            }
+            config.arm_zone = Some(rect(&zone)?);
+        }
+        if let Some(secs) = self.idle_after {
+            if config.arm_zone.is_none() {
+                return Err("--idle-after needs --arm-zone".to_string());
+            }
+            if !(secs > 0.0 && secs.is_finite()) {
+                return Err(format!("idle time must be a positive number of \
+                                    seconds, got {}",
+                                   secs));
+            }
+            config.idle_after = Duration::from_secs_f64(secs);
+        }
+        let regions = self.regions.unwrap_or_default();
+        for (i, r) in regions.iter().enumerate() {
+            if regions[..i].iter().any(|other| other.name == r.name) {
+                return Err(format!("region `{}` is defined twice", r.name));
+            }
+            if r.rect[2] <= 0 || r.rect[3] <= 0 {
+                return Err(format!("region `{}` needs a positive size",
+                                   r.name));
+            }
+        }
+        if let Some(name) = self.region {
+            let region = regions.iter()
+                .find(|r| r.name == name)
+                .ok_or_else(|| format!("unknown region `{}`", name))?;
+            config.start_region = Some(region.rect());
+        }
+        if let Some(roi) = self.roi {
+            if config.start_region.is_some() {
+                return Err("--roi and --region can't be combined".to_string());
+            }
+            config.start_region = Some(rect(&roi)?);
+        }
+        config.regions = regions;
+        let zones = self.zones.unwrap_or_default();
+        for (i, z) in zones.iter().enumerate() {
+            if zones[..i].iter().any(|other| other.name == z.name) {
+                return Err(format!("zone `{}` is defined twice", z.name));
+            }
+            if z.name.is_empty() ||
+               !z.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("invalid zone name `{}`; use letters, \
+                                    digits and _",
+                                   z.name));
+            }
+            z.corners()?;
+        }
+        config.zone_dims = self.zone_dims.unwrap_or(false);
+        config.zone_events = self.zone_events;
+        if config.zone_dims || config.zone_events.is_some() {
+            if zones.is_empty() {
+                return Err("--zone-dims and --zone-events need [[zones]] in \
+                            the config file"
+                    .to_string());
+            }
+            if config.mode != Mode::Track {
+                return Err("zones follow the targets of --mode track"
+                    .to_string());
+            }
+        }
+        config.zones = zones;
+        let labels = self.labels.unwrap_or_default();
+        for (i, l) in labels.iter().enumerate() {
+            if labels[..i].iter().any(|other| other.class == l.class) {
+                return Err(format!("class {} is labeled twice", l.class));
+            }
+        }
+        config.labels = labels;
+        config.params = check_params(self.params)?;
+        config.notify = self.notify.unwrap_or(false);
+        if let Some(player) = self.sound_player {
+            config.sound_player = player;
+        }
+        config.save_profile = self.save_profile;
+        config.load_profile = self.load_profile;
+        if let Some(ref inputs) = self.input {
+            config.inputs = inputs.iter()
+                .map(|i| i.parse())
+                .collect::<Result<Vec<InputSpec>, String>>()?;
+            let direct = |i: &InputSpec| matches!(*i, InputSpec::V4l2(_));
+            if config.inputs.iter().any(direct) && !v4l2::BUILT {
+                return Err("v4l2 inputs need a build with `--features rpi`"
+                    .to_string());
+            }
+            let depth = |i: &InputSpec| matches!(*i, InputSpec::Realsense(_));
+            if config.inputs.iter().any(depth) && !realsense::BUILT {
+                return Err("realsense inputs need a build with `--features \
+                            realsense`"
+                    .to_string());
+            }
+        }
+        if let Some(ref dir) = self.replay {
+            if self.input.is_some() || self.session.is_some() {
+                return Err("--replay reads the frames of the session and \
+                            can't be combined with --input or --session"
+                    .to_string());
+            }
+            config.inputs = vec![session::frames(dir).parse()?];
+        }
+        let demo = self.demo.unwrap_or(false);
+        if demo {
+            if self.input.is_some() || self.replay.is_some() {
+                return Err("--demo brings its own input and can't be \
+                            combined with --input or --replay"
+                    .to_string());
+            }
+            let trajectory: synthetic::Trajectory = synthetic::DEMO.parse()?;
+            let spec = InputSpec::Synthetic(synthetic::DEMO.to_string());
+            config.inputs = vec![spec];
+            // The ball is selected as it enters.
+            if config.start_region.is_none() {
+                config.start_region = Some(synthetic::selection(&trajectory));
+            }
+        }
+        if self.session.is_some() && config.inputs.len() > 1 {
+            return Err("--session records a single --input".to_string());
+        }
+        if config.undistort && config.inputs.len() > 1 {
+            return Err("--undistort applies the --intrinsics of a single \
+                        --input"
+                .to_string());
+        }
+        config.session = self.session;
+        config.replay = self.replay;
+        if let Some(speed) = self.speed {
+            if !(speed >= input::MIN_SPEED && speed.is_finite()) {
+                return Err(format!("speed must be at least {}, got {}",
+                                   input::MIN_SPEED,
+                                   speed));
+            }
+            config.playback.speed = speed;
+        }
+        if let Some(ms) = self.frame_delay {
+            config.playback.frame_delay = Duration::from_millis(ms);
+        }
+        config.playback.looping = self.looping.unwrap_or(false) || demo;
+        if let Some(resolution) = self.resolution {
+            config.capture.size = Some(size(&resolution)?);
+        }
+        if let Some(fps) = self.fps {
+            if fps.is_nan() || fps <= 0.0 {
+                return Err(format!("fps must be positive, got {}", fps));
+            }
+            config.capture.fps = Some(fps);
+        }
+        if let Some(format) = self.pixel_format {
+            let chars: Vec<char> = format.chars().collect();
+            if chars.len() != 4 || !format.is_ascii() {
+                return Err(format!("pixel format `{}` is not four \
+                                    characters, e.g. MJPG",
+                                   format));
+            }
+            config.capture.fourcc = Some([chars[0], chars[1], chars[2],
+                                          chars[3]]);
+        }
+        config.capture.exposure = self.exposure;
+        config.capture.gain = self.gain;
+        config.capture.white_balance = self.white_balance;
+        config.capture.lock = self.lock_camera.unwrap_or(false);
+        if let Some(secs) = self.camera_timeout {
+            if !(secs >= 0.0 && secs.is_finite()) {
+                return Err(format!("camera timeout must be a number of \
+                                    seconds, got {}",
+                                   secs));
+            }
+            config.capture.timeout = Some(Duration::from_secs_f64(secs));
+        }
+        match self.latency_budget {
+            Some(0) => config.latency_budget = None,
+            Some(ms) => config.latency_budget = Some(Duration::from_millis(ms)),
+            None => {}
+        }
+        config.headless = self.headless.unwrap_or(false);
+        if !config.headless && !window::BUILT {
+            return Err("this build has no windows; run with --headless, or \
+                        build with `--features gui`"
+                .to_string());
+        }
+        config.record = self.record;
+        config.log_features = self.log_features;
+        config.dataset = self.dataset;
+        config.sync_log = self.sync_log;
+        config.report = self.report;
+        if let Some(secs) = self.sync_beacons {
+            if !(secs > 0.0 && secs.is_finite()) {
+                return Err(format!("beacons need a positive interval in \
+                                    seconds, got {}",
+                                   secs));
+            }
+            config.sync_beacons = Some(Duration::from_secs_f64(secs));
+        }
+        config.trackbars = self.trackbars.unwrap_or(false);
+        if config.headless && config.trackbars {
+            return Err("--trackbars needs a window and can't be combined \
+                        with --headless"
+                .to_string());
+        }
+        let unattended = config.start_region.is_some() ||
+                         config.load_profile.is_some() ||
+                         config.face_cascade.is_some() ||
+                         config.skin_zone.is_some() ||
+                         config.arm_zone.is_some() ||
+                         !config.tracker.needs_selection();
+        if config.headless && config.mode == Mode::Track && !unattended {
+            return Err("--headless can't select a target; start tracking \
+                        with --roi, --region, --load-profile, \
+                        --face-cascade, --skin-zone, --arm-zone or a tracker \
+                        that needs no selection"
+                .to_string());
+        }
+        if let Some(privacy) = self.privacy {
+            if config.mode != Mode::Track {
+                return Err("--privacy masks the video of --mode track"
+                    .to_string());
+            }
+            if config.session.is_some() || config.inputs.len() > 1 {
+                return Err("--privacy can't keep raw frames with --session \
+                            or show them with several --input"
+                    .to_string());
+            }
+            config.privacy = Some(privacy.parse()?);
+        }
+        if let Some(h) = self.histogram {
+            config.tracking.camshift.histogram = h.parse()?;
+        }
+        if let Some(n) = self.saturation_bins {
+            if !(1..=256).contains(&n) {
+                return Err(format!("saturation bins must be within \
+                                    [1, 256], got {}",
+                                   n));
+            }
+            config.tracking.camshift.saturation_bins = n;
+        }
+        if let Some(rate) = self.hist_adapt {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(format!("histogram adaptation rate must be within \
+                                    [0, 1], got {}",
+                                   rate));
+            }
+            config.tracking.camshift.adapt_rate = rate;
+        }
+        if let Some(px) = self.min_window {
+            if px < 1 {
+                return Err(format!("min window must be positive, got {}",
+                                   px));
+            }
+            config.tracking.camshift.min_window = px;
+        }
+        let opencl = self.opencl.unwrap_or(false);
+        if opencl && !OPENCL_BUILT {
+            return Err("--opencl needs a build with `--features opencl`"
+                .to_string());
+        }
+        config.tracking.camshift.opencl = opencl;
+        config.tracker_rules().check(config.tracker)?;
+        if let Some(c) = self.hist_adapt_confidence {
+            config.tracking.camshift.adapt_confidence =
+                check_confidence("hist adapt confidence", c)?;
+        }
+        if let Some(f) = self.search_expansion {
+            if f.is_nan() || f < 1.0 {
+                return Err(format!("search expansion must be at least 1, \
+                                    got {}",
+                                   f));
+            }
+            config.tracking.template.search_expansion = f;
+        }
+        if let Some(policy) = self.template_update {
+            config.tracking.template.update = policy.parse()?;
+        }
+        if let Some(c) = self.update_confidence {
+            config.tracking.template.update_confidence =
+                check_confidence("update confidence", c)?;
+        }
+        if let Some(server) = self.server {
+            config.server = server;
+        }
+        if !net::BUILT {
+            let services = [("--discover", self.discover.is_some()),
+                            ("--metrics", self.metrics.is_some()),
+                            ("--dashboard", self.dashboard.is_some()),
+                            ("--api", self.api.is_some())];
+            if let Some(&(option, _)) = services.iter().find(|s| s.1) {
+                return Err(format!("{} needs a build with `--features net`",
+                                   option));
+            }
+        }
+        config.discover = self.discover;
+        if let Some(prediction) = self.prediction {
+            config.prediction = prediction;
+        }
+        if let Some(control) = self.control {
+            config.control = control;
+        }
+        config.metrics = self.metrics;
+        config.dashboard = self.dashboard;
+        config.api = self.api;
+        if self.grpc.is_some() && !grpc::BUILT {
+            return Err("--grpc needs a build with `--features grpc`"
+                .to_string());
+        }
+        config.grpc = self.grpc;
+        config.sinks = self.sinks.unwrap_or_default();
+        for sink in &config.sinks {
+            sink.validate()?;
+        }
+        if let Some(filter) = self.log {
+            config.log_filter = filter.parse()?;
+        }
+        if self.verbose.unwrap_or(false) {
+            config.log_filter.default =
+                config.log_filter.default.max(log::LevelFilter::Debug);
+        }
+        if let Some(format) = self.log_format {
+            config.log_format = format.parse()?;
+        }
+        if let Some(n) = self.normalize {
+            config.normalization = n.parse()?;
+        }
+        config.convention = Convention {
+            origin: match self.origin {
+                Some(origin) => Some(origin.parse()?),
+                None => None,
+            },
+            y_up: self.y_up.unwrap_or(false),
+            unmirror: self.unmirror.unwrap_or(false),
+        };
+        if self.plane.is_some() && config.inputs.len() > 1 {
+            return Err("--plane maps the positions of a single --input"
+                .to_string());
+        }
+        config.plane = self.plane;
+        if let Some(size) = self.plane_size {
+            config.plane_size = plane_size(&size)?;
+        }
+        if let Some(f) = self.format {
+            config.format = f.parse()?;
+        }
+        config.measure_latency = self.measure_latency.unwrap_or(false);
+        // The time of capture travels with the samples.
+        config.ascii_stamps = self.ascii_stamps.unwrap_or(false) ||
+                              config.measure_latency;
+        config.dry_run = self.dry_run.unwrap_or(false);
+        config.expect_dims = self.expect_dims;
+        config.print_manifest = self.print_manifest.unwrap_or(false);
+        config.send_manifest = self.send_manifest.unwrap_or(false);
+        if config.send_manifest &&
+           !matches!(config.format, Format::Ascii | Format::Json) {
+            return Err("--send-manifest needs --format ascii or json; \
+                        framed streams start with a header of their own"
+                .to_string());
+        }
+        config.send_rate = check_send_rate(self.send_rate)?;
+        if let Some(rate) = self.resample {
+            let rate = rate::check("resampling rate", rate)?;
+            if config.send_rate.is_some() {
+                return Err("--resample and --send-rate both set the rate of \
+                            the samples; give one"
+                    .to_string());
+            }
+            config.resample = Some(rate);
+        }
+        if let Some(rate) = self.keepalive {
+            config.keepalive = Some(rate::check("keepalive rate", rate)?);
+        }
+        if let Some(values) = self.keepalive_values {
+            config.keepalive_values = values.parse()?;
+        }
+        if let Some(secs) = self.plot_seconds {
+            if !(secs > 0.0 && secs.is_finite()) {
+                return Err(format!("the plot must span a positive number of \
+                                    seconds, got {}",
+                                   secs));
+            }
+            config.plot_seconds = secs;
+        }
+        config.trail = self.trail.unwrap_or(0);
+        if config.trail > 0 && config.mode != Mode::Track {
+            return Err("--trail follows the targets of --mode track"
+                .to_string());
+        }
+        let markers = self.segment_markers.unwrap_or(false);
+        config.segment = match self.segment {
+            Some(segment) => {
+                Some(Segmentation {
+                    markers,
+                    ..segment.parse::<Segmentation>()?
+                })
+            }
+            None if markers => {
+                return Err("--segment-markers needs --segment".to_string())
+            }
+            None => None,
+        };
+        config.filters = check_filters(self.filters)?;
+        if let Some(steps) = self.preprocess {
+            Pipeline::new(&steps)?;
+            config.preprocess = steps;
+        }
+        if let Some(c) = self.min_confidence {
+            config.loss.min_confidence = check_confidence("min confidence", c)?;
+        }
+        if let Some(n) = self.lost_frames {
+            config.loss.patience = n;
+        }
+        if let Some(px) = self.min_size {
+            config.loss.min_size = px;
+        }
+        if let Some(mode) = self.on_lost {
+            config.loss.on_lost = mode.parse()?;
+        }
+        config.loss.reacquire = self.reacquire.unwrap_or(false);
+        if let Some(c) = self.reacquire_confidence {
+            config.loss.reacquire_confidence =
+                check_confidence("reacquire confidence", c)?;
+        }
+
+        if let Some(n) = self.targets {
+            if n == 0 {
+                return Err("--targets must be at least 1".to_string());
+            }
+            if n > 1 && (config.mode != Mode::Track || self.points.is_some()) {
+                return Err("several --targets need --mode track and can't \
+                            be combined with --points"
+                    .to_string());
+            }
+            config.targets = n;
+        }
+        if let Some(preset) = self.preset {
+            config.hands = preset == "hands";
+            if config.hands && config.targets != 2 {
+                return Err(format!("the hands preset tracks 2 targets, \
+                                    not {}",
+                                   config.targets));
+            }
+        }
+        if config.inputs.len() > 1 {
+            if config.mode != Mode::Track || self.points.is_some() ||
+               config.hands {
+                return Err("several --input need --mode track and can't be \
+                            combined with --points or the hands preset"
+                    .to_string());
+            }
+            if let Some(n) = self.targets {
+                if n != config.inputs.len() {
+                    return Err(format!("{} inputs track {} targets, one \
+                                        each, not {}",
+                                       config.inputs.len(),
+                                       config.inputs.len(),
+                                       n));
+                }
+            }
+            if config.headless && config.tracker.needs_selection() {
+                return Err("--headless with several --input needs a \
+                            tracker that needs no selection"
+                    .to_string());
+            }
+            config.targets = config.inputs.len();
+        }
+        if let Some(path) = self.stereo {
+            if config.inputs.len() != 2 {
+                return Err("--stereo needs two --input, the left camera \
+                            first"
+                    .to_string());
+            }
+            config.stereo = Some(Rig::load(&path)?);
+        }
+        let depth = |i: &InputSpec| matches!(*i, InputSpec::Realsense(_));
+        if config.inputs.iter().any(depth) {
+            if config.inputs.len() > 1 || config.stereo.is_some() {
+                return Err("a realsense input measures depth on its own and \
+                            can't be combined with other --input or --stereo"
+                    .to_string());
+            }
+            config.depth = config.mode == Mode::Track && self.points.is_none();
+        }
+        if let Some(names) = self.target_names {
+            let names: Vec<String> =
+                names.split(',').map(|n| n.trim().to_string()).collect();
+            if config.mode != Mode::Track || config.hands ||
+               config.stereo.is_some() {
+                return Err("--target-names names the targets of --mode \
+                            track, and can't be combined with the hands \
+                            preset or --stereo"
+                    .to_string());
+            }
+            if names.len() != config.targets {
+                return Err(format!("--target-names has {} names for {} \
+                                    targets",
+                                   names.len(),
+                                   config.targets));
+            }
+            let valid = |n: &String| {
+                !n.is_empty() &&
+                n.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            };
+            if let Some(n) = names.iter().find(|n| !valid(n)) {
+                return Err(format!("invalid target name `{}`; use letters, \
+                                    digits and _",
+                                   n));
+            }
+            if (1..names.len()).any(|i| names[..i].contains(&names[i])) {
+                return Err("--target-names has the same name twice"
+                    .to_string());
+            }
+            config.target_names = names;
+        }
+
+        if config.mode == Mode::Flow {
+            if shaped {
+                return Err("--mode flow sends its own dimensions and can't \
+                            be combined with --features, --dims, --points, \
+                            --velocity or --acceleration"
+                    .to_string());
+            }
+            return Ok(config);
+        }
+
+        if config.mode == Mode::Aruco {
+            if config.intrinsics.is_none() {
+                return Err("--mode aruco needs --intrinsics".to_string());
+            }
+            if shaped {
+                return Err("--mode aruco sends the marker pose and can't be \
+                            combined with --features, --dims, --points, \
+                            --velocity or --acceleration"
+                    .to_string());
+            }
+            // The pose is already as the camera sees it, with y down.
+            if config.convention.y_up || config.convention.unmirror {
+                return Err("--mode aruco sends the pose in the frame of the \
+                            camera and can't be combined with --unmirror or \
+                            --y-up"
+                    .to_string());
+            }
+            return Ok(config);
+        }
+
+        if config.mode == Mode::Pose {
+            if !DNN_BUILT {
+                return Err("--mode pose needs a build with `--features dnn`"
+                    .to_string());
+            }
+            match (self.pose_model, self.pose_config) {
+                (Some(model), Some(description)) => {
+                    config.pose_model = Some((model, description));
+                }
+                _ => {
+                    return Err("--mode pose needs --pose-model and \
+                                --pose-config"
+                        .to_string());
+                }
+            }
+            if shaped {
+                return Err("--mode pose sends --joints and can't be \
+                            combined with --features, --dims, --points, \
+                            --velocity or --acceleration"
+                    .to_string());
+            }
+            if let Some(j) = self.joints {
+                config.joints = pose::parse_joints(&j)?;
+            }
+            if let Some(c) = self.joint_confidence {
+                config.joint_confidence =
+                    check_confidence("joint confidence", c)?;
+            }
+            return Ok(config);
+        }
+
+        if config.mode == Mode::Qr {
+            let channels: Vec<String> = self.qr_channels
+                .unwrap_or_default()
+                .split(',')
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect();
+            if channels.is_empty() {
+                return Err("--mode qr needs --qr-channels".to_string());
+            }
+            if shaped {
+                return Err("--mode qr sends the codes of --qr-channels and \
+                            can't be combined with --features, --dims, \
+                            --points, --velocity or --acceleration"
+                    .to_string());
+            }
+            config.qr_channels = channels;
+            return Ok(config);
+        }
+
+        if config.mode == Mode::Gaze {
+            if config.face_cascade.is_none() || self.eye_cascade.is_none() {
+                return Err("--mode gaze needs --face-cascade and \
+                            --eye-cascade"
+                    .to_string());
+            }
+            if shaped {
+                return Err("--mode gaze sends gaze_x and gaze_y and can't be \
+                            combined with --features, --dims, --points, \
+                            --velocity or --acceleration"
+                    .to_string());
+            }
+            config.eye_cascade = self.eye_cascade;
+            return Ok(config);
+        }
+
+        if config.mode == Mode::Landmarks {
+            if config.face_cascade.is_none() || self.landmark_model.is_none() {
+                return Err("--mode landmarks needs --face-cascade and \
+                            --landmark-model"
+                    .to_string());
+            }
+            if shaped {
+                return Err("--mode landmarks sends --landmarks and can't be \
+                            combined with --features, --dims, --points, \
+                            --velocity or --acceleration"
+                    .to_string());
+            }
+            config.landmark_model = self.landmark_model;
+            if let Some(l) = self.landmarks {
+                config.landmarks = landmarks::parse_list(&l)?;
+            }
+            return Ok(config);
+        }
+
+        if config.mode == Mode::Markers {
+            let markers = self.markers.unwrap_or_default();
+            if markers.is_empty() {
+                return Err("--mode markers needs at least one --marker"
+                    .to_string());
+            }
+            for (i, m) in markers.iter().enumerate() {
+                if markers[..i].iter().any(|other| other.name == m.name) {
+                    return Err(format!("marker `{}` is defined twice",
+                                       m.name));
+                }
+            }
+            let reshaped = self.dims.is_some() || self.points.is_some() ||
+                           self.velocity.unwrap_or(false) ||
+                           self.acceleration.unwrap_or(false);
+            if reshaped {
+                return Err("--mode markers sends --features for every \
+                            marker and can't be combined with --dims, \
+                            --points, --velocity or --acceleration"
+                    .to_string());
+            }
+            config.markers = markers;
+            if let Some(f) = self.features {
+                config.features = f.parse()?;
+            }
+            return Ok(config);
+        }
+
+        config.derive = if self.acceleration.unwrap_or(false) {
+            Order::Acceleration
+        } else if self.velocity.unwrap_or(false) {
+            Order::Velocity
+        } else {
+            Order::None
+        };
+
+        if let Some(n) = self.points {
+            if config.tracker != Backend::Lk {
+                return Err(format!("--points needs a point tracker (lk), \
+                                    not {}",
+                                   config.tracker));
+            }
+            if n == 0 || self.features.is_some() || self.dims.is_some() {
+                return Err("--points takes a positive count and replaces \
+                            --features and --dims"
+                    .to_string());
+            }
+            config.points = Some(n);
+            return Ok(config);
+        }
+
+        // `dims` counts everything that is sent for a target, derivatives
+        // included.
+        let dims = match self.dims {
+            Some(dims) if dims <= config.derive.dims() => {
+                return Err(format!("{} dimensions leave no room for the {} \
+                                    derived dimensions",
+                                   dims,
+                                   config.derive.dims()));
+            }
+            Some(dims) => Some(dims - config.derive.dims()),
+            None => None,
+        };
+        config.features = match (self.features, dims) {
+            (Some(f), Some(dims)) => {
+                let features: FeatureSet = f.parse()?;
+                if features.dims() != dims {
+                    return Err(format!("{} features are listed but dims \
+                                        asks for {}",
+                                       features.dims(),
+                                       dims));
+                }
+                features
+            }
+            (Some(f), None) => f.parse()?,
+            (None, Some(dims)) => FeatureSet::with_dims(dims)?,
+            (None, None) => FeatureSet::default(),
+        };
+        Ok(config)
+    }
+}
+
+/// The settings of the config file that are applied again when it changes;
+/// see the [`reload`](../reload/index.html) module.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Live {
+    pub params: Vec<(String, f64)>,
+    pub send_rate: Option<f64>,
+    pub filters: Vec<FilterSpec>,
+}
+
+impl Live {
+    /// The keys of the live settings in the config file.
+    pub const KEYS: [&'static str; 3] = ["params", "send_rate", "filters"];
+
+    /// Reads the live settings of the config file `text`. The other
+    /// settings must be well-formed, but are not validated further.
+    pub fn parse(text: &str) -> Result<Live, String> {
+        let settings: Settings = toml::from_str(text)
+            .map_err(|e| e.to_string())?;
+        Ok(Live {
+            params: check_params(settings.params)?,
+            send_rate: check_send_rate(settings.send_rate)?,
+            filters: check_filters(settings.filters)?,
+        })
+    }
+}
+
+fn check_params(params: Option<BTreeMap<String, f64>>)
+                -> Result<Vec<(String, f64)>, String> {
+    let params: Vec<_> = params.unwrap_or_default().into_iter().collect();
+    let mut checked = VisionParams::default();
+    for &(ref name, value) in &params {
+        checked.set(name, value)?;
+    }
+    Ok(params)
+}
+
+fn check_send_rate(rate: Option<f64>) -> Result<Option<f64>, String> {
+    rate.map(|hz| rate::check("send rate", hz)).transpose()
+}
+
+/// `c`, if it is a confidence in `[0, 1]`; `what` names it in the error.
+fn check_confidence(what: &str, c: f64) -> Result<f64, String> {
+    if !(0.0..=1.0).contains(&c) {
+        return Err(format!("{} must be between 0 and 1, got {}", what, c));
+    }
+    Ok(c)
+}
+
+fn check_filters(filters: Option<Vec<FilterSpec>>)
+                 -> Result<Vec<FilterSpec>, String> {
+    let filters = filters.unwrap_or_default();
+    // Catch invalid parameters now rather than when tracking starts.
+    for f in &filters {
+        f.build()?;
+    }
+    Ok(filters)
+}
+
+/// The id of the detector class `class`, given by name or id. Names are
+/// looked up in the `labels` file, or the VOC classes without one.
+fn class_id(class: &str, labels: Option<&String>) -> Result<i32, String> {
+    if let Ok(id) = class.parse() {
+        return Ok(id);
+    }
+    let position = match labels {
+        Some(path) => {
+            let mut text = String::new();
+            File::open(path)
+                .and_then(|mut f| f.read_to_string(&mut text))
+                .map_err(|e| format!("cannot read labels {}: {}", path, e))?;
+            text.lines().position(|l| l.trim() == class)
+        }
+        None => VOC_LABELS.iter().position(|&l| l == class),
+    };
+    position.map(|i| i as i32)
+        .ok_or_else(|| format!("unknown detector class `{}`", class))
+}
+
+/// Parses a rectangle given as `x,y,w,h`.
+pub fn rect(s: &str) -> Result<Rect, String> {
+    let v = s.split(',')
+        .map(|c| c.trim().parse::<i32>())
+        .collect::<Result<Vec<i32>, _>>()
+        .map_err(|e| format!("invalid rectangle `{}`: {}", s, e))?;
+    if v.len() != 4 || v[2] <= 0 || v[3] <= 0 {
+        return Err(format!("rectangle `{}` is not x,y,w,h with a positive \
+                            size",
+                           s));
+    }
+    Ok(Rect::new(v[0], v[1], v[2], v[3]))
+}
+
+fn size(s: &str) -> Result<(i32, i32), String> {
+    let invalid = || format!("size `{}` is not WIDTHxHEIGHT", s);
+    let i = s.find('x').ok_or_else(invalid)?;
+    match (s[..i].parse(), s[i + 1..].parse()) {
+        (Ok(width), Ok(height)) if width > 0 && height > 0 => {
+            Ok((width, height))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn plane_size(s: &str) -> Result<[f64; 2], String> {
+    let invalid = || format!("plane size `{}` is not WIDTHxHEIGHT", s);
+    let i = s.find('x').ok_or_else(invalid)?;
+    match (s[..i].parse::<f64>(), s[i + 1..].parse::<f64>()) {
+        (Ok(width), Ok(height)) if width > 0.0 && height > 0.0 => {
+            Ok([width, height])
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses the option `name` as a number, if present.
+fn number<T>(matches: &Matches, name: &str) -> Result<Option<T>, String>
+    where T: ::std::str::FromStr,
+          T::Err: ::std::fmt::Display
+{
+    match matches.opt_str(name) {
+        Some(v) => {
+            v.parse::<T>()
+                .map(Some)
+                .map_err(|e| format!("invalid --{} `{}`: {}", name, v, e))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The parameters of `upper`, and those of `lower` that it doesn't set.
+fn merge(upper: Option<BTreeMap<String, f64>>,
+         lower: Option<BTreeMap<String, f64>>)
+         -> Option<BTreeMap<String, f64>> {
+    match (upper, lower) {
+        (Some(mut upper), Some(lower)) => {
+            for (name, value) in lower {
+                upper.entry(name).or_insert(value);
+            }
+            Some(upper)
+        }
+        (upper, lower) => upper.or(lower),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::FilterSpec;
+    use crate::tracker::Backend;
+    use super::{Mode, Settings, TrackerRules, options};
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter()
+            .map(|&(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn reads_settings_from_the_environment() {
+        let settings = Settings::from_env(vars(&[
+            ("ESP_VISION_SEND_RATE", "30"),
+            ("ESP_VISION_FILTERS", "[{ type = \"median\", window = 5 }]"),
+            ("ESP_VISION_CONFIG", "esp-vision.toml"),
+            ("HOME", "/root"),
+        ]))
+            .unwrap();
+        assert_eq!(settings.send_rate, Some(30.0));
+        assert_eq!(settings.filters,
+                   Some(vec![FilterSpec::Median { window: 5 }]));
+        assert!(Settings::from_env(vars(&[("ESP_VISION_NOPE", "1")]))
+            .is_err());
+        assert!(Settings::from_env(vars(&[("ESP_VISION_SEND_RATE", "fast")]))
+            .is_err());
+    }
+
+    #[test]
+    fn reads_variables_that_are_not_toml_as_strings() {
+        let settings = Settings::from_env(vars(&[
+            ("ESP_VISION_SERVER", "192.168.1.10:8001"),
+            ("ESP_VISION_FEATURES", "\"x,y\""),
+            // Valid TOML, but not of the type of the setting.
+            ("ESP_VISION_CONTROL", "8002"),
+        ]))
+            .unwrap();
+        assert_eq!(settings.server, Some("192.168.1.10:8001".to_string()));
+        assert_eq!(settings.features, Some("x,y".to_string()));
+        assert_eq!(settings.control, Some("8002".to_string()));
+    }
+
+    #[test]
+    fn layers_the_command_line_the_environment_the_file_and_the_profile() {
+        let matches = options()
+            .parse(&["--server", "cli:8001", "--headless"])
+            .unwrap();
+        let cli = Settings::from_matches(&matches).unwrap();
+        let env = Settings::from_env(vars(&[("ESP_VISION_SERVER",
+                                             "env:8001"),
+                                            ("ESP_VISION_SEND_RATE", "20")]))
+            .unwrap();
+        let file: Settings = toml::from_str("server = \"file:8001\"\n\
+                                             send_rate = 10\n\
+                                             features = \"x,y\"\n\
+                                             profile = \"hand\"")
+            .unwrap();
+        let settings = cli.or(env).or(file).defaults().unwrap();
+        assert_eq!(settings.server, Some("cli:8001".to_string()));
+        assert_eq!(settings.send_rate, Some(20.0));
+        assert_eq!(settings.features, Some("x,y".to_string()));
+        assert_eq!(settings.tracker, Some("camshift".to_string()));
+        assert_eq!(settings.headless, Some(true));
+    }
+
+    #[test]
+    fn keeps_the_parameters_of_the_profile_a_file_doesnt_set() {
+        let file: Settings = toml::from_str("profile = \"hand\"\n\
+                                             [params]\n\
+                                             v_min = 60")
+            .unwrap();
+        let params = file.defaults().unwrap().params.unwrap();
+        assert_eq!(params.get("v_min"), Some(&60.0));
+        assert_eq!(params.get("h_max"), Some(&20.0));
+        assert_eq!(params.get("s_min"), Some(&48.0));
+    }
+
+    #[test]
+    fn tells_profiles_from_presets() {
+        assert!(Settings::profile("hand").is_ok());
+        assert!(Settings::preset("hand").is_err());
+        assert!(Settings::profile("hands").is_err());
+        assert!(Settings::profile("demo").is_err());
+    }
+
+    #[test]
+    fn rejects_confidences_outside_0_and_1() {
+        for c in &["1.5", "-0.1", "nan"] {
+            let text = format!("min_confidence = {}", c);
+            let file: Settings = toml::from_str(&text).unwrap();
+            assert!(file.resolve().is_err(), "{}", c);
+        }
+        let file: Settings = toml::from_str("min_confidence = 0.2").unwrap();
+        assert_eq!(file.resolve().unwrap().loss.min_confidence, 0.2);
+    }
+
+    #[test]
+    fn checks_trackers_against_the_config() {
+        let rules = TrackerRules {
+            dnn_model: false,
+            opencl: true,
+            cuda: false,
+            mode: Mode::Track,
+            points: false,
+        };
+        assert!(rules.check(Backend::Camshift).is_ok());
+        assert!(rules.check(Backend::Kcf).is_err());
+        let rules = TrackerRules {
+            opencl: false,
+            points: true,
+            ..rules
+        };
+        assert!(rules.check(Backend::Lk).is_ok());
+        assert!(rules.check(Backend::Csrt).is_err());
+        let rules = TrackerRules {
+            points: false,
+            cuda: true,
+            ..rules
+        };
+        assert!(rules.check(Backend::Kcf).is_err());
+        let rules = TrackerRules { mode: Mode::Flow, ..rules };
+        assert!(rules.check(Backend::Kcf).is_ok());
+        // Without a model, whatever the build.
+        assert!(rules.check(Backend::Dnn).is_err());
+    }
+}
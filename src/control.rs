@@ -0,0 +1,102 @@
+//! Control channel for live parameter updates.
+//!
+//! ESP (or anything else that can open a TCP connection) sends one update per
+//! line, in the form `<name> <value>`, e.g. `s_min 60`. Every line is answered
+//! with `ok` or `error: <reason>`. Sending `list` returns the current values,
+//! and `pause` and `resume` stop and restart sending samples to ESP.
+use crate::net::Net;
+use crate::params::VisionParams;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// `ControlServer` applies parameter updates to a shared `VisionParams`.
+pub struct ControlServer {
+    params: Arc<Mutex<VisionParams>>,
+}
+
+impl ControlServer {
+    /// Binds to `addr` and serves control connections on `net`, each as a
+    /// task of its own. `paused` is set and cleared by `pause` and `resume`.
+    pub fn spawn(net: &Net,
+                 addr: &str,
+                 params: VisionParams,
+                 paused: Arc<AtomicBool>)
+                 -> io::Result<ControlServer> {
+        let listener = net.block_on(TcpListener::bind(addr))?;
+        let params = Arc::new(Mutex::new(params));
+        let shared = params.clone();
+        net.spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        info!("Control connection from {}", peer);
+                        let params = shared.clone();
+                        let paused = paused.clone();
+                        tokio::spawn(async move {
+                            serve(stream, &params, &paused).await
+                        });
+                    }
+                    Err(e) => warn!("Control channel error: {}", e),
+                }
+            }
+        });
+        Ok(ControlServer { params })
+    }
+
+    /// A snapshot of the current parameters.
+    pub fn params(&self) -> VisionParams {
+        self.params.lock().unwrap().clone()
+    }
+
+    /// Updates one parameter, as a control message would.
+    pub fn set(&self, name: &str, value: f64) -> Result<(), String> {
+        self.params.lock().unwrap().set(name, value)
+    }
+}
+
+async fn serve(stream: TcpStream,
+               params: &Mutex<VisionParams>,
+               paused: &AtomicBool) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let reply = match apply(line.trim(), params, paused) {
+            Ok(reply) => reply,
+            Err(e) => format!("error: {}", e),
+        };
+        let reply = format!("{}\n", reply);
+        if writer.write_all(reply.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Parses and applies one control message.
+fn apply(line: &str,
+         params: &Mutex<VisionParams>,
+         paused: &AtomicBool)
+         -> Result<String, String> {
+    let mut parts = line.split_whitespace();
+    let name = match parts.next() {
+        Some(name) => name,
+        None => return Err("empty message".to_string()),
+    };
+    if name == "list" {
+        return Ok(format!("{:?}", *params.lock().unwrap()));
+    }
+    if name == "pause" || name == "resume" {
+        paused.store(name == "pause", Ordering::SeqCst);
+        info!("Control: {}", name);
+        return Ok("ok".to_string());
+    }
+    let value = parts.next()
+        .ok_or_else(|| format!("missing value for `{}`", name))?
+        .parse::<f64>()
+        .map_err(|e| format!("invalid value for `{}`: {}", name, e))?;
+    params.lock().unwrap().set(name, value)?;
+    info!("Control: {} = {}", name, value);
+    Ok("ok".to_string())
+}
@@ -0,0 +1,344 @@
+//! `esp-vision coordinate --peer NAME=ADDR... [options]`: merges the samples
+//! of several instances, e.g. one per room, into a single stream for ESP.
+//!
+//! Every peer is an instance sending its samples to the coordinator in the
+//! framed format, whose header names their dimensions:
+//!
+//! ```text
+//! esp-vision --target-names hand --sink tcp:10.0.0.5:9101,format=framed
+//! esp-vision --target-names hand --sink tcp:10.0.0.5:9102,format=framed
+//! esp-vision coordinate --peer room1=0.0.0.0:9101 --peer room2=0.0.0.0:9102
+//! ```
+//!
+//! The coordinator listens on the address of every peer, waits until all of
+//! them sent their header, then sends ESP a sample `--rate` times per second
+//! with the latest values of every peer, in the order of the `--peer`
+//! options, whatever the order they arrive in. The dimensions are named
+//! after the peer, `room1_hand_x room1_hand_y room2_hand_x ...`, and are NaN
+//! while the peer sent nothing for `--stale` seconds or is disconnected, so
+//! that ESP always gets the same dimensions. A peer that comes back with
+//! other dimensions stays NaN until it is back to those it started with.
+//! The instance on the machine of the coordinator is a peer like the others,
+//! sending to a local address.
+use crate::config::Config;
+use crate::framed::{Decoder, Dimension, Frame};
+use crate::net::Net;
+use crate::pipeline::Timings;
+use crate::rate;
+use crate::sample::Sample;
+use crate::serialize::Format;
+use crate::sink::{Sink, TcpSink};
+use getopts::Options;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::net::{TcpListener, TcpStream};
+
+/// Name of the subcommand.
+pub const COMMAND: &str = "coordinate";
+/// Samples sent to ESP per second unless `--rate` says otherwise.
+const DEFAULT_RATE: f64 = 30.0;
+/// Seconds after which the values of a silent peer are NaN, unless
+/// `--stale` says otherwise.
+const DEFAULT_STALE: f64 = 0.5;
+/// Time between checks that every peer sent its header.
+const WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A peer, as given to `--peer NAME=ADDR`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeerSpec {
+    pub name: String,
+    /// The address to listen on for the peer.
+    pub addr: String,
+}
+
+impl FromStr for PeerSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PeerSpec, String> {
+        let i = s.find('=')
+            .ok_or_else(|| format!("peer `{}` is not NAME=ADDR", s))?;
+        let (name, addr) = (&s[..i], &s[i + 1..]);
+        if name.is_empty() ||
+           !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("peer name `{}` may only have letters, \
+                                digits and _",
+                               name));
+        }
+        if addr.is_empty() {
+            return Err(format!("peer `{}` has no address", name));
+        }
+        Ok(PeerSpec {
+            name: name.to_string(),
+            addr: addr.to_string(),
+        })
+    }
+}
+
+/// What was received from a peer.
+#[derive(Debug, Default)]
+struct PeerState {
+    /// The dimensions of its last header.
+    dimensions: Option<Vec<Dimension>>,
+    /// Its last sample, and when it was received.
+    latest: Option<(Sample, Instant)>,
+}
+
+/// Runs the subcommand; `args` is the whole command line.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optmulti("",
+                  "peer",
+                  "a peer, in order, and the address to receive its framed \
+                   samples on",
+                  "NAME=ADDR");
+    opts.optopt("",
+                "server",
+                "address of ESP's TcpInputStream (127.0.0.1:8001)",
+                "ADDR");
+    opts.optopt("",
+                "rate",
+                &format!("samples sent to ESP per second ({})", DEFAULT_RATE),
+                "HZ");
+    opts.optopt("",
+                "stale",
+                &format!("seconds without samples after which the values of \
+                          a peer are NaN ({})",
+                         DEFAULT_STALE),
+                "SECS");
+    opts.optopt("",
+                "format",
+                "wire format of the ESP sink: ascii, json, binary or framed \
+                 (ascii)",
+                "FORMAT");
+    opts.optflag("",
+                 "ascii-stamps",
+                 "append the time and sequence number to ASCII samples");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[2..]).map_err(|e| e.to_string())?;
+    let brief = format!("Usage: {} {} --peer NAME=ADDR... [options]",
+                        args[0],
+                        COMMAND);
+    if matches.opt_present("h") {
+        print!("{}", opts.usage(&brief));
+        return Ok(());
+    }
+    let peers = matches.opt_strs("peer")
+        .iter()
+        .map(|p| p.parse())
+        .collect::<Result<Vec<PeerSpec>, String>>()?;
+    if peers.is_empty() || !matches.free.is_empty() {
+        return Err(opts.usage(&brief));
+    }
+    for (i, peer) in peers.iter().enumerate() {
+        if peers[..i].iter().any(|p| p.name == peer.name) {
+            return Err(format!("peer `{}` is given twice", peer.name));
+        }
+    }
+    let seconds = |name: &str, default: f64| match matches.opt_str(name) {
+        Some(s) => {
+            match s.parse::<f64>() {
+                Ok(v) if v > 0.0 && v.is_finite() => Ok(v),
+                _ => Err(format!("invalid {} `{}`", name, s)),
+            }
+        }
+        None => Ok(default),
+    };
+    let rate = match matches.opt_str("rate") {
+        Some(rate) => {
+            match rate.parse::<f64>() {
+                Ok(hz) => rate::check("rate", hz)?,
+                _ => return Err(format!("invalid rate `{}`", rate)),
+            }
+        }
+        None => DEFAULT_RATE,
+    };
+    let interval = Duration::from_secs_f64(1.0 / rate);
+    let stale = Duration::from_secs_f64(seconds("stale", DEFAULT_STALE)?);
+    let format: Format = match matches.opt_str("format") {
+        Some(format) => format.parse()?,
+        None => Format::Ascii,
+    };
+    let server = matches.opt_str("server")
+        .unwrap_or_else(|| Config::default().server);
+
+    let net = Net::new().map_err(|e| e.to_string())?;
+    let states = peers.iter()
+        .map(|peer| {
+            listen(&net, peer)
+                .map_err(|e| format!("cannot listen on {}: {}", peer.addr, e))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    // The dimensions ESP gets are those of the first headers.
+    let mut waiting: Vec<String> = Vec::new();
+    let dimensions = loop {
+        let known: Vec<Option<Vec<Dimension>>> = states.iter()
+            .map(|s| s.lock().unwrap().dimensions.clone())
+            .collect();
+        let missing: Vec<&str> = peers.iter()
+            .zip(&known)
+            .filter(|&(_, d)| d.is_none())
+            .map(|(p, _)| p.name.as_str())
+            .collect();
+        if missing.is_empty() {
+            break known.into_iter().flatten().collect::<Vec<_>>();
+        }
+        if missing != waiting {
+            info!("Waiting for {}", missing.join(", "));
+            waiting = missing.iter().map(|m| m.to_string()).collect();
+        }
+        thread::sleep(WAIT_INTERVAL);
+    };
+    let mut names = Vec::new();
+    let mut units = Vec::new();
+    for (peer, dims) in peers.iter().zip(&dimensions) {
+        for d in dims {
+            names.push(format!("{}_{}", peer.name, d.name));
+            units.push(d.unit.clone());
+        }
+    }
+    info!("Sending {} dimensions to ESP: {}", names.len(), names.join(" "));
+
+    let serializer =
+        format.serializer(names, units, matches.opt_present("ascii-stamps"));
+    let mut sink = TcpSink::connect(&net,
+                                    server.as_str(),
+                                    serializer,
+                                    Timings::default(),
+                                    None)
+        .map_err(|e| format!("cannot connect to {}: {}", server, e))?;
+    let start = Instant::now();
+    let mut seq = 0;
+    loop {
+        let due = start + interval.mul_f64(seq as f64);
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        }
+        let now = Instant::now();
+        let values = states.iter()
+            .zip(&dimensions)
+            .flat_map(|(state, dims)| {
+                values(&state.lock().unwrap(), dims, now, stale)
+            })
+            .collect();
+        let sample = Sample::new(seq, (now - start).as_secs_f64(), values);
+        match sink.send(&sample) {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            result => result.map_err(|e| format!("cannot send: {}", e))?,
+        }
+        seq += 1;
+    }
+}
+
+/// The values of a peer with the dimensions `dims` for a sample at `now`:
+/// its latest, or NaN if they are older than `stale` or have other
+/// dimensions.
+fn values(state: &PeerState,
+          dims: &[Dimension],
+          now: Instant,
+          stale: Duration)
+          -> Vec<f64> {
+    if let Some((ref sample, received)) = state.latest {
+        let fresh = now.saturating_duration_since(received) <= stale;
+        if fresh && state.dimensions.as_deref() == Some(dims) {
+            return sample.values.clone();
+        }
+    }
+    vec![f64::NAN; dims.len()]
+}
+
+/// Listens for `peer` on `net`, one connection at a time, and keeps what it
+/// sends.
+fn listen(net: &Net, peer: &PeerSpec) -> io::Result<Arc<Mutex<PeerState>>> {
+    let listener = net.block_on(TcpListener::bind(peer.addr.as_str()))?;
+    let state = Arc::new(Mutex::new(PeerState::default()));
+    let shared = state.clone();
+    let name = peer.name.clone();
+    net.spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, addr)) => {
+                    info!("Peer {} connected from {}", name, addr);
+                    receive(stream, &shared).await;
+                    shared.lock().unwrap().latest = None;
+                    warn!("Peer {} disconnected", name);
+                }
+                Err(e) => warn!("Peer {} listener error: {}", name, e),
+            }
+        }
+    });
+    Ok(state)
+}
+
+/// Decodes the framed stream of a peer until it hangs up.
+async fn receive(mut stream: TcpStream, state: &Mutex<PeerState>) {
+    let mut decoder = Decoder::new();
+    let mut buf = [0; 4096];
+    loop {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => decoder.feed(&buf[..n]),
+        }
+        while let Some(frame) = decoder.decode() {
+            let mut state = state.lock().unwrap();
+            match frame {
+                Frame::Header(dimensions) => {
+                    state.dimensions = Some(dimensions);
+                    state.latest = None;
+                }
+                Frame::Sample(sample) => {
+                    state.latest = Some((sample, Instant::now()));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framed::Dimension;
+    use crate::sample::Sample;
+    use std::time::{Duration, Instant};
+    use super::{PeerSpec, PeerState, values};
+
+    #[test]
+    fn parses_peers() {
+        assert_eq!("room1=0.0.0.0:9101".parse::<PeerSpec>().unwrap(),
+                   PeerSpec {
+                       name: "room1".to_string(),
+                       addr: "0.0.0.0:9101".to_string(),
+                   });
+        assert!("room1".parse::<PeerSpec>().is_err());
+        assert!("room 1=:9101".parse::<PeerSpec>().is_err());
+        assert!("room1=".parse::<PeerSpec>().is_err());
+    }
+
+    #[test]
+    fn fills_silent_and_changed_peers_with_nan() {
+        let dim = |name: &str| {
+            Dimension {
+                name: name.to_string(),
+                unit: "px".to_string(),
+            }
+        };
+        let dims = vec![dim("x"), dim("y")];
+        let now = Instant::now();
+        let stale = Duration::from_millis(500);
+        let mut state = PeerState {
+            dimensions: Some(dims.clone()),
+            latest: Some((Sample::new(3, 0.1, vec![1.0, 2.0]), now)),
+        };
+        assert_eq!(values(&state, &dims, now, stale), [1.0, 2.0]);
+        let later = now + Duration::from_secs(1);
+        assert!(values(&state, &dims, later, stale).iter().all(|v| v.is_nan()));
+        state.dimensions = Some(vec![dim("x")]);
+        let changed = values(&state, &dims, now, stale);
+        assert_eq!(changed.len(), 2);
+        assert!(changed.iter().all(|v| v.is_nan()));
+    }
+}
@@ -0,0 +1,36 @@
+//! CUDA acceleration, for builds with the `cuda` feature.
+//!
+//! `--cuda` runs the networks of the `dnn` tracker and the `pose` mode on
+//! OpenCV's CUDA DNN backend, and the dense flow of the `flow` mode with
+//! `cv::cuda::FarnebackOpticalFlow`. OpenCV has to be built with CUDA as
+//! well:
+//!
+//! ```text
+//! cargo build --release --features cuda
+//! ```
+#[cfg(feature = "dnn")]
+use rust_vision::*;
+
+/// Whether this build has the `cuda` feature.
+pub const BUILT: bool = cfg!(feature = "cuda");
+
+/// Whether a CUDA device can be used.
+#[cfg(feature = "cuda")]
+pub fn available() -> bool {
+    cuda::get_cuda_enabled_device_count() > 0
+}
+
+#[cfg(not(feature = "cuda"))]
+pub fn available() -> bool {
+    false
+}
+
+/// Runs `net` on the CUDA backend.
+#[cfg(all(feature = "cuda", feature = "dnn"))]
+pub fn use_for(net: &mut Net) {
+    net.set_preferable_backend(DnnBackend::Cuda);
+    net.set_preferable_target(DnnTarget::Cuda);
+}
+
+#[cfg(all(not(feature = "cuda"), feature = "dnn"))]
+pub fn use_for(_net: &mut Net) {}
@@ -0,0 +1,281 @@
+//! A web dashboard, with `--dashboard ADDR`.
+//!
+//! Installations that run headless, e.g. with the camera in a ceiling
+//! mount, can be checked on from any browser at `http://ADDR/`: the page
+//! shows the annotated video as it would be displayed, the position of every
+//! target, the frame rate and whether samples reach ESP. Behind it are:
+//!
+//! - `GET /stream`: the annotated frames as an MJPEG stream, which browsers
+//!   show in an `<img>`. Frames are only encoded while someone watches.
+//! - `GET /status`: the status as a JSON object, e.g.
+//!   `{"fps":29.8,"sent_per_second":30,"esp":"connected","paused":false,
+//!   "tracker":"camshift","stopped":false,
+//!   "targets":[{"x":312.0,"y":240.0},null]}`, where lost targets are
+//!   `null`. `stopped` is set while the [`api`](../api/index.html) stopped
+//!   tracking.
+//!
+//! Builds without the `net` feature have no dashboard.
+use crate::net::Net;
+use rust_vision::{Mat, imencode};
+use std::fmt::Write;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// What the dashboard shows besides the video.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Status {
+    /// Capture rate, in frames per second.
+    pub fps: f64,
+    /// Samples sent to ESP during the last second.
+    pub send_rate: u64,
+    /// Whether the last sample reached ESP.
+    pub connected: bool,
+    /// Whether sending is paused.
+    pub paused: bool,
+    /// The tracking backend.
+    pub tracker: String,
+    /// Whether tracking was stopped until it is started again.
+    pub stopped: bool,
+    /// Center of every target, in pixels; `None` for lost ones.
+    pub targets: Vec<Option<[f64; 2]>>,
+}
+
+impl Status {
+    pub fn to_json(&self) -> String {
+        let targets: Vec<String> = self.targets
+            .iter()
+            .map(|t| match *t {
+                Some([x, y]) => format!("{{\"x\":{:.1},\"y\":{:.1}}}", x, y),
+                None => "null".to_string(),
+            })
+            .collect();
+        let mut out = String::new();
+        write!(out,
+               "{{\"fps\":{:.1},\"sent_per_second\":{},\"esp\":\"{}\",\
+                \"paused\":{},\"tracker\":\"{}\",\"stopped\":{},\
+                \"targets\":[{}]}}",
+               self.fps,
+               self.send_rate,
+               if self.connected {
+                   "connected"
+               } else {
+                   "disconnected"
+               },
+               self.paused,
+               self.tracker,
+               self.stopped,
+               targets.join(","))
+            .unwrap();
+        out
+    }
+}
+
+/// `Dashboard` is shared between the main loop, which publishes every
+/// annotated frame, and the web server.
+pub struct Dashboard {
+    status: Mutex<Status>,
+    /// The last frame, as a JPEG image, for the streams.
+    frames: watch::Sender<Arc<Vec<u8>>>,
+}
+
+impl Dashboard {
+    /// Serves the dashboard on `addr` from `net`.
+    pub fn serve(net: &Net, addr: &str) -> io::Result<Arc<Dashboard>> {
+        let (frames, _) = watch::channel(Arc::new(Vec::new()));
+        let dashboard = Arc::new(Dashboard {
+            status: Mutex::default(),
+            frames,
+        });
+        server::spawn(net, addr, dashboard.clone())?;
+        Ok(dashboard)
+    }
+
+    /// Shows the annotated `frame` and `status`.
+    pub fn publish(&self, frame: &Mat, status: Status) {
+        *self.status.lock().unwrap() = status;
+        if self.frames.receiver_count() > 0 {
+            self.frames.send_replace(Arc::new(imencode(".jpg", frame)));
+        }
+    }
+}
+
+#[cfg(not(feature = "net"))]
+mod server {
+    use crate::net::{self, Net};
+    use std::io;
+    use std::sync::Arc;
+    use super::Dashboard;
+
+    pub fn spawn(_net: &Net,
+                 _addr: &str,
+                 _dashboard: Arc<Dashboard>)
+                 -> io::Result<()> {
+        Err(net::unavailable("dashboard"))
+    }
+}
+
+#[cfg(feature = "net")]
+mod server {
+    use crate::net::Net;
+    use std::io;
+    use std::sync::Arc;
+    use super::Dashboard;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    /// Separates the images of the MJPEG stream.
+    const BOUNDARY: &str = "frame";
+
+    /// The page at `/`, which shows the stream and polls the status.
+    const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>esp-vision</title>
+<style>
+body { font-family: sans-serif; margin: 1em; background: #222; color: #eee; }
+img { max-width: 100%; }
+td { padding: 0 1em 0 0; }
+</style>
+</head>
+<body>
+<img src="/stream" alt="annotated video">
+<table>
+<tr><td>Frame rate</td><td id="fps">-</td></tr>
+<tr><td>Sent to ESP</td><td id="sent">-</td></tr>
+<tr><td>ESP</td><td id="esp">-</td></tr>
+<tr><td>Tracker</td><td id="tracker">-</td></tr>
+<tr><td>Targets</td><td id="targets">-</td></tr>
+</table>
+<script>
+function show(id, text) { document.getElementById(id).textContent = text; }
+function poll() {
+  fetch("/status").then(r => r.json()).then(s => {
+    show("fps", s.fps.toFixed(1) + " fps");
+    show("sent", s.sent_per_second + " samples/s");
+    show("esp", s.esp + (s.paused ? ", paused" : ""));
+    show("tracker", s.tracker + (s.stopped ? ", stopped" : ""));
+    show("targets", s.targets.length == 0 ? "none" : s.targets.map(t =>
+      t ? "(" + t.x.toFixed(0) + ", " + t.y.toFixed(0) + ")" : "lost"
+    ).join(" "));
+  }).catch(() => show("esp", "esp-vision is not running"));
+}
+setInterval(poll, 500);
+poll();
+</script>
+</body>
+</html>
+"#;
+
+    pub fn spawn(net: &Net,
+                 addr: &str,
+                 dashboard: Arc<Dashboard>)
+                 -> io::Result<()> {
+        let listener = net.block_on(TcpListener::bind(addr))?;
+        net.spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        debug!("Dashboard requested by {}", peer);
+                        let dashboard = dashboard.clone();
+                        tokio::spawn(async move {
+                            respond(stream, &dashboard).await.ok()
+                        });
+                    }
+                    Err(e) => warn!("Dashboard error: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Answers one HTTP request: the page, the status or the stream, and 404
+    /// for anything else.
+    async fn respond(mut stream: TcpStream,
+                     dashboard: &Dashboard)
+                     -> io::Result<()> {
+        let mut buf = [0; 1024];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let path = if request.starts_with("GET ") {
+            request.split_whitespace().nth(1).unwrap_or("")
+        } else {
+            ""
+        };
+        match path {
+            "/" => ok(&mut stream, "text/html; charset=utf-8", PAGE).await,
+            "/status" => {
+                let json = dashboard.status.lock().unwrap().to_json();
+                ok(&mut stream, "application/json", &json).await
+            }
+            "/stream" => send_stream(stream, dashboard).await,
+            _ => {
+                stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: \
+                                   0\r\nConnection: close\r\n\r\n")
+                    .await
+            }
+        }
+    }
+
+    /// Sends `body` with a 200 status.
+    async fn ok(stream: &mut TcpStream,
+                content_type: &str,
+                body: &str)
+                -> io::Result<()> {
+        let response = format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\n\
+                                Content-Length: {}\r\nCache-Control: \
+                                no-cache\r\nConnection: close\r\n\r\n{}",
+                               content_type,
+                               body.len(),
+                               body);
+        stream.write_all(response.as_bytes()).await
+    }
+
+    /// Sends every frame published from now on, until the browser goes away.
+    async fn send_stream(mut stream: TcpStream,
+                         dashboard: &Dashboard)
+                         -> io::Result<()> {
+        let mut frames = dashboard.frames.subscribe();
+        let header = format!("HTTP/1.1 200 OK\r\nContent-Type: \
+                              multipart/x-mixed-replace; boundary={}\r\n\
+                              Cache-Control: no-cache\r\nConnection: \
+                              close\r\n\r\n",
+                             BOUNDARY);
+        stream.write_all(header.as_bytes()).await?;
+        while frames.changed().await.is_ok() {
+            let jpeg = frames.borrow_and_update().clone();
+            let part = format!("--{}\r\nContent-Type: image/jpeg\r\n\
+                                Content-Length: {}\r\n\r\n",
+                               BOUNDARY,
+                               jpeg.len());
+            stream.write_all(part.as_bytes()).await?;
+            stream.write_all(&jpeg).await?;
+            stream.write_all(b"\r\n").await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Status;
+
+    #[test]
+    fn writes_the_status_as_json() {
+        let status = Status {
+            fps: 29.84,
+            send_rate: 30,
+            connected: true,
+            paused: false,
+            tracker: "kcf".to_string(),
+            stopped: false,
+            targets: vec![Some([312.0, 240.5]), None],
+        };
+        assert_eq!(status.to_json(),
+                   "{\"fps\":29.8,\"sent_per_second\":30,\
+                    \"esp\":\"connected\",\"paused\":false,\
+                    \"tracker\":\"kcf\",\"stopped\":false,\
+                    \"targets\":[{\"x\":312.0,\"y\":240.5},null]}");
+    }
+}
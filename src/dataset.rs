@@ -0,0 +1,43 @@
+//! `--dataset FILE`: the samples, labeled with the gesture class being
+//! performed, for training ESP offline.
+//!
+//! While recording, the keys `1` to `9` tag the class being performed and
+//! `0` goes back to no gesture, class 0 being the null class of ESP's
+//! classifiers. Every sample is written as a CSV line with its `time`, `seq`,
+//! the dimensions sent to ESP and its `class`.
+use crate::sample::Sample;
+use crate::sink::Sink;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufWriter};
+use std::rc::Rc;
+
+/// `Dataset` writes the samples it is sent, with the current class.
+pub struct Dataset {
+    writer: BufWriter<File>,
+    class: Rc<Cell<u32>>,
+}
+
+impl Dataset {
+    /// Creates `path` for samples whose dimensions are called `names`,
+    /// labeled with the value of `class` as they are sent.
+    pub fn create(path: &str,
+                  names: &[String],
+                  class: Rc<Cell<u32>>)
+                  -> io::Result<Dataset> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "time,seq,{},class", names.join(","))?;
+        Ok(Dataset { writer, class })
+    }
+}
+
+impl Sink for Dataset {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        write!(self.writer, "{:.6},{}", sample.time, sample.seq)?;
+        for v in &sample.values {
+            write!(self.writer, ",{}", v)?;
+        }
+        writeln!(self.writer, ",{}", self.class.get())
+    }
+}
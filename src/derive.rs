@@ -0,0 +1,157 @@
+//! Derived features: velocity and acceleration of the centroid.
+//!
+//! DTW tends to work better on deltas than on absolute positions. Derivatives
+//! are computed from capture timestamps, so they are expressed in pixels per
+//! second (and pixels per second squared) no matter how irregularly frames
+//! arrive.
+use crate::normalize::Unit;
+use std::time::Instant;
+
+/// How many derivatives are appended to the feature vector.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    /// Nothing is appended.
+    None,
+    /// `dx dy` is appended.
+    Velocity,
+    /// `dx dy ddx ddy` is appended.
+    Acceleration,
+}
+
+impl Order {
+    /// Number of extra dimensions.
+    pub fn dims(&self) -> usize {
+        match *self {
+            Order::None => 0,
+            Order::Velocity => 2,
+            Order::Acceleration => 4,
+        }
+    }
+
+    /// Names of the extra dimensions.
+    pub fn names(&self) -> &'static [&'static str] {
+        let names = &["dx", "dy", "ddx", "ddy"];
+        &names[..self.dims()]
+    }
+
+    /// Units of the extra dimensions.
+    pub fn units(&self) -> &'static [Unit] {
+        let units = &[Unit::DeltaX, Unit::DeltaY, Unit::DeltaX, Unit::DeltaY];
+        &units[..self.dims()]
+    }
+}
+
+/// `Differentiator` remembers the previous samples needed to compute
+/// derivatives.
+pub struct Differentiator {
+    order: Order,
+    last: Option<(Instant, [f64; 2])>,
+    velocity: Option<[f64; 2]>,
+}
+
+impl Differentiator {
+    pub fn new(order: Order) -> Differentiator {
+        Differentiator {
+            order,
+            last: None,
+            velocity: None,
+        }
+    }
+
+    /// Forgets the history, e.g. when tracking is re-initialized and the
+    /// jump to the new region must not show up as a velocity spike.
+    pub fn reset(&mut self) {
+        self.last = None;
+        self.velocity = None;
+    }
+
+    /// Feeds the centroid captured at `t` and appends the derivatives to
+    /// `out`. The first sample after a reset reports zero motion.
+    pub fn update(&mut self,
+                  t: Instant,
+                  centroid: [f64; 2],
+                  out: &mut Vec<f64>) {
+        if self.order == Order::None {
+            return;
+        }
+
+        let mut velocity = [0.0; 2];
+        let mut acceleration = [0.0; 2];
+        if let Some((last_t, last)) = self.last {
+            if t > last_t {
+                let dt = (t - last_t).as_secs_f64();
+                velocity = [(centroid[0] - last[0]) / dt,
+                            (centroid[1] - last[1]) / dt];
+                if let Some(v) = self.velocity {
+                    acceleration = [(velocity[0] - v[0]) / dt,
+                                    (velocity[1] - v[1]) / dt];
+                }
+                self.velocity = Some(velocity);
+            } else if let Some(v) = self.velocity {
+                velocity = v;
+            }
+        }
+        self.last = Some((t, centroid));
+
+        out.extend_from_slice(&velocity);
+        if self.order == Order::Acceleration {
+            out.extend_from_slice(&acceleration);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{Differentiator, Order};
+
+    /// What `derive` appends for each centroid, sampled every `dt` seconds.
+    fn run(derive: &mut Differentiator,
+           dt: f64,
+           centroids: &[[f64; 2]])
+           -> Vec<Vec<f64>> {
+        let start = Instant::now();
+        centroids.iter()
+            .enumerate()
+            .map(|(i, &c)| {
+                let mut out = Vec::new();
+                let t = start + Duration::from_secs_f64(i as f64 * dt);
+                derive.update(t, c, &mut out);
+                out
+            })
+            .collect()
+    }
+
+    #[test]
+    fn measures_velocity_and_acceleration_per_second() {
+        let mut derive = Differentiator::new(Order::Acceleration);
+        let centroids = [[0.0, 0.0], [10.0, 0.0], [30.0, -5.0]];
+        let out = run(&mut derive, 0.5, &centroids);
+        assert_eq!(out,
+                   [vec![0.0, 0.0, 0.0, 0.0],
+                    vec![20.0, 0.0, 0.0, 0.0],
+                    vec![40.0, -10.0, 40.0, -20.0]]);
+        derive.reset();
+        assert_eq!(run(&mut derive, 0.5, &[[30.0, -5.0]]),
+                   [vec![0.0, 0.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn repeats_the_velocity_when_the_time_stands_still() {
+        let mut derive = Differentiator::new(Order::Velocity);
+        let start = Instant::now();
+        let mut out = Vec::new();
+        derive.update(start, [0.0, 0.0], &mut out);
+        let t = start + Duration::from_millis(250);
+        derive.update(t, [1.0, 2.0], &mut out);
+        derive.update(t, [9.0, 9.0], &mut out);
+        assert_eq!(out, [0.0, 0.0, 4.0, 8.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn appends_nothing_without_an_order() {
+        let mut derive = Differentiator::new(Order::None);
+        assert_eq!(run(&mut derive, 0.5, &[[0.0, 0.0], [1.0, 1.0]]),
+                   [Vec::<f64>::new(), Vec::new()]);
+    }
+}
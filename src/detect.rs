@@ -0,0 +1,95 @@
+//! Detectors that find a target to start tracking without a selection.
+use rust_vision::*;
+
+/// Faces smaller than this (in pixels) are ignored.
+const MIN_FACE: i32 = 40;
+/// Skin blobs smaller than this (in pixels) are ignored.
+const MIN_SKIN_AREA: f64 = 1500.0;
+/// Skin tones in YCrCb; the luma is ignored so lighting matters less.
+const SKIN_LOW: [i32; 3] = [0, 133, 77];
+const SKIN_HIGH: [i32; 3] = [255, 173, 127];
+
+/// `FaceDetector` finds faces with a Haar or LBP cascade, such as OpenCV's
+/// `haarcascade_frontalface_default.xml`.
+pub struct FaceDetector {
+    cascade: CascadeClassifier,
+}
+
+impl FaceDetector {
+    /// Loads the cascade from the XML file at `path`.
+    pub fn new(path: &str) -> Result<FaceDetector, String> {
+        let cascade = CascadeClassifier::new(path);
+        if cascade.empty() {
+            return Err(format!("cannot load the cascade {}", path));
+        }
+        Ok(FaceDetector { cascade })
+    }
+
+    /// The largest face in the (BGR) `frame`, if any.
+    pub fn largest(&self, frame: &Mat) -> Option<Rect> {
+        let gray = frame.cvt_color(ColorConversionCodes::BGR2GRAY)
+            .equalize_hist();
+        self.cascade
+            .detect_multi_scale(&gray, 1.1, 4, Size2i::new(MIN_FACE, MIN_FACE))
+            .into_iter()
+            .max_by_key(|r| r.width * r.height)
+    }
+}
+
+/// The central part of a detected face, which holds skin rather than hair
+/// and background. This is what the hue histogram should be built from.
+pub fn face_core(face: Rect) -> Rect {
+    let (dx, dy) = (face.width / 5, face.height / 5);
+    Rect::new(face.x + dx,
+              face.y + dy,
+              face.width - 2 * dx,
+              face.height - 2 * dy)
+}
+
+/// `SkinDetector` finds a hand, or any other skin-colored blob, inside an
+/// activation zone.
+pub struct SkinDetector {
+    zone: Rect,
+    kernel: Mat,
+}
+
+impl SkinDetector {
+    pub fn new(zone: Rect) -> SkinDetector {
+        SkinDetector {
+            zone,
+            kernel: get_structuring_element(MorphShapes::Ellipse,
+                                            Size2i::new(5, 5)),
+        }
+    }
+
+    pub fn zone(&self) -> Rect {
+        self.zone
+    }
+
+    /// The bounding box of the largest skin blob inside the zone of the
+    /// (BGR) `frame`, if any.
+    pub fn find(&self, frame: &Mat) -> Option<Rect> {
+        let zone = crate::tracker::clip(self.zone, frame.cols, frame.rows);
+        if zone.width == 0 || zone.height == 0 {
+            return None;
+        }
+        let scalar = |c: [i32; 3]| Scalar::new(c[0], c[1], c[2], 0);
+        let mask = frame.roi(zone)
+            .cvt_color(ColorConversionCodes::BGR2YCrCb)
+            .in_range(scalar(SKIN_LOW), scalar(SKIN_HIGH))
+            .morphology_ex(MorphTypes::Open, &self.kernel, 1)
+            .morphology_ex(MorphTypes::Close, &self.kernel, 2);
+        mask.find_contours(RetrievalModes::External,
+                           ContourApproximationModes::Simple)
+            .iter()
+            .map(|c| (contour_area(c), c))
+            .filter(|&(area, _)| area >= MIN_SKIN_AREA)
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .map(|(_, contour)| {
+                let mut r = bounding_rect(contour);
+                r.x += zone.x;
+                r.y += zone.y;
+                r
+            })
+    }
+}
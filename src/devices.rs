@@ -0,0 +1,61 @@
+//! `esp-vision list-cameras`: the cameras that can be opened, to pick the
+//! index for `--input camera:N` on machines with more than one.
+use rust_vision::{CapProp, VideoCapture};
+use std::fs;
+
+/// Name of the subcommand.
+pub const COMMAND: &str = "list-cameras";
+/// Indices probed; opening a missing camera is fast, so this is generous.
+const MAX_INDEX: i32 = 10;
+/// Resolutions tried on each camera.
+const RESOLUTIONS: [(i32, i32); 6] = [(320, 240),
+                                      (640, 480),
+                                      (800, 600),
+                                      (1280, 720),
+                                      (1920, 1080),
+                                      (3840, 2160)];
+
+/// Prints the index, name and resolutions of every camera that opens.
+pub fn list() {
+    let mut found = 0;
+    for index in 0..MAX_INDEX {
+        let cap = VideoCapture::new(index);
+        if !cap.is_open() {
+            continue;
+        }
+        found += 1;
+        let (width, height) = size(&cap);
+        println!("camera:{}  {}",
+                 index,
+                 name(index).unwrap_or_else(|| "(no name)".to_string()));
+        println!("    default {}x{}", width, height);
+        println!("    supports {}", supported(&cap).join(", "));
+    }
+    if found == 0 {
+        println!("No camera found");
+    }
+}
+
+/// The device name, as the kernel reports it. Only known on Linux.
+fn name(index: i32) -> Option<String> {
+    let path = format!("/sys/class/video4linux/video{}/name", index);
+    fs::read_to_string(path).ok().map(|name| name.trim().to_string())
+}
+
+fn size(cap: &VideoCapture) -> (i32, i32) {
+    (cap.get(CapProp::FrameWidth) as i32, cap.get(CapProp::FrameHeight) as i32)
+}
+
+/// The resolutions of `RESOLUTIONS` that the camera accepts. Drivers pick
+/// the closest resolution they have, so what they picked is read back.
+fn supported(cap: &VideoCapture) -> Vec<String> {
+    let mut sizes = Vec::new();
+    for &(width, height) in &RESOLUTIONS {
+        cap.set(CapProp::FrameWidth, f64::from(width));
+        cap.set(CapProp::FrameHeight, f64::from(height));
+        if size(cap) == (width, height) {
+            sizes.push(format!("{}x{}", width, height));
+        }
+    }
+    sizes
+}
@@ -0,0 +1,331 @@
+//! Finding ESP on the local network, with `--discover`.
+//!
+//! Rather than typing its address, ESP can be advertised over mDNS, e.g.
+//! with `avahi-publish-service ESP _esp._tcp 8001` or `dns-sd -R ESP
+//! _esp._tcp local 8001` on its machine. `--discover` browses for the
+//! `_esp._tcp` service, or the one given as `--discover=_gestures._tcp`,
+//! and connects to the first instance that answers. When none does within
+//! a few seconds, `--server` is used as usual.
+//!
+//! Only the one-shot queries of RFC 6762 are sent, from an ephemeral port,
+//! so that no mDNS responder has to run here; responders answer them
+//! directly. Builds without the `net` feature don't browse.
+// Without `net`, only the wire format is left, for the tests.
+#![cfg_attr(not(feature = "net"), allow(dead_code, unused_imports))]
+#[cfg(not(feature = "net"))]
+use crate::net;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// The service browsed for unless `--discover` names another.
+pub const DEFAULT_SERVICE: &str = "_esp._tcp";
+/// How long to wait for answers.
+const TIMEOUT: Duration = Duration::from_secs(3);
+/// The mDNS group and port.
+const MDNS: (Ipv4Addr, u16) = (Ipv4Addr::new(224, 0, 0, 251), 5353);
+/// DNS record types.
+const A: u16 = 1;
+const PTR: u16 = 12;
+const SRV: u16 = 33;
+/// The class of Internet records. In a question, the top bit asks for a
+/// unicast answer.
+const IN: u16 = 1;
+
+/// The address of ESP from the instances of `service` on the network, or
+/// `fallback` if none answers.
+pub fn server(service: &str, fallback: &str) -> String {
+    info!("Looking for {} on the local network", service);
+    match browse(service, TIMEOUT) {
+        Ok(Some((instance, addr))) => {
+            info!("Found {} at {}", instance, addr);
+            addr.to_string()
+        }
+        Ok(None) => {
+            warn!("No {} answered; connecting to {}", service, fallback);
+            fallback.to_string()
+        }
+        Err(e) => {
+            warn!("Cannot look for {}: {}; connecting to {}",
+                  service,
+                  e,
+                  fallback);
+            fallback.to_string()
+        }
+    }
+}
+
+/// Asks for the instances of `service` and returns the name and address of
+/// the first one that answers within `timeout`.
+#[cfg(not(feature = "net"))]
+pub fn browse(_service: &str,
+              _timeout: Duration)
+              -> io::Result<Option<(String, SocketAddr)>> {
+    Err(net::unavailable("mDNS discovery"))
+}
+
+/// Asks for the instances of `service` and returns the name and address of
+/// the first one that answers within `timeout`.
+#[cfg(feature = "net")]
+pub fn browse(service: &str,
+              timeout: Duration)
+              -> io::Result<Option<(String, SocketAddr)>> {
+    let domain = format!("{}.local", service.trim_end_matches('.'));
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.send_to(&query(&domain), MDNS)?;
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0; 9000];
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left == Duration::from_millis(0) {
+            return Ok(None);
+        }
+        socket.set_read_timeout(Some(left))?;
+        let (n, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock ||
+                          e.kind() == io::ErrorKind::TimedOut => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e),
+        };
+        let records = match parse(&buf[..n]) {
+            Some(records) => records,
+            None => {
+                debug!("Ignoring an invalid mDNS answer from {}", from);
+                continue;
+            }
+        };
+        if let Some(found) = resolve(&records, &domain, from.ip()) {
+            return Ok(Some(found));
+        }
+    }
+}
+
+/// A PTR query for `domain`, asking for a unicast answer.
+fn query(domain: &str) -> Vec<u8> {
+    // Id, flags, one question and no records.
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in domain.split('.') {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&PTR.to_be_bytes());
+    packet.extend_from_slice(&(IN | 0x8000).to_be_bytes());
+    packet
+}
+
+/// The parts of a resource record we use.
+#[derive(Clone, Debug, PartialEq)]
+enum Record {
+    /// `name` has an instance called `instance`.
+    Ptr { name: String, instance: String },
+    /// The instance `name` is served on `target`'s `port`.
+    Srv {
+        name: String,
+        target: String,
+        port: u16,
+    },
+    /// `name` has the address `ip`.
+    A { name: String, ip: Ipv4Addr },
+}
+
+/// The address of the first instance of `domain` in `records`. Instances
+/// whose host has no A record are taken to run on `sender`.
+fn resolve(records: &[Record],
+           domain: &str,
+           sender: IpAddr)
+           -> Option<(String, SocketAddr)> {
+    records.iter()
+        .filter_map(|r| match *r {
+            Record::Ptr { ref name, ref instance }
+                if name.eq_ignore_ascii_case(domain) => Some(instance),
+            _ => None,
+        })
+        .filter_map(|instance| {
+            let (target, port) = records.iter()
+                .filter_map(|r| match *r {
+                    Record::Srv { ref name, ref target, port }
+                        if name.eq_ignore_ascii_case(instance) => {
+                        Some((target, port))
+                    }
+                    _ => None,
+                })
+                .next()?;
+            let ip = records.iter()
+                .filter_map(|r| match *r {
+                    Record::A { ref name, ip }
+                        if name.eq_ignore_ascii_case(target) => {
+                        Some(IpAddr::V4(ip))
+                    }
+                    _ => None,
+                })
+                .next()
+                .unwrap_or(sender);
+            Some((instance.clone(), SocketAddr::new(ip, port)))
+        })
+        .next()
+}
+
+/// The PTR, SRV and A records of a DNS message, or `None` if it is not a
+/// valid answer.
+fn parse(packet: &[u8]) -> Option<Vec<Record>> {
+    let u16_at = |i: usize| -> Option<u16> {
+        Some(u16::from_be_bytes([*packet.get(i)?, *packet.get(i + 1)?]))
+    };
+    // Only responses.
+    if u16_at(2)? & 0x8000 == 0 {
+        return None;
+    }
+    let questions = u16_at(4)?;
+    let records = (6..12).step_by(2)
+        .map(|i| u16_at(i).map(usize::from))
+        .sum::<Option<usize>>()?;
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = name(packet, pos)?.1 + 4;
+    }
+    let mut parsed = Vec::new();
+    for _ in 0..records {
+        let (owner, next) = name(packet, pos)?;
+        let kind = u16_at(next)?;
+        let length = usize::from(u16_at(next + 8)?);
+        let data = next + 10;
+        let rdata = packet.get(data..data + length)?;
+        match kind {
+            PTR => {
+                parsed.push(Record::Ptr {
+                    name: owner,
+                    instance: name(packet, data)?.0,
+                })
+            }
+            SRV if length >= 6 => {
+                parsed.push(Record::Srv {
+                    name: owner,
+                    target: name(packet, data + 6)?.0,
+                    port: u16::from_be_bytes([rdata[4], rdata[5]]),
+                })
+            }
+            A if length == 4 => {
+                parsed.push(Record::A {
+                    name: owner,
+                    ip: Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]),
+                })
+            }
+            _ => {}
+        }
+        pos = data + length;
+    }
+    Some(parsed)
+}
+
+/// The (possibly compressed) name at `pos`, and the position after it.
+fn name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    // Where the name ends in the packet, once a pointer was followed.
+    let mut end = None;
+    // Pointers only go backwards, so this many jumps means a loop.
+    let mut jumps = 0;
+    loop {
+        let len = usize::from(*packet.get(pos)?);
+        if len == 0 {
+            let end = end.unwrap_or(pos + 1);
+            return Some((labels.join("."), end));
+        }
+        if len & 0xc0 == 0xc0 {
+            let low = usize::from(*packet.get(pos + 1)?);
+            let target = ((len & 0x3f) << 8) | low;
+            jumps += 1;
+            if target >= pos || jumps > packet.len() {
+                return None;
+            }
+            end = end.or(Some(pos + 2));
+            pos = target;
+            continue;
+        }
+        let label = packet.get(pos + 1..pos + 1 + len)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        pos += 1 + len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use super::{Record, parse, query, resolve};
+
+    /// An answer to `query("_esp._tcp.local")` announcing `ESP` on port
+    /// 8001 of `studio.local` at 192.168.1.10, with compressed names.
+    fn answer() -> Vec<u8> {
+        let mut p = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 2];
+        // 12: _esp._tcp.local PTR ESP._esp._tcp.local
+        p.extend_from_slice(b"\x04_esp\x04_tcp\x05local\x00");
+        p.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0x11, 0x94, 0, 6]);
+        // 39: ESP + pointer to 12
+        p.extend_from_slice(b"\x03ESP\xc0\x0c");
+        // 45: ESP._esp._tcp.local SRV 0 0 8001 studio.local
+        p.extend_from_slice(b"\xc0\x27");
+        p.extend_from_slice(&[0, 33, 0x80, 1, 0, 0, 0, 120, 0, 15]);
+        p.extend_from_slice(&[0, 0, 0, 0, 0x1f, 0x41]);
+        // 63: studio + pointer to local at 22
+        p.extend_from_slice(b"\x06studio\xc0\x16");
+        // studio.local A 192.168.1.10
+        p.extend_from_slice(b"\xc0\x3f");
+        p.extend_from_slice(&[0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4]);
+        p.extend_from_slice(&[192, 168, 1, 10]);
+        p
+    }
+
+    #[test]
+    fn asks_for_the_instances_of_the_service() {
+        assert_eq!(query("_esp._tcp.local"),
+                   b"\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+                     \x04_esp\x04_tcp\x05local\x00\x00\x0c\x80\x01"
+                       .to_vec());
+    }
+
+    #[test]
+    fn reads_compressed_answers() {
+        let records = parse(&answer()).unwrap();
+        assert_eq!(records,
+                   vec![Record::Ptr {
+                            name: "_esp._tcp.local".to_string(),
+                            instance: "ESP._esp._tcp.local".to_string(),
+                        },
+                        Record::Srv {
+                            name: "ESP._esp._tcp.local".to_string(),
+                            target: "studio.local".to_string(),
+                            port: 8001,
+                        },
+                        Record::A {
+                            name: "studio.local".to_string(),
+                            ip: Ipv4Addr::new(192, 168, 1, 10),
+                        }]);
+    }
+
+    #[test]
+    fn resolves_the_instance_to_an_address() {
+        let records = parse(&answer()).unwrap();
+        let sender = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let found = resolve(&records, "_esp._tcp.local", sender);
+        assert_eq!(found,
+                   Some(("ESP._esp._tcp.local".to_string(),
+                         "192.168.1.10:8001".parse::<SocketAddr>().unwrap())));
+        // Without an A record, the instance runs on the sender.
+        let found = resolve(&records[..2], "_esp._tcp.local", sender);
+        assert_eq!(found.unwrap().1, SocketAddr::new(sender, 8001));
+        assert_eq!(resolve(&records, "_other._tcp.local", sender), None);
+    }
+
+    #[test]
+    fn rejects_truncated_and_looping_answers() {
+        let answer = answer();
+        assert_eq!(parse(&answer[..answer.len() - 2]), None);
+        let mut looping = answer.clone();
+        // The owner of the SRV record points at itself.
+        looping[45] = 0xc0;
+        looping[46] = 45;
+        assert_eq!(parse(&looping), None);
+    }
+}
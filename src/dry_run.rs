@@ -0,0 +1,107 @@
+//! Checking the samples without ESP.
+//!
+//! Wiring ESP up is easier once the features are known to be right.
+//! `--dry-run` runs everything as usual but connects to nothing at
+//! `--server`: the samples are printed on stdout instead, one per line in
+//! columns under the names of the dimensions, which are repeated every
+//! `HEADER_EVERY` lines. The other outputs, the log and the dataset still
+//! get the samples.
+//!
+//! `--expect-dims N` declares the dimensions of the ESP pipeline, the `N` of
+//! its `TcpInputStream(8001, N)`. With or without `--dry-run`, the
+//! application doesn't start if it would send another number, counting the
+//! two stamps of `--ascii-stamps`, and a dry run reports any sample of
+//! another length.
+use crate::sample::Sample;
+use crate::sink::Sink;
+use std::io::{self, Write};
+
+/// Lines between repetitions of the names.
+const HEADER_EVERY: usize = 25;
+/// Width of every column.
+const COLUMN: usize = 10;
+
+/// `Console` prints samples on stdout.
+pub struct Console {
+    names: Vec<String>,
+    /// Lines printed since the names.
+    lines: usize,
+    /// Samples of the wrong length so far.
+    mismatches: u64,
+}
+
+impl Console {
+    pub fn new(names: Vec<String>) -> Console {
+        Console {
+            names,
+            lines: 0,
+            mismatches: 0,
+        }
+    }
+
+    /// The names of the dimensions, under `seq` and `time`.
+    fn header(&self) -> String {
+        let mut line = format!("{:>8} {:>9}", "seq", "time");
+        for name in &self.names {
+            line += &format!(" {:>w$}", name, w = COLUMN);
+        }
+        line
+    }
+
+    /// `sample` in columns under the header.
+    fn row(&self, sample: &Sample) -> String {
+        let mut line = format!("{:>8} {:>9.3}", sample.seq, sample.time);
+        for value in &sample.values {
+            line += &format!(" {:>w$.3}", value, w = COLUMN);
+        }
+        line
+    }
+}
+
+impl Sink for Console {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        if sample.values.len() != self.names.len() {
+            self.mismatches += 1;
+            if self.mismatches == 1 {
+                warn!("Sample {} has {} dimensions rather than {}",
+                      sample.seq,
+                      sample.values.len(),
+                      self.names.len());
+            }
+        }
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        if self.lines % HEADER_EVERY == 0 {
+            writeln!(out, "{}", self.header())?;
+        }
+        self.lines += 1;
+        writeln!(out, "{}", self.row(sample))
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        if self.mismatches > 0 {
+            warn!("{} samples didn't have {} dimensions",
+                  self.mismatches,
+                  self.names.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample::Sample;
+    use super::Console;
+
+    #[test]
+    fn prints_samples_in_columns() {
+        let console = Console::new(vec!["x".to_string(), "y".to_string()]);
+        let header = console.header();
+        let row = console.row(&Sample::new(7, 1.5, vec![320.0, f64::NAN]));
+        assert_eq!(header,
+                   "     seq      time          x          y");
+        assert_eq!(row, "       7     1.500    320.000        NaN");
+        assert_eq!(header.len(), row.len());
+    }
+}
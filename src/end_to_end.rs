@@ -0,0 +1,204 @@
+//! End-to-end tests: the whole application tracks a synthetic input and
+//! streams to a `MockEsp`, and the tests check what ESP receives.
+use crate::framed::{Decoder, Frame};
+use crate::mock_esp::MockEsp;
+use crate::serialize::BINARY_MAGIC;
+use crate::synthetic::{Trajectory, WIDTH};
+
+/// The disc of `synthetic:line` at its start, in the mirrored frame.
+const ROI: &str = "550,210,60,60";
+/// How far the sent position may be from the disc's, in pixels.
+const TOLERANCE: f64 = 8.0;
+
+/// Runs the application on `synthetic:line` with `options`, sending to a
+/// new `MockEsp`, which is returned once the run is over.
+fn run(options: &[&str]) -> MockEsp {
+    let esp = MockEsp::start();
+    let addr = esp.addr().to_string();
+    let mut args = vec!["esp-vision",
+                        "--headless",
+                        "--input",
+                        "synthetic:line",
+                        "--speed",
+                        "4",
+                        "--roi",
+                        ROI,
+                        "--server",
+                        &addr,
+                        "--control",
+                        "127.0.0.1:0",
+                        "--prediction",
+                        "127.0.0.1:0"];
+    args.extend_from_slice(options);
+    crate::run(args.iter().map(|a| a.to_string()).collect()).unwrap();
+    esp
+}
+
+/// Number of frames of `synthetic:line`.
+fn frames() -> usize {
+    "line".parse::<Trajectory>().unwrap().frames()
+}
+
+fn values(line: &str) -> Vec<f64> {
+    assert!(line.ends_with(' '), "unterminated values in `{}`", line);
+    line.split_whitespace().map(|v| v.parse().unwrap()).collect()
+}
+
+#[test]
+fn sends_a_line_of_ascii_per_frame() {
+    let lines = run(&[]).finish_lines();
+    assert!(lines.len() + 1 >= frames() && lines.len() <= frames(),
+            "{} samples for {} frames",
+            lines.len(),
+            frames());
+    let positions: Vec<Vec<f64>> = lines.iter().map(|l| values(l)).collect();
+    for p in &positions {
+        assert_eq!(p.len(), 2);
+        assert!((p[1] - 240.0).abs() < TOLERANCE, "y is {}", p[1]);
+    }
+    // The disc goes to the other side of the frame and back.
+    let left = positions.iter().map(|p| p[0]).fold(f64::MAX, f64::min);
+    assert!((positions[0][0] - 580.0).abs() < TOLERANCE);
+    assert!((left - 60.0).abs() < TOLERANCE, "leftmost x is {}", left);
+    assert!(positions.iter().all(|p| p[0] > 0.0 && p[0] < WIDTH as f64));
+}
+
+#[test]
+fn stamps_ascii_lines_with_time_and_sequence() {
+    let lines = run(&["--ascii-stamps"]).finish_lines();
+    let mut last_time = -1.0;
+    for (seq, line) in lines.iter().enumerate() {
+        let v = values(line);
+        assert_eq!(v.len(), 4);
+        assert!(v[2] > last_time);
+        assert_eq!(v[3], seq as f64);
+        last_time = v[2];
+    }
+}
+
+#[test]
+fn sends_json_lines_keyed_by_dimension() {
+    let lines = run(&["--format", "json"]).finish_lines();
+    assert!(!lines.is_empty());
+    for (seq, line) in lines.iter().enumerate() {
+        assert!(line.starts_with("{\"x\":"), "{}", line);
+        assert!(line.contains(",\"y\":"), "{}", line);
+        assert!(line.ends_with(&format!(",\"seq\":{}}}", seq)), "{}", line);
+    }
+}
+
+/// Splits binary frames into their sequence number, time and values.
+fn binary_frames(mut bytes: &[u8]) -> Vec<(u64, f64, Vec<f32>)> {
+    let mut frames = Vec::new();
+    while !bytes.is_empty() {
+        assert_eq!(&bytes[..2], BINARY_MAGIC);
+        let len = u16::from_le_bytes([bytes[2], bytes[3]]) as usize;
+        let payload = &bytes[4..4 + len];
+        let mut seq = [0; 8];
+        seq.copy_from_slice(&payload[..8]);
+        let mut time = [0; 8];
+        time.copy_from_slice(&payload[8..16]);
+        let values = payload[16..]
+            .chunks(4)
+            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+        frames.push((u64::from_le_bytes(seq),
+                     f64::from_le_bytes(time),
+                     values));
+        bytes = &bytes[4 + len..];
+    }
+    frames
+}
+
+#[test]
+fn sends_binary_frames() {
+    let bytes = run(&["--format", "binary"]).finish();
+    let samples = binary_frames(&bytes);
+    assert_eq!(bytes.len(), samples.len() * (4 + 16 + 2 * 4));
+    for (i, &(seq, _, ref values)) in samples.iter().enumerate() {
+        assert_eq!(seq, i as u64);
+        assert_eq!(values.len(), 2);
+    }
+}
+
+#[test]
+fn sends_framed_streams_with_a_header() {
+    let bytes = run(&["--format", "framed", "--normalize", "unit"]).finish();
+    let mut decoder = Decoder::new();
+    decoder.feed(&bytes);
+    let frames: Vec<Frame> = std::iter::from_fn(|| decoder.decode()).collect();
+    match frames[0] {
+        Frame::Header(ref dimensions) => {
+            let names: Vec<(&str, &str)> = dimensions.iter()
+                .map(|d| (d.name.as_str(), d.unit.as_str()))
+                .collect();
+            assert_eq!(names, [("x", "unit"), ("y", "unit")]);
+        }
+        ref frame => panic!("{:?} instead of the header", frame),
+    }
+    assert!(frames.len() > 1);
+    for (seq, frame) in frames[1..].iter().enumerate() {
+        match *frame {
+            Frame::Sample(ref sample) => assert_eq!(sample.seq, seq as u64),
+            ref frame => panic!("a second header: {:?}", frame),
+        }
+    }
+    assert_eq!(decoder.skipped(), 0);
+}
+
+#[test]
+fn limits_the_send_rate() {
+    let bytes = run(&["--format", "binary", "--send-rate", "10"]).finish();
+    let samples = binary_frames(&bytes);
+    // How many samples get through depends on how loaded the machine is;
+    // the spacing of the ticks is tested in `rate`, with given times.
+    assert!(!samples.is_empty());
+    for pair in samples.windows(2) {
+        assert!(pair[1].1 > pair[0].1,
+                "samples at {} and {}",
+                pair[0].1,
+                pair[1].1);
+    }
+}
+
+#[test]
+fn sends_the_beacons_after_the_segment_markers() {
+    let bytes = run(&["--format",
+                      "framed",
+                      "--segment",
+                      "100:20",
+                      "--segment-markers",
+                      "--sync-beacons",
+                      "1"])
+        .finish();
+    let mut decoder = Decoder::new();
+    decoder.feed(&bytes);
+    let frames: Vec<Frame> = std::iter::from_fn(|| decoder.decode()).collect();
+    match frames[0] {
+        Frame::Header(ref dimensions) => {
+            let names: Vec<&str> =
+                dimensions.iter().map(|d| d.name.as_str()).collect();
+            assert_eq!(names, ["x", "y", "segment", "sync"]);
+        }
+        ref frame => panic!("{:?} instead of the header", frame),
+    }
+    let mut beacons = Vec::new();
+    for frame in &frames[1..] {
+        match *frame {
+            Frame::Sample(ref sample) => {
+                let v = &sample.values;
+                assert_eq!(v.len(), 4);
+                assert!([-1.0, 0.0, 1.0].contains(&v[2]), "{:?}", v);
+                if v[3] != 0.0 {
+                    beacons.push(v[3]);
+                }
+            }
+            ref frame => panic!("a second header: {:?}", frame),
+        }
+    }
+    // Beacons count up from the first sample on.
+    assert!(!beacons.is_empty());
+    for (i, &b) in beacons.iter().enumerate() {
+        assert_eq!(b, (i + 1) as f64);
+    }
+}
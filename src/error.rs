@@ -0,0 +1,107 @@
+//! Errors that stop the application, and its exit codes.
+//!
+//! Modules report their errors as strings or `io::Error`s; where one ends
+//! the run, it is wrapped in an `Error` that says what failed and how to fix
+//! it. The exit code tells scripts what kind of failure it was, following
+//! `sysexits.h`:
+//!
+//! | Code | Meaning                                                   |
+//! |------|-----------------------------------------------------------|
+//! | 1    | a subcommand failed                                       |
+//! | 64   | invalid command line or config file                       |
+//! | 65   | a file could be read but not understood, e.g. a profile   |
+//! | 66   | an input could not be opened, or a camera stopped         |
+//! | 69   | ESP, a port or an accelerator is not available            |
+//! | 70   | an internal failure, such as the network runtime          |
+//! | 73   | an output file could not be created                       |
+use crate::input::InputSpec;
+use std::fmt;
+use std::io;
+
+/// Why the application stopped.
+#[derive(Debug)]
+pub enum Error {
+    /// A subcommand failed.
+    Command(String),
+    /// Invalid command line or config file.
+    Usage(String),
+    /// A file that was read is not what it should be.
+    Data(String),
+    /// An input could not be opened.
+    Input(String),
+    /// A live input stopped delivering frames.
+    InputEnded(InputSpec),
+    /// ESP could not be reached at the address.
+    Connect(String, io::Error),
+    /// Nothing could listen on the address; the string names the endpoint.
+    Listen(&'static str, String, io::Error),
+    /// An accelerator or another facility is missing.
+    Unavailable(String),
+    /// Something that should not fail did.
+    Internal(String),
+    /// An output file could not be created.
+    Output(String),
+}
+
+impl Error {
+    /// The exit code of the process.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Command(_) => 1,
+            Error::Usage(_) => 64,
+            Error::Data(_) => 65,
+            Error::Input(_) | Error::InputEnded(_) => 66,
+            Error::Connect(..) | Error::Listen(..) | Error::Unavailable(_) => {
+                69
+            }
+            Error::Internal(_) => 70,
+            Error::Output(_) => 73,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Command(ref e) |
+            Error::Usage(ref e) |
+            Error::Data(ref e) |
+            Error::Input(ref e) |
+            Error::Unavailable(ref e) |
+            Error::Internal(ref e) |
+            Error::Output(ref e) => write!(f, "{}", e),
+            Error::InputEnded(InputSpec::Camera(index)) => {
+                write!(f,
+                       "camera {} stopped delivering frames; was it \
+                        unplugged?",
+                       index)
+            }
+            Error::InputEnded(ref spec) => {
+                write!(f, "the input {} stopped delivering frames", spec)
+            }
+            Error::Connect(ref addr, ref e)
+                if e.kind() == io::ErrorKind::ConnectionRefused => {
+                write!(f,
+                       "ESP is not listening on {}; start a pipeline with \
+                        a TcpInputStream on that port, or pass --server",
+                       addr)
+            }
+            Error::Connect(ref addr, ref e) => {
+                write!(f, "cannot connect to ESP at {}: {}", addr, e)
+            }
+            Error::Listen(what, ref addr, ref e)
+                if e.kind() == io::ErrorKind::AddrInUse => {
+                write!(f,
+                       "{} is in use; is another esp-vision running? If so, \
+                        pass another address with --{}",
+                       addr,
+                       what)
+            }
+            Error::Listen(what, ref addr, ref e) => {
+                write!(f, "cannot listen for --{} on {}: {}", what, addr, e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,289 @@
+//! `esp-vision evaluate TRUTH --input SOURCE`: measures how well every
+//! tracker follows an annotated recording, to pick the backend that suits
+//! a scenario with data rather than by eye.
+//!
+//! `TRUTH` has the rectangle of the target in every frame, one line per
+//! frame, as in the OTB and VOT datasets: `x,y,width,height`, or the four
+//! corners `x1,y1,x2,y2,x3,y3,x4,y4` of a rotated box, whose bounding
+//! rectangle is used. Values may be separated by commas, tabs or spaces.
+//! Frames where the target is out of view are marked with `NaN` values or
+//! an empty rectangle.
+//!
+//! ```text
+//! esp-vision evaluate groundtruth_rect.txt --input images:Basketball/img \
+//!     --trackers camshift,kcf,csrt
+//! ```
+//!
+//! Every tracker starts from the first annotated rectangle. As in VOT, when
+//! the overlap with the truth drops to `--min-iou` or below, the tracker
+//! counts a failure and starts again from the truth a few frames later. For
+//! every tracker, the mean intersection over union and the mean distance
+//! between the centers are printed, over the frames it tracked, with the
+//! failures per hundred frames and the frame rate.
+use crate::input::{CaptureSettings, Input, InputSpec, Playback};
+use crate::params::VisionParams;
+use crate::tracker::{Backend, TrackerOptions};
+use getopts::Options;
+use rust_vision::{Mat, Rect};
+use std::fs;
+use std::time::Instant;
+
+/// Name of the subcommand.
+pub const COMMAND: &str = "evaluate";
+/// Trackers evaluated unless `--trackers` says otherwise: those started
+/// from a selection that need no model.
+const DEFAULT_TRACKERS: &str = "camshift,kcf,csrt,mosse,lk,template";
+/// Frames skipped after a failure before starting again, as in VOT.
+const SKIP_AFTER_FAILURE: usize = 5;
+
+/// Runs the subcommand; `args` is the whole command line.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optopt("",
+                "input",
+                "the annotated recording: video:PATH or images:DIR",
+                "SOURCE");
+    opts.optopt("",
+                "trackers",
+                &format!("comma-separated trackers to evaluate ({})",
+                         DEFAULT_TRACKERS),
+                "LIST");
+    opts.optopt("",
+                "min-iou",
+                "overlap at or below which tracking failed (0)",
+                "IOU");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[2..]).map_err(|e| e.to_string())?;
+    let brief = format!("Usage: {} {} TRUTH --input SOURCE [options]",
+                        args[0],
+                        COMMAND);
+    if matches.opt_present("h") {
+        print!("{}", opts.usage(&brief));
+        return Ok(());
+    }
+    if matches.free.len() != 1 {
+        return Err(opts.usage(&brief));
+    }
+    let spec: InputSpec = matches.opt_str("input")
+        .ok_or_else(|| opts.usage(&brief))?
+        .parse()?;
+    let trackers = matches.opt_str("trackers")
+        .unwrap_or_else(|| DEFAULT_TRACKERS.to_string())
+        .split(',')
+        .map(str::parse)
+        .collect::<Result<Vec<Backend>, String>>()?;
+    let min_iou = match matches.opt_str("min-iou") {
+        Some(iou) => {
+            match iou.parse::<f64>() {
+                Ok(iou) if (0.0..1.0).contains(&iou) => iou,
+                _ => return Err(format!("invalid --min-iou `{}`", iou)),
+            }
+        }
+        None => 0.0,
+    };
+    let truth = load_truth(&matches.free[0])?;
+
+    println!("{:>10} {:>9} {:>13} {:>9} {:>10} {:>8}",
+             "tracker",
+             "mean IoU",
+             "center error",
+             "failures",
+             "per 100",
+             "fps");
+    for backend in trackers {
+        let score = evaluate(backend, &spec, &truth, min_iou)?;
+        println!("{:>10} {:>9.3} {:>10.1} px {:>9} {:>10.2} {:>8.1}",
+                 backend.to_string(),
+                 score.iou,
+                 score.center_error,
+                 score.failures,
+                 100.0 * score.failures as f64 / score.frames as f64,
+                 score.frames as f64 / score.seconds);
+    }
+    Ok(())
+}
+
+/// How a tracker did on a recording.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Score {
+    /// Mean intersection over union, over the frames tracked.
+    pub iou: f64,
+    /// Mean distance between the centers, in pixels.
+    pub center_error: f64,
+    pub failures: usize,
+    /// Frames compared with the truth.
+    pub frames: usize,
+    /// Time spent tracking.
+    pub seconds: f64,
+}
+
+/// Tracks the input of `spec` with `backend` and compares with `truth`,
+/// starting over after each failure.
+fn evaluate(backend: Backend,
+            spec: &InputSpec,
+            truth: &[Option<Rect>],
+            min_iou: f64)
+            -> Result<Score, String> {
+    let playback = Playback {
+        paced: false,
+        ..Playback::default()
+    };
+    let mut input = Input::open(spec, &playback, &CaptureSettings::default())?;
+    if input.is_live() {
+        return Err(format!("{} needs recorded --input, e.g. a video",
+                           COMMAND));
+    }
+    let params = VisionParams::default();
+    let mut tracker = backend.create(&TrackerOptions::default());
+    let mut score = Score::default();
+    let mut initialized = false;
+    // Frames left before starting again after a failure.
+    let mut skip = 0;
+    let mut tracking = 0.0;
+    for expected in truth {
+        let frame = Mat::new();
+        if !input.read(&frame) {
+            break;
+        }
+        let expected = match *expected {
+            Some(rect) => rect,
+            None => continue,
+        };
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        let start = Instant::now();
+        if !initialized {
+            tracker.init(&frame, expected, &params);
+            initialized = true;
+            tracking += start.elapsed().as_secs_f64();
+            continue;
+        }
+        let track = tracker.update(&frame, &params);
+        tracking += start.elapsed().as_secs_f64();
+        let r = &track.region;
+        let found = Rect::new((r.cx - r.width / 2.0).round() as i32,
+                              (r.cy - r.height / 2.0).round() as i32,
+                              r.width.round() as i32,
+                              r.height.round() as i32);
+        let overlap = iou(found, expected);
+        score.frames += 1;
+        if overlap <= min_iou {
+            score.failures += 1;
+            initialized = false;
+            skip = SKIP_AFTER_FAILURE;
+            continue;
+        }
+        score.iou += overlap;
+        score.center_error += center_distance(found, expected);
+    }
+    if score.frames == 0 {
+        return Err("no frame of the input was annotated after the first"
+            .to_string());
+    }
+    let tracked = (score.frames - score.failures).max(1) as f64;
+    score.iou /= tracked;
+    score.center_error /= tracked;
+    score.seconds = tracking;
+    Ok(score)
+}
+
+/// Reads one rectangle per line, `None` where the target is out of view.
+fn load_truth(path: &str) -> Result<Vec<Option<Rect>>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let truth = text.lines()
+        .enumerate()
+        .filter(|&(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            parse_rect(line)
+                .map_err(|e| format!("{}:{}: {}", path, i + 1, e))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    match truth.first() {
+        Some(Some(_)) => Ok(truth),
+        _ => Err(format!("{} must start with the rectangle of the target",
+                         path)),
+    }
+}
+
+/// Parses `x,y,w,h` or the corners of a rotated box.
+fn parse_rect(line: &str) -> Result<Option<Rect>, String> {
+    let v = line.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.parse::<f64>().map_err(|_| format!("invalid value `{}`", v))
+        })
+        .collect::<Result<Vec<f64>, String>>()?;
+    if v.iter().any(|v| v.is_nan()) {
+        return Ok(None);
+    }
+    let (x0, y0, x1, y1) = match v.len() {
+        4 => (v[0], v[1], v[0] + v[2], v[1] + v[3]),
+        8 => {
+            let xs = [v[0], v[2], v[4], v[6]];
+            let ys = [v[1], v[3], v[5], v[7]];
+            (xs.iter().cloned().fold(f64::MAX, f64::min),
+             ys.iter().cloned().fold(f64::MAX, f64::min),
+             xs.iter().cloned().fold(f64::MIN, f64::max),
+             ys.iter().cloned().fold(f64::MIN, f64::max))
+        }
+        n => return Err(format!("expected 4 or 8 values, got {}", n)),
+    };
+    let rect = Rect::new(x0.round() as i32,
+                         y0.round() as i32,
+                         (x1 - x0).round() as i32,
+                         (y1 - y0).round() as i32);
+    if rect.width <= 0 || rect.height <= 0 {
+        Ok(None)
+    } else {
+        Ok(Some(rect))
+    }
+}
+
+/// Intersection over union of two rectangles.
+fn iou(a: Rect, b: Rect) -> f64 {
+    let w = (a.x + a.width).min(b.x + b.width) - a.x.max(b.x);
+    let h = (a.y + a.height).min(b.y + b.height) - a.y.max(b.y);
+    if w <= 0 || h <= 0 {
+        return 0.0;
+    }
+    let intersection = (w * h) as f64;
+    let union = (a.width * a.height + b.width * b.height) as f64 -
+                intersection;
+    intersection / union
+}
+
+fn center_distance(a: Rect, b: Rect) -> f64 {
+    let dx = (a.x * 2 + a.width - b.x * 2 - b.width) as f64 / 2.0;
+    let dy = (a.y * 2 + a.height - b.y * 2 - b.height) as f64 / 2.0;
+    dx.hypot(dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_vision::Rect;
+    use super::{iou, parse_rect};
+
+    #[test]
+    fn reads_otb_and_vot_rectangles() {
+        assert_eq!(parse_rect("10,20,30,40"),
+                   Ok(Some(Rect::new(10, 20, 30, 40))));
+        assert_eq!(parse_rect("10\t20  30 40"),
+                   Ok(Some(Rect::new(10, 20, 30, 40))));
+        assert_eq!(parse_rect("20,10,40,20,30,50,10,40"),
+                   Ok(Some(Rect::new(10, 10, 30, 40))));
+        assert_eq!(parse_rect("NaN,NaN,NaN,NaN"), Ok(None));
+        assert_eq!(parse_rect("0,0,0,0"), Ok(None));
+        assert!(parse_rect("1,2,3").is_err());
+    }
+
+    #[test]
+    fn measures_the_overlap() {
+        let a = Rect::new(0, 0, 10, 10);
+        assert_eq!(iou(a, a), 1.0);
+        assert_eq!(iou(a, Rect::new(5, 0, 10, 10)), 50.0 / 150.0);
+        assert_eq!(iou(a, Rect::new(20, 20, 10, 10)), 0.0);
+    }
+}
@@ -0,0 +1,78 @@
+//! `--log-features FILE`: the samples, with the state of the tracker, as CSV
+//! for offline analysis, e.g. with pandas.
+//!
+//! The header names the columns: `time`, `seq`, the dimensions sent to ESP,
+//! then in the track mode the `confidence` and `state` of every target
+//! (`confidence1`, `state1`, ... with several targets). Values missing
+//! because a target is lost are `NaN`.
+use crate::loss::TrackState;
+use crate::sample::Sample;
+use crate::sink::Sink;
+use std::cell::RefCell;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufWriter};
+use std::rc::Rc;
+
+/// The confidence and state of every target for the next sample; `None`
+/// for targets that are not selected yet.
+pub type Status = Rc<RefCell<Vec<Option<(f64, TrackState)>>>>;
+
+/// `FeatureLog` writes the samples it is sent to a CSV file.
+pub struct FeatureLog {
+    writer: BufWriter<File>,
+    targets: usize,
+    status: Status,
+    line: String,
+}
+
+impl FeatureLog {
+    /// Creates `path` for samples whose dimensions are called `names`,
+    /// followed by the status of `targets` targets.
+    pub fn create(path: &str,
+                  names: &[String],
+                  targets: usize,
+                  status: Status)
+                  -> io::Result<FeatureLog> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let mut header = vec!["time".to_string(), "seq".to_string()];
+        header.extend(names.iter().cloned());
+        for i in 1..targets + 1 {
+            let suffix = if targets == 1 {
+                String::new()
+            } else {
+                i.to_string()
+            };
+            header.push(format!("confidence{}", suffix));
+            header.push(format!("state{}", suffix));
+        }
+        writeln!(writer, "{}", header.join(","))?;
+        Ok(FeatureLog {
+            writer,
+            targets,
+            status,
+            line: String::new(),
+        })
+    }
+}
+
+impl Sink for FeatureLog {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        self.line.clear();
+        write!(self.line, "{:.6},{}", sample.time, sample.seq).unwrap();
+        for v in &sample.values {
+            write!(self.line, ",{}", v).unwrap();
+        }
+        let status = self.status.borrow();
+        for i in 0..self.targets {
+            match status.get(i).cloned().and_then(|s| s) {
+                Some((confidence, state)) => {
+                    write!(self.line, ",{},{}", confidence, state).unwrap()
+                }
+                None => self.line.push_str(",NaN,"),
+            }
+        }
+        writeln!(self.writer, "{}", self.line)
+    }
+}
@@ -0,0 +1,164 @@
+//! `esp-vision replay-features FILE`: sends the samples of a
+//! `--log-features` file to ESP again, without a camera, e.g. to retrain a
+//! model offline.
+//!
+//! Samples are sent with their recorded timing, or at `--rate` samples per
+//! second. Only the dimensions are sent, not the status of the targets.
+use crate::config::Config;
+use crate::net::Net;
+use crate::pipeline::Timings;
+use crate::rate;
+use crate::sample::Sample;
+use crate::serialize::Format;
+use crate::sink::{Sink, TcpSink};
+use getopts::Options;
+use std::fs::File;
+use std::io::{self, prelude::*};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Name of the subcommand.
+pub const COMMAND: &str = "replay-features";
+/// Time to wait for room in the send queue.
+const QUEUE_FULL_DELAY: Duration = Duration::from_millis(1);
+
+/// Runs the subcommand; `args` is the whole command line.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut opts = Options::new();
+    opts.optopt("",
+                "server",
+                "address of ESP's TcpInputStream (127.0.0.1:8001)",
+                "ADDR");
+    opts.optopt("",
+                "rate",
+                "samples per second, instead of the recorded timing",
+                "HZ");
+    opts.optopt("",
+                "format",
+                "wire format of the ESP sink: ascii, json, binary or framed \
+                 (ascii)",
+                "FORMAT");
+    opts.optflag("",
+                 "ascii-stamps",
+                 "append the time and sequence number to ASCII samples");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = opts.parse(&args[2..]).map_err(|e| e.to_string())?;
+    let brief = format!("Usage: {} {} FILE [options]", args[0], COMMAND);
+    if matches.opt_present("h") {
+        print!("{}", opts.usage(&brief));
+        return Ok(());
+    }
+    if matches.free.len() != 1 {
+        return Err(opts.usage(&brief));
+    }
+    let rate = match matches.opt_str("rate") {
+        Some(rate) => {
+            match rate.parse::<f64>() {
+                Ok(hz) => Some(rate::check("rate", hz)?),
+                _ => return Err(format!("invalid rate `{}`", rate)),
+            }
+        }
+        None => None,
+    };
+    let format: Format = match matches.opt_str("format") {
+        Some(format) => format.parse()?,
+        None => Format::Ascii,
+    };
+    let server = matches.opt_str("server")
+        .unwrap_or_else(|| Config::default().server);
+
+    let (names, samples) = load(&matches.free[0])?;
+    info!("Sending {} samples of {} dimensions to ESP: {}",
+          samples.len(),
+          names.len(),
+          names.join(" "));
+
+    // The log doesn't say what the values are measured in.
+    let serializer = format.serializer(names,
+                                       Vec::new(),
+                                       matches.opt_present("ascii-stamps"));
+    let net = Net::new().map_err(|e| e.to_string())?;
+    let mut sink = TcpSink::connect(&net,
+                                    server.as_str(),
+                                    serializer,
+                                    Timings::default(),
+                                    None)
+        .map_err(|e| format!("cannot connect to {}: {}", server, e))?;
+    let start = Instant::now();
+    let first = samples.first().map_or(0.0, |s| s.time);
+    for (i, sample) in samples.iter().enumerate() {
+        let due = match rate {
+            Some(hz) => Duration::from_secs_f64(i as f64 / hz),
+            None => Duration::from_secs_f64((sample.time - first).max(0.0)),
+        };
+        let elapsed = start.elapsed();
+        if due > elapsed {
+            thread::sleep(due - elapsed);
+        }
+        // Every sample is sent; wait for room rather than drop it.
+        loop {
+            match sink.send(sample) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(QUEUE_FULL_DELAY)
+                }
+                result => {
+                    result.map_err(|e| format!("cannot send: {}", e))?;
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The names of the dimensions of the `--log-features` file at `path`, and
+/// its samples.
+pub fn load(path: &str) -> Result<(Vec<String>, Vec<Sample>), String> {
+    let mut text = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let mut lines = text.lines();
+    let header: Vec<String> = lines.next()
+        .ok_or_else(|| format!("{} is empty", path))?
+        .split(',')
+        .map(|c| c.to_string())
+        .collect();
+    let names = dimension_names(&header)
+        .ok_or_else(|| format!("{} is not a --log-features file", path))?;
+    let mut samples = Vec::new();
+    for (n, line) in lines.enumerate() {
+        let sample = parse(line, names.len())
+            .ok_or_else(|| format!("{}:{}: invalid sample", path, n + 2))?;
+        samples.push(sample);
+    }
+    Ok((names, samples))
+}
+
+/// The dimensions in `header`: the columns after `time` and `seq`, and
+/// before the status of the targets.
+fn dimension_names(header: &[String]) -> Option<Vec<String>> {
+    if header.len() < 2 || header[0] != "time" || header[1] != "seq" {
+        return None;
+    }
+    let mut end = header.len();
+    while end >= 4 && header[end - 2].starts_with("confidence") &&
+          header[end - 1].starts_with("state") {
+        end -= 2;
+    }
+    Some(header[2..end].to_vec())
+}
+
+/// The sample of a line, with its first `dims` values.
+fn parse(line: &str, dims: usize) -> Option<Sample> {
+    let mut columns = line.split(',');
+    let time = columns.next()?.parse().ok().filter(|t: &f64| t.is_finite())?;
+    let seq = columns.next()?.parse().ok()?;
+    let values = columns.take(dims)
+        .map(|c| c.parse().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    if values.len() != dims {
+        return None;
+    }
+    Some(Sample::new(seq, time, values))
+}
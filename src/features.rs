@@ -0,0 +1,297 @@
+//! Features extracted from the tracked region and sent to ESP.
+use crate::normalize::Unit;
+use rust_vision::{Point2f, Rect, RotatedRect};
+use std::str::FromStr;
+
+/// The features `ellipse` stands for: the center, axes and rotation of the
+/// CAMShift ellipse.
+const ELLIPSE: [Feature; 5] = [Feature::X,
+                               Feature::Y,
+                               Feature::EllipseWidth,
+                               Feature::EllipseHeight,
+                               Feature::Angle];
+
+/// A single dimension of the outgoing feature vector.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feature {
+    /// Horizontal centroid of the tracked region.
+    X,
+    /// Vertical centroid of the tracked region.
+    Y,
+    /// Area of the bounding box.
+    Area,
+    /// Square root of the area. It grows linearly as the target approaches
+    /// the camera, which makes it a cheap proxy for depth.
+    Size,
+    /// Rotation of the CAMShift ellipse, in degrees.
+    Angle,
+    /// Ratio of the ellipse's width to its height.
+    Aspect,
+    /// Width of the CAMShift ellipse, along its axis rotated by `angle`.
+    EllipseWidth,
+    /// Height of the CAMShift ellipse, across that axis.
+    EllipseHeight,
+}
+
+impl Feature {
+    /// The name used on the command line and in ESP dimension labels.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Feature::X => "x",
+            Feature::Y => "y",
+            Feature::Area => "area",
+            Feature::Size => "size",
+            Feature::Angle => "angle",
+            Feature::Aspect => "aspect",
+            Feature::EllipseWidth => "ellipse_width",
+            Feature::EllipseHeight => "ellipse_height",
+        }
+    }
+
+    /// How the feature scales with the frame.
+    pub fn unit(&self) -> Unit {
+        match *self {
+            Feature::X => Unit::X,
+            Feature::Y => Unit::Y,
+            Feature::Area => Unit::Area,
+            Feature::Size | Feature::EllipseWidth | Feature::EllipseHeight => {
+                Unit::Length
+            }
+            Feature::Angle => Unit::Angle,
+            Feature::Aspect => Unit::None,
+        }
+    }
+}
+
+impl FromStr for Feature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Feature, String> {
+        match s {
+            "x" => Ok(Feature::X),
+            "y" => Ok(Feature::Y),
+            "area" => Ok(Feature::Area),
+            "size" => Ok(Feature::Size),
+            "angle" => Ok(Feature::Angle),
+            "aspect" => Ok(Feature::Aspect),
+            "ellipse_width" => Ok(Feature::EllipseWidth),
+            "ellipse_height" => Ok(Feature::EllipseHeight),
+            _ => Err(format!("unknown feature `{}`", s)),
+        }
+    }
+}
+
+/// `FeatureSet` is the ordered list of features sent for every frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FeatureSet {
+    features: Vec<Feature>,
+}
+
+impl Default for FeatureSet {
+    fn default() -> FeatureSet {
+        FeatureSet::with_dims(2).unwrap()
+    }
+}
+
+impl FeatureSet {
+    /// Creates a feature set from an explicit list.
+    pub fn new(features: Vec<Feature>) -> FeatureSet {
+        FeatureSet { features }
+    }
+
+    /// The conventional feature set for `dims` dimensions: `x`, `x y` or
+    /// `x y size`.
+    pub fn with_dims(dims: usize) -> Result<FeatureSet, String> {
+        let all = [Feature::X, Feature::Y, Feature::Size];
+        if dims == 0 || dims > all.len() {
+            return Err(format!("no default feature set for {} dimensions, \
+                                use --features instead",
+                               dims));
+        }
+        Ok(FeatureSet::new(all[..dims].to_vec()))
+    }
+
+    /// Number of dimensions, i.e. what ESP's `TcpInputStream` should expect.
+    pub fn dims(&self) -> usize {
+        self.features.len()
+    }
+
+    pub fn features(&self) -> &[Feature] {
+        &self.features
+    }
+
+    /// Whether the size of the ellipse is sent, in which case the preview
+    /// draws the ellipse rather than its bounding box.
+    pub fn has_ellipse(&self) -> bool {
+        self.features.iter().any(|f| {
+            matches!(*f, Feature::EllipseWidth | Feature::EllipseHeight)
+        })
+    }
+
+    /// Computes the feature vector for the tracked region.
+    pub fn extract(&self, region: &Region) -> Vec<f64> {
+        let area = region.width * region.height;
+        self.features
+            .iter()
+            .map(|f| match *f {
+                Feature::X => region.cx,
+                Feature::Y => region.cy,
+                Feature::Area => area,
+                Feature::Size => area.sqrt(),
+                Feature::Angle => region.angle,
+                Feature::Aspect => region.aspect,
+                Feature::EllipseWidth => region.axes[0],
+                Feature::EllipseHeight => region.axes[1],
+            })
+            .collect()
+    }
+
+    /// Concatenates the feature vectors of several regions. Missing regions
+    /// are sent as NaN so that the dimensions stay fixed.
+    pub fn extract_all(&self, regions: &[Option<Region>]) -> Vec<f64> {
+        let mut values = Vec::with_capacity(regions.len() * self.dims());
+        for region in regions {
+            match *region {
+                Some(ref region) => values.extend(self.extract(region)),
+                None => values.extend(vec![f64::NAN; self.dims()]),
+            }
+        }
+        values
+    }
+}
+
+/// The coordinates of the first `n` points, `x y` each. Points that are no
+/// longer tracked are sent as NaN so that the dimensions stay fixed.
+pub fn point_values(points: &[Point2f], n: usize) -> Vec<f64> {
+    (0..n)
+        .flat_map(|i| match points.get(i) {
+            Some(p) => vec![p.x as f64, p.y as f64],
+            None => vec![f64::NAN, f64::NAN],
+        })
+        .collect()
+}
+
+impl FromStr for FeatureSet {
+    type Err = String;
+
+    /// Parses a comma-separated list such as `x,y,size`. `ellipse` stands
+    /// for the whole CAMShift ellipse: `x,y,ellipse_width,ellipse_height,
+    /// angle`.
+    fn from_str(s: &str) -> Result<FeatureSet, String> {
+        let mut features = Vec::new();
+        for f in s.split(',').map(str::trim) {
+            if f == "ellipse" {
+                features.extend_from_slice(&ELLIPSE);
+            } else {
+                features.push(f.parse()?);
+            }
+        }
+        Ok(FeatureSet::new(features))
+    }
+}
+
+/// `Region` is the geometry of the tracked region that features are computed
+/// from. Filters operate on it before the features are extracted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Region {
+    /// Centroid of the bounding box, in pixels.
+    pub cx: f64,
+    pub cy: f64,
+    /// Size of the bounding box, in pixels.
+    pub width: f64,
+    pub height: f64,
+    /// Rotation of the CAMShift ellipse, in degrees.
+    pub angle: f64,
+    /// Width over height of the ellipse, `0` for a degenerate one.
+    pub aspect: f64,
+    /// Width and height of the ellipse before its rotation, in pixels; those
+    /// of the bounding box for trackers that don't estimate rotation.
+    pub axes: [f64; 2],
+}
+
+impl Region {
+    pub fn from_box(track_box: &RotatedRect) -> Region {
+        let bounding: Rect = track_box.bounding_rect();
+        let aspect = if track_box.size.height > 0.0 {
+            (track_box.size.width / track_box.size.height) as f64
+        } else {
+            0.0
+        };
+        Region {
+            cx: (bounding.x + bounding.width / 2) as f64,
+            cy: (bounding.y + bounding.height / 2) as f64,
+            width: bounding.width as f64,
+            height: bounding.height as f64,
+            angle: track_box.angle as f64,
+            aspect,
+            axes: [track_box.size.width as f64, track_box.size.height as f64],
+        }
+    }
+
+    /// The region of an axis-aligned box, for trackers that don't estimate
+    /// rotation.
+    pub fn from_rect(rect: Rect) -> Region {
+        Region {
+            cx: rect.x as f64 + rect.width as f64 / 2.0,
+            cy: rect.y as f64 + rect.height as f64 / 2.0,
+            width: rect.width as f64,
+            height: rect.height as f64,
+            angle: 0.0,
+            aspect: if rect.height > 0 {
+                rect.width as f64 / rect.height as f64
+            } else {
+                0.0
+            },
+            axes: [rect.width as f64, rect.height as f64],
+        }
+    }
+
+    pub fn centroid(&self) -> [f64; 2] {
+        [self.cx, self.cy]
+    }
+
+    /// The bounding box, rounded to whole pixels.
+    pub fn bounding_rect(&self) -> Rect {
+        Rect::new((self.cx - self.width / 2.0).round() as i32,
+                  (self.cy - self.height / 2.0).round() as i32,
+                  self.width.round() as i32,
+                  self.height.round() as i32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_vision::Rect;
+    use super::{Feature, FeatureSet, Region};
+
+    #[test]
+    fn expands_the_ellipse() {
+        let set: FeatureSet = "size, ellipse".parse().unwrap();
+        assert_eq!(set.features(),
+                   [Feature::Size,
+                    Feature::X,
+                    Feature::Y,
+                    Feature::EllipseWidth,
+                    Feature::EllipseHeight,
+                    Feature::Angle]);
+        assert!(set.has_ellipse());
+        assert!("x,depth".parse::<FeatureSet>().is_err());
+        assert!(FeatureSet::with_dims(4).is_err());
+    }
+
+    #[test]
+    fn extracts_the_features_of_a_region() {
+        let set: FeatureSet = "x,y,area,size,aspect,angle".parse().unwrap();
+        let region = Region::from_rect(Rect::new(10, 20, 40, 10));
+        assert_eq!(set.extract(&region), [30.0, 25.0, 400.0, 20.0, 4.0, 0.0]);
+    }
+
+    #[test]
+    fn sends_missing_regions_as_nan() {
+        let set = FeatureSet::with_dims(2).unwrap();
+        let region = Region::from_rect(Rect::new(0, 0, 4, 2));
+        let values = set.extract_all(&[None, Some(region)]);
+        assert!(values[0].is_nan() && values[1].is_nan());
+        assert_eq!(values[2..], [2.0, 1.0]);
+    }
+}
@@ -0,0 +1,76 @@
+//! Sounds and desktop notifications for predictions, for eyes-free use.
+//!
+//! Every new prediction plays the `sound` of its class, from `[[labels]]` in
+//! the config file, with the command of `--sound-player` (`afplay` on macOS,
+//! `paplay` elsewhere). With `--notify`, it also pops up a desktop
+//! notification, with `notify-send` on Linux and `osascript` on macOS.
+//! Both run in the background; a missing command is logged and otherwise
+//! ignored.
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// The sound player unless `--sound-player` names another.
+pub const DEFAULT_PLAYER: &str = if cfg!(target_os = "macos") {
+    "afplay"
+} else {
+    "paplay"
+};
+
+/// `Feedback` announces predictions.
+pub struct Feedback {
+    player: String,
+    notify: bool,
+}
+
+impl Feedback {
+    /// Plays sounds with `player`, and shows notifications if `notify`.
+    pub fn new(player: String, notify: bool) -> Feedback {
+        Feedback { player, notify }
+    }
+
+    /// Announces the prediction of `name`, with `sound` if it has one.
+    pub fn announce(&self, name: &str, sound: Option<&str>) {
+        if let Some(sound) = sound {
+            let mut play = Command::new(&self.player);
+            play.arg(sound);
+            run(play);
+        }
+        if self.notify {
+            if let Some(notification) = notification(name) {
+                run(notification);
+            }
+        }
+    }
+}
+
+/// The command showing a notification of `name`, if this platform has one.
+fn notification(name: &str) -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+        command.arg("-e").arg(format!("display notification \"{}\" with \
+                                       title \"esp-vision\"",
+                                      name));
+        Some(command)
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command.arg("esp-vision").arg(name);
+        Some(command)
+    } else {
+        None
+    }
+}
+
+/// Starts `command` and reaps it in the background.
+fn run(mut command: Command) {
+    let started = command.stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    match started {
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        }
+        Err(e) => warn!("Cannot run {:?}: {}", command, e),
+    }
+}
@@ -0,0 +1,360 @@
+//! Smoothing filters for the tracked region.
+//!
+//! A `Filter` smooths a fixed number of channels, one measurement at a time.
+//! Filters are stacked into a `Chain` that runs over the centroid and the
+//! size of the tracked `Region` before features are extracted. The chain is
+//! configured from the `[[filters]]` tables of the config file, in order:
+//!
+//! ```toml
+//! [[filters]]
+//! type = "median"
+//! window = 5
+//!
+//! [[filters]]
+//! type = "ema"
+//! alpha = 0.4
+//! ```
+use crate::features::Region;
+use crate::kalman::{self, KalmanFilter};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// `Filter` smooths a stream of measurements.
+pub trait Filter: Send {
+    /// Filters the measurement taken at `t` in place. `values` has the same
+    /// length on every call until the next `reset`.
+    fn apply(&mut self, t: Instant, values: &mut [f64]);
+
+    /// Forgets the history, e.g. when a new region is selected.
+    fn reset(&mut self);
+}
+
+/// The configuration of a filter, as written in the config file.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum FilterSpec {
+    /// Constant-velocity Kalman filter, see the [`kalman`](../kalman/)
+    /// module.
+    Kalman {
+        #[serde(default = "default_process_noise")]
+        process_noise: f64,
+        #[serde(default = "default_measurement_noise")]
+        measurement_noise: f64,
+    },
+    /// Exponential moving average; `alpha` in `(0, 1]` is the weight of the
+    /// newest measurement.
+    Ema { alpha: f64 },
+    /// Median of the last `window` measurements, against single-frame spikes.
+    Median { window: usize },
+    /// Savitzky–Golay: the value of a polynomial of degree `order` fitted to
+    /// the last `window` measurements.
+    #[serde(rename = "savgol")]
+    SavitzkyGolay { window: usize, order: usize },
+}
+
+fn default_process_noise() -> f64 {
+    kalman::DEFAULT_PROCESS_NOISE
+}
+
+fn default_measurement_noise() -> f64 {
+    kalman::DEFAULT_MEASUREMENT_NOISE
+}
+
+impl FilterSpec {
+    /// Validates the parameters and creates the filter.
+    pub fn build(&self) -> Result<Box<dyn Filter>, String> {
+        match *self {
+            FilterSpec::Kalman { process_noise, measurement_noise } => {
+                if !(process_noise > 0.0 && measurement_noise > 0.0) {
+                    return Err("kalman: noises must be positive".to_string());
+                }
+                Ok(Box::new(KalmanFilter::new(process_noise,
+                                              measurement_noise)))
+            }
+            FilterSpec::Ema { alpha } => {
+                if !(alpha > 0.0 && alpha <= 1.0) {
+                    return Err(format!("ema: alpha must be in (0, 1], got {}",
+                                       alpha));
+                }
+                Ok(Box::new(Ema::new(alpha)))
+            }
+            FilterSpec::Median { window } => {
+                if window == 0 {
+                    return Err("median: window must not be 0".to_string());
+                }
+                Ok(Box::new(Median::new(window)))
+            }
+            FilterSpec::SavitzkyGolay { window, order } => {
+                if order >= window {
+                    return Err(format!("savgol: order ({}) must be smaller \
+                                        than window ({})",
+                                       order,
+                                       window));
+                }
+                Ok(Box::new(SavitzkyGolay::new(window, order)))
+            }
+        }
+    }
+}
+
+/// `Chain` runs filters one after the other over a `Region`.
+pub struct Chain {
+    filters: Vec<Box<dyn Filter>>,
+}
+
+impl Chain {
+    pub fn new(specs: &[FilterSpec]) -> Result<Chain, String> {
+        let filters = specs.iter()
+            .map(|s| s.build())
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Chain { filters })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        for f in &mut self.filters {
+            f.reset();
+        }
+    }
+
+    /// Smooths the centroid and size of the region measured at `t`. The
+    /// angle and aspect ratio are passed through untouched.
+    pub fn filter(&mut self, t: Instant, measured: Region) -> Region {
+        let mut values =
+            [measured.cx, measured.cy, measured.width, measured.height];
+        for f in &mut self.filters {
+            f.apply(t, &mut values);
+        }
+        Region {
+            cx: values[0],
+            cy: values[1],
+            width: values[2].max(0.0),
+            height: values[3].max(0.0),
+            ..measured
+        }
+    }
+}
+
+/// Exponential moving average.
+pub struct Ema {
+    alpha: f64,
+    state: Vec<f64>,
+}
+
+impl Ema {
+    pub fn new(alpha: f64) -> Ema {
+        Ema {
+            alpha,
+            state: Vec::new(),
+        }
+    }
+}
+
+impl Filter for Ema {
+    fn apply(&mut self, _: Instant, values: &mut [f64]) {
+        if self.state.is_empty() {
+            self.state = values.to_vec();
+            return;
+        }
+        for (s, v) in self.state.iter_mut().zip(values.iter_mut()) {
+            *s += self.alpha * (*v - *s);
+            *v = *s;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state.clear();
+    }
+}
+
+/// Sliding-window median. Values that are not finite, e.g. of a lost
+/// target, are left out of it.
+pub struct Median {
+    window: usize,
+    history: VecDeque<Vec<f64>>,
+}
+
+impl Median {
+    pub fn new(window: usize) -> Median {
+        Median {
+            window,
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl Filter for Median {
+    fn apply(&mut self, _: Instant, values: &mut [f64]) {
+        if self.history.len() == self.window {
+            self.history.pop_front();
+        }
+        self.history.push_back(values.to_vec());
+
+        let mut column = Vec::with_capacity(self.history.len());
+        for (i, v) in values.iter_mut().enumerate() {
+            column.clear();
+            column.extend(self.history
+                .iter()
+                .map(|h| h[i])
+                .filter(|h| h.is_finite()));
+            if column.is_empty() {
+                continue;
+            }
+            column.sort_by(f64::total_cmp);
+            let mid = column.len() / 2;
+            *v = if column.len() % 2 == 1 {
+                column[mid]
+            } else {
+                (column[mid - 1] + column[mid]) / 2.0
+            };
+        }
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+    }
+}
+
+/// Causal Savitzky–Golay filter: a least-squares polynomial is fitted to the
+/// last `window` measurements and evaluated at the newest one. Measurements
+/// are assumed to be evenly spaced. Until the window is full, measurements
+/// pass through unchanged.
+pub struct SavitzkyGolay {
+    coefficients: Vec<f64>,
+    history: VecDeque<Vec<f64>>,
+}
+
+impl SavitzkyGolay {
+    pub fn new(window: usize, order: usize) -> SavitzkyGolay {
+        SavitzkyGolay {
+            coefficients: savgol_coefficients(window, order, window - 1),
+            history: VecDeque::new(),
+        }
+    }
+}
+
+impl Filter for SavitzkyGolay {
+    fn apply(&mut self, _: Instant, values: &mut [f64]) {
+        let window = self.coefficients.len();
+        if self.history.len() == window {
+            self.history.pop_front();
+        }
+        self.history.push_back(values.to_vec());
+        if self.history.len() < window {
+            return;
+        }
+        for (i, v) in values.iter_mut().enumerate() {
+            *v = self.history
+                .iter()
+                .zip(self.coefficients.iter())
+                .map(|(h, c)| h[i] * c)
+                .sum();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.history.clear();
+    }
+}
+
+/// Weights `c` such that `sum(c[j] * y[j])` is the value at the sample `at`
+/// of the least-squares polynomial of degree `order` through `y[0..window]`.
+///
+/// With the design matrix `A[j][k] = x_j^k` and `x_j = j - at` (so the
+/// sample `at` is at `x = 0`), the fitted value at `0` is the constant term,
+/// i.e. the first row of `(AᵀA)⁻¹Aᵀ`.
+fn savgol_coefficients(window: usize, order: usize, at: usize) -> Vec<f64> {
+    let n = order + 1;
+    let xs: Vec<f64> = (0..window).map(|j| j as f64 - at as f64).collect();
+
+    // Normal equations AᵀA, augmented with the identity for inversion.
+    let mut m = vec![vec![0.0; 2 * n]; n];
+    for (r, row) in m.iter_mut().enumerate() {
+        for (c, v) in row[..n].iter_mut().enumerate() {
+            *v = xs.iter().map(|x| x.powi((r + c) as i32)).sum();
+        }
+        row[n + r] = 1.0;
+    }
+    // Gauss-Jordan elimination with partial pivoting.
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|a, b| m[*a][col].abs().total_cmp(&m[*b][col].abs()))
+            .unwrap();
+        m.swap(col, pivot);
+        let p = m[col][col];
+        for v in m[col].iter_mut() {
+            *v /= p;
+        }
+        let pivot_row = m[col].clone();
+        for (r, row) in m.iter_mut().enumerate() {
+            if r != col {
+                let factor = row[col];
+                for (v, p) in row.iter_mut().zip(pivot_row.iter()) {
+                    *v -= factor * p;
+                }
+            }
+        }
+    }
+
+    // c_j = sum_k inv[0][k] * x_j^k
+    xs.iter()
+        .map(|x| (0..n).map(|k| m[0][n + k] * x.powi(k as i32)).sum())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+    use super::{Ema, Filter, Median, savgol_coefficients};
+
+    /// What `filter` makes of `inputs`, one channel at a time.
+    fn run<F: Filter>(filter: &mut F, inputs: &[f64]) -> Vec<f64> {
+        let t = Instant::now();
+        inputs.iter()
+            .map(|&v| {
+                let mut values = [v];
+                filter.apply(t, &mut values);
+                values[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn averages_exponentially() {
+        let mut ema = Ema::new(0.5);
+        assert_eq!(run(&mut ema, &[0.0, 10.0, 10.0]), [0.0, 5.0, 7.5]);
+        ema.reset();
+        assert_eq!(run(&mut ema, &[4.0]), [4.0]);
+    }
+
+    #[test]
+    fn takes_the_median_of_the_window() {
+        let mut median = Median::new(3);
+        assert_eq!(run(&mut median, &[1.0, 100.0, 2.0, 3.0]),
+                   [1.0, 50.5, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn leaves_values_that_are_not_finite_out_of_the_median() {
+        let mut median = Median::new(3);
+        let out = run(&mut median, &[1.0, f64::NAN, 3.0, f64::NAN, f64::NAN]);
+        assert_eq!(out, [1.0, 1.0, 2.0, 3.0, 3.0]);
+        median.reset();
+        assert!(run(&mut median, &[f64::NAN])[0].is_nan());
+    }
+
+    #[test]
+    fn fits_known_savitzky_golay_coefficients() {
+        let expect = |c: Vec<f64>, known: [f64; 5]| {
+            for (c, k) in c.iter().zip(known.iter()) {
+                assert!((c - k / 35.0).abs() < 1e-9, "{:?}", c);
+            }
+        };
+        // Smoothing at the center, and at the newest sample as filtered.
+        expect(savgol_coefficients(5, 2, 2), [-3.0, 12.0, 17.0, 12.0, -3.0]);
+        expect(savgol_coefficients(5, 2, 4), [3.0, -5.0, -3.0, 9.0, 31.0]);
+    }
+}
@@ -0,0 +1,146 @@
+//! Dense optical flow: global motion features that need no selection.
+//!
+//! Waving and swiping are about how the whole picture moves rather than
+//! about a trackable object. `MotionEnergy` computes Farnebäck flow on a
+//! downscaled gray frame and summarizes it in four dimensions. Builds with
+//! the `cuda` feature can compute the flow on the GPU.
+use crate::normalize::Unit;
+#[cfg(feature = "cuda")]
+use rust_vision::cuda::{FarnebackOpticalFlow, GpuMat};
+use rust_vision::{ColorConversionCodes, Mat, Size2i};
+use std::f64::consts::PI;
+use std::time::Instant;
+
+/// Names of the dimensions produced by `MotionEnergy::update`.
+pub const NAMES: [&str; 4] = ["flow_x", "flow_y", "motion", "direction"];
+/// Units of the dimensions produced by `MotionEnergy::update`.
+pub const UNITS: [Unit; 4] = [Unit::DeltaX, Unit::DeltaY, Unit::Length,
+                              Unit::Direction];
+
+/// Width the frame is downscaled to before computing the flow.
+const WIDTH: i32 = 160;
+/// Number of direction bins for the dominant direction.
+const DIRECTIONS: usize = 8;
+/// Per-pixel flow below this (in downscaled pixels per frame) is noise.
+const MIN_FLOW: f64 = 0.5;
+
+// Parameters of the Farnebäck flow.
+const PYR_SCALE: f64 = 0.5;
+const LEVELS: i32 = 3;
+const WINSIZE: i32 = 15;
+const ITERATIONS: i32 = 3;
+const POLY_N: i32 = 5;
+const POLY_SIGMA: f64 = 1.2;
+
+/// `MotionEnergy` turns consecutive frames into the mean flow (`flow_x
+/// flow_y`), the mean flow magnitude (`motion`) and the dominant direction of
+/// motion in degrees (`direction`, counter-clockwise from the positive x
+/// axis as on screen, `0` when nothing moves). Speeds are in full-frame
+/// pixels per second. `--unmirror` and `--y-up` turn the direction with
+/// `flow_x` and `flow_y`, so that it stays that of the mean flow.
+pub struct MotionEnergy {
+    prev: Option<(Instant, Mat)>,
+    /// The flow on CUDA, if enabled.
+    #[cfg(feature = "cuda")]
+    gpu: Option<FarnebackOpticalFlow>,
+}
+
+impl MotionEnergy {
+    /// Computes the flow on CUDA if `cuda` is set, which needs a build
+    /// with the `cuda` feature.
+    pub fn new(cuda: bool) -> MotionEnergy {
+        #[cfg(not(feature = "cuda"))]
+        assert!(!cuda, "built without the cuda feature");
+        MotionEnergy {
+            prev: None,
+            #[cfg(feature = "cuda")]
+            gpu: if cuda {
+                Some(FarnebackOpticalFlow::new(LEVELS,
+                                               PYR_SCALE,
+                                               WINSIZE,
+                                               ITERATIONS,
+                                               POLY_N,
+                                               POLY_SIGMA))
+            } else {
+                None
+            },
+        }
+    }
+
+    /// The flow from `last` to `gray`.
+    fn flow(&self, last: &Mat, gray: &Mat) -> Mat {
+        #[cfg(feature = "cuda")]
+        {
+            if let Some(ref gpu) = self.gpu {
+                let flow = gpu.calc(&GpuMat::upload(last),
+                                    &GpuMat::upload(gray));
+                return flow.download();
+            }
+        }
+        last.calc_optical_flow_farneback(gray,
+                                         PYR_SCALE,
+                                         LEVELS,
+                                         WINSIZE,
+                                         ITERATIONS,
+                                         POLY_N,
+                                         POLY_SIGMA)
+    }
+
+    /// Feeds the (BGR) `frame` captured at `t`. The first frame reports no
+    /// motion.
+    pub fn update(&mut self, t: Instant, frame: &Mat) -> Vec<f64> {
+        let height = (frame.rows * WIDTH / frame.cols.max(1)).max(1);
+        let gray = frame.cvt_color(ColorConversionCodes::BGR2GRAY)
+            .resize(Size2i::new(WIDTH, height));
+        let values = match self.prev {
+            Some((last_t, ref last)) if t > last_t => {
+                let dt = (t - last_t).as_secs_f64();
+                let flow = self.flow(last, &gray);
+                // Back to full-frame pixels per second.
+                let scale = frame.cols as f64 / WIDTH as f64 / dt;
+                summarize(&flow.data_f32(), scale)
+            }
+            _ => vec![0.0; NAMES.len()],
+        };
+        self.prev = Some((t, gray));
+        values
+    }
+}
+
+impl Default for MotionEnergy {
+    fn default() -> MotionEnergy {
+        MotionEnergy::new(false)
+    }
+}
+
+/// Summarizes an interleaved `x y` flow field, scaling speeds by `scale`.
+fn summarize(flow: &[f32], scale: f64) -> Vec<f64> {
+    let n = (flow.len() / 2).max(1) as f64;
+    let mut sum = [0.0; 2];
+    let mut motion = 0.0;
+    let mut bins = [0.0; DIRECTIONS];
+    for v in flow.chunks(2) {
+        let (x, y) = (v[0] as f64, v[1] as f64);
+        let magnitude = x.hypot(y);
+        sum[0] += x;
+        sum[1] += y;
+        motion += magnitude;
+        if magnitude >= MIN_FLOW {
+            // Image y points down; flip it so angles are counter-clockwise.
+            // Bins are centered on their direction, e.g. bin 0 is "right".
+            let turns = (-y).atan2(x) / (2.0 * PI) + 0.5 / DIRECTIONS as f64;
+            let bin = (turns.rem_euclid(1.0) * DIRECTIONS as f64) as usize;
+            bins[bin.min(DIRECTIONS - 1)] += magnitude;
+        }
+    }
+
+    let (dominant, weight) = bins.iter()
+        .enumerate()
+        .fold((0, 0.0), |best, (i, &w)| if w > best.1 { (i, w) } else { best });
+    let direction = if weight > 0.0 {
+        dominant as f64 * 360.0 / DIRECTIONS as f64
+    } else {
+        0.0
+    };
+    vec![sum[0] / n * scale, sum[1] / n * scale, motion / n * scale, direction]
+}
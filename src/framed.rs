@@ -0,0 +1,332 @@
+//! The framed binary protocol of `--format framed`, and a decoder for it.
+//!
+//! The other formats break silently when the number of dimensions changes.
+//! A framed stream starts with a header that names the dimensions and their
+//! units, and every frame carries a CRC, so consumers can check what they
+//! receive and resynchronize after corruption. All numbers are
+//! little-endian.
+//!
+//! - The header: the magic bytes `EVH1`, the length of the body as `u16`,
+//!   the body, and its CRC-32 as `u32`. The body is the number of dimensions
+//!   as `u16`, then the name and the unit of each, as a `u8` length followed
+//!   by UTF-8. Units are `px`, `unit`, `signed`, `aspect` or `letterbox`
+//!   for the coordinate spaces of `--normalize` (`^2` for areas), and empty for
+//!   values that don't depend on the frame, such as angles.
+//! - A frame per sample, all of the same size: the magic bytes `EVF1`, the
+//!   sequence number as `u64`, the time as `f64`, every value as `f32`, and
+//!   the CRC-32 of all of these but the magic as `u32`.
+//!
+//! The CRC is the one of zlib and Ethernet. Consumers written in Rust can
+//! use the `Decoder`:
+//!
+//! ```
+//! use esp_vision::framed::{self, Decoder, Dimension, Frame};
+//! use esp_vision::Sample;
+//!
+//! let x = Dimension { name: "x".to_string(), unit: "px".to_string() };
+//! let mut stream = Vec::new();
+//! framed::header(&[x.clone()], &mut stream);
+//! framed::frame(&Sample::new(0, 0.5, vec![320.0]), &mut stream);
+//!
+//! let mut decoder = Decoder::new();
+//! decoder.feed(&stream);
+//! assert_eq!(decoder.decode(), Some(Frame::Header(vec![x])));
+//! assert_eq!(decoder.decode(),
+//!            Some(Frame::Sample(Sample::new(0, 0.5, vec![320.0]))));
+//! assert_eq!(decoder.decode(), None);
+//! ```
+use crate::sample::Sample;
+
+/// Magic bytes at the start of the header.
+pub const HEADER_MAGIC: &[u8; 4] = b"EVH1";
+/// Magic bytes at the start of every frame.
+pub const FRAME_MAGIC: &[u8; 4] = b"EVF1";
+
+/// A dimension of the samples, as described by the header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dimension {
+    pub name: String,
+    pub unit: String,
+}
+
+/// What the decoder found in a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    /// A header; the samples that follow have these dimensions.
+    Header(Vec<Dimension>),
+    Sample(Sample),
+}
+
+/// The size of a frame of `dims` values, in bytes.
+pub fn frame_len(dims: usize) -> usize {
+    4 + 8 + 8 + 4 * dims + 4
+}
+
+/// Appends the header describing `dimensions` to `out`. Names and units are
+/// cut to 255 bytes.
+pub fn header(dimensions: &[Dimension], out: &mut Vec<u8>) {
+    let mut body = (dimensions.len() as u16).to_le_bytes().to_vec();
+    for d in dimensions {
+        for s in &[&d.name, &d.unit] {
+            let bytes = &s.as_bytes()[..s.len().min(255)];
+            body.push(bytes.len() as u8);
+            body.extend_from_slice(bytes);
+        }
+    }
+    out.extend_from_slice(HEADER_MAGIC);
+    out.extend_from_slice(&(body.len() as u16).to_le_bytes());
+    out.extend_from_slice(&body);
+    out.extend_from_slice(&crc32(&body).to_le_bytes());
+}
+
+/// Appends the frame of `sample` to `out`.
+pub fn frame(sample: &Sample, out: &mut Vec<u8>) {
+    out.extend_from_slice(FRAME_MAGIC);
+    let start = out.len();
+    out.extend_from_slice(&sample.seq.to_le_bytes());
+    out.extend_from_slice(&sample.time.to_le_bytes());
+    for v in &sample.values {
+        out.extend_from_slice(&(*v as f32).to_le_bytes());
+    }
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_le_bytes());
+}
+
+/// The CRC-32 of `bytes`, as zlib computes it.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// `Decoder` splits a framed stream into headers and samples. Bytes that
+/// are not part of a valid header or frame, including frames before the
+/// first header, are skipped.
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    dimensions: Option<Vec<Dimension>>,
+    skipped: usize,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// The dimensions of the last header, if any.
+    pub fn dimensions(&self) -> Option<&[Dimension]> {
+        self.dimensions.as_ref().map(|d| &d[..])
+    }
+
+    /// The number of bytes skipped so far.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Adds bytes received from the stream.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The next header or sample, or `None` until more bytes are fed.
+    pub fn decode(&mut self) -> Option<Frame> {
+        loop {
+            if self.buf.len() < 4 {
+                return None;
+            }
+            let decoded = if self.buf.starts_with(HEADER_MAGIC) {
+                self.header()?
+            } else if self.buf.starts_with(FRAME_MAGIC) &&
+                      self.dimensions.is_some() {
+                self.frame()?
+            } else {
+                None
+            };
+            match decoded {
+                Some((frame, len)) => {
+                    self.buf.drain(..len);
+                    if let Frame::Header(ref dimensions) = frame {
+                        self.dimensions = Some(dimensions.clone());
+                    }
+                    return Some(frame);
+                }
+                None => {
+                    let skip = self.next_start();
+                    self.buf.drain(..skip);
+                    self.skipped += skip;
+                }
+            }
+        }
+    }
+
+    /// Where the next header or frame may start, after the first byte: the
+    /// next magic bytes, or the end of the buffer if it may be the start of
+    /// some, so that they are kept until more bytes are fed.
+    fn next_start(&self) -> usize {
+        let buf = &self.buf;
+        (1..buf.len())
+            .find(|&k| {
+                let start = &buf[k..buf.len().min(k + 4)];
+                HEADER_MAGIC.starts_with(start) ||
+                FRAME_MAGIC.starts_with(start)
+            })
+            .unwrap_or(buf.len())
+    }
+
+    /// The header at the start of the buffer and its size: `None` if it is
+    /// incomplete, `Some(None)` if it is invalid.
+    fn header(&self) -> Option<Option<(Frame, usize)>> {
+        let buf = &self.buf;
+        if buf.len() < 6 {
+            return None;
+        }
+        let body_len = usize::from(u16::from_le_bytes([buf[4], buf[5]]));
+        let len = 6 + body_len + 4;
+        if buf.len() < len {
+            return None;
+        }
+        let body = &buf[6..6 + body_len];
+        let crc = u32::from_le_bytes([buf[len - 4],
+                                      buf[len - 3],
+                                      buf[len - 2],
+                                      buf[len - 1]]);
+        if crc32(body) != crc {
+            return Some(None);
+        }
+        Some(dimensions(body).map(|d| (Frame::Header(d), len)))
+    }
+
+    /// The frame at the start of the buffer and its size, as `header`.
+    fn frame(&self) -> Option<Option<(Frame, usize)>> {
+        let dims = self.dimensions.as_ref().map_or(0, Vec::len);
+        let len = frame_len(dims);
+        if self.buf.len() < len {
+            return None;
+        }
+        let data = &self.buf[4..len - 4];
+        let tail = &self.buf[len - 4..len];
+        let crc = u32::from_le_bytes([tail[0], tail[1], tail[2], tail[3]]);
+        if crc32(data) != crc {
+            return Some(None);
+        }
+        let mut seq = [0; 8];
+        seq.copy_from_slice(&data[..8]);
+        let mut time = [0; 8];
+        time.copy_from_slice(&data[8..16]);
+        let values = data[16..]
+            .chunks(4)
+            .map(|c| f64::from(f32::from_le_bytes([c[0], c[1], c[2], c[3]])))
+            .collect();
+        let sample = Sample::new(u64::from_le_bytes(seq),
+                                 f64::from_le_bytes(time),
+                                 values);
+        Some(Some((Frame::Sample(sample), len)))
+    }
+}
+
+/// The dimensions in the body of a header, or `None` if it is invalid.
+fn dimensions(body: &[u8]) -> Option<Vec<Dimension>> {
+    let count = u16::from_le_bytes([*body.first()?, *body.get(1)?]);
+    let mut pos = 2;
+    let mut string = || -> Option<String> {
+        let len = usize::from(*body.get(pos)?);
+        let bytes = body.get(pos + 1..pos + 1 + len)?;
+        pos += 1 + len;
+        String::from_utf8(bytes.to_vec()).ok()
+    };
+    let mut dimensions = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let name = string()?;
+        let unit = string()?;
+        dimensions.push(Dimension { name, unit });
+    }
+    Some(dimensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample::Sample;
+    use super::{Decoder, Dimension, Frame, crc32, frame, frame_len, header};
+
+    fn dimensions() -> Vec<Dimension> {
+        vec![Dimension {
+                 name: "x".to_string(),
+                 unit: "px".to_string(),
+             },
+             Dimension {
+                 name: "angle".to_string(),
+                 unit: String::new(),
+             }]
+    }
+
+    #[test]
+    fn computes_the_crc_of_zlib() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn decodes_what_it_encodes_in_pieces() {
+        let mut stream = Vec::new();
+        header(&dimensions(), &mut stream);
+        for seq in 0..3 {
+            frame(&Sample::new(seq, seq as f64, vec![1.5, -2.0]), &mut stream);
+        }
+        // `x`, `px`, `angle` and no unit, after the number of dimensions.
+        assert_eq!(stream.len() - 3 * frame_len(2),
+                   4 + 2 + (2 + 2 + 3 + 6 + 1) + 4);
+        let mut decoder = Decoder::new();
+        let mut frames = Vec::new();
+        for piece in stream.chunks(5) {
+            decoder.feed(piece);
+            while let Some(frame) = decoder.decode() {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(frames[0], Frame::Header(dimensions()));
+        assert_eq!(frames[1..],
+                   [Frame::Sample(Sample::new(0, 0.0, vec![1.5, -2.0])),
+                    Frame::Sample(Sample::new(1, 1.0, vec![1.5, -2.0])),
+                    Frame::Sample(Sample::new(2, 2.0, vec![1.5, -2.0]))]);
+        assert_eq!(decoder.skipped(), 0);
+    }
+
+    #[test]
+    fn skips_corrupted_frames() {
+        let mut stream = Vec::new();
+        // Frames before the header can't be decoded.
+        frame(&Sample::new(0, 0.0, vec![1.0, 1.0]), &mut stream);
+        header(&dimensions(), &mut stream);
+        let corrupted = stream.len() + 10;
+        frame(&Sample::new(1, 1.0, vec![1.0, 1.0]), &mut stream);
+        frame(&Sample::new(2, 2.0, vec![1.0, 1.0]), &mut stream);
+        stream[corrupted] ^= 0xff;
+        let mut decoder = Decoder::new();
+        decoder.feed(&stream);
+        assert_eq!(decoder.decode(), Some(Frame::Header(dimensions())));
+        assert_eq!(decoder.decode(),
+                   Some(Frame::Sample(Sample::new(2, 2.0, vec![1.0, 1.0]))));
+        assert_eq!(decoder.decode(), None);
+        assert_eq!(decoder.skipped(), 2 * frame_len(2));
+    }
+
+    #[test]
+    fn keeps_magic_split_across_feeds() {
+        let mut stream = vec![b'E'; 1000];
+        let garbage = stream.len();
+        header(&dimensions(), &mut stream);
+        let mut decoder = Decoder::new();
+        decoder.feed(&stream[..garbage + 2]);
+        assert_eq!(decoder.decode(), None);
+        decoder.feed(&stream[garbage + 2..]);
+        assert_eq!(decoder.decode(), Some(Frame::Header(dimensions())));
+        assert_eq!(decoder.skipped(), garbage);
+    }
+}
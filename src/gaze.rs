@@ -0,0 +1,132 @@
+//! Gaze direction: where the pupils sit within the eyes.
+//!
+//! Eyes are found with a Haar cascade such as OpenCV's `haarcascade_eye.xml`
+//! in the upper half of the largest face, and the pupil is the centroid of
+//! the darkest blob of each eye. The offset of the pupil from the center of
+//! the eye is divided by half the size of the eye, so it is roughly in
+//! `[-1, 1]` on both axes, and averaged over the eyes. Like everything else,
+//! left and right are as seen on screen, and flipped by `--unmirror` and
+//! `--y-up`.
+use rust_vision::*;
+
+/// Names of the dimensions of the `gaze` mode.
+pub const NAMES: [&str; 2] = ["gaze_x", "gaze_y"];
+
+/// Eyes smaller than this (in pixels) are ignored.
+const MIN_EYE: i32 = 15;
+/// Pixels at most this much brighter than the darkest pixel of the eye
+/// belong to the pupil.
+const DARK_MARGIN: f64 = 20.0;
+
+/// `Eye` is one detected eye.
+pub struct Eye {
+    pub rect: Rect,
+    /// The center of the pupil, if it was found.
+    pub pupil: Option<Point2f>,
+}
+
+impl Eye {
+    /// The offset of the pupil from the center of the eye, in half sizes of
+    /// the eye.
+    pub fn offset(&self) -> Option<(f64, f64)> {
+        self.pupil.map(|p| {
+            let half_w = f64::from(self.rect.width) / 2.0;
+            let half_h = f64::from(self.rect.height) / 2.0;
+            ((f64::from(p.x) - f64::from(self.rect.x) - half_w) / half_w,
+             (f64::from(p.y) - f64::from(self.rect.y) - half_h) / half_h)
+        })
+    }
+}
+
+/// `Gaze` finds the eyes of a face and their pupils.
+pub struct Gaze {
+    cascade: CascadeClassifier,
+    kernel: Mat,
+}
+
+impl Gaze {
+    /// Loads the eye cascade from the XML file at `path`.
+    pub fn new(path: &str) -> Result<Gaze, String> {
+        let cascade = CascadeClassifier::new(path);
+        if cascade.empty() {
+            return Err(format!("cannot load the cascade {}", path));
+        }
+        Ok(Gaze {
+            cascade,
+            kernel: get_structuring_element(MorphShapes::Ellipse,
+                                            Size2i::new(3, 3)),
+        })
+    }
+
+    /// The (at most two) largest eyes of the `face` in the (BGR) `frame`.
+    pub fn eyes(&self, frame: &Mat, face: Rect) -> Vec<Eye> {
+        // Eyes are in the upper half; the nostrils and mouth look like eyes
+        // to the cascade.
+        let upper = crate::tracker::clip(Rect::new(face.x,
+                                              face.y,
+                                              face.width,
+                                              face.height / 2),
+                                    frame.cols,
+                                    frame.rows);
+        if upper.width == 0 || upper.height == 0 {
+            return Vec::new();
+        }
+        let gray = frame.roi(upper)
+            .cvt_color(ColorConversionCodes::BGR2GRAY)
+            .equalize_hist();
+        let mut rects = self.cascade
+            .detect_multi_scale(&gray, 1.1, 4, Size2i::new(MIN_EYE, MIN_EYE));
+        rects.sort_by_key(|r| -(r.width * r.height));
+        rects.truncate(2);
+        rects.sort_by_key(|r| r.x);
+        rects.into_iter()
+            .map(|mut rect| {
+                let pupil = self.pupil(&gray, rect).map(|p| {
+                    Point2f::new(p.x + upper.x as f32, p.y + upper.y as f32)
+                });
+                rect.x += upper.x;
+                rect.y += upper.y;
+                Eye { rect, pupil }
+            })
+            .collect()
+    }
+
+    /// The centroid of the darkest blob of `eye` in the equalized `gray`
+    /// image, in the coordinates of `gray`.
+    fn pupil(&self, gray: &Mat, eye: Rect) -> Option<Point2f> {
+        // The top of the box holds the eyebrow, which is as dark as the
+        // pupil.
+        let top = eye.height / 4;
+        let eye = Rect::new(eye.x, eye.y + top, eye.width, eye.height - top);
+        let roi = gray.roi(eye);
+        let (darkest, _, _, _) = roi.min_max_loc();
+        let mask = roi.threshold(darkest + DARK_MARGIN,
+                       255.0,
+                       ThresholdTypes::BinaryInv)
+            .morphology_ex(MorphTypes::Open, &self.kernel, 1);
+        mask.find_contours(RetrievalModes::External,
+                           ContourApproximationModes::Simple)
+            .iter()
+            .map(|c| (contour_area(c), c))
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .map(|(_, c)| moments(c))
+            .filter(|m| m.m00 > 0.0)
+            .map(|m| {
+                Point2f::new((m.m10 / m.m00) as f32 + eye.x as f32,
+                             (m.m01 / m.m00) as f32 + eye.y as f32)
+            })
+    }
+}
+
+/// `gaze_x gaze_y`: the offsets of the pupils averaged over `eyes`, or NaN
+/// if no pupil was found.
+pub fn values(eyes: &[Eye]) -> Vec<f64> {
+    let offsets: Vec<(f64, f64)> =
+        eyes.iter().filter_map(|e| e.offset()).collect();
+    if offsets.is_empty() {
+        return vec![f64::NAN, f64::NAN];
+    }
+    let n = offsets.len() as f64;
+    vec![offsets.iter().map(|o| o.0).sum::<f64>() / n,
+         offsets.iter().map(|o| o.1).sum::<f64>() / n]
+}
@@ -0,0 +1,228 @@
+//! A gRPC service, with `--grpc ADDR` in builds with the `grpc` feature.
+//!
+//! For services that would rather not parse ESP's wire formats, the
+//! `EspVision` service of `proto/esp_vision.proto` streams the samples sent
+//! to ESP to every `Subscribe` call, and takes the control requests of the
+//! [`api`](../api/index.html) as unary calls: `SetRoi` and `SelectTracker`.
+//! `Dimensions` names the values of the samples. Generate a client for any
+//! language from the `.proto`, e.g. for Go:
+//!
+//! ```text
+//! cargo build --release --features grpc
+//! protoc --go_out=. --go-grpc_out=. proto/esp_vision.proto
+//! ```
+use crate::api::Request;
+use crate::config::TrackerRules;
+use crate::net::Net;
+use crate::sample::Sample;
+use crate::sink::Sink;
+use std::io;
+use std::sync::mpsc::{self, Receiver, TryIter};
+use tokio::sync::broadcast;
+
+/// Whether this build has the `grpc` feature.
+pub const BUILT: bool = cfg!(feature = "grpc");
+/// Samples buffered for each subscriber; slower ones skip samples.
+const SUBSCRIBER_QUEUE: usize = 256;
+
+/// `Grpc` is the main loop's side of the service: it takes the control
+/// requests, and its `Publisher` the samples.
+pub struct Grpc {
+    samples: broadcast::Sender<Sample>,
+    requests: Receiver<Request>,
+}
+
+impl Grpc {
+    /// Serves the service on `addr` from `net`, with the dimension `names`.
+    /// `rules` refuse the backends the config can't track with.
+    pub fn serve(net: &Net,
+                 addr: &str,
+                 names: Vec<String>,
+                 rules: TrackerRules)
+                 -> io::Result<Grpc> {
+        let (samples, _) = broadcast::channel(SUBSCRIBER_QUEUE);
+        let (sender, requests) = mpsc::channel();
+        service::spawn(net, addr, names, rules, samples.clone(), sender)?;
+        Ok(Grpc { samples, requests })
+    }
+
+    /// The sink that streams samples to the subscribers.
+    pub fn publisher(&self) -> Publisher {
+        Publisher { samples: self.samples.clone() }
+    }
+
+    /// The requests that came since the last call.
+    pub fn requests(&self) -> TryIter<Request> {
+        self.requests.try_iter()
+    }
+}
+
+/// `Publisher` sends every sample to the subscribers, if any.
+pub struct Publisher {
+    samples: broadcast::Sender<Sample>,
+}
+
+impl Sink for Publisher {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        // Without subscribers, the sample is dropped.
+        self.samples.send(sample.clone()).ok();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "grpc")]
+mod proto {
+    tonic::include_proto!("esp_vision");
+}
+
+#[cfg(not(feature = "grpc"))]
+mod service {
+    use crate::api::Request;
+    use crate::config::TrackerRules;
+    use crate::net::Net;
+    use crate::sample::Sample;
+    use std::io;
+    use std::sync::mpsc::Sender;
+    use tokio::sync::broadcast;
+
+    pub fn spawn(_net: &Net,
+                 _addr: &str,
+                 _names: Vec<String>,
+                 _rules: TrackerRules,
+                 _samples: broadcast::Sender<Sample>,
+                 _requests: Sender<Request>)
+                 -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Other,
+                           "this build has no gRPC; build with `--features \
+                            grpc`"))
+    }
+}
+
+#[cfg(feature = "grpc")]
+mod service {
+    use crate::api::Request;
+    use crate::config::TrackerRules;
+    use crate::net::Net;
+    use crate::sample::Sample;
+    use rust_vision::Rect;
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+    use std::sync::mpsc::Sender;
+    use super::proto::{self, esp_vision_server::{EspVision, EspVisionServer}};
+    use tokio::net::TcpListener;
+    use tokio::sync::broadcast;
+    use tokio_stream::wrappers::{BroadcastStream, TcpListenerStream};
+    use tokio_stream::{Stream, StreamExt};
+    use tonic::{Response, Status};
+
+    struct Service {
+        names: Vec<String>,
+        rules: TrackerRules,
+        samples: broadcast::Sender<Sample>,
+        requests: Mutex<Sender<Request>>,
+    }
+
+    /// Binds to `addr` and serves the service on `net`.
+    pub fn spawn(net: &Net,
+                 addr: &str,
+                 names: Vec<String>,
+                 rules: TrackerRules,
+                 samples: broadcast::Sender<Sample>,
+                 requests: Sender<Request>)
+                 -> io::Result<()> {
+        let listener = net.block_on(TcpListener::bind(addr))?;
+        let service = Service {
+            names,
+            rules,
+            samples,
+            requests: Mutex::new(requests),
+        };
+        net.spawn(run(listener, service));
+        Ok(())
+    }
+
+    impl Service {
+        fn request(&self, request: Request) -> Result<(), Status> {
+            info!("gRPC: {:?}", request);
+            self.requests
+                .lock()
+                .unwrap()
+                .send(request)
+                .map_err(|_| Status::unavailable("esp-vision is stopping"))
+        }
+    }
+
+    /// Serves `service` on `listener` until the runtime stops.
+    async fn run(listener: TcpListener, service: Service) {
+        let result = tonic::transport::Server::builder()
+            .add_service(EspVisionServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await;
+        if let Err(e) = result {
+            warn!("gRPC error: {}", e);
+        }
+    }
+
+    type Samples =
+        Pin<Box<dyn Stream<Item = Result<proto::Sample, Status>> + Send>>;
+
+    #[tonic::async_trait]
+    impl EspVision for Service {
+        type SubscribeStream = Samples;
+
+        async fn dimensions(&self,
+                            _: tonic::Request<proto::DimensionsRequest>)
+                            -> Result<Response<proto::DimensionsReply>,
+                                      Status> {
+            Ok(Response::new(proto::DimensionsReply {
+                names: self.names.clone(),
+            }))
+        }
+
+        async fn subscribe(&self,
+                           request: tonic::Request<proto::SubscribeRequest>)
+                           -> Result<Response<Samples>, Status> {
+            if let Some(peer) = request.remote_addr() {
+                info!("Streaming samples over gRPC to {}", peer);
+            }
+            // Samples a slow subscriber missed are skipped.
+            let samples = BroadcastStream::new(self.samples.subscribe())
+                .filter_map(Result::ok)
+                .map(|s| {
+                    Ok(proto::Sample {
+                        seq: s.seq,
+                        time: s.time,
+                        values: s.values,
+                    })
+                });
+            Ok(Response::new(Box::pin(samples)))
+        }
+
+        async fn set_roi(&self,
+                         request: tonic::Request<proto::Roi>)
+                         -> Result<Response<proto::ControlReply>, Status> {
+            let roi = request.into_inner();
+            if roi.width <= 0 || roi.height <= 0 {
+                return Err(Status::invalid_argument("the region needs a \
+                                                     positive size"));
+            }
+            let rect = Rect::new(roi.x, roi.y, roi.width, roi.height);
+            self.request(Request::Roi(rect))?;
+            Ok(Response::new(proto::ControlReply {}))
+        }
+
+        async fn select_tracker(&self,
+                                request: tonic::Request<proto::Tracker>)
+                                -> Result<Response<proto::ControlReply>,
+                                          Status> {
+            let backend = request.into_inner()
+                .name
+                .parse()
+                .map_err(Status::invalid_argument)?;
+            self.rules.check(backend).map_err(Status::invalid_argument)?;
+            self.request(Request::Tracker(backend))?;
+            Ok(Response::new(proto::ControlReply {}))
+        }
+    }
+}
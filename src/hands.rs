@@ -0,0 +1,133 @@
+//! Left and right identity of two tracked hands.
+//!
+//! Two trackers follow two regions, but nothing ties a tracker to a hand:
+//! while the hands cross, their regions overlap and the trackers may come
+//! out swapped. `Hands` keeps a constant-velocity estimate of each hand,
+//! coasts through the crossing and afterwards assigns the regions to the
+//! hands whose estimates they are closest to.
+use crate::features::Region;
+use std::time::Instant;
+
+/// Weight of a new velocity measurement.
+const VELOCITY_GAIN: f64 = 0.5;
+
+#[derive(Clone, Copy, Debug)]
+struct Motion {
+    position: [f64; 2],
+    velocity: [f64; 2],
+}
+
+impl Motion {
+    fn predict(&self, dt: f64) -> [f64; 2] {
+        [self.position[0] + self.velocity[0] * dt,
+         self.position[1] + self.velocity[1] * dt]
+    }
+
+    fn observe(&mut self, position: [f64; 2], dt: f64) {
+        if dt > 0.0 {
+            for (i, &p) in position.iter().enumerate() {
+                let v = (p - self.position[i]) / dt;
+                self.velocity[i] += VELOCITY_GAIN * (v - self.velocity[i]);
+            }
+        }
+        self.position = position;
+    }
+}
+
+/// `Hands` decides which of two targets is the left and which the right
+/// hand.
+pub struct Hands {
+    /// Target of the left and of the right hand.
+    order: [usize; 2],
+    motion: [Option<Motion>; 2],
+    last: Option<Instant>,
+}
+
+impl Hands {
+    pub fn new() -> Hands {
+        Hands {
+            order: [0, 1],
+            motion: [None, None],
+            last: None,
+        }
+    }
+
+    /// Forgets the identities, e.g. after a hand was selected again; they
+    /// are assigned by position once both hands are visible.
+    pub fn reset(&mut self) {
+        *self = Hands::new();
+    }
+
+    /// Feeds the regions of both targets, captured at `t`, and returns the
+    /// targets of the left and of the right hand.
+    pub fn assign(&mut self,
+                  t: Instant,
+                  regions: [Option<Region>; 2])
+                  -> [usize; 2] {
+        let dt = match self.last {
+            Some(last) if t > last => (t - last).as_secs_f64(),
+            _ => 0.0,
+        };
+        self.last = Some(t);
+
+        if let (Some(a), Some(b)) = (regions[0], regions[1]) {
+            match (self.motion[0], self.motion[1]) {
+                (Some(left), Some(right)) => {
+                    if overlap(&a, &b) {
+                        // Crossing: the regions can't be told apart, so
+                        // coast on the estimates until they separate.
+                        for m in self.motion.iter_mut().flatten() {
+                            m.position = m.predict(dt);
+                        }
+                        return self.order;
+                    }
+                    let (l, r) = (left.predict(dt), right.predict(dt));
+                    let keep = distance(l, &regions[self.order[0]]) +
+                               distance(r, &regions[self.order[1]]);
+                    let swap = distance(l, &regions[self.order[1]]) +
+                               distance(r, &regions[self.order[0]]);
+                    if swap < keep {
+                        self.order = [self.order[1], self.order[0]];
+                    }
+                }
+                // The frame is mirrored, so the left hand is on the left.
+                _ => self.order = if a.cx <= b.cx { [0, 1] } else { [1, 0] },
+            }
+        }
+
+        for hand in 0..2 {
+            if let Some(region) = regions[self.order[hand]] {
+                let position = region.centroid();
+                match self.motion[hand] {
+                    Some(ref mut m) => m.observe(position, dt),
+                    None => {
+                        self.motion[hand] = Some(Motion {
+                            position,
+                            velocity: [0.0, 0.0],
+                        })
+                    }
+                }
+            }
+        }
+        self.order
+    }
+}
+
+impl Default for Hands {
+    fn default() -> Hands {
+        Hands::new()
+    }
+}
+
+fn distance(p: [f64; 2], region: &Option<Region>) -> f64 {
+    match *region {
+        Some(ref r) => (p[0] - r.cx).hypot(p[1] - r.cy),
+        None => 0.0,
+    }
+}
+
+/// Whether the bounding boxes of `a` and `b` intersect.
+fn overlap(a: &Region, b: &Region) -> bool {
+    (a.cx - b.cx).abs() * 2.0 < a.width + b.width &&
+    (a.cy - b.cy).abs() * 2.0 < a.height + b.height
+}
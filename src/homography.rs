@@ -0,0 +1,153 @@
+//! Output coordinates on a plane, such as a table the camera looks down at
+//! at an angle.
+//!
+//! `--plane FILE` maps every position of a sample from camera pixels to the
+//! plane, through the homography between four corners clicked on the frame
+//! and those of a `--plane-size` rectangle:
+//!
+//! ```toml
+//! corners = [[102.0, 61.0], [538.0, 74.0], [601.0, 420.0], [37.0, 402.0]]
+//! size = [120.0, 80.0]
+//! ```
+//!
+//! The corners are, in order, the top left, top right, bottom right and
+//! bottom left of the plane, as displayed in the window; `size` is its width
+//! and height in any unit. Press `p` and click the four corners to write the
+//! file. Displacements and sizes stay in pixels.
+use std::fs::File;
+use std::io::prelude::*;
+
+/// The corners of the plane, in the order they are clicked.
+pub const CORNERS: [&str; 4] = ["top left", "top right", "bottom right",
+                                "bottom left"];
+
+/// `Plane` is the calibration of a plane, as saved to its file.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plane {
+    /// The corners of the plane on the frame, in pixels.
+    pub corners: [[f64; 2]; 4],
+    /// The width and height of the plane.
+    pub size: [f64; 2],
+}
+
+impl Plane {
+    pub fn load(path: &str) -> Result<Plane, String> {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| format!("cannot read plane {}: {}", path, e))?;
+        toml::from_str(&text)
+            .map_err(|e| format!("invalid plane {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = toml::to_string(self)
+            .map_err(|e| format!("cannot serialize the plane: {}", e))?;
+        File::create(path)
+            .and_then(|mut f| f.write_all(text.as_bytes()))
+            .map_err(|e| format!("cannot write plane {}: {}", path, e))
+    }
+
+    /// The mapping from the frame to the plane, if no three corners are on
+    /// a line.
+    pub fn homography(&self) -> Option<Homography> {
+        let [w, h] = self.size;
+        Homography::new(&self.corners,
+                        &[[0.0, 0.0], [w, 0.0], [w, h], [0.0, h]])
+    }
+}
+
+/// `Homography` maps the points of a plane to those of another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Homography {
+    /// The 3x3 matrix, row by row.
+    h: [f64; 9],
+}
+
+impl Homography {
+    /// The homography taking each of the points `from` to the point of `to`
+    /// at the same index, if there is one.
+    pub fn new(from: &[[f64; 2]; 4],
+               to: &[[f64; 2]; 4])
+               -> Option<Homography> {
+        // With the last coefficient fixed to 1, every pair of points gives
+        // two linear equations in the other eight.
+        let mut a = [[0.0; 9]; 8];
+        for (i, (p, q)) in from.iter().zip(to).enumerate() {
+            let [x, y] = *p;
+            let [u, v] = *q;
+            a[2 * i] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+            a[2 * i + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+        }
+        let solution = solve(&mut a)?;
+        let mut h = [1.0; 9];
+        h[..8].copy_from_slice(&solution);
+        Some(Homography { h })
+    }
+
+    /// The image of `(x, y)`.
+    pub fn map(&self, x: f64, y: f64) -> (f64, f64) {
+        let h = &self.h;
+        let w = h[6] * x + h[7] * y + h[8];
+        ((h[0] * x + h[1] * y + h[2]) / w, (h[3] * x + h[4] * y + h[5]) / w)
+    }
+}
+
+/// Solves the linear system whose augmented matrix is `a` by Gaussian
+/// elimination, or `None` if it is singular or not finite.
+fn solve(a: &mut [[f64; 9]; 8]) -> Option<[f64; 8]> {
+    for col in 0..8 {
+        let pivot = (col..8).max_by(|&i, &j| {
+            a[i][col].abs().total_cmp(&a[j][col].abs())
+        })?;
+        // NaN, from corners that are, sorts above every number.
+        if !(a[pivot][col].abs() >= 1e-9 && a[pivot][col].is_finite()) {
+            return None;
+        }
+        a.swap(col, pivot);
+        let pivot_row = a[col];
+        for row in a.iter_mut().skip(col + 1) {
+            let factor = row[col] / pivot_row[col];
+            for (v, p) in row.iter_mut().zip(&pivot_row).skip(col) {
+                *v -= factor * p;
+            }
+        }
+    }
+    let mut x = [0.0; 8];
+    for row in (0..8).rev() {
+        let sum: f64 = (row + 1..8).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (a[row][8] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Homography, Plane};
+
+    const SQUARE: [[f64; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0],
+                                   [0.0, 1.0]];
+
+    #[test]
+    fn maps_the_corners_onto_the_plane() {
+        let plane = Plane {
+            corners: [[102.0, 61.0], [538.0, 74.0], [601.0, 420.0],
+                      [37.0, 402.0]],
+            size: [120.0, 80.0],
+        };
+        let homography = plane.homography().unwrap();
+        let (x, y) = homography.map(601.0, 420.0);
+        assert!((x - 120.0).abs() < 1e-6 && (y - 80.0).abs() < 1e-6,
+                "{} {}",
+                x,
+                y);
+    }
+
+    #[test]
+    fn has_no_homography_for_corners_that_are_not_numbers() {
+        let mut corners = SQUARE;
+        corners[2] = [f64::NAN, 1.0];
+        assert_eq!(Homography::new(&corners, &SQUARE), None);
+    }
+}
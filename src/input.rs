@@ -0,0 +1,587 @@
+//! Sources of frames.
+//!
+//! `--input` picks where frames come from:
+//!
+//! - `camera:N` (default `camera:0`): the camera with index `N`.
+//! - `video:PATH`: a video file, played at its own frame rate times
+//!   `--speed`, and from the start again with `--loop`.
+//! - `images:DIR` or `images:DIR/PATTERN`: the images of a directory, or
+//!   those whose name matches a pattern with `*` wildcards such as
+//!   `frame_*.png`, in natural order (`frame_2` before `frame_10`), one every
+//!   `--frame-delay` milliseconds. `--loop` applies as well.
+//! - `rtsp://...`, `http://...` or `https://...`: a network camera. When the
+//!   stream drops, it is opened again until the camera is back.
+//! - `mjpeg:http://...`: an MJPEG stream over HTTP, as served by ESP32-CAM
+//!   boards, read without help from OpenCV's video backends. See the
+//!   [`mjpeg`](../mjpeg/index.html) module.
+//! - `synthetic:circle`, `synthetic:line` or `synthetic:X,Y;X,Y;...`: a
+//!   colored disc moving along a known trajectory, to test without a camera.
+//!   See the [`synthetic`](../synthetic/index.html) module.
+//! - `v4l2:N` or `v4l2:PATH`: a V4L2 device read without OpenCV, such as the
+//!   CSI camera of a Raspberry Pi, in builds with the `rpi` feature. See the
+//!   [`v4l2`](../v4l2/index.html) module.
+//! - `realsense` or `realsense:SERIAL`: an Intel RealSense camera, whose
+//!   depth comes with every target, in builds with the `realsense` feature.
+//!   See the [`realsense`](../realsense/index.html) module.
+//!
+//! Cameras are asked for `--resolution`, `--fps` and `--pixel-format` when
+//! given, with a warning for each one the camera doesn't grant.
+//!
+//! Automatic exposure and white balance shift the colors of the target as
+//! the camera adapts, which throws off color histograms. `--exposure`,
+//! `--gain` and `--white-balance` fix them, and `--lock-camera` fixes all
+//! three at the values the camera settles on after starting. Not every
+//! backend supports these; a warning says so.
+//!
+//! Frames from every source go through the same pipeline.
+use crate::metrics;
+use crate::mjpeg::MjpegStream;
+use crate::rate;
+use crate::realsense::RealSense;
+use crate::synthetic::{Synthetic, Trajectory};
+use crate::v4l2::V4l2Camera;
+use rust_vision::{CapProp, Mat, VideoCapture, imread};
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::Ordering as atomic;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Frame rate assumed for videos that don't tell theirs.
+const DEFAULT_FPS: f64 = 30.0;
+/// The slowest `--speed`. With the frame rates videos can claim, the time
+/// between frames still fits in a `Duration`.
+pub const MIN_SPEED: f64 = 0.001;
+/// Extensions of the files `images:DIR` picks up.
+const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "bmp", "tiff"];
+/// Frames the automatic controls get to settle before they are locked.
+const SETTLE_FRAMES: u32 = 30;
+/// `AutoExposure` values for manual and automatic exposure, as the V4L2
+/// backend of OpenCV maps them.
+const MANUAL_EXPOSURE: f64 = 0.25;
+const AUTO_EXPOSURE: f64 = 0.75;
+/// Time between attempts to reopen a network stream.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Where frames come from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InputSpec {
+    Camera(i32),
+    Video(String),
+    Images(String),
+    /// The URL of a network camera.
+    Stream(String),
+    /// The `http://` URL of an MJPEG stream.
+    Mjpeg(String),
+    /// The trajectory of a synthetic input.
+    Synthetic(String),
+    /// The index or path of a V4L2 device.
+    V4l2(String),
+    /// The serial number of a RealSense camera, or empty for any.
+    Realsense(String),
+}
+
+impl Default for InputSpec {
+    fn default() -> InputSpec {
+        InputSpec::Camera(0)
+    }
+}
+
+impl FromStr for InputSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<InputSpec, String> {
+        let (kind, arg) = match s.find(':') {
+            Some(i) => (&s[..i], &s[i + 1..]),
+            None => (s, ""),
+        };
+        match kind {
+            "camera" if arg.is_empty() => Ok(InputSpec::Camera(0)),
+            "camera" => {
+                arg.parse()
+                    .map(InputSpec::Camera)
+                    .map_err(|_| format!("invalid camera index `{}`", arg))
+            }
+            "video" if !arg.is_empty() => {
+                Ok(InputSpec::Video(arg.to_string()))
+            }
+            "images" if !arg.is_empty() => {
+                Ok(InputSpec::Images(arg.to_string()))
+            }
+            "rtsp" | "http" | "https" => Ok(InputSpec::Stream(s.to_string())),
+            "mjpeg" if !arg.is_empty() => Ok(InputSpec::Mjpeg(arg.to_string())),
+            "synthetic" => {
+                arg.parse::<Trajectory>()?;
+                Ok(InputSpec::Synthetic(arg.to_string()))
+            }
+            "v4l2" => Ok(InputSpec::V4l2(arg.to_string())),
+            "realsense" => Ok(InputSpec::Realsense(arg.to_string())),
+            _ => {
+                Err(format!("unknown input `{}`; expected camera:N, \
+                             video:PATH, images:DIR, mjpeg:URL, \
+                             synthetic:TRAJECTORY, v4l2:DEVICE, \
+                             realsense[:SERIAL] or a URL",
+                            s))
+            }
+        }
+    }
+}
+
+impl fmt::Display for InputSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InputSpec::Camera(index) => write!(f, "camera:{}", index),
+            InputSpec::Video(ref path) => write!(f, "video:{}", path),
+            InputSpec::Images(ref path) => write!(f, "images:{}", path),
+            InputSpec::Stream(ref url) => write!(f, "{}", url),
+            InputSpec::Mjpeg(ref url) => write!(f, "mjpeg:{}", url),
+            InputSpec::Synthetic(ref trajectory) => {
+                write!(f, "synthetic:{}", trajectory)
+            }
+            InputSpec::V4l2(ref device) => write!(f, "v4l2:{}", device),
+            InputSpec::Realsense(ref serial) if serial.is_empty() => {
+                write!(f, "realsense")
+            }
+            InputSpec::Realsense(ref serial) => {
+                write!(f, "realsense:{}", serial)
+            }
+        }
+    }
+}
+
+/// How recorded input is played back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Playback {
+    /// Factor applied to the recorded frame rate; 2 plays twice as fast.
+    pub speed: f64,
+    /// Start over at the end instead of stopping.
+    pub looping: bool,
+    /// Time between the frames of an image sequence.
+    pub frame_delay: Duration,
+    /// Wait for the time of every frame; otherwise frames are read as fast
+    /// as they can be decoded.
+    pub paced: bool,
+}
+
+impl Default for Playback {
+    fn default() -> Playback {
+        Playback {
+            speed: 1.0,
+            looping: false,
+            frame_delay: Duration::from_millis(33),
+            paced: true,
+        }
+    }
+}
+
+/// What is asked of a camera.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CaptureSettings {
+    /// Width and height of the frames.
+    pub size: Option<(i32, i32)>,
+    pub fps: Option<f64>,
+    /// Pixel format, such as `MJPG` or `YUYV`.
+    pub fourcc: Option<[char; 4]>,
+    /// Fixed exposure, in the units of the backend.
+    pub exposure: Option<f64>,
+    pub gain: Option<f64>,
+    /// Fixed white balance, in kelvin.
+    pub white_balance: Option<f64>,
+    /// Lock exposure, gain and white balance once they settled.
+    pub lock: bool,
+    /// Give up on a camera that fails for this long; `None` waits for it
+    /// forever.
+    pub timeout: Option<Duration>,
+}
+
+/// What frames are read from.
+enum Source {
+    Capture(VideoCapture),
+    /// A network camera and its URL, to reconnect.
+    Stream(VideoCapture, String),
+    Mjpeg(MjpegStream),
+    /// The images in order, and the index of the next one.
+    Images(Vec<PathBuf>, usize),
+    Synthetic(Synthetic),
+    V4l2(V4l2Camera),
+    Realsense(RealSense),
+}
+
+/// `Input` delivers frames from an `InputSpec`.
+pub struct Input {
+    source: Source,
+    /// Time between frames of recorded input; `None` for live input, which
+    /// comes at its own pace.
+    interval: Option<Duration>,
+    looping: bool,
+    paced: bool,
+    next: Option<Instant>,
+    /// Frames left until the camera controls are locked.
+    settle: Option<u32>,
+}
+
+impl Input {
+    pub fn open(spec: &InputSpec,
+                playback: &Playback,
+                capture: &CaptureSettings)
+                -> Result<Input, String> {
+        let (source, interval) = match *spec {
+            InputSpec::Camera(index) => {
+                let cap = VideoCapture::new(index);
+                if cap.is_open() {
+                    configure(&cap, capture);
+                }
+                (Source::Capture(cap), None)
+            }
+            InputSpec::Video(ref path) => {
+                let cap = VideoCapture::from_file(path);
+                let fps = match cap.get(CapProp::Fps) {
+                    fps if fps >= rate::MIN_RATE && fps.is_finite() => fps,
+                    _ => DEFAULT_FPS,
+                };
+                let fps = fps * playback.speed;
+                let interval = Duration::from_secs_f64(1.0 / fps);
+                (Source::Capture(cap), Some(interval))
+            }
+            InputSpec::Stream(ref url) => {
+                (Source::Stream(VideoCapture::from_file(url), url.clone()),
+                 None)
+            }
+            InputSpec::Mjpeg(ref url) => {
+                (Source::Mjpeg(MjpegStream::open(url)?), None)
+            }
+            InputSpec::Images(ref pattern) => {
+                let paths = list_images(pattern)?;
+                info!("Reading {} images from {}", paths.len(), pattern);
+                (Source::Images(paths, 0), Some(playback.frame_delay))
+            }
+            InputSpec::Synthetic(ref trajectory) => {
+                let fps = DEFAULT_FPS * playback.speed;
+                (Source::Synthetic(Synthetic::new(trajectory.parse()?)),
+                 Some(Duration::from_secs_f64(1.0 / fps)))
+            }
+            InputSpec::V4l2(ref device) => {
+                (Source::V4l2(V4l2Camera::open(device, capture)?), None)
+            }
+            InputSpec::Realsense(ref serial) => {
+                (Source::Realsense(RealSense::open(serial, capture)?), None)
+            }
+        };
+        match source {
+            Source::Capture(ref cap) | Source::Stream(ref cap, _) => {
+                if let (false, &InputSpec::Camera(index)) =
+                       (cap.is_open(), spec) {
+                    return Err(format!("camera {} is missing or busy; is \
+                                        another app using it? `esp-vision \
+                                        devices` lists the cameras",
+                                       index));
+                }
+                if !cap.is_open() {
+                    return Err(format!("cannot open the input {}", spec));
+                }
+            }
+            Source::Mjpeg(_) |
+            Source::Images(..) |
+            Source::Synthetic(_) |
+            Source::V4l2(_) |
+            Source::Realsense(_) => {}
+        }
+        let settle = match *spec {
+            InputSpec::Camera(_) if capture.lock => Some(SETTLE_FRAMES),
+            _ => None,
+        };
+        Ok(Input {
+            source,
+            interval,
+            looping: playback.looping,
+            paced: playback.paced,
+            next: None,
+            settle,
+        })
+    }
+
+    /// Whether frames come at the pace of the source rather than of the
+    /// playback.
+    pub fn is_live(&self) -> bool {
+        self.interval.is_none()
+    }
+
+    /// Time between frames of recorded input, at the playback speed.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// Hands exposure, gain and white balance back to the camera, and locks
+    /// them again once they settled. Does nothing unless the input is a
+    /// camera.
+    pub fn relock(&mut self) {
+        if self.interval.is_some() {
+            return;
+        }
+        if let Source::Capture(ref cap) = self.source {
+            cap.set(CapProp::AutoExposure, AUTO_EXPOSURE);
+            cap.set(CapProp::AutoWb, 1.0);
+            self.settle = Some(SETTLE_FRAMES);
+        }
+    }
+
+    /// Reads the next frame into `frame`, waiting for its time if the input
+    /// is recorded. Returns `false` at the end of the input.
+    pub fn read(&mut self, frame: &Mat) -> bool {
+        if let (Some(interval), true) = (self.interval, self.paced) {
+            if let Some(next) = self.next {
+                let now = Instant::now();
+                if next > now {
+                    thread::sleep(next - now);
+                }
+            }
+            // Counting from now rather than from the last deadline doesn't
+            // try to catch up after a slow frame.
+            self.next = Some(Instant::now() + interval);
+        }
+        let recorded = self.interval.is_some();
+        match self.source {
+            Source::Capture(ref cap) => {
+                if cap.read(frame) {
+                    match self.settle {
+                        Some(0) => {
+                            lock(cap);
+                            self.settle = None;
+                        }
+                        Some(n) => self.settle = Some(n - 1),
+                        None => {}
+                    }
+                    return true;
+                }
+                if self.looping && recorded {
+                    cap.set(CapProp::PosFrames, 0.0);
+                    return cap.read(frame);
+                }
+                false
+            }
+            Source::Stream(ref mut cap, ref url) => {
+                while !cap.read(frame) {
+                    warn!("Lost the stream {}; reconnecting", url);
+                    thread::sleep(RECONNECT_DELAY);
+                    *cap = VideoCapture::from_file(url);
+                    if cap.is_open() {
+                        info!("Reconnected to {}", url);
+                        metrics::RECONNECTS.fetch_add(1, atomic::SeqCst);
+                    }
+                }
+                true
+            }
+            Source::Mjpeg(ref stream) => stream.read(frame),
+            Source::Images(ref paths, ref mut next) => {
+                if *next == paths.len() && self.looping {
+                    *next = 0;
+                }
+                while *next < paths.len() {
+                    let path = &paths[*next];
+                    *next += 1;
+                    let image = imread(&path.to_string_lossy());
+                    if image.cols > 0 {
+                        image.copy_to(frame);
+                        return true;
+                    }
+                    warn!("Cannot read the image {}", path.display());
+                }
+                false
+            }
+            Source::Synthetic(ref mut synthetic) => {
+                synthetic.read(frame, self.looping)
+            }
+            Source::V4l2(ref mut camera) => camera.read(frame),
+            Source::Realsense(ref mut camera) => camera.read(frame),
+        }
+    }
+}
+
+/// Asks `cap` for `settings`, and warns about what it delivers instead.
+fn configure(cap: &VideoCapture, settings: &CaptureSettings) {
+    // The pixel format goes first, as it decides the sizes and rates on
+    // offer.
+    if let Some(fourcc) = settings.fourcc {
+        let code = fourcc_code(fourcc);
+        cap.set(CapProp::Fourcc, f64::from(code));
+        let actual = cap.get(CapProp::Fourcc) as u32;
+        if actual != code {
+            warn!("Asked for pixel format {}, the camera \
+                   delivers {}",
+                  fourcc_name(code),
+                  fourcc_name(actual));
+        }
+    }
+    if let Some((width, height)) = settings.size {
+        cap.set(CapProp::FrameWidth, f64::from(width));
+        cap.set(CapProp::FrameHeight, f64::from(height));
+        let actual = (cap.get(CapProp::FrameWidth) as i32,
+                      cap.get(CapProp::FrameHeight) as i32);
+        if actual != (width, height) {
+            warn!("Asked for {}x{}, the camera delivers {}x{}",
+                  width,
+                  height,
+                  actual.0,
+                  actual.1);
+        }
+    }
+    if let Some(fps) = settings.fps {
+        cap.set(CapProp::Fps, fps);
+        let actual = cap.get(CapProp::Fps);
+        if (actual - fps).abs() > 0.5 {
+            warn!("Asked for {} fps, the camera delivers {}",
+                  fps,
+                  actual);
+        }
+    }
+    if let Some(exposure) = settings.exposure {
+        set(cap, CapProp::AutoExposure, MANUAL_EXPOSURE, "manual exposure");
+        set(cap, CapProp::Exposure, exposure, "the exposure");
+    }
+    if let Some(gain) = settings.gain {
+        set(cap, CapProp::Gain, gain, "the gain");
+    }
+    if let Some(kelvin) = settings.white_balance {
+        set(cap, CapProp::AutoWb, 0.0, "manual white balance");
+        set(cap, CapProp::WbTemperature, kelvin, "the white balance");
+    }
+}
+
+/// Sets `prop`, with a warning if the camera refuses.
+fn set(cap: &VideoCapture, prop: CapProp, value: f64, what: &str) {
+    if !cap.set(prop, value) {
+        warn!("The camera doesn't support setting {}", what);
+    }
+}
+
+/// Turns off automatic exposure and white balance at their current values.
+fn lock(cap: &VideoCapture) {
+    let exposure = cap.get(CapProp::Exposure);
+    let gain = cap.get(CapProp::Gain);
+    let kelvin = cap.get(CapProp::WbTemperature);
+    set(cap, CapProp::AutoExposure, MANUAL_EXPOSURE, "manual exposure");
+    set(cap, CapProp::Exposure, exposure, "the exposure");
+    set(cap, CapProp::Gain, gain, "the gain");
+    set(cap, CapProp::AutoWb, 0.0, "manual white balance");
+    set(cap, CapProp::WbTemperature, kelvin, "the white balance");
+    info!("Locked exposure {}, gain {} and white balance {} K",
+          exposure,
+          gain,
+          kelvin);
+}
+
+/// The code OpenCV uses for a pixel format.
+fn fourcc_code(fourcc: [char; 4]) -> u32 {
+    fourcc.iter()
+        .enumerate()
+        .fold(0, |code, (i, &c)| code | (c as u32 & 0xff) << (8 * i))
+}
+
+fn fourcc_name(code: u32) -> String {
+    (0..4).map(|i| ((code >> (8 * i)) & 0xff) as u8 as char).collect()
+}
+
+/// The images of the directory `pattern`, or those matching the file name
+/// pattern at its end, in natural order.
+fn list_images(pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let path = Path::new(pattern);
+    let (dir, name) = if path.is_dir() {
+        (path, None)
+    } else {
+        let name = path.file_name().and_then(|n| n.to_str());
+        (path.parent().unwrap_or_else(|| Path::new(".")), name)
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let entries = fs::read_dir(dir)
+        .map_err(|e| format!("cannot list {}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries.filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            let file = match p.file_name().and_then(|n| n.to_str()) {
+                Some(file) => file,
+                None => return false,
+            };
+            match name {
+                Some(name) => matches(name, file),
+                None => {
+                    match p.extension().and_then(|e| e.to_str()) {
+                        Some(e) => {
+                            IMAGE_EXTENSIONS.contains(&&e.to_lowercase()[..])
+                        }
+                        None => false,
+                    }
+                }
+            }
+        })
+        .collect();
+    if paths.is_empty() {
+        return Err(format!("no images match {}", pattern));
+    }
+    paths.sort_by(|a, b| {
+        natural_cmp(&a.to_string_lossy(), &b.to_string_lossy())
+    });
+    Ok(paths)
+}
+
+/// Whether `name` matches `pattern`, in which `*` stands for any run of
+/// characters.
+fn matches(pattern: &str, name: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || name.len() < first.len() + last.len() ||
+       !name.ends_with(last) {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Compares `a` and `b` with runs of digits compared as numbers, so that
+/// `frame_2` comes before `frame_10`.
+fn natural_cmp(mut a: &str, mut b: &str) -> Ordering {
+    loop {
+        match (a.chars().next(), b.chars().next()) {
+            (None, None) => return Ordering::Equal,
+            (None, _) => return Ordering::Less,
+            (_, None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (m, rest_a) = split_number(a);
+                let (n, rest_b) = split_number(b);
+                if m != n {
+                    return m.cmp(&n);
+                }
+                a = rest_a;
+                b = rest_b;
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a = &a[x.len_utf8()..];
+                b = &b[y.len_utf8()..];
+            }
+        }
+    }
+}
+
+/// The number `s` starts with, and the rest of `s`.
+fn split_number(s: &str) -> (u64, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    (s[..end].parse().unwrap_or(u64::MAX), &s[end..])
+}
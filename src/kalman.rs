@@ -0,0 +1,149 @@
+//! Kalman smoothing of the tracked region.
+//!
+//! Each channel (the centroid coordinates and the box dimensions) is filtered
+//! with an independent constant-velocity model. The process noise `q` is the
+//! spectral density of the (white) acceleration, in px²/s³, and the
+//! measurement noise `r` is the variance of a CAMShift measurement, in px².
+//! Larger `q` follows the target more eagerly; larger `r` smooths more.
+use crate::filter::Filter;
+use std::time::Instant;
+
+/// Default process noise, in px²/s³.
+pub const DEFAULT_PROCESS_NOISE: f64 = 500.0;
+/// Default measurement noise, in px².
+pub const DEFAULT_MEASUREMENT_NOISE: f64 = 16.0;
+
+/// State of one coordinate: position, velocity and their covariance.
+#[derive(Clone, Copy, Debug)]
+struct Axis {
+    x: f64,
+    v: f64,
+    p: [[f64; 2]; 2],
+}
+
+impl Axis {
+    fn new(x: f64, r: f64) -> Axis {
+        Axis {
+            x,
+            v: 0.0,
+            // We know the position up to the measurement noise, and nothing
+            // about the velocity yet.
+            p: [[r, 0.0], [0.0, 1e6]],
+        }
+    }
+
+    fn predict(&mut self, dt: f64, q: f64) {
+        self.x += self.v * dt;
+        let p = self.p;
+        let (dt2, dt3) = (dt * dt, dt * dt * dt);
+        self.p = [[p[0][0] + dt * (p[1][0] + p[0][1]) + dt2 * p[1][1] +
+                   q * dt3 / 3.0,
+                   p[0][1] + dt * p[1][1] + q * dt2 / 2.0],
+                  [p[1][0] + dt * p[1][1] + q * dt2 / 2.0,
+                   p[1][1] + q * dt]];
+    }
+
+    fn update(&mut self, z: f64, r: f64) {
+        let p = self.p;
+        let s = p[0][0] + r;
+        let k = [p[0][0] / s, p[1][0] / s];
+        let y = z - self.x;
+        self.x += k[0] * y;
+        self.v += k[1] * y;
+        self.p = [[(1.0 - k[0]) * p[0][0], (1.0 - k[0]) * p[0][1]],
+                  [p[1][0] - k[1] * p[0][0], p[1][1] - k[1] * p[0][1]]];
+    }
+}
+
+/// `KalmanFilter` smooths every channel with its own constant-velocity
+/// model.
+pub struct KalmanFilter {
+    q: f64,
+    r: f64,
+    last: Option<Instant>,
+    axes: Vec<Axis>,
+}
+
+impl KalmanFilter {
+    pub fn new(process_noise: f64, measurement_noise: f64) -> KalmanFilter {
+        KalmanFilter {
+            q: process_noise,
+            r: measurement_noise,
+            last: None,
+            axes: Vec::new(),
+        }
+    }
+}
+
+impl Filter for KalmanFilter {
+    fn apply(&mut self, t: Instant, values: &mut [f64]) {
+        let (q, r) = (self.q, self.r);
+        match self.last {
+            Some(last) => {
+                let dt = if t > last {
+                    (t - last).as_secs_f64()
+                } else {
+                    0.0
+                };
+                for (axis, v) in self.axes.iter_mut().zip(values.iter_mut()) {
+                    axis.predict(dt, q);
+                    axis.update(*v, r);
+                    *v = axis.x;
+                }
+            }
+            None => {
+                self.axes = values.iter().map(|v| Axis::new(*v, r)).collect()
+            }
+        }
+        self.last = Some(t);
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+        self.axes.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::filter::Filter;
+    use super::{DEFAULT_MEASUREMENT_NOISE, DEFAULT_PROCESS_NOISE,
+                KalmanFilter};
+
+    /// What a default filter makes of `inputs`, sampled at 30 Hz.
+    fn run(kalman: &mut KalmanFilter, inputs: &[f64]) -> Vec<f64> {
+        let start = Instant::now();
+        inputs.iter()
+            .enumerate()
+            .map(|(i, &v)| {
+                let mut values = [v];
+                let t = start + Duration::from_secs_f64(i as f64 / 30.0);
+                kalman.apply(t, &mut values);
+                values[0]
+            })
+            .collect()
+    }
+
+    fn kalman() -> KalmanFilter {
+        KalmanFilter::new(DEFAULT_PROCESS_NOISE, DEFAULT_MEASUREMENT_NOISE)
+    }
+
+    #[test]
+    fn follows_a_constant_speed_without_lag() {
+        let inputs: Vec<f64> = (0..=60).map(|i| i as f64 * 10.0).collect();
+        let out = run(&mut kalman(), &inputs);
+        assert!((out[60] - 600.0).abs() < 0.1, "{}", out[60]);
+    }
+
+    #[test]
+    fn damps_a_single_jump() {
+        let mut kalman = kalman();
+        let mut inputs = vec![100.0; 30];
+        inputs.push(150.0);
+        let out = run(&mut kalman, &inputs);
+        assert!(out[30] > 100.0 && out[30] < 125.0, "{}", out[30]);
+        kalman.reset();
+        assert_eq!(run(&mut kalman, &[7.0]), [7.0]);
+    }
+}
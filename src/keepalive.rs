@@ -0,0 +1,148 @@
+//! Keepalive samples while nothing new is sent, with `--keepalive HZ`.
+//!
+//! ESP can't tell a still stream from a dead connection: with `--on-lost
+//! skip`, before a selection, or while tracking is stopped, no samples are
+//! sent at all. With `--keepalive HZ`, a sample is sent at that rate whenever
+//! no other was: the last values sent (`--keepalive-values last`, the
+//! default), or NaN (`--keepalive-values nan`) as an explicit idle marker.
+//! Keepalive samples repeat the sequence number of the last sample sent, with
+//! the current time, so consumers can tell them apart from new data. Paused
+//! streams stay silent.
+use crate::sample::Sample;
+use crate::sink::Sink;
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// What keepalive samples carry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Idle {
+    /// The values of the last sample sent, or NaN before the first one.
+    Last,
+    /// NaN for every value.
+    Nan,
+}
+
+impl FromStr for Idle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Idle, String> {
+        match s {
+            "last" => Ok(Idle::Last),
+            "nan" => Ok(Idle::Nan),
+            _ => Err(format!("unknown keepalive values `{}`; expected last or \
+                              nan",
+                             s)),
+        }
+    }
+}
+
+/// `Keepalive` forwards samples to the wrapped sink and, on `tick`, sends a
+/// keepalive sample when none was sent for an interval.
+pub struct Keepalive<S> {
+    inner: S,
+    /// The interval between keepalives; `None` sends none.
+    interval: Option<Duration>,
+    idle: Idle,
+    dims: usize,
+    start: Instant,
+    /// The last sample sent.
+    last: Option<Sample>,
+    sent: Instant,
+}
+
+impl<S: Sink> Keepalive<S> {
+    /// Sends `hz` keepalives per second while idle, if any, for samples of
+    /// `dims` values whose times count from `start`.
+    pub fn new(inner: S,
+               hz: Option<f64>,
+               idle: Idle,
+               dims: usize,
+               start: Instant)
+               -> Keepalive<S> {
+        Keepalive {
+            inner,
+            interval: hz.map(|hz| Duration::from_secs_f64(1.0 / hz)),
+            idle,
+            dims,
+            start,
+            last: None,
+            sent: start,
+        }
+    }
+
+    /// Sends a keepalive if nothing was sent for an interval at `now`.
+    pub fn tick(&mut self, now: Instant) -> io::Result<()> {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return Ok(()),
+        };
+        if now.saturating_duration_since(self.sent) < interval {
+            return Ok(());
+        }
+        self.sent = now;
+        let time = now.saturating_duration_since(self.start).as_secs_f64();
+        let sample = match self.last {
+            Some(ref last) if self.idle == Idle::Last => {
+                Sample::new(last.seq, time, last.values.clone())
+            }
+            ref last => {
+                let seq = last.as_ref().map_or(0, |l| l.seq);
+                Sample::new(seq, time, vec![f64::NAN; self.dims])
+            }
+        };
+        self.inner.send(&sample)
+    }
+}
+
+impl<S: Sink> Sink for Keepalive<S> {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        self.sent = Instant::now();
+        self.last = Some(sample.clone());
+        self.inner.send(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample::Sample;
+    use crate::sink::Sink;
+    use std::time::{Duration, Instant};
+    use super::{Idle, Keepalive};
+
+    #[test]
+    fn repeats_the_last_sample_while_idle() {
+        let start = Instant::now();
+        let mut sink = Keepalive::new(Vec::new(),
+                                      Some(2.0),
+                                      Idle::Last,
+                                      2,
+                                      start);
+        sink.send(&Sample::new(7, 0.0, vec![1.0, 2.0])).unwrap();
+        let now = Instant::now();
+        sink.tick(now + Duration::from_millis(100)).unwrap();
+        assert_eq!(sink.inner.len(), 1);
+        sink.tick(now + Duration::from_millis(600)).unwrap();
+        assert_eq!(sink.inner.len(), 2);
+        assert_eq!(sink.inner[1].seq, 7);
+        assert_eq!(sink.inner[1].values, vec![1.0, 2.0]);
+        assert!(sink.inner[1].time >= 0.6);
+        // The next one waits for another interval.
+        sink.tick(now + Duration::from_millis(700)).unwrap();
+        assert_eq!(sink.inner.len(), 2);
+    }
+
+    #[test]
+    fn sends_nan_as_the_idle_marker() {
+        let start = Instant::now();
+        let mut sink = Keepalive::new(Vec::new(),
+                                      Some(1.0),
+                                      Idle::Nan,
+                                      2,
+                                      start);
+        sink.tick(start + Duration::from_secs(1)).unwrap();
+        assert_eq!(sink.inner.len(), 1);
+        assert!(sink.inner[0].values.iter().all(|v| v.is_nan()));
+        assert_eq!(sink.inner[0].values.len(), 2);
+    }
+}
@@ -0,0 +1,129 @@
+//! Keyboard commands of the main window.
+//!
+//! | Key          | Command                                         |
+//! |--------------|-------------------------------------------------|
+//! | `ESC`, `q`   | quit                                            |
+//! | `r`          | stop tracking all targets, to select again      |
+//! | space        | pause or resume sending to ESP                  |
+//! | `s`          | save the displayed frame as a PNG snapshot      |
+//! | `k`          | turn the smoothing filters on or off            |
+//! | `b`          | show or hide the backprojection                 |
+//! | `z`          | show or hide the zoomed view of the target      |
+//! | `h`          | show or hide the heads-up display               |
+//! | `g`          | show or hide the plot of the samples            |
+//! | `t`          | print the values of the trackbars               |
+//! | `l`          | lock the camera exposure again once it settled  |
+//! | `c`          | recalibrate the colors from the reference patch |
+//! | `p`          | calibrate the `--plane` by clicking its corners |
+//! | `i`          | write the quality report of the session so far  |
+//! | arrow keys   | move the last selection                         |
+//! | `+`, `-`     | grow or shrink the last selection               |
+//! | `u`          | undo the last selection                         |
+//! | `1` to `9`   | start tracking from a named region              |
+//! | `0` to `9`   | with `--dataset`, tag the class performed       |
+
+/// What a key press asks for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Quit,
+    Reset,
+    Pause,
+    Snapshot,
+    Smoothing,
+    BackProject,
+    Zoom,
+    Hud,
+    Plot,
+    PrintTrackbars,
+    Relock,
+    Recalibrate,
+    Plane,
+    Report,
+    /// Move the last selection one step left (-1) or right (1), and up (-1)
+    /// or down (1).
+    Nudge(i32, i32),
+    /// Grow (1) or shrink (-1) the last selection by one step on each side.
+    Grow(i32),
+    Undo,
+    /// A digit: a named region, or a class with `--dataset`.
+    Number(u32),
+}
+
+/// The arrow keys left, up, right and down, as `waitKeyEx` returns them
+/// with GTK and Cocoa, whose modifiers are above the lower 16 bits, and
+/// with Windows and Qt.
+const ARROWS: [[i32; 4]; 4] = [[0xff51, 0xff52, 0xff53, 0xff54],
+                               [0xf702, 0xf700, 0xf703, 0xf701],
+                               [0x25_0000, 0x26_0000, 0x27_0000, 0x28_0000],
+                               [0x0100_0012,
+                                0x0100_0013,
+                                0x0100_0014,
+                                0x0100_0015]];
+
+/// The ASCII character of `key`, as returned by `show`, without the
+/// modifiers. `None` for other keys, or when no key was pressed.
+pub fn character(key: i32) -> Option<char> {
+    match key & 0xffff {
+        code @ 0..=0x7f if key >= 0 => Some(code as u8 as char),
+        _ => None,
+    }
+}
+
+/// The command of `key`, as returned by `show`. Negative when no key was
+/// pressed.
+pub fn command(key: i32) -> Option<Command> {
+    let arrow = ARROWS.iter().find_map(|codes| {
+        codes.iter().position(|&code| code == key || code == key & 0xffff)
+    });
+    if let Some(arrow) = arrow {
+        let (dx, dy) = [(-1, 0), (0, -1), (1, 0), (0, 1)][arrow];
+        return Some(Command::Nudge(dx, dy));
+    }
+    let c = character(key)?;
+    match c {
+        '\x1b' | 'q' => Some(Command::Quit),
+        'r' => Some(Command::Reset),
+        ' ' => Some(Command::Pause),
+        's' => Some(Command::Snapshot),
+        'k' => Some(Command::Smoothing),
+        'b' => Some(Command::BackProject),
+        'z' => Some(Command::Zoom),
+        'h' => Some(Command::Hud),
+        'g' => Some(Command::Plot),
+        't' => Some(Command::PrintTrackbars),
+        'l' => Some(Command::Relock),
+        'c' => Some(Command::Recalibrate),
+        'p' => Some(Command::Plane),
+        'i' => Some(Command::Report),
+        '+' | '=' => Some(Command::Grow(1)),
+        '-' => Some(Command::Grow(-1)),
+        'u' => Some(Command::Undo),
+        '0'..='9' => Some(Command::Number(c as u32 - '0' as u32)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, command};
+
+    #[test]
+    fn reads_the_arrow_keys_of_every_backend() {
+        // GTK with Shift held, Windows, Cocoa and Qt.
+        assert_eq!(command(0x1_ff51), Some(Command::Nudge(-1, 0)));
+        assert_eq!(command(0x26_0000), Some(Command::Nudge(0, -1)));
+        assert_eq!(command(0xf703), Some(Command::Nudge(1, 0)));
+        assert_eq!(command(0x0100_0015), Some(Command::Nudge(0, 1)));
+        // Not `Q`, which the lower byte of the GTK left arrow is.
+        assert_eq!(command(i32::from(b'Q')), None);
+    }
+
+    #[test]
+    fn reads_characters_without_the_modifiers() {
+        assert_eq!(command(0x1_002b), Some(Command::Grow(1)));
+        assert_eq!(command(i32::from(b'7')), Some(Command::Number(7)));
+        // Insert in GTK, whose lower byte is `c`.
+        assert_eq!(command(0xff63), None);
+        assert_eq!(command(-1), None);
+    }
+}
@@ -0,0 +1,155 @@
+//! The predictions of ESP on the video, with their names and colors.
+//!
+//! ESP sends class numbers back. The config file names them and gives them
+//! colors, with RGB components, and optionally a sound to play; see the
+//! [`feedback`](../feedback/index.html) module:
+//!
+//! ```toml
+//! [[labels]]
+//! class = 1
+//! name = "wave"
+//! color = [0, 200, 255]
+//! sound = "sounds/wave.wav"
+//! ```
+//!
+//! The current prediction is shown in large type in the top left corner, in
+//! its color, and the ones before it in a strip in the bottom right corner,
+//! newest on the right, fading as they get older. Labels that are not
+//! listed are shown as received, in green.
+use crate::feedback::Feedback;
+use crate::prediction::Prediction;
+use rust_vision::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of past predictions in the strip.
+const HISTORY: usize = 8;
+/// How long past predictions take to fade out.
+const FADE: Duration = Duration::from_secs(10);
+/// Predictions of the same class at least this far apart are separate
+/// gestures; closer ones continue the same.
+const APART: Duration = Duration::from_secs(1);
+/// The size of a prediction in the strip, and the gap between them.
+const BLOCK_WIDTH: i32 = 48;
+const BLOCK_HEIGHT: i32 = 12;
+const GAP: i32 = 4;
+/// Unlisted labels are green, in blue, green and red.
+const GREEN: [i32; 3] = [0, 255, 0];
+
+/// The name and color of a class.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct LabelSpec {
+    pub class: u32,
+    pub name: String,
+    /// Red, green and blue.
+    pub color: Option<[u8; 3]>,
+    /// The sound file played when the class is predicted.
+    pub sound: Option<String>,
+}
+
+impl LabelSpec {
+    /// The color in blue, green and red.
+    fn bgr(&self) -> [i32; 3] {
+        match self.color {
+            Some([r, g, b]) => [b.into(), g.into(), r.into()],
+            None => GREEN,
+        }
+    }
+}
+
+/// A prediction as displayed.
+#[derive(Clone, Debug)]
+struct Shown {
+    name: String,
+    /// Blue, green and red.
+    color: [i32; 3],
+    /// When it was last received.
+    received: Instant,
+}
+
+/// `Labels` keeps track of the predictions, and draws them.
+pub struct Labels {
+    specs: Vec<LabelSpec>,
+    /// The current prediction first.
+    history: VecDeque<Shown>,
+    feedback: Option<Feedback>,
+}
+
+impl Labels {
+    /// Names the classes of `specs`, and announces new predictions with
+    /// `feedback`, if any.
+    pub fn new(specs: Vec<LabelSpec>, feedback: Option<Feedback>) -> Labels {
+        Labels {
+            specs,
+            history: VecDeque::with_capacity(HISTORY + 1),
+            feedback,
+        }
+    }
+
+    /// The spec of the class ESP sent as `label`, e.g. `1` or `1.0`.
+    fn spec(&self, label: &str) -> Option<&LabelSpec> {
+        let class = label.parse::<f64>().ok()?;
+        self.specs.iter().find(|s| f64::from(s.class) == class)
+    }
+
+    /// Adds `prediction` to the history and announces it, unless it
+    /// continues the current one.
+    pub fn update(&mut self, prediction: &Prediction) {
+        let (name, color, sound) = match self.spec(&prediction.label) {
+            Some(spec) => (spec.name.clone(), spec.bgr(), spec.sound.clone()),
+            None => (prediction.label.clone(), GREEN, None),
+        };
+        if let Some(current) = self.history.front_mut() {
+            let gap = prediction.received
+                .saturating_duration_since(current.received);
+            if current.name == name && gap < APART {
+                current.received = prediction.received;
+                return;
+            }
+        }
+        if let Some(ref feedback) = self.feedback {
+            feedback.announce(&name, sound.as_ref().map(String::as_str));
+        }
+        self.history.push_front(Shown {
+            name,
+            color,
+            received: prediction.received,
+        });
+        self.history.truncate(HISTORY + 1);
+    }
+
+    /// Draws the current prediction if it is still `fresh`, and the strip
+    /// of past ones.
+    pub fn draw(&self, frame: &Mat, fresh: bool) {
+        let mut past = self.history.iter();
+        if fresh {
+            if let Some(current) = past.next() {
+                let [b, g, r] = current.color;
+                frame.put_text(&current.name,
+                               Point2i::new(10, 40),
+                               HersheyFonts::HersheySimplex,
+                               1.5,
+                               Scalar::new(b, g, r, 0));
+            }
+        }
+        let now = Instant::now();
+        for (i, shown) in past.take(HISTORY).enumerate() {
+            let age = now.duration_since(shown.received).as_secs_f64();
+            let fade = 1.0 - age / FADE.as_secs_f64();
+            if fade <= 0.0 {
+                break;
+            }
+            let block = Rect::new(frame.cols - 10 -
+                                  (i as i32 + 1) * (BLOCK_WIDTH + GAP) +
+                                  GAP,
+                                  frame.rows - 10 - BLOCK_HEIGHT,
+                                  BLOCK_WIDTH,
+                                  BLOCK_HEIGHT);
+            let faded = |c: i32| (f64::from(c) * fade) as i32;
+            let [b, g, r] = shown.color;
+            let color = Scalar::new(faded(b), faded(g), faded(r), 0);
+            frame.rectangle_custom(block, color, -1, LineTypes::Line8);
+        }
+    }
+}
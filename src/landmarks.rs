@@ -0,0 +1,103 @@
+//! Facial landmarks: selected keypoints of the largest face.
+//!
+//! Landmarks follow the 68-point iBUG scheme used by OpenCV's LBF facemark
+//! model (`lbfmodel.yaml`). Named landmarks average a group of points; a
+//! bare number picks a single point. Sides are as seen on screen, which is
+//! mirrored.
+use rust_vision::{FacemarkLBF, Mat, Point2f, Rect};
+use std::str::FromStr;
+
+/// Number of points of the iBUG scheme.
+const POINTS: usize = 68;
+
+/// `LandmarkSpec` is one streamed landmark.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LandmarkSpec {
+    /// Prefix of the dimension names, e.g. `nose` for `nose_x`.
+    pub name: String,
+    /// The points that are averaged.
+    pub points: Vec<usize>,
+}
+
+impl FromStr for LandmarkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LandmarkSpec, String> {
+        let points: Vec<usize> = match s {
+            "chin" => vec![8],
+            "nose" => vec![30],
+            "left_eye" => (36..42).collect(),
+            "right_eye" => (42..48).collect(),
+            "left_brow" => (17..22).collect(),
+            "right_brow" => (22..27).collect(),
+            "mouth" => (48..68).collect(),
+            _ => {
+                match s.parse::<usize>() {
+                    Ok(i) if i < POINTS => vec![i],
+                    _ => return Err(format!("unknown landmark `{}`", s)),
+                }
+            }
+        };
+        let name = if points.len() == 1 && s.parse::<usize>().is_ok() {
+            format!("p{}", s)
+        } else {
+            s.to_string()
+        };
+        Ok(LandmarkSpec { name, points })
+    }
+}
+
+/// Parses a comma-separated list such as `nose,chin`.
+pub fn parse_list(s: &str) -> Result<Vec<LandmarkSpec>, String> {
+    s.split(',').map(|l| l.trim().parse()).collect()
+}
+
+/// `Landmarks` fits the facemark model to a face and extracts the selected
+/// landmarks.
+pub struct Landmarks {
+    facemark: FacemarkLBF,
+    specs: Vec<LandmarkSpec>,
+}
+
+impl Landmarks {
+    /// Loads the LBF model at `path`.
+    pub fn new(path: &str,
+               specs: Vec<LandmarkSpec>)
+               -> Result<Landmarks, String> {
+        let mut facemark = FacemarkLBF::new();
+        if !facemark.load_model(path) {
+            return Err(format!("cannot load the landmark model {}", path));
+        }
+        Ok(Landmarks { facemark, specs })
+    }
+
+    /// All points of the `face` in `frame`, if the model could be fitted.
+    pub fn fit(&self, frame: &Mat, face: Rect) -> Option<Vec<Point2f>> {
+        self.facemark
+            .fit(frame, &[face])
+            .into_iter()
+            .next()
+            .filter(|points| points.len() == POINTS)
+    }
+
+    /// `x y` of every selected landmark, or NaN without a face.
+    pub fn values(&self, points: Option<&Vec<Point2f>>) -> Vec<f64> {
+        let mut values = Vec::with_capacity(2 * self.specs.len());
+        for spec in &self.specs {
+            match points {
+                Some(points) => {
+                    let n = spec.points.len() as f64;
+                    let (x, y) = spec.points
+                        .iter()
+                        .fold((0.0, 0.0), |(x, y), &i| {
+                            (x + points[i].x as f64, y + points[i].y as f64)
+                        });
+                    values.push(x / n);
+                    values.push(y / n);
+                }
+                None => values.extend_from_slice(&[f64::NAN, f64::NAN]),
+            }
+        }
+        values
+    }
+}
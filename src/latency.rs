@@ -0,0 +1,119 @@
+//! End-to-end latency measurement, with `--measure-latency`.
+//!
+//! Every sample is stamped with the time its frame was captured (the ASCII
+//! format gets `--ascii-stamps`; the others are always stamped). The ESP
+//! sink then measures how long after the capture each sample was written to
+//! the connection. If the server echoes what it receives, as
+//! `esp-vision echo` does in place of ESP, the echoes are matched to the
+//! samples in order, which measures the round trip from the camera to the
+//! server and back. Both distributions are printed on exit.
+use crate::bench;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Name of the echo server subcommand.
+pub const ECHO_COMMAND: &str = "echo";
+
+/// `Latency` collects the latencies of the samples of one sink.
+pub struct Latency {
+    /// The instant sample times count from.
+    epoch: Instant,
+    /// From capture until the sample was written.
+    written: Mutex<Vec<Duration>>,
+    /// From capture until the sample was echoed back.
+    echoed: Mutex<Vec<Duration>>,
+    /// The length and capture time of the samples not echoed yet, and the
+    /// bytes of the first already echoed.
+    pending: Mutex<(VecDeque<(usize, Instant)>, usize)>,
+}
+
+impl Latency {
+    /// Sample times are seconds since `epoch`.
+    pub fn new(epoch: Instant) -> Latency {
+        Latency {
+            epoch,
+            written: Mutex::new(Vec::new()),
+            echoed: Mutex::new(Vec::new()),
+            pending: Mutex::new((VecDeque::new(), 0)),
+        }
+    }
+
+    /// Notes that `len` bytes of the sample at `time` were just written.
+    pub fn written(&self, time: f64, len: usize) {
+        let captured = self.epoch + Duration::from_secs_f64(time.max(0.0));
+        self.written.lock().unwrap().push(captured.elapsed());
+        self.pending.lock().unwrap().0.push_back((len, captured));
+    }
+
+    /// Notes that `n` more bytes were echoed back.
+    pub fn echoed(&self, mut n: usize) {
+        let mut pending = self.pending.lock().unwrap();
+        let (ref mut samples, ref mut received) = *pending;
+        while let Some(&(len, captured)) = samples.front() {
+            if *received + n < len {
+                *received += n;
+                return;
+            }
+            n -= len - *received;
+            *received = 0;
+            samples.pop_front();
+            self.echoed.lock().unwrap().push(captured.elapsed());
+        }
+    }
+
+    /// Prints the distributions measured so far.
+    pub fn report(&self) {
+        println!("Latency from capture:");
+        report("written", &mut self.written.lock().unwrap());
+        report("echoed", &mut self.echoed.lock().unwrap());
+    }
+}
+
+fn report(name: &str, times: &mut Vec<Duration>) {
+    if times.is_empty() {
+        println!("{:>10}: no samples", name);
+        return;
+    }
+    times.sort();
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    println!("{:>10}: {} samples, p50 {:.1} ms, p90 {:.1} ms, p99 {:.1} ms, \
+              max {:.1} ms",
+             name,
+             times.len(),
+             ms(bench::percentile(times, 0.5)),
+             ms(bench::percentile(times, 0.9)),
+             ms(bench::percentile(times, 0.99)),
+             ms(times[times.len() - 1]));
+}
+
+/// Runs `esp-vision echo [ADDR]`: a server that sends back whatever it
+/// receives, to stand in for ESP while measuring latency.
+pub fn run_echo(args: &[String]) -> Result<(), String> {
+    let addr = args.get(2).map_or("127.0.0.1:8001", String::as_str);
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| format!("cannot listen on {}: {}", addr, e))?;
+    info!("Echoing on {}", addr);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || echo(stream));
+            }
+            Err(e) => warn!("Echo error: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn echo(stream: TcpStream) {
+    let peer = stream.peer_addr().ok();
+    if let Ok(mut writer) = stream.try_clone() {
+        let mut reader = stream;
+        if let Ok(n) = io::copy(&mut reader, &mut writer) {
+            info!("Echoed {} bytes to {:?}", n, peer);
+        }
+    }
+}
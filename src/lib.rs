@@ -0,0 +1,2257 @@
+//! Example applications that demonstrate how to use OpenCV and ESP ([repository
+//! link](https://github.com/nebgnahz/esp-vision)).
+//!
+//! - OpenCV is wrapped by a Rust library (currently a private repository)
+//! - [ESP](https://github.com/damellis/ESP): Example-based Sensor Predictions
+//!
+//! This application runs OpenCV
+//! [CAMShift](http://docs.opencv.org/3.1.0/db/df8/tutorial_py_meanshift.html)
+//! algorithm. Users select a region to track and the centroid of the tracked
+//! region is sent over to ESP (through a TCP connection).
+//!
+//! # Getting Started
+//!
+//! Below is a possible ESP example that you can use (note the TCP port `8001`).
+//!
+//! ```c++
+//! #include <ESP.h>
+//!
+//! TcpInputStream stream(8001, 2);
+//! GestureRecognitionPipeline pipeline;
+//!
+//! int timeout = 500;      // milliseconds
+//! double null_rej = 0.4;
+//!
+//! void setup() {
+//!     stream.setLabelsForAllDimensions({"x", "y", "z"});
+//!     useInputStream(stream);
+//!
+//!     DTW dtw(false, true, null_rej);
+//!     dtw.enableTrimTrainingData(true, 0.1, 75);
+//!
+//!     pipeline.setClassifier(dtw);
+//!     pipeline.addPostProcessingModule(ClassLabelTimeoutFilter(timeout));
+//!     usePipeline(pipeline);
+//!
+//!     registerTuneable(
+//!         null_rej, 0.1, 5.0, "Variability",
+//!         "How different from the training data a new gesture can be and "
+//!         "still be considered the same gesture. The higher the number, the "
+//!         "more different it can be.",
+//!         [](double new_null_rej) {
+//!             pipeline.getClassifier()->setNullRejectionCoeff(new_null_rej);
+//!             pipeline.getClassifier()->recomputeNullRejectionThresholds();
+//!         });
+//!
+//!     registerTuneable(
+//!         timeout, 1, 3000, "Timeout",
+//!         "How long (in milliseconds) to wait after recognizing a "
+//!         "gesture before recognizing another one.",
+//!         [](double new_timeout) {
+//!             ClassLabelTimeoutFilter* filter =
+//!                 dynamic_cast<ClassLabelTimeoutFilter*>(
+//!                     pipeline.getPostProcessingModule(0));
+//!             assert(filter != nullptr);
+//!             filter->setTimeoutDuration(new_timeout);
+//!         });
+//! }
+//!```
+//!
+//! After running the ESP example, in this application, type `cargo run` would
+//! bring up the application.
+//!
+//! `--profile` picks working settings for a common use case, which any other
+//! option or setting still overrides, parameter by parameter: `hand` tracks
+//! a skin-colored hand with CAMShift and a Kalman filter, `face` the largest
+//! face found with OpenCV's `haarcascade_frontalface_default.xml` (in the
+//! working directory, or given with `--face-cascade`), and `marker` red and
+//! blue markers. All of them send positions in `[0, 1]`.
+//!
+//! ESP on another machine is given with `--server 192.168.1.10:8001`. If it
+//! is advertised over mDNS as `_esp._tcp`, `--discover` finds it instead; see
+//! the [`discover`](discover/index.html) module.
+//!
+//! Before involving ESP at all, `--dry-run` prints the samples on the
+//! console under the names of their dimensions instead of connecting, and
+//! `--expect-dims 2` refuses to start unless they match the
+//! `TcpInputStream(8001, 2)` above; see the [`dry_run`](dry_run/index.html)
+//! module. `--print-manifest` prints the dimensions that would be sent, with
+//! their units, and the lines declaring them in the example, ready to
+//! paste; see the [`manifest`](manifest/index.html) module.
+//!
+//! # Trackers
+//!
+//! CAMShift needs a target with a distinctive hue. If its hue is shared with
+//! skin, as a red one's is, `--histogram hs` adds the saturation to the
+//! histogram (`--saturation-bins`, 8 by default). Over a long session, the
+//! lighting drifts away from the selection's; `--hist-adapt 0.02` blends the
+//! tracked window into the histogram on every frame whose confidence reaches
+//! `--hist-adapt-confidence`. `--tracker kcf` and
+//! `--tracker csrt` select OpenCV's correlation filter trackers instead; they
+//! are initialized from the same selection and produce the same features. On
+//! low-power machines such as a Raspberry Pi, `--tracker mosse` is fast
+//! enough to keep up with the camera where CAMShift is not. For long
+//! unattended runs, `--tracker ensemble` runs CAMShift and CSRT together and
+//! keeps the target as long as either has it. See the
+//! [`tracker`](tracker/index.html) module.
+//!
+//! With a fixed camera, `--tracker mog2` (or `knn`) learns the background and
+//! tracks the largest moving blob instead of a color. It starts right away;
+//! selecting a region restricts the search to it.
+//!
+//! `--tracker dnn` needs no selection either: an OpenCV DNN detector
+//! (`--dnn-model` and `--dnn-config`, MobileNet-SSD or `--dnn-kind yolo`)
+//! looks for objects of `--dnn-class` scoring at least `--dnn-confidence`
+//! every `--detect-every` frames, and a MOSSE tracker follows the target in
+//! between.
+//!
+//! Rigid, textured targets such as a printed card are best followed with
+//! `--tracker template`, which matches the selected patch within a window
+//! `--search-expansion` times its size. `--template-update` decides whether
+//! the patch is replaced by later matches: `never`, `always`, or `confident`
+//! (the default) for matches scoring at least `--update-confidence`.
+//!
+//! `--face-cascade FILE` starts tracking automatically: a Haar or LBP cascade
+//! such as OpenCV's `haarcascade_frontalface_default.xml` looks for faces
+//! until one is found, and tracking starts from the center of the largest.
+//! A region can still be selected by hand at any time.
+//!
+//! For kiosk-style installations, `--skin-zone x,y,w,h` starts tracking as
+//! soon as a hand enters the zone: skin tones are segmented in YCrCb, and the
+//! largest skin blob inside the zone is tracked.
+//!
+//! When the regions of interest are known in advance, such as with a camera
+//! above a table, name them in the config file (see the
+//! [`config`](config/index.html) module). `--region NAME` starts tracking
+//! from one, and the keys `1` to `9` select them in order at any time.
+//! Without a display to select on, `--roi x,y,w,h` gives the region directly.
+//!
+//! A tracker of your own plugs in without forking this crate: depend on it as
+//! a library, implement [`Tracker`](trait.Tracker.html), register it under a
+//! name and run the application from your `main`. `--tracker NAME` then
+//! selects it like a built-in one:
+//!
+//! ```no_run
+//! use esp_vision::rust_vision::{Mat, Rect};
+//! use esp_vision::{Region, Track, Tracker, VisionParams};
+//!
+//! /// Stays where it was started.
+//! struct Still(Rect);
+//!
+//! impl Tracker for Still {
+//!     fn name(&self) -> &'static str {
+//!         "still"
+//!     }
+//!
+//!     fn init(&mut self, _: &Mat, selection: Rect, _: &VisionParams) {
+//!         self.0 = selection;
+//!     }
+//!
+//!     fn update(&mut self, _: &Mat, _: &VisionParams) -> Track {
+//!         Track {
+//!             region: Region::from_rect(self.0),
+//!             window: self.0,
+//!             confidence: 1.0,
+//!             points: Vec::new(),
+//!         }
+//!     }
+//! }
+//!
+//! fn main() {
+//!     esp_vision::register_tracker("still", || {
+//!             Box::new(Still(Rect::default()))
+//!         })
+//!         .unwrap();
+//!     esp_vision::main();
+//! }
+//! ```
+//!
+//! # Input
+//!
+//! Frames come from the first camera unless `--input` says otherwise:
+//! `--input camera:1` picks another camera (`esp-vision list-cameras` prints
+//! the cameras found, with their names and resolutions), and
+//! `--input video:clip.mp4` replays a recording at its own frame rate, or
+//! `--speed` times that, to debug on exactly the same frames again.
+//! `--input images:frames/` reads a directory of numbered images, one every
+//! `--frame-delay` milliseconds. `--loop` starts recorded input over at the
+//! end. Network cameras are given by their URL, e.g.
+//! `--input rtsp://192.168.1.20/stream1`; the stream is reopened when it
+//! drops. So is a camera that fails or is unplugged, with the outage shown
+//! in the window, until `--camera-timeout SECS`; see the
+//! [`watchdog`](watchdog/index.html) module. An ESP32-CAM board serving
+//! MJPEG replaces the USB camera with
+//! `--input mjpeg:http://192.168.1.30:81/stream`. On a Raspberry Pi, whose
+//! CSI camera OpenCV doesn't read reliably, builds with the `rpi` feature
+//! read V4L2 devices directly with `--input v4l2:0`; see the
+//! [`v4l2`](v4l2/index.html) module. Intel RealSense cameras, in builds with
+//! the `realsense` feature, add the distance of every target in millimeters
+//! to what is sent with `--input realsense`; see the
+//! [`realsense`](realsense/index.html) module. Without a camera,
+//! `--input synthetic:circle` renders a colored disc moving in a circle,
+//! which the tests also track; see the [`input`](input/index.html) and
+//! [`synthetic`](synthetic/index.html) modules. `--demo` goes further for a
+//! first try or a presentation: a ball bounces around a synthetic scene,
+//! selected from the first frame and tracked and streamed for as long as the
+//! application runs.
+//!
+//! Cameras deliver their default format unless asked otherwise. For gestures,
+//! a high frame rate matters more than resolution: `--resolution 640x480
+//! --fps 60`, often with `--pixel-format MJPG`, which most USB cameras need
+//! to go beyond 30 fps. What the camera actually delivers is checked, with a
+//! warning when it differs.
+//!
+//! Automatic exposure and white balance change the hue of the target as the
+//! lighting changes, which breaks the histogram of CAMShift. `--lock-camera`
+//! locks them once the camera settled, and `l` lets the camera adapt to new
+//! lighting and locks them again. `--exposure`, `--gain` and
+//! `--white-balance` fix them to given values instead.
+//!
+//! Wide-angle lenses bend straight lines near the edges of the frame, and
+//! gesture trajectories with them. `--undistort` removes the distortion
+//! described by `--intrinsics`, either a TOML file (see the
+//! [`camera`](camera/index.html) module) or the YAML file written by
+//! OpenCV's calibration sample, before tracking. `esp-vision calibrate`
+//! measures the intrinsics from views of a printed checkerboard; see the
+//! [`calibrate`](calibrate/index.html) module.
+//!
+//! `[[preprocess]]` tables in the config file crop, downscale, blur or
+//! brighten every frame before it is tracked; downscaling is the first thing
+//! to try when tracking can't keep up with the camera. See the
+//! [`preprocess`](preprocess/index.html) module.
+//!
+//! Repeating `--input` opens several cameras at once, each in its own window
+//! with one target, for installations that watch a scene from more than one
+//! angle. The targets are sent together, `x1 y1 x2 y2` for two cameras with
+//! the default features. Regions, profiles and recording apply to the first
+//! camera.
+//!
+//! Two calibrated cameras side by side measure depth: with `--stereo
+//! rig.toml`, the target is selected in both views and sent as its features
+//! in the left view followed by `z`, its metric distance from the left
+//! camera, rather than a size that only hints at it. See the
+//! [`stereo`](stereo/index.html) module.
+//!
+//! # Headless
+//!
+//! On a server or a single-board computer without a display, `--headless`
+//! opens no window and only captures, tracks and sends. As nothing can be
+//! selected, tracking starts from `--roi`, `--region` or `--load-profile`, a
+//! detector, or a tracker that needs no selection.
+//!
+//! Where the video of people mustn't be seen, `--privacy blur` or `--privacy
+//! black` masks the preview, the recordings and the dashboard, keeping only
+//! the overlays of the targets, while their features are streamed as usual.
+//! See the [`privacy`](privacy/index.html) module.
+//!
+//! Kiosks can leave the camera waiting: with `--arm-zone X,Y,W,H`, nothing
+//! is tracked or sent until something moves in that zone, and tracking stops
+//! again after `--idle-after SECS` without motion, saving CPU and bandwidth
+//! while nobody is there. See the [`standby`](standby/index.html) module.
+//!
+//! Ctrl-C or SIGTERM stop it as `q` does: the cameras are released, the
+//! samples queued for ESP are written and a summary of the run is logged;
+//! see the [`shutdown`](shutdown/index.html) module. With `--report
+//! FILE`, the quality of the session is written to `FILE` as well: the
+//! frames processed and dropped, the intervals during which targets were
+//! lost, their average confidence, the stability of the send rate and the
+//! reconnections; `i` writes it at any time. See the
+//! [`report`](report/index.html) module.
+//!
+//! Scripts that start it can tell why it stopped from the exit code: 64 for
+//! a bad option, 66 for a camera that can't be opened or was unplugged, 69
+//! when ESP is not listening, and so on; see the
+//! [`error`](error/index.html) module.
+//!
+//! For boards where highgui or OpenCV's DNN module are not available, such
+//! as when cross-compiling for small ARM boards, `cargo build
+//! --no-default-features` leaves out the windows (feature `gui`), the HTTP
+//! API, the dashboard, the metrics endpoint, `--discover` and the network
+//! outputs of `--sink` (`net`), and the `dnn` tracker and pose mode (`dnn`);
+//! `--features gui` and so on add them back one by one. The connection to
+//! ESP is always built. See the [`window`](window/index.html) module.
+//!
+//! # Recording
+//!
+//! `--record demo.mp4` writes the displayed frames, with everything drawn on
+//! them, to a video file, to review a demo or share a tracking failure later.
+//!
+//! To reproduce a tracking failure rather than watch it, `--session DIR`
+//! records the captured frames with every selection, key press and parameter
+//! change, and `esp-vision replay DIR` runs it again with the same options.
+//! The replay feeds the tracker the same frames, events and capture times, so
+//! it sends the same samples; only `q` is taken from the keyboard. See the
+//! [`session`](session/index.html) module.
+//!
+//! `--log-features samples.csv` writes every sample to a CSV file as well,
+//! with the confidence and state of the targets, to analyze gestures without
+//! ESP. `esp-vision replay-features samples.csv` sends them to ESP again
+//! later, with their recorded timing or `--rate HZ`, to retrain without a
+//! camera. See the [`feature_log`](feature_log/index.html) and
+//! [`feature_replay`](feature_replay/index.html) modules.
+//!
+//! `esp-vision compare monday.csv friday.csv` compares such logs of the same
+//! gestures from different sessions: the offset and scale of every
+//! dimension and the DTW distance between them tell when the camera or the
+//! lighting drifted enough to retrain the ESP model. See the
+//! [`compare`](compare/index.html) module.
+//!
+//! To align the samples ESP records with the video exactly, `--sync-log
+//! sync.csv` logs the capture time and frame of every sample, and
+//! `--sync-beacons SECS` numbers a sample every `SECS` seconds in an extra
+//! `sync` dimension, which ESP records with the others. See the
+//! [`sync`](sync/index.html) module.
+//!
+//! `esp-vision bench --input video:clip.mp4 --roi X,Y,W,H` tracks a
+//! recording as fast as it goes, without a window, and prints percentiles
+//! of the time every stage takes, to compare trackers and machines. See the
+//! [`bench`](bench/index.html) module.
+//!
+//! `esp-vision process --input clip.mp4 --out features.csv --roi X,Y,W,H`
+//! tracks a recording just as fast and writes the features of every frame
+//! to a CSV file, timed as in the recording, to build datasets from
+//! existing clips. See the [`offline`](offline/index.html) module.
+//!
+//! `esp-vision evaluate groundtruth.txt --input video:clip.mp4` runs every
+//! tracker on a recording annotated with the rectangle of the target, in
+//! the format of the OTB and VOT benchmarks, and prints their overlap with
+//! it, the error of their center and how often they failed, to choose a
+//! backend for a scenario with data. See the
+//! [`evaluate`](evaluate/index.html) module.
+//!
+//! `--measure-latency` prints how long after the capture of their frame the
+//! samples were written to ESP on exit. Run `esp-vision echo` in place of
+//! ESP to measure the round trip over the network as well; see the
+//! [`latency`](latency/index.html) module.
+//!
+//! Installations that run unattended can be watched with `--metrics
+//! 0.0.0.0:9100`, which serves Prometheus metrics on `/metrics`; see the
+//! [`metrics`](metrics/index.html) module. To see what they see, `--dashboard
+//! 0.0.0.0:8080` serves a page with the annotated video, the position of the
+//! targets, the frame rate and the connection to ESP, which any browser on
+//! the network can open; see the [`dashboard`](dashboard/index.html) module.
+//! Where a show-control system runs the installation, `--api 0.0.0.0:8003`
+//! lets it start and stop tracking, set the region, switch the tracker and
+//! pause streaming with HTTP requests; see the [`api`](api/index.html)
+//! module. Services in a larger system can subscribe to the samples and set
+//! the region or the tracker over gRPC instead, with `--grpc 0.0.0.0:50051`
+//! in builds with the `grpc` feature; see the [`grpc`](grpc/index.html)
+//! module and `proto/esp_vision.proto`.
+//!
+//! The samples can go to other places at the same time, each with its own
+//! format and rate: `--sink osc:127.0.0.1:9000,rate=30 --sink
+//! csv:session.csv` also sends them to an OSC visualizer and logs them. An
+//! output that fails is logged and skipped, and ESP gets its samples as
+//! usual; see the [`outputs`](outputs/index.html) module.
+//!
+//! Instances in different rooms can feed a single ESP pipeline: each sends
+//! its samples to `esp-vision coordinate --peer room1=0.0.0.0:9101 --peer
+//! room2=0.0.0.0:9102` with `--sink tcp:HOST:PORT,format=framed`, which
+//! sends ESP the dimensions of every peer side by side, in the order of the
+//! `--peer` options, and NaN for peers that went silent. See the
+//! [`coordinate`](coordinate/index.html) module.
+//!
+//! To collect training data, `--dataset gestures.csv` writes every sample
+//! labeled with the class being performed: press `1` to `9` when starting a
+//! gesture and `0` when done, and the current class is shown in the top
+//! right corner. The number keys then no longer select named regions. See
+//! the [`dataset`](dataset/index.html) module.
+//!
+//! # Profiles
+//!
+//! Selecting the same object on every run is tedious, and a selection made
+//! under different conditions tracks differently. With `--save-profile FILE`,
+//! the histogram, search window and HSV thresholds of the first target are
+//! written to `FILE` every second while it is tracked; `--load-profile FILE`
+//! starts the next session from them, with the same `--tracker` and
+//! histogram options. See the [`profile`](profile/index.html) module.
+//!
+//! # Global Motion
+//!
+//! Waving and swiping don't need a target at all. `--mode flow` streams
+//! global motion computed with dense optical flow instead, without any
+//! selection: the mean flow (`flow_x flow_y`), its mean magnitude (`motion`)
+//! and the dominant direction in degrees (`direction`). See the
+//! [`flow`](flow/index.html) module.
+//!
+//! # Colored Markers
+//!
+//! Stickers in distinct colors are tracked without any selection with
+//! `--mode markers`. Every `--marker name:h,s,v:h,s,v` gives a name and an
+//! HSV range (a lower hue above the upper one wraps around, as red needs);
+//! the `--features` of each marker are sent in order, named after it, e.g.
+//! `red_x red_y blue_x blue_y`. A marker that is not visible is sent as NaN.
+//! Markers can also be listed in the config file, see the
+//! [`config`](config/index.html) module.
+//!
+//! # Facial Landmarks
+//!
+//! For head gestures such as nods and shakes, `--mode landmarks` fits an LBF
+//! facemark model (`--landmark-model lbfmodel.yaml`) to the largest face found
+//! with `--face-cascade`, and sends the `--landmarks` (by default
+//! `nose_x nose_y chin_x chin_y`). They are NaN while no face is visible. See
+//! the [`landmarks`](landmarks/index.html) module.
+//!
+//! # Gaze
+//!
+//! For gaze-driven input, `--mode gaze` finds the eyes of the largest face
+//! (`--face-cascade`) with `--eye-cascade haarcascade_eye.xml` and sends where
+//! the pupils sit within them, `gaze_x gaze_y`, roughly in `[-1, 1]` whatever
+//! `--normalize` says. They are NaN while no pupil is found. Keep the head
+//! still and the face well lit. See the [`gaze`](gaze/index.html) module.
+//!
+//! # ArUco Markers
+//!
+//! A printed ArUco marker gives the full pose of an object. `--mode aruco`
+//! follows the marker `--aruco-id` of the `--aruco-dictionary` and sends its
+//! position in meters and its rotation in degrees (`x y z roll pitch yaw`) in
+//! OpenCV's camera frame, or NaN while it is not visible. This needs the
+//! `--marker-length` of the printed marker and the `--intrinsics` of the
+//! camera, see the [`camera`](camera/index.html) module.
+//!
+//! # QR Codes
+//!
+//! QR codes tell tagged objects apart without any color calibration. With
+//! `--mode qr --qr-channels cup,plate`, the centroid of the code whose payload
+//! is `cup` is sent as `cup_x cup_y`, followed by `plate_x plate_y`; codes
+//! that are not visible are sent as NaN.
+//!
+//! # Human Pose
+//!
+//! For whole-body gestures, `--mode pose` runs an OpenPose network
+//! (`--pose-model` and `--pose-config`) and sends the `--joints`, by default
+//! `left_wrist_x left_wrist_y right_wrist_x right_wrist_y`. Joints whose
+//! confidence is below `--joint-confidence` are sent as NaN. See the
+//! [`pose`](pose/index.html) module for the joint names.
+//!
+//! # Features
+//!
+//! By default the centroid (`x y`) is sent, which matches a
+//! `TcpInputStream(8001, 2)`. The example above declares three dimensions; use
+//! `cargo run -- --dims 3` to send the square root of the bounding box area
+//! as a depth proxy for `z`. `--features` picks the features explicitly, e.g.
+//! `--features x,y,area`. `angle` and `aspect` stream the rotation and the
+//! width/height ratio of the CAMShift ellipse, which makes twisting gestures
+//! learnable. `--features ellipse` sends the whole ellipse, `x y
+//! ellipse_width ellipse_height angle`, and draws it in the window instead
+//! of its bounding box. `--velocity` appends the velocity of the centroid
+//! (`dx dy`, in pixels per second) and `--acceleration` additionally appends
+//! its acceleration; both are computed from capture timestamps.
+//!
+//! `--targets N` tracks several regions at once, e.g. both hands and the head.
+//! Each selection is assigned to the next target, and the dimensions of all
+//! targets are sent in order with their index appended (`x1 y1 x2 y2 ...`).
+//! Every target detects its own loss; a lost or not yet selected target is
+//! sent as NaN.
+//!
+//! `--preset hands` tracks two hands: select one, then the other. They are
+//! sent as `left_x left_y right_x right_y` (or six dimensions with `--dims
+//! 3`), and each hand keeps its side of the vector even while the hands
+//! cross. See the [`hands`](hands/index.html) module.
+//!
+//! With `--tracker lk`, corners inside the selection are tracked with optical
+//! flow and the features describe their mean and extent. `--points N` sends
+//! the first `N` points themselves instead (`x0 y0 x1 y1 ...`); points that
+//! have been lost are sent as NaN.
+//!
+//! Coordinates are raw pixels unless `--normalize` selects a
+//! resolution-independent space: `unit` (`[0, 1]`), `signed` (`[-1, 1]`),
+//! `aspect` (centered, `[-1, 1]` vertically with square pixels) or
+//! `letterbox` (`[0, 1]` along the longer side, with square pixels).
+//! Positions are those of the preview, which is mirrored; `--unmirror` sends
+//! them as the camera sees them, `--y-up` points the y axis up and `--origin
+//! corner` or `center` moves the origin, for consumers with other
+//! conventions. See the [`normalize`](normalize/index.html) module.
+//!
+//! To get positions on a table or a wall seen at an angle, rather than in
+//! camera pixels, pass `--plane table.toml --plane-size 120x80`, press `p`
+//! and click the four corners of the plane, starting at the top left. The
+//! positions are then in the units of `--plane-size`, and normalized against
+//! it; see the [`homography`](homography/index.html) module.
+//!
+//! Samples are sent as space-separated text, which is what `TcpInputStream`
+//! expects. Other consumers can ask for `--format json` (one object per line)
+//! or `--format binary`; see the [`serialize`](serialize/index.html) module.
+//! `--format framed` starts with a header naming the dimensions and their
+//! units, and adds a CRC to every frame, so that consumers notice when the
+//! dimensions change or bytes get corrupted; the
+//! [`framed`](framed/index.html) module has a decoder for Rust consumers.
+//! Every sample carries a monotonic capture time and a sequence number; both
+//! are part of the JSON and binary formats and `--ascii-stamps` appends them
+//! to the text format.
+//!
+//! ESP's DTW works best at 20-30 samples per second. If the camera is faster,
+//! `--send-rate 30` sends the most recent sample 30 times per second without
+//! slowing down tracking. As frames don't arrive at exactly even times,
+//! `--resample 30` interpolates the samples onto a clock of exactly 30 Hz
+//! instead, filling in dropped frames, at the cost of up to a frame of
+//! latency.
+//!
+//! ESP's timeouts can't tell a still stream from a dead connection. With
+//! `--keepalive 2`, the last sample is sent again twice per second while no
+//! new one is, e.g. while the target is lost; `--keepalive-values nan` sends
+//! NaN instead. See the [`keepalive`](keepalive/index.html) module.
+//!
+//! Rather than streaming everything for ESP to null-reject, `--segment
+//! 300:100` only sends gestures: from when the target moves at 300 pixels
+//! (or units of `--normalize`) per second until it stays below 100 for a
+//! moment. `--segment-markers` sends everything with an extra `segment`
+//! dimension marking where gestures start and end instead. See the
+//! [`segment`](segment/index.html) module.
+//!
+//! Zones of the frame, given as `[[zones]]` in the config file, are drawn
+//! over the preview and report targets entering and leaving them, as a
+//! `zone_NAME` dimension per zone with `--zone-dims`, or as events sent to
+//! `--zone-events ADDR` over UDP. See the [`zones`](zones/index.html)
+//! module.
+//!
+//! CAMShift jitters under noisy lighting. `--kalman` smooths the centroid and
+//! size with a constant-velocity Kalman filter, tuned with `--process-noise`
+//! and `--measurement-noise`. Longer chains of filters (moving average,
+//! median, Savitzky–Golay) are set up in the config file, see the
+//! [`config`](config/index.html) and [`filter`](filter/index.html) modules.
+//! Press `k` in the window to switch between the raw and the filtered output.
+//!
+//! # Tracking Loss
+//!
+//! The fraction of the search window covered by the target's backprojection
+//! serves as the tracking confidence. When it stays below `--min-confidence`
+//! for `--lost-frames` frames, or the window shrinks below `--min-size`, the
+//! target is considered lost and nothing is sent until it is found again
+//! (`--on-lost nan` sends NaN samples instead). The state is shown on screen.
+//! With `--reacquire`, the whole frame is searched for the stored histogram
+//! while the target is lost, and tracking resumes automatically once a
+//! candidate reaches `--reacquire-confidence`.
+//! The CAMShift search window stays inside the frame and never shrinks below
+//! `--min-window` pixels, so that a target at the edge can be followed back.
+//! Run with `--help` for all options.
+//!
+//! # Predictions
+//!
+//! ESP can send the recognized gesture back to us. The application listens on
+//! port `5204` and overlays the most recent label on the video. Add the
+//! following to the ESP example to enable it:
+//!
+//! ```c++
+//! TcpOStream oStream("localhost", 5204);
+//! useOutputStream(oStream);
+//! ```
+//!
+//! `[[labels]]` in the config file turn the class numbers into names and
+//! colors, and a strip of the past predictions fades out in the bottom right
+//! corner, so the window makes a demo on its own; see the
+//! [`labels`](labels/index.html) module. Where nobody watches the window,
+//! labels can play a `sound`, and `--notify` pops up a desktop notification
+//! for every prediction; see the [`feedback`](feedback/index.html) module.
+//!
+//! # Control Channel
+//!
+//! The HSV mask thresholds, the number of histogram bins, the number of
+//! CAMShift iterations and the learning rate of the background model can be
+//! adjusted while running. Connect to port `8002`
+//! and send one `<name> <value>` pair per line, e.g.
+//!
+//! ```text
+//! s_min 60
+//! hist_bins 32
+//! ```
+//!
+//! See [`VisionParams::set`](params/struct.VisionParams.html#method.set) for
+//! the accepted names. `pause` and `resume` stop and restart sending to ESP,
+//! as the space key does, e.g. from a script that records training data.
+//!
+//! With `--trackbars`, a second window has trackbars for the HSV mask and the
+//! histogram bins, which take effect right away; pressing `t` prints the
+//! tuned values as control messages, and `--save-profile` keeps them.
+//!
+//! The same names set the starting values in the `[params]` table of the
+//! config file. Edits to the file while running apply the new parameters,
+//! send rate and filters right away, and log the other changed settings as
+//! needing a restart; see the [`reload`](reload/index.html) module.
+//!
+//! # Keyboard
+//!
+//! `ESC` or `q` quits, and `r` stops tracking all targets so they can be
+//! selected again. Space pauses sending to ESP, e.g. between the repetitions
+//! of a gesture, while tracking goes on; `s` saves the displayed frame as
+//! `snapshot-SEQ.png`. See the [`keys`](keys/index.html) module for all keys.
+//!
+//! An imprecise drag needn't be redone: the arrow keys move the last
+//! selection and `+` and `-` grow or shrink it two pixels at a time, with
+//! the target learnt again from the adjusted region, and `u` undoes the last
+//! selection, going back to the target it replaced.
+//!
+//! When the lighting changes, e.g. between the rehearsal and the show, `c`
+//! sets the HSV mask to the colors of `--reference-patch X,Y,W,H`, or of
+//! the first target, and the targets learn their histograms again from
+//! where they are, without selecting them again; see the
+//! [`recalibrate`](recalibrate/index.html) module.
+//!
+//! To see what CAMShift sees, press `b`: a second window shows the
+//! backprojection of the first target with its search window. A good mask
+//! and histogram light up the target and little else. Pressing `z` shows a
+//! magnified view of the first tracked target in the top right corner, to
+//! check that the tracker follows the intended part of it, e.g. the hand
+//! rather than the sleeve. With `--trail N`, the last `N` centroids of every
+//! target are drawn as a line fading with age, to see the shape of the
+//! gesture just made before keeping it as a training example. While a
+//! selection is dragged out, the rest of the frame is dimmed. Pressing `h`
+//! toggles a heads-up display with the capture rate, the latency from
+//! capture to display, the samples sent per second, the state of the
+//! connection to ESP, the confidence of the first target, the time each
+//! stage of the loop takes and the number of frames dropped. Pressing `g`
+//! plots the last ten seconds of every dimension sent in a window of its
+//! own, to see jitter, dropouts and the smoothing while tuning the filters;
+//! see the [`plot`](plot/index.html) module. Live
+//! frames that tracking can't keep up with are dropped rather than queued,
+//! so that tracking always works on the newest one, as are frames older
+//! than `--latency-budget`. Frames are captured on threads of
+//! their own, and samples are sent by the network runtime that also serves
+//! the control and prediction channels, so that neither waits on the
+//! display; see the [`pipeline`](pipeline/index.html) and
+//! [`net`](net/index.html) modules.
+//!
+//! Messages are logged to standard error, `debug` ones too with `--verbose`.
+//! `--log` sets the level per module and `--log-format json` writes one JSON
+//! object per line, for collecting logs from a deployment; see the
+//! [`logging`](logging/index.html) module.
+//!
+//! Enjoy watching yourself :)
+extern crate getopts;
+#[macro_use]
+extern crate log;
+pub extern crate rust_vision;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+use rust_vision::*;
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+mod api;
+mod aruco;
+mod bench;
+mod calibrate;
+mod camera;
+mod compare;
+mod config;
+mod control;
+mod coordinate;
+mod cuda;
+mod dashboard;
+mod dataset;
+mod derive;
+mod detect;
+mod devices;
+mod discover;
+mod dry_run;
+#[cfg(test)]
+mod end_to_end;
+mod error;
+mod evaluate;
+mod feature_log;
+mod feature_replay;
+mod features;
+mod feedback;
+mod filter;
+mod flow;
+pub mod framed;
+mod gaze;
+mod grpc;
+mod hands;
+mod homography;
+mod input;
+mod kalman;
+mod keepalive;
+mod keys;
+mod landmarks;
+mod labels;
+mod latency;
+mod logging;
+mod loss;
+mod manifest;
+mod markers;
+mod metrics;
+mod mjpeg;
+#[cfg(test)]
+mod mock_esp;
+mod mouse;
+mod net;
+mod normalize;
+mod offline;
+mod outputs;
+mod overlay;
+mod params;
+mod pipeline;
+mod plot;
+mod pose;
+mod prediction;
+mod preprocess;
+mod privacy;
+mod profile;
+mod qr;
+mod record;
+mod rate;
+mod realsense;
+mod recalibrate;
+mod reload;
+mod report;
+mod sample;
+mod segment;
+mod serialize;
+mod session;
+mod shutdown;
+mod standby;
+mod stereo;
+mod sync;
+mod synthetic;
+mod sink;
+mod target;
+mod trackbars;
+mod tracker;
+mod v4l2;
+mod watchdog;
+mod window;
+mod zones;
+pub use crate::features::Region;
+pub use crate::params::VisionParams;
+pub use crate::sample::Sample;
+pub use crate::tracker::{Factory, Track, Tracker, register_tracker};
+use crate::api::{Api, Request};
+use crate::aruco::Aruco;
+use crate::config::{Config, Mode};
+use crate::control::ControlServer;
+use crate::dashboard::Dashboard;
+use crate::dataset::Dataset;
+use crate::detect::{FaceDetector, SkinDetector};
+use crate::dry_run::Console;
+use crate::error::Error;
+use crate::feature_log::FeatureLog;
+use crate::filter::Chain;
+use crate::flow::MotionEnergy;
+use crate::gaze::Gaze;
+use crate::grpc::Grpc;
+use crate::hands::Hands;
+use crate::homography::Plane;
+use crate::keepalive::Keepalive;
+use crate::keys::Command;
+use crate::landmarks::Landmarks;
+use crate::feedback::Feedback;
+use crate::labels::Labels;
+use crate::latency::Latency;
+use crate::loss::{OnLost, TrackState};
+use crate::manifest::{Announced, Manifest};
+use crate::markers::Markers;
+use crate::metrics::Metrics;
+use crate::mouse::{Mouse, SelectionStatus};
+use crate::normalize::{Normalizer, Unit};
+use crate::outputs::Fanout;
+use crate::overlay::{Hud, Trail};
+use crate::params;
+use crate::net::Net;
+use crate::pipeline::{Capture, Read, Timings};
+use crate::plot::Plot;
+use crate::pose::Pose;
+use crate::prediction::PredictionListener;
+use crate::preprocess::Converter;
+use crate::profile::{self, Profile};
+use crate::qr::QrCodes;
+use crate::rate::{Resampler, Throttle};
+use crate::reload::Reloader;
+use crate::report::Report;
+use crate::record::Recorder;
+use crate::sample::Sample;
+use crate::segment::Segmenter;
+use crate::session::{Event, Session, SessionRecorder};
+use crate::shutdown::Shutdown;
+use crate::standby::{Standby, Transition};
+use crate::sink::{Gate, Metered, Sink, SinkStats, TcpSink, Tee};
+use crate::sync::{Beacons, SyncLog};
+use crate::target::Target;
+use crate::trackbars::Trackbars;
+use crate::zones::{EventSender, Zones};
+
+/// How long a prediction stays on screen after it was received.
+const PREDICTION_TIMEOUT_MS: u64 = 2000;
+/// Name of the window showing the backprojection.
+const BACK_PROJECT_WINDOW: &str = "Backprojection";
+/// How often `--save-profile` is written while the target is tracked.
+const PROFILE_INTERVAL_MS: u64 = 1000;
+/// How many selections `u` can undo.
+const UNDO_DEPTH: usize = 10;
+
+/// Records `event` to the session, if one is recorded.
+fn record(session: &mut Option<SessionRecorder>, event: &Event) {
+    if let Some(Err(e)) = session.as_mut().map(|s| s.event(event)) {
+        warn!("{}", e);
+        *session = None;
+    }
+}
+
+/// Stops tracking all targets, so that they start again from the next
+/// selection.
+fn reset(targets: &mut [Option<Target>], hands: &mut Option<Hands>) {
+    for slot in targets {
+        *slot = None;
+    }
+    if let Some(ref mut hands) = *hands {
+        hands.reset();
+    }
+}
+
+/// Moves `zones` on with the `centroids` of the targets at `time`, and logs
+/// the zones entered and left and sends them to `sender`.
+fn follow_zones(zones: &mut Zones,
+                centroids: &[[f64; 2]],
+                sender: Option<&EventSender>,
+                time: f64) {
+    for event in zones.update(centroids) {
+        info!("{} the zone {}",
+              if event.entered { "Entered" } else { "Left" },
+              event.zone);
+        if let Some(Err(e)) = sender.map(|s| s.send(&event, time)) {
+            warn!("Cannot send the zone event: {}", e);
+        }
+    }
+}
+
+/// A camera besides the first, with its own window and target.
+struct Camera {
+    input: Capture,
+    frame: Mat,
+    window: String,
+    selection_status: SelectionStatus,
+    mouse: Option<Mouse>,
+}
+
+/// The entry point to the application, which runs the command line of the
+/// process and exits on errors. Click into
+/// [source](../src/esp_vision/src/lib.rs.html) for more information.
+pub fn main() {
+    logging::init();
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some(devices::COMMAND) {
+        devices::list();
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some(calibrate::COMMAND) {
+        if let Err(e) = calibrate::run(&args) {
+            exit(Error::Command(e));
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some(latency::ECHO_COMMAND) {
+        if let Err(e) = latency::run_echo(&args) {
+            exit(Error::Command(e));
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some(bench::COMMAND) {
+        if let Err(e) = bench::run(&args) {
+            exit(Error::Command(e));
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some(offline::COMMAND) {
+        if let Err(e) = offline::run(&args) {
+            exit(Error::Command(e));
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some(evaluate::COMMAND) {
+        if let Err(e) = evaluate::run(&args) {
+            exit(Error::Command(e));
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some(compare::COMMAND) {
+        if let Err(e) = compare::run(&args) {
+            exit(Error::Command(e));
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some(coordinate::COMMAND) {
+        if let Err(e) = coordinate::run(&args) {
+            exit(Error::Command(e));
+        }
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some(feature_replay::COMMAND) {
+        if let Err(e) = feature_replay::run(&args) {
+            exit(Error::Command(e));
+        }
+        return;
+    }
+    if let Err(e) = run(args) {
+        exit(e);
+    }
+}
+
+/// Logs `e` and exits with its code.
+fn exit(e: Error) -> ! {
+    error!("{}", e);
+    process::exit(e.exit_code())
+}
+
+/// Tracks and streams until the input ends or `q` is pressed.
+fn run(args: Vec<String>) -> Result<(), Error> {
+    let args = if args.get(1).map(String::as_str) ==
+                  Some(session::REPLAY_COMMAND) {
+        session::replay_args(&args).map_err(Error::Data)?
+    } else {
+        args
+    };
+    let mut config = Config::from_args(&args).map_err(Error::Usage)?;
+    logging::configure(config.log_filter.clone(), config.log_format);
+    info!("Sending {} dimensions to ESP: {}",
+          config.dims(),
+          config.dimension_names().join(" "));
+    match config.expect_dims {
+        Some(n) if n != config.esp_dims() => {
+            return Err(Error::Usage(format!("ESP expects {} dimensions but \
+                                             {} would be sent",
+                                            n,
+                                            config.esp_dims())));
+        }
+        _ => {}
+    }
+    if config.print_manifest {
+        let manifest = Manifest::new(&config);
+        print!("{}\n{}",
+               manifest.table(),
+               manifest.esp_snippet(&config.server));
+        return Ok(());
+    }
+
+    if config.cuda && !cuda::available() {
+        return Err(Error::Unavailable("no CUDA device is available; run \
+                                       without --cuda"
+            .to_string()));
+    }
+    if config.tracking.camshift.opencl && !tracker::enable_opencl() {
+        warn!("OpenCL is not available; tracking on the CPU");
+        config.tracking.camshift.opencl = false;
+    }
+    let net = Net::new().map_err(|e| {
+        Error::Internal(format!("cannot start the network runtime: {}", e))
+    })?;
+    let shutdown = Shutdown::listen(&net);
+    let names = config.dimension_names();
+    let timings = Timings::default();
+    // Sample times count from here.
+    let start = Instant::now();
+    let latency = if config.measure_latency {
+        Some(Arc::new(Latency::new(start)))
+    } else {
+        None
+    };
+    let tcp: Box<dyn Sink> = if config.dry_run {
+        info!("Dry run: printing the samples instead of sending them");
+        Box::new(Console::new(names))
+    } else {
+        let server = match config.discover {
+            Some(ref service) => discover::server(service, &config.server),
+            None => config.server.clone(),
+        };
+        let mut serializer = config.format
+            .serializer(names, config.dimension_labels(), config.ascii_stamps);
+        if config.send_manifest {
+            if let Some(line) = Manifest::new(&config).line(config.format) {
+                serializer = Box::new(Announced::new(line, serializer));
+            }
+        }
+        Box::new(TcpSink::connect(&net,
+                                  server.as_str(),
+                                  serializer,
+                                  timings.clone(),
+                                  latency.clone())
+            .map_err(|e| Error::Connect(server.clone(), e))?)
+    };
+    let stats = Rc::new(Cell::new(SinkStats::default()));
+    let tcp = Metered::new(tcp, stats.clone());
+    // Reloading the config file may change the send rate.
+    let send_rate = Rc::new(Cell::new(config.send_rate));
+    let sink: Box<dyn Sink> = match config.resample {
+        Some(hz) => Box::new(Resampler::new(tcp, hz)),
+        None => Box::new(Throttle::with_rate(tcp, send_rate.clone())),
+    };
+    let status = Rc::new(RefCell::new(Vec::new()));
+    let sink: Box<dyn Sink> = match config.log_features {
+        Some(ref path) => {
+            let targets = match config.mode {
+                Mode::Track => config.targets,
+                _ => 0,
+            };
+            let log = FeatureLog::create(path,
+                                         &config.dimension_names(),
+                                         targets,
+                                         status.clone())
+                .map_err(|e| {
+                    Error::Output(format!("cannot log the features to {}: \
+                                           {}",
+                                          path,
+                                          e))
+                })?;
+            Box::new(Tee::new(sink, log))
+        }
+        None => sink,
+    };
+    let class = Rc::new(Cell::new(0));
+    let sink: Box<dyn Sink> = match config.dataset {
+        Some(ref path) => {
+            let dataset = Dataset::create(path,
+                                          &config.dimension_names(),
+                                          class.clone())
+                .map_err(|e| {
+                    Error::Output(format!("cannot write the dataset to {}: \
+                                           {}",
+                                          path,
+                                          e))
+                })?;
+            Box::new(Tee::new(sink, dataset))
+        }
+        None => sink,
+    };
+    let sync_frame = Rc::new(Cell::new(0));
+    let sink: Box<dyn Sink> = match config.sync_log {
+        Some(ref path) => {
+            let log = SyncLog::create(path,
+                                      start,
+                                      sync_frame.clone(),
+                                      config.beacon_index())
+                .map_err(|e| {
+                    Error::Output(format!("cannot write the sync log to {}: \
+                                           {}",
+                                          path,
+                                          e))
+                })?;
+            Box::new(Tee::new(sink, log))
+        }
+        None => sink,
+    };
+    let grpc = match config.grpc {
+        Some(ref addr) => {
+            Some(Grpc::serve(&net,
+                             addr,
+                             config.dimension_names(),
+                             config.tracker_rules())
+                .map_err(|e| Error::Listen("grpc", addr.clone(), e))?)
+        }
+        None => None,
+    };
+    let sink: Box<dyn Sink> = match grpc {
+        Some(ref grpc) => Box::new(Tee::new(sink, grpc.publisher())),
+        None => sink,
+    };
+    let sink: Box<dyn Sink> = if config.sinks.is_empty() {
+        sink
+    } else {
+        let targets = match config.mode {
+            Mode::Track => config.targets,
+            _ => 0,
+        };
+        let outputs = Fanout::open(&config.sinks,
+                                   &net,
+                                   &config.dimension_names(),
+                                   &config.dimension_labels(),
+                                   config.ascii_stamps,
+                                   targets,
+                                   &status);
+        Box::new(Tee::new(sink, outputs))
+    };
+    // The plot shows the samples as sent, with the markers of the
+    // segmentation and the beacons.
+    let plot = Plot::new(config.dimension_names(), config.plot_seconds);
+    let sink: Box<dyn Sink> = if config.headless {
+        sink
+    } else {
+        Box::new(Tee::new(sink, plot.clone()))
+    };
+    // Beacons go after the markers of the segmentation, as the dimensions
+    // are named.
+    let sink: Box<dyn Sink> = match config.sync_beacons {
+        Some(interval) => Box::new(Beacons::new(sink, interval)),
+        None => sink,
+    };
+    let sink: Box<dyn Sink> = match config.segment {
+        Some(segmentation) => {
+            let units = config.tracked_units();
+            if !units.iter().any(|&u| u == Unit::X || u == Unit::Y) {
+                return Err(Error::Usage(format!("--mode {} sends no \
+                                                 positions to segment",
+                                                config.mode)));
+            }
+            Box::new(Segmenter::new(sink, segmentation, &units))
+        }
+        None => sink,
+    };
+
+    let has_sounds = config.labels.iter().any(|l| l.sound.is_some());
+    let feedback = if config.notify || has_sounds {
+        Some(Feedback::new(config.sound_player.clone(), config.notify))
+    } else {
+        None
+    };
+    let mut labels = Labels::new(config.labels.clone(), feedback);
+    let predictions = PredictionListener::spawn(&net,
+                                               config.prediction.as_str())
+        .map_err(|e| {
+            Error::Listen("prediction", config.prediction.clone(), e)
+        })?;
+    let (mut profile, initial) = profile::initial(&config)?;
+    let paused = Arc::new(AtomicBool::new(false));
+    let mut sink = Keepalive::new(Gate::new(sink, paused.clone()),
+                                  config.keepalive,
+                                  config.keepalive_values,
+                                  config.tracked_dims(),
+                                  start);
+    let control = ControlServer::spawn(&net,
+                                       config.control.as_str(),
+                                       initial,
+                                       paused.clone())
+        .map_err(|e| Error::Listen("control", config.control.clone(), e))?;
+    let metrics = match config.metrics {
+        Some(ref addr) => {
+            Some(Metrics::serve(&net, addr)
+                .map_err(|e| Error::Listen("metrics", addr.clone(), e))?)
+        }
+        None => None,
+    };
+    let dashboard = match config.dashboard {
+        Some(ref addr) => {
+            Some(Dashboard::serve(&net, addr)
+                .map_err(|e| Error::Listen("dashboard", addr.clone(), e))?)
+        }
+        None => None,
+    };
+    let api = match config.api {
+        Some(ref addr) => {
+            Some(Api::serve(&net,
+                            addr,
+                            paused.clone(),
+                            config.tracker_rules())
+                .map_err(|e| Error::Listen("api", addr.clone(), e))?)
+        }
+        None => None,
+    };
+    let mut profile_saved = Instant::now();
+    let mut requested = config.start_region;
+    // Set by the API to keep targets from starting by themselves.
+    let mut stopped = false;
+
+    let mut session = match config.session {
+        Some(ref dir) => {
+            Some(SessionRecorder::create(dir, &args[1..])
+                .map_err(Error::Output)?)
+        }
+        None => None,
+    };
+    let replay = match config.replay {
+        Some(ref dir) => Some(Session::load(dir).map_err(Error::Data)?),
+        None => None,
+    };
+    let mut frame_index = 0;
+
+    let mut selection_status = SelectionStatus::default();
+
+    let open = |spec| {
+        Capture::spawn(spec,
+                       &config.playback,
+                       &config.capture,
+                       config.latency_budget,
+                       timings.clone())
+            .map_err(Error::Input)
+    };
+    let input = open(&config.inputs[0])?;
+    let mut cameras: Vec<Camera> = config.inputs[1..]
+        .iter()
+        .enumerate()
+        .map(|(i, spec)| {
+            Ok(Camera {
+                input: open(spec)?,
+                frame: Mat::new(),
+                window: format!("Window {}", i + 2),
+                selection_status: SelectionStatus::default(),
+                mouse: None,
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    let mut mouse = None;
+    if !config.headless {
+        window::open("Window");
+        // A replay takes its selections from the session.
+        if replay.is_none() {
+            mouse = Some(Mouse::attach("Window"));
+        }
+        for camera in &mut cameras {
+            window::open(&camera.window);
+            camera.mouse = Some(Mouse::attach(&camera.window));
+        }
+    }
+    let mut trackbars = if config.trackbars {
+        Some(Trackbars::new(control.params()))
+    } else {
+        None
+    };
+
+    let mut m = Mat::new();
+    // Each selection fills the next slot, starting over after the last.
+    let mut targets: Vec<Option<Target>> =
+        (0..config.targets).map(|_| None).collect();
+    let mut trails: Vec<Trail> =
+        targets.iter().map(|_| Trail::new(config.trail)).collect();
+    let mut next_target = 0;
+    // The slot and region of the last selection, which the arrow keys, `+`
+    // and `-` adjust, and the adjustment to apply to the next frame.
+    let mut last_selection: Option<(usize, Rect)> = None;
+    let mut adjusted = None;
+    // The slots that selections filled and the targets they replaced, most
+    // recent last, for `u`.
+    let mut undo: Vec<(usize, Option<Target>)> = Vec::new();
+    // The windows of the targets when `c` was pressed, to recalibrate the
+    // colors from on the next frame.
+    let mut recalibration: Option<Vec<Option<Rect>>> = None;
+    // The last targets are those of the other cameras, one each.
+    let first_targets = targets.len() - cameras.len();
+    let faces = match config.face_cascade {
+        Some(ref path) => Some(FaceDetector::new(path).map_err(Error::Data)?),
+        None => None,
+    };
+    let skin = config.skin_zone.map(SkinDetector::new);
+    let mut standby =
+        config.arm_zone.map(|zone| Standby::new(zone, config.idle_after));
+    let mut zones = if config.mode == Mode::Track && !config.zones.is_empty() {
+        Some(Zones::new(&config.zones))
+    } else {
+        None
+    };
+    let zone_events = match config.zone_events {
+        Some(ref addr) => {
+            Some(EventSender::connect(addr).map_err(|e| {
+                Error::Output(format!("cannot send the zone events to {}: \
+                                       {}",
+                                      addr,
+                                      e))
+            })?)
+        }
+        None => None,
+    };
+    let aruco = if config.mode == Mode::Aruco {
+        config.intrinsics.as_ref().map(|intrinsics| {
+            if config.undistort {
+                Aruco::new(config.aruco.clone(), &intrinsics.undistorted())
+            } else {
+                Aruco::new(config.aruco.clone(), intrinsics)
+            }
+        })
+    } else {
+        None
+    };
+    let mut pose = match config.pose_model {
+        Some((ref model, ref desc)) => {
+            Some(Pose::new(model,
+                           desc,
+                           config.joints.clone(),
+                           config.joint_confidence,
+                           config.cuda)
+                .map_err(Error::Data)?)
+        }
+        None => None,
+    };
+    let qr = match config.mode {
+        Mode::Qr => Some(QrCodes::new(config.qr_channels.clone())),
+        _ => None,
+    };
+    let landmarks = match config.landmark_model {
+        Some(ref path) => {
+            Some(Landmarks::new(path, config.landmarks.clone())
+                .map_err(Error::Data)?)
+        }
+        None => None,
+    };
+    let gaze = match config.eye_cascade {
+        Some(ref path) => Some(Gaze::new(path).map_err(Error::Data)?),
+        None => None,
+    };
+    let mut hands = if config.hands {
+        Some(Hands::new())
+    } else {
+        None
+    };
+    let mut normalizer = Normalizer::new(config.normalization,
+                                         config.dimension_units());
+    normalizer.set_convention(config.convention);
+    // The corners of the plane clicked so far, while calibrating it.
+    let mut plane_corners: Option<Vec<[f64; 2]>> = None;
+    if let Some(ref path) = config.plane {
+        if Path::new(path).exists() {
+            let plane = Plane::load(path).map_err(Error::Data)?;
+            match plane.homography() {
+                Some(homography) => {
+                    normalizer.set_plane(homography, plane.size)
+                }
+                None => {
+                    return Err(Error::Data(format!("three of the corners \
+                                                    of {} are on a line",
+                                                   path)));
+                }
+            }
+        } else {
+            info!("Press p and click the corners of the plane to \
+                   calibrate {}",
+                  path);
+        }
+    }
+    // The steps and filters were checked when the config was read.
+    let mut converter = Converter::new(&config).unwrap();
+    // Only used to know whether smoothing can be toggled.
+    let mut filters = Chain::new(&config.filters).unwrap();
+    let mut smoothing = !filters.is_empty();
+    let mut reloader = config.config_file
+        .as_ref()
+        .map(|path| Reloader::new(path, config.overridden.clone()));
+    let mut show_back_project = false;
+    let mut show_plot = false;
+    let mut show_zoom = false;
+    let mut hud = Hud::new();
+    let mut report = Report::new();
+    let mut recorder = config.record.as_ref().map(|path| Recorder::new(path));
+    let mut show_hud = false;
+    let mut motion = match config.mode {
+        Mode::Flow => Some(MotionEnergy::new(config.cuda)),
+        _ => None,
+    };
+    let markers = match config.mode {
+        Mode::Markers => Some(Markers::new(config.markers.clone())),
+        _ => None,
+    };
+    let mut seq = 0;
+
+    loop {
+        if shutdown.requested() {
+            break;
+        }
+        // Mouse events arrive while the last frame was shown.
+        for event in mouse.iter().flat_map(Mouse::events) {
+            selection_status.handle(event);
+        }
+        for camera in &mut cameras {
+            if let Some(ref mouse) = camera.mouse {
+                for event in mouse.events() {
+                    camera.selection_status.handle(event);
+                }
+            }
+        }
+        if let Some(ref mut trackbars) = trackbars {
+            trackbars.sync(&control);
+        }
+        if let Some(ref replay) = replay {
+            for event in replay.events(frame_index) {
+                if let Event::Param(ref name, value) = *event {
+                    control.set(name, value).ok();
+                }
+            }
+        }
+        if let Some(changes) = reloader.as_mut()
+            .and_then(|r| r.poll(Instant::now())) {
+            for &(ref name, value) in &changes.params {
+                control.set(name, value).ok();
+            }
+            match changes.send_rate {
+                Some(_) if config.resample.is_some() => {
+                    warn!("Ignoring the send rate: --resample sets the rate \
+                           of the samples");
+                }
+                Some(rate) => send_rate.set(rate),
+                None => {}
+            }
+            if let Some(specs) = changes.filters {
+                // The filters were checked when the file was read.
+                for target in targets.iter_mut().flatten() {
+                    target.set_filters(Chain::new(&specs).unwrap());
+                }
+                filters = Chain::new(&specs).unwrap();
+                smoothing = !filters.is_empty();
+                config.filters = specs;
+            }
+        }
+        let params = control.params();
+        let read = match input.read(&mut m) {
+            Read::Frame(captured) => {
+                cameras.iter_mut()
+                    .map(|c| c.input.read(&mut c.frame))
+                    .find(|r| !matches!(*r, Read::Frame(_)))
+                    .unwrap_or(Read::Frame(captured))
+            }
+            read => read,
+        };
+        let captured = match read {
+            Read::Frame(captured) => captured,
+            Read::Lost(since) => {
+                // Nothing is tracked until the camera is back; the last
+                // frame shows the outage.
+                sink.tick(Instant::now()).ok();
+                let key = if config.headless || m.cols == 0 {
+                    -1
+                } else {
+                    let view = m.clone();
+                    overlay::outage(&view, since.elapsed());
+                    window::show_and_wait("Window", &view, 1)
+                };
+                if keys::command(key) == Some(Command::Quit) {
+                    break;
+                }
+                continue;
+            }
+            Read::Ended if input.is_live() => {
+                return Err(Error::InputEnded(config.inputs[0].clone()));
+            }
+            Read::Ended => {
+                info!("End of the input");
+                break;
+            }
+        };
+        let processing = Instant::now();
+        let captured = match replay.as_ref().and_then(|r| r.time(frame_index)) {
+            Some(time) => start + time,
+            None => captured,
+        };
+        sync_frame.set(frame_index);
+        if let Some(Err(e)) = session.as_mut()
+            .map(|s| s.frame(&m, captured - start, &params)) {
+            warn!("{}", e);
+            session = None;
+        }
+        // Depth is measured in the frames as captured.
+        let captured_size = (m.cols, m.rows);
+        let right_size = cameras.first().map(|c| (c.frame.cols, c.frame.rows));
+        converter.convert(&mut m);
+        for camera in &mut cameras {
+            converter.convert_other(&mut camera.frame);
+        }
+
+        if let Some(ref mut motion) = motion {
+            let time = (captured - start).as_secs_f64();
+            let mut sample = motion.update(captured, &m);
+            normalizer.apply(&mut sample, m.cols, m.rows);
+            sink.send(&Sample::new(seq, time, sample)).ok();
+            seq += 1;
+        }
+
+        if let Some(ref markers) = markers {
+            let regions = markers.update(&m);
+            for (spec, region) in markers.specs().iter().zip(&regions) {
+                if let Some(ref region) = *region {
+                    let r = region.bounding_rect();
+                    m.rectangle(r);
+                    m.put_text(&spec.name,
+                               Point2i::new(r.x, r.y - 5),
+                               HersheyFonts::HersheySimplex,
+                               0.5,
+                               Scalar::new(0, 255, 0, 0));
+                }
+            }
+            let time = (captured - start).as_secs_f64();
+            let mut sample = config.features.extract_all(&regions);
+            normalizer.apply(&mut sample, m.cols, m.rows);
+            sink.send(&Sample::new(seq, time, sample)).ok();
+            seq += 1;
+        }
+
+        if let Some(ref gaze) = gaze {
+            let face = faces.as_ref().and_then(|f| f.largest(&m));
+            let eyes = face.map_or(Vec::new(), |face| gaze.eyes(&m, face));
+            for eye in &eyes {
+                m.rectangle(eye.rect);
+                if let Some(p) = eye.pupil {
+                    m.circle(Point2i::new(p.x as i32, p.y as i32),
+                             2,
+                             Scalar::new(0, 255, 255, 0),
+                             -1);
+                }
+            }
+            let time = (captured - start).as_secs_f64();
+            let sample = gaze::values(&eyes);
+            sink.send(&Sample::new(seq, time, sample)).ok();
+            seq += 1;
+        }
+
+        if let Some(ref landmarks) = landmarks {
+            let face = faces.as_ref().and_then(|f| f.largest(&m));
+            let points = face.and_then(|face| {
+                m.rectangle(face);
+                landmarks.fit(&m, face)
+            });
+            for p in points.iter().flatten() {
+                m.circle(Point2i::new(p.x as i32, p.y as i32),
+                         2,
+                         Scalar::new(0, 255, 255, 0),
+                         -1);
+            }
+            let time = (captured - start).as_secs_f64();
+            let mut sample = landmarks.values(points.as_ref());
+            normalizer.apply(&mut sample, m.cols, m.rows);
+            sink.send(&Sample::new(seq, time, sample)).ok();
+            seq += 1;
+        }
+
+        if let Some(ref aruco) = aruco {
+            let detection = aruco.update(&m);
+            let pose = match detection {
+                Some(ref d) => {
+                    let next = d.corners.iter().cycle().skip(1);
+                    for (a, b) in d.corners.iter().zip(next) {
+                        m.line(Point2i::new(a.x as i32, a.y as i32),
+                               Point2i::new(b.x as i32, b.y as i32),
+                               Scalar::new(0, 255, 0, 0),
+                               2);
+                    }
+                    d.pose.to_vec()
+                }
+                None => vec![f64::NAN; aruco::NAMES.len()],
+            };
+            let time = (captured - start).as_secs_f64();
+            sink.send(&Sample::new(seq, time, pose)).ok();
+            seq += 1;
+        }
+
+        if let Some(ref mut pose) = pose {
+            let joints = pose.update(&m);
+            for p in joints.iter().flatten() {
+                m.circle(Point2i::new(p.x as i32, p.y as i32),
+                         4,
+                         Scalar::new(0, 255, 255, 0),
+                         -1);
+            }
+            let time = (captured - start).as_secs_f64();
+            let mut sample = pose::values(&joints);
+            normalizer.apply(&mut sample, m.cols, m.rows);
+            sink.send(&Sample::new(seq, time, sample)).ok();
+            seq += 1;
+        }
+
+        if let Some(ref qr) = qr {
+            let codes = qr.detect(&m);
+            for code in &codes {
+                if let Some(p) = code.corners.first() {
+                    m.put_text(&code.payload,
+                               Point2i::new(p.x as i32, p.y as i32),
+                               HersheyFonts::HersheySimplex,
+                               0.5,
+                               Scalar::new(0, 255, 0, 0));
+                }
+            }
+            let time = (captured - start).as_secs_f64();
+            let mut sample = qr.values(&codes);
+            normalizer.apply(&mut sample, m.cols, m.rows);
+            sink.send(&Sample::new(seq, time, sample)).ok();
+            seq += 1;
+        }
+
+        if let Some(ref replay) = replay {
+            for event in replay.events(frame_index) {
+                if let Event::Select(rect) = *event {
+                    selection_status.selection = rect;
+                    selection_status.status = true;
+                }
+            }
+        }
+        // Motion in the arm zone wakes tracking up, from the moving blob
+        // unless a detector finds the target.
+        let mut woke = None;
+        match standby.as_mut().and_then(|s| s.update(&m, captured)) {
+            Some(Transition::Wake(blob)) => {
+                info!("Motion in the arm zone, starting to track");
+                woke = Some(blob);
+            }
+            Some(Transition::Sleep) => {
+                info!("Nothing moved for {:?}, waiting for motion",
+                      config.idle_after);
+                reset(&mut targets, &mut hands);
+            }
+            None => {}
+        }
+        let armed = standby.as_ref().map_or(false, Standby::armed);
+        if let Some(zone) = standby.as_ref().filter(|s| s.armed()) {
+            m.rectangle_custom(zone.zone(),
+                               Scalar::new(0, 255, 255, 0),
+                               1,
+                               LineTypes::Line8);
+        }
+        // An adjusted selection learns the target again from the frame,
+        // before anything is drawn over it.
+        if let Some((slot, rect)) = adjusted.take() {
+            if targets[slot].is_some() {
+                targets[slot] = Some(Target::new(&config, &m, rect, &params));
+                info!("Adjusted the selection to {:?}", rect);
+            }
+        }
+        if let Some(windows) = recalibration.take() {
+            let patch = config.reference_patch
+                .or_else(|| windows.iter().flatten().next().cloned());
+            match patch.and_then(|p| recalibrate::bounds(&m, p)) {
+                Some((low, high)) => {
+                    let bounds = low.iter().chain(&high);
+                    for (name, &v) in params::NAMES.iter().zip(bounds) {
+                        control.set(name, f64::from(v)).ok();
+                    }
+                    let params = control.params();
+                    for (slot, window) in targets[..first_targets]
+                        .iter_mut()
+                        .zip(windows) {
+                        if let (Some(target), Some(window)) = (slot, window) {
+                            target.recalibrate(&m, window, &params);
+                        }
+                    }
+                    info!("Recalibrated the colors: HSV from {:?} to {:?}",
+                          low,
+                          high);
+                }
+                None => {
+                    info!("Nothing to recalibrate from; give \
+                           --reference-patch or select a target")
+                }
+            }
+        }
+        // There is nothing to select in the other modes. Backends that need
+        // no selection start on the whole frame.
+        let idle = !targets[..first_targets].iter().any(Option::is_some);
+        let selection = if config.mode != Mode::Track || armed {
+            None
+        } else if idle && profile.is_some() {
+            profile.as_ref().map(Profile::window)
+        } else if selection_status.status {
+            selection_status.status = false;
+            record(&mut session, &Event::Select(selection_status.selection));
+            Some(selection_status.selection)
+        } else if let Some(region) = requested.take() {
+            Some(tracker::clip(region, m.cols, m.rows))
+        } else if stopped {
+            None
+        } else if idle && !config.tracker.needs_selection() {
+            Some(Rect::new(0, 0, m.cols, m.rows))
+        } else if idle {
+            // Look for a face or a hand until one is found or a region is
+            // selected.
+            let face = faces.as_ref().and_then(|f| f.largest(&m)).map(|face| {
+                info!("Found a face at {:?}", face);
+                detect::face_core(face)
+            });
+            face.or_else(|| {
+                skin.as_ref().and_then(|skin| {
+                    let hand = skin.find(&m);
+                    m.rectangle_custom(skin.zone(),
+                                       Scalar::new(0, 255, 255, 0),
+                                       1,
+                                       LineTypes::Line8);
+                    hand
+                })
+            })
+            .or(woke)
+        } else {
+            None
+        };
+        if let Some(selection) = selection {
+            let mut target = Target::new(&config, &m, selection, &params);
+            if let Some(profile) = profile.take() {
+                target.restore(&profile.model).map_err(Error::Data)?;
+                info!("Restored the profile at {:?}", selection);
+            }
+            if targets.len() == 1 {
+                info!("Initialize tracking, setting up {} search",
+                      target.name());
+            } else {
+                info!("Initialize tracking of target {} of {}, setting \
+                       up {} search",
+                      next_target + 1,
+                      targets.len(),
+                      target.name());
+            }
+            undo.push((next_target, targets[next_target].replace(target)));
+            if undo.len() > UNDO_DEPTH {
+                undo.remove(0);
+            }
+            last_selection = Some((next_target, selection));
+            if let Some(ref mut hands) = hands {
+                hands.reset();
+            }
+            next_target = (next_target + 1) % first_targets;
+            m.rectangle(selection);
+        }
+        for (camera, slot) in cameras.iter_mut()
+            .zip(&mut targets[first_targets..]) {
+            let selection = if camera.selection_status.status {
+                camera.selection_status.status = false;
+                Some(camera.selection_status.selection)
+            } else if slot.is_none() && !config.tracker.needs_selection() {
+                Some(Rect::new(0, 0, camera.frame.cols, camera.frame.rows))
+            } else {
+                None
+            };
+            if let Some(selection) = selection {
+                let target =
+                    Target::new(&config, &camera.frame, selection, &params);
+                info!("Initialize tracking in {}, setting up {} search",
+                      camera.window,
+                      target.name());
+                *slot = Some(target);
+                camera.frame.rectangle(selection);
+            }
+        }
+
+        if let Some(click) = selection_status.click.take() {
+            let done = match plane_corners {
+                Some(ref mut corners) => {
+                    corners.push([f64::from(click.x), f64::from(click.y)]);
+                    corners.len() == homography::CORNERS.len()
+                }
+                None => false,
+            };
+            if done {
+                let c = plane_corners.take().unwrap();
+                let plane = Plane {
+                    corners: [c[0], c[1], c[2], c[3]],
+                    size: config.plane_size,
+                };
+                match plane.homography() {
+                    Some(homography) => {
+                        normalizer.set_plane(homography, plane.size);
+                        info!("Calibrated the plane");
+                        if let Some(ref path) = config.plane {
+                            match plane.save(path) {
+                                Ok(()) => {
+                                    info!("Wrote the plane to {}", path)
+                                }
+                                Err(e) => warn!("{}", e),
+                            }
+                        }
+                    }
+                    None => {
+                        info!("Three of the corners are on a line; press \
+                               p to calibrate again")
+                    }
+                }
+            }
+        }
+
+        // With `--privacy`, the targets are tracked in a copy of the frame,
+        // and the overlays drawn over the masked frame.
+        let raw = config.privacy.map(|privacy| {
+            let raw = m.clone();
+            privacy.apply(&m);
+            raw
+        });
+        let mut inset = None;
+        let mut confidence = None;
+        // The region of every target, `None` for lost ones.
+        let mut tracked = Vec::new();
+        if targets.iter().any(Option::is_some) {
+            let count = targets.len();
+            // The region and the values of every target; NaN for targets
+            // that are lost or not selected yet.
+            let mut regions = Vec::with_capacity(count);
+            let mut values = Vec::with_capacity(count);
+            let mut statuses = Vec::with_capacity(count);
+            let mut tracking = false;
+            for (i, slot) in targets.iter_mut().enumerate() {
+                let target = match *slot {
+                    Some(ref mut target) => target,
+                    None => {
+                        trails[i].clear();
+                        regions.push(None);
+                        values.push(vec![f64::NAN; config.target_dims()]);
+                        statuses.push(None);
+                        continue;
+                    }
+                };
+                let (source, frame, row) = if i < first_targets {
+                    (raw.as_ref().unwrap_or(&m), &m, i)
+                } else {
+                    let frame = &cameras[i - first_targets].frame;
+                    (frame, frame, 0)
+                };
+                let mut o = target.update(&config,
+                                          source,
+                                          &params,
+                                          captured,
+                                          smoothing);
+                if config.depth {
+                    let r = &o.track.region;
+                    let (cols, rows) = captured_size;
+                    let [x, y] = converter.captured(captured_size,
+                                                    [r.cx, r.cy]);
+                    let z = realsense::latest()
+                        .and_then(|d| d.at(x, y, cols, rows));
+                    o.values.push(z.unwrap_or(f64::NAN));
+                }
+                if confidence.is_none() {
+                    confidence = Some(o.track.confidence);
+                }
+                statuses.push(Some((o.track.confidence, o.state)));
+                if let (Some(metrics), true) = (&metrics, o.lost) {
+                    metrics.lost();
+                }
+                // Zoom in on the first tracked target, before anything is
+                // drawn over it.
+                if show_zoom && inset.is_none() && i < first_targets &&
+                   o.state == TrackState::Tracking {
+                    inset = overlay::zoom(&m, o.track.region.bounding_rect());
+                }
+
+                if config.features.has_ellipse() {
+                    overlay::ellipse(frame,
+                                     &o.track.region,
+                                     Scalar::new(0, 255, 0, 0));
+                } else {
+                    frame.rectangle(o.track.region.bounding_rect());
+                }
+                for p in &o.track.points {
+                    frame.circle(Point2i::new(p.x as i32, p.y as i32),
+                                 3,
+                                 Scalar::new(0, 255, 255, 0),
+                                 -1);
+                }
+                if smoothing {
+                    frame.rectangle_custom(o.filtered.bounding_rect(),
+                                           Scalar::new(255, 0, 0, 0),
+                                           2,
+                                           LineTypes::Line8);
+                }
+                match o.state {
+                    TrackState::Tracking => trails[i].push(&o.track.region),
+                    TrackState::Lost => trails[i].clear(),
+                }
+                trails[i].draw(frame);
+
+                let (color, status) = match o.state {
+                    TrackState::Tracking => {
+                        (Scalar::new(0, 255, 0, 0), "tracking")
+                    }
+                    TrackState::Lost => (Scalar::new(0, 0, 255, 0), "LOST"),
+                };
+                let text = if count == 1 {
+                    format!("{} ({:.2})", status, o.track.confidence)
+                } else {
+                    format!("{}: {} ({:.2})", i + 1, status, o.track.confidence)
+                };
+                let origin =
+                    Point2i::new(10, frame.rows - 10 - 20 * row as i32);
+                frame.put_text(&text,
+                               origin,
+                               HersheyFonts::HersheySimplex,
+                               0.6,
+                               color);
+
+                if show_back_project && i == 0 {
+                    if let Some(back_project) = target.back_project() {
+                        let view = back_project
+                            .cvt_color(ColorConversionCodes::GRAY2BGR);
+                        view.rectangle_custom(o.track.window,
+                                              Scalar::new(0, 255, 255, 0),
+                                              2,
+                                              LineTypes::Line8);
+                        window::show(BACK_PROJECT_WINDOW, &view);
+                    }
+                }
+
+                // The profile follows the first target while it is tracked.
+                let due = profile_saved.elapsed() >=
+                          Duration::from_millis(PROFILE_INTERVAL_MS);
+                if let Some(ref path) = config.save_profile {
+                    if i == 0 && o.state == TrackState::Tracking && due {
+                        let profile = Profile::new(target.name(),
+                                                   &params,
+                                                   o.track.window,
+                                                   target.model());
+                        if let Err(e) = profile.save(path) {
+                            warn!("{}", e);
+                        }
+                        profile_saved = captured;
+                    }
+                }
+
+                match o.state {
+                    TrackState::Tracking => {
+                        tracking = true;
+                        regions.push(Some(o.track.region));
+                        values.push(o.values);
+                    }
+                    TrackState::Lost => {
+                        regions.push(None);
+                        values.push(vec![f64::NAN; o.values.len()]);
+                    }
+                }
+            }
+
+            let order: Vec<usize> = match hands {
+                Some(ref mut hands) => {
+                    let order =
+                        hands.assign(captured, [regions[0], regions[1]]);
+                    for (hand, &i) in ["L", "R"].iter().zip(&order) {
+                        if let Some(ref r) = regions[i] {
+                            m.put_text(hand,
+                                       Point2i::new(r.cx as i32, r.cy as i32),
+                                       HersheyFonts::HersheySimplex,
+                                       1.0,
+                                       Scalar::new(0, 255, 255, 0));
+                        }
+                    }
+                    order.to_vec()
+                }
+                None => (0..count).collect(),
+            };
+            let mut sample = Vec::with_capacity(config.tracked_dims());
+            for &i in &order {
+                sample.extend_from_slice(&values[i]);
+            }
+            if let Some(ref rig) = config.stereo {
+                // The left view, and the depth from both.
+                sample.truncate(config.target_dims());
+                let z = match (regions[0], regions[1], right_size) {
+                    (Some(left), Some(right), Some(right_size)) => {
+                        rig.depth(converter.captured(captured_size,
+                                                     left.centroid()),
+                                  converter.captured(right_size,
+                                                     right.centroid()))
+                    }
+                    _ => None,
+                };
+                sample.push(z.unwrap_or(f64::NAN));
+            }
+            let time = (captured - start).as_secs_f64();
+            if let Some(ref mut zones) = zones {
+                let centroids: Vec<[f64; 2]> = regions[..first_targets]
+                    .iter()
+                    .flatten()
+                    .map(Region::centroid)
+                    .collect();
+                follow_zones(zones, &centroids, zone_events.as_ref(), time);
+                if config.zone_dims {
+                    sample.extend(zones.values());
+                }
+            }
+
+            // With `skip`, nothing is sent unless at least one target is
+            // tracked.
+            normalizer.apply(&mut sample, m.cols, m.rows);
+            *status.borrow_mut() = order.iter().map(|&i| statuses[i]).collect();
+            report.targets(time, &statuses);
+            tracked = regions;
+            if tracking || config.loss.on_lost == OnLost::Nan {
+                sink.send(&Sample::new(seq, time, sample)).ok();
+            }
+            seq += 1;
+        } else if let Some(ref mut zones) = zones {
+            let time = (captured - start).as_secs_f64();
+            follow_zones(zones, &[], zone_events.as_ref(), time);
+        }
+        if let Some(ref zones) = zones {
+            zones.draw(&m);
+        }
+        sink.tick(Instant::now()).ok();
+
+        let mut fresh = false;
+        if let Some(p) = predictions.latest() {
+            labels.update(&p);
+            fresh = p.is_fresh(Duration::from_millis(PREDICTION_TIMEOUT_MS));
+        }
+        labels.draw(&m, fresh);
+
+        if let Some(rect) = selection_status.dragging {
+            overlay::rubber_band(&m, rect);
+        }
+        if let Some(ref inset) = inset {
+            overlay::picture_in_picture(&m, inset);
+        }
+        if let Some(ref corners) = plane_corners {
+            for c in corners {
+                m.circle(Point2i::new(c[0] as i32, c[1] as i32),
+                         4,
+                         Scalar::new(255, 0, 255, 0),
+                         -1);
+            }
+            m.put_text(&format!("click the {} corner of the plane",
+                                homography::CORNERS[corners.len()]),
+                       Point2i::new(10, 60),
+                       HersheyFonts::HersheySimplex,
+                       0.6,
+                       Scalar::new(255, 0, 255, 0));
+        }
+        if config.dataset.is_some() {
+            m.put_text(&format!("class {}", class.get()),
+                       Point2i::new(m.cols - 110, 30),
+                       HersheyFonts::HersheySimplex,
+                       0.8,
+                       Scalar::new(0, 255, 255, 0));
+        }
+        if paused.load(Ordering::SeqCst) {
+            m.put_text("PAUSED",
+                       Point2i::new(m.cols / 2 - 40, 30),
+                       HersheyFonts::HersheySimplex,
+                       0.8,
+                       Scalar::new(0, 0, 255, 0));
+        }
+        let dropped = input.dropped() +
+                      cameras.iter().map(|c| c.input.dropped()).sum::<u64>();
+        let times = *timings.lock().unwrap();
+        hud.update(captured, stats.get(), times, dropped);
+        if let Some(ref metrics) = metrics {
+            metrics.frame(dropped, stats.get().sent, times);
+        }
+        report.frame(stats.get());
+        if show_hud {
+            hud.draw(&m, stats.get(), confidence);
+        }
+        if dashboard.is_some() || api.is_some() {
+            let status = dashboard::Status {
+                fps: hud.fps(),
+                send_rate: hud.send_rate(),
+                connected: stats.get().connected,
+                paused: paused.load(Ordering::SeqCst),
+                tracker: config.tracker.to_string(),
+                stopped,
+                targets: tracked.iter()
+                    .map(|r| r.map(|r| [r.cx, r.cy]))
+                    .collect(),
+            };
+            if let Some(ref api) = api {
+                api.publish(status.clone());
+            }
+            if let Some(ref dashboard) = dashboard {
+                dashboard.publish(&m, status);
+            }
+        }
+
+        if let Some(Err(e)) = recorder.as_mut().map(|r| r.write(&m)) {
+            warn!("{}", e);
+            recorder = None;
+        }
+
+        timings.lock().unwrap().process = processing.elapsed();
+        let rendering = Instant::now();
+        for camera in &cameras {
+            if let Some(rect) = camera.selection_status.dragging {
+                overlay::rubber_band(&camera.frame, rect);
+            }
+            if !config.headless {
+                window::show(&camera.window, &camera.frame);
+            }
+        }
+        if show_plot {
+            window::show(plot::WINDOW, &plot.render());
+        }
+        // The inputs pace the loop; the display only polls the keyboard.
+        let key = if config.headless {
+            -1
+        } else {
+            window::show_and_wait("Window", &m, 1)
+        };
+        timings.lock().unwrap().render = rendering.elapsed();
+        let key = match replay {
+            // Only quitting is left to users during a replay.
+            Some(ref replay) if keys::command(key) != Some(Command::Quit) => {
+                replay.key(frame_index).unwrap_or(-1)
+            }
+            _ => key,
+        };
+        if keys::command(key).is_some() {
+            record(&mut session, &Event::Key(key));
+        }
+        frame_index += 1;
+        match keys::command(key) {
+            Some(Command::Quit) => break,
+            Some(Command::Reset) => {
+                info!("Tracking reset");
+                reset(&mut targets, &mut hands);
+                next_target = 0;
+                last_selection = None;
+                undo.clear();
+            }
+            Some(Command::Pause) => {
+                let was_paused = paused.fetch_xor(true, Ordering::SeqCst);
+                info!("Streaming {}",
+                      if was_paused { "resumed" } else { "paused" });
+            }
+            Some(Command::Snapshot) => {
+                let path = format!("snapshot-{}.png", seq);
+                if imwrite(&path, &m) {
+                    info!("Saved {}", path);
+                } else {
+                    warn!("Failed to save {}", path);
+                }
+            }
+            Some(Command::Report) => {
+                let path = config.report
+                    .clone()
+                    .unwrap_or_else(|| format!("report-{}.txt", seq));
+                match report.write(&path, start.elapsed(), dropped) {
+                    Ok(()) => info!("Saved {}", path),
+                    Err(e) => warn!("Failed to save {}: {}", path, e),
+                }
+            }
+            Some(Command::Smoothing) if !filters.is_empty() => {
+                smoothing = !smoothing;
+                info!("Smoothing {}",
+                      if smoothing { "on" } else { "off" });
+            }
+            Some(Command::BackProject) |
+            Some(Command::Zoom) if config.privacy.is_some() => {
+                info!("The back projection and the zoom show the video, \
+                       which --privacy masks");
+            }
+            Some(Command::BackProject) => {
+                show_back_project = !show_back_project;
+                if show_back_project {
+                    window::open(BACK_PROJECT_WINDOW);
+                } else {
+                    window::close(BACK_PROJECT_WINDOW);
+                }
+            }
+            Some(Command::Zoom) => show_zoom = !show_zoom,
+            Some(Command::Hud) => show_hud = !show_hud,
+            Some(Command::Plot) => {
+                show_plot = !show_plot;
+                if show_plot {
+                    window::open(plot::WINDOW);
+                } else {
+                    window::close(plot::WINDOW);
+                }
+            }
+            Some(Command::PrintTrackbars) if trackbars.is_some() => {
+                trackbars::print(&params);
+            }
+            Some(Command::Relock) => {
+                info!("Relocking the camera controls");
+                input.relock();
+                for camera in &cameras {
+                    camera.input.relock();
+                }
+            }
+            Some(Command::Recalibrate) => {
+                let windows = tracked.iter()
+                    .map(|r| r.map(|r| r.bounding_rect()))
+                    .collect();
+                recalibration = Some(windows);
+            }
+            Some(Command::Plane) if !config.headless => {
+                info!("Click the four corners of the plane, starting at \
+                       the top left");
+                plane_corners = Some(Vec::new());
+            }
+            Some(command @ Command::Nudge(..)) |
+            Some(command @ Command::Grow(_)) => {
+                if let Some((slot, rect)) = last_selection {
+                    let rect = tracker::clip(mouse::adjust(rect, command),
+                                             m.cols,
+                                             m.rows);
+                    if rect.width > 0 && rect.height > 0 {
+                        last_selection = Some((slot, rect));
+                        adjusted = last_selection;
+                    }
+                }
+            }
+            Some(Command::Undo) => {
+                match undo.pop() {
+                    Some((slot, previous)) => {
+                        info!("Undid the last selection");
+                        targets[slot] = previous;
+                        next_target = slot;
+                        last_selection = None;
+                        if let Some(ref mut hands) = hands {
+                            hands.reset();
+                        }
+                    }
+                    None => info!("No selection to undo"),
+                }
+            }
+            Some(Command::Number(n)) if config.dataset.is_some() => {
+                info!("Class {}", n);
+                class.set(n);
+            }
+            Some(Command::Number(n)) if n > 0 => {
+                if let Some(region) = config.regions.get(n as usize - 1) {
+                    info!("Selected the region {}", region.name);
+                    requested = Some(region.rect());
+                }
+            }
+            _ => {}
+        }
+        let requests: Vec<Request> = api.iter()
+            .flat_map(Api::requests)
+            .chain(grpc.iter().flat_map(Grpc::requests))
+            .collect();
+        for request in requests {
+            match request {
+                Request::Start => stopped = false,
+                Request::Stop => {
+                    stopped = true;
+                    reset(&mut targets, &mut hands);
+                    next_target = 0;
+                }
+                Request::Roi(rect) => {
+                    stopped = false;
+                    requested = Some(rect);
+                }
+                Request::Tracker(backend) => {
+                    info!("Switching to the {} tracker", backend);
+                    config.tracker = backend;
+                    reset(&mut targets, &mut hands);
+                    next_target = 0;
+                    // The first target goes on from where it was.
+                    if let Some(&Some(region)) = tracked.first() {
+                        requested = Some(region.bounding_rect());
+                    }
+                }
+            }
+        }
+    }
+
+    let dropped = input.dropped() +
+                  cameras.iter().map(|c| c.input.dropped()).sum::<u64>();
+    // The cameras are released first, and the windows closed.
+    input.close();
+    for camera in cameras {
+        if !config.headless {
+            window::close(&camera.window);
+        }
+        camera.input.close();
+    }
+    if !config.headless {
+        window::close("Window");
+        if show_back_project {
+            window::close(BACK_PROJECT_WINDOW);
+        }
+        if show_plot {
+            window::close(plot::WINDOW);
+        }
+    }
+    // Dropping the sinks writes the samples queued for ESP and closes the
+    // logs, as dropping the recorders closes the recordings.
+    drop(sink);
+    drop(session);
+    drop(recorder);
+    info!("Processed {} frames in {:.1} s, sent {} samples and dropped {} \
+           frames",
+          frame_index,
+          start.elapsed().as_secs_f64(),
+          stats.get().sent,
+          dropped);
+    if let Some(ref latency) = latency {
+        latency.report();
+    }
+    if let Some(ref path) = config.report {
+        match report.write(path, start.elapsed(), dropped) {
+            Ok(()) => info!("Wrote the report of the session to {}", path),
+            Err(e) => warn!("Cannot write the report to {}: {}", path, e),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+//! Logging.
+//!
+//! Messages go through the `log` macros to standard error, as text or, with
+//! `--log-format json`, as one JSON object per line:
+//!
+//! ```text
+//! {"time":1718000000.123,"level":"info","module":"loss","message":"..."}
+//! ```
+//!
+//! Only `info` and above are logged, `debug` with `--verbose`. `--log`
+//! sets the level per module, in the form `LEVEL,MODULE=LEVEL,...`, e.g.
+//! `warn,loss=debug,control=info`. Modules are named as in this crate,
+//! without its name, and include their submodules: `tracker` covers
+//! `tracker::camshift`. Reports that are the point of a command, such as
+//! the tables of `bench`, are printed rather than logged.
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Prefix of the targets of this crate's messages.
+const CRATE: &str = "esp_vision::";
+
+/// How messages are written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format `{}`", s)),
+        }
+    }
+}
+
+/// Which messages are logged.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogFilter {
+    /// The level of modules that have none of their own.
+    pub default: LevelFilter,
+    /// Levels of modules and their submodules.
+    pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl Default for LogFilter {
+    fn default() -> LogFilter {
+        LogFilter {
+            default: LevelFilter::Info,
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl FromStr for LogFilter {
+    type Err = String;
+
+    /// Parses `LEVEL,MODULE=LEVEL,...`; every part is optional.
+    fn from_str(s: &str) -> Result<LogFilter, String> {
+        let mut filter = LogFilter::default();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let parse = |l: &str| {
+                l.parse::<LevelFilter>()
+                    .map_err(|_| format!("unknown log level `{}`", l))
+            };
+            match part.find('=') {
+                Some(i) => {
+                    filter.modules
+                        .push((part[..i].to_string(), parse(&part[i + 1..])?))
+                }
+                None => filter.default = parse(part)?,
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl LogFilter {
+    /// The level of the module `target`, that of its most specific entry.
+    fn level(&self, target: &str) -> LevelFilter {
+        let module = target.strip_prefix(CRATE).unwrap_or(target);
+        self.modules
+            .iter()
+            .filter(|&&(ref name, _)| {
+                module == name ||
+                module.starts_with(name.as_str()) &&
+                module[name.len()..].starts_with("::")
+            })
+            .max_by_key(|&&(ref name, _)| name.len())
+            .map_or(self.default, |&(_, level)| level)
+    }
+
+    /// The most verbose level of any module.
+    fn max(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.default, Ord::max)
+    }
+}
+
+struct Logger {
+    settings: RwLock<(LogFilter, LogFormat)>,
+}
+
+static LOGGER: Logger = Logger {
+    settings: RwLock::new((LogFilter {
+                               default: LevelFilter::Info,
+                               modules: Vec::new(),
+                           },
+                           LogFormat::Text)),
+};
+
+/// Installs the logger, logging `info` and above as text until
+/// `configure` is called.
+pub fn init() {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+/// Logs what `filter` lets through, in `format`.
+pub fn configure(filter: LogFilter, format: LogFormat) {
+    log::set_max_level(filter.max());
+    *LOGGER.settings.write().unwrap() = (filter, format);
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let settings = self.settings.read().unwrap();
+        metadata.level() <= settings.0.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let module = record.target()
+            .strip_prefix(CRATE)
+            .unwrap_or_else(|| record.target());
+        let line = match self.settings.read().unwrap().1 {
+            LogFormat::Text => {
+                format!("[{} {}] {}\n",
+                        level(record.level()),
+                        module,
+                        record.args())
+            }
+            LogFormat::Json => {
+                let time = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs_f64())
+                    .unwrap_or(0.0);
+                format!("{{\"time\":{:.3},\"level\":\"{}\",\"module\":{},\
+                         \"message\":{}}}\n",
+                        time,
+                        level(record.level()).to_lowercase(),
+                        json_string(module),
+                        json_string(&record.args().to_string()))
+            }
+        };
+        io::stderr().write_all(line.as_bytes()).ok();
+    }
+
+    fn flush(&self) {
+        io::stderr().flush().ok();
+    }
+}
+
+fn level(level: Level) -> &'static str {
+    match level {
+        Level::Error => "ERROR",
+        Level::Warn => "WARN",
+        Level::Info => "INFO",
+        Level::Debug => "DEBUG",
+        Level::Trace => "TRACE",
+    }
+}
+
+/// `s` as a quoted JSON string.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                write!(out, "\\u{:04x}", c as u32).unwrap()
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,180 @@
+//! Detection of tracking loss.
+//!
+//! When the target leaves the frame, CAMShift latches onto background noise
+//! and happily keeps reporting positions. `LossDetector` declares the track
+//! lost when the confidence stays below a threshold for a number of frames,
+//! or immediately when the search window degenerates.
+use crate::tracker::Track;
+use std::fmt;
+use std::str::FromStr;
+
+/// Whether the tracker is locked onto the target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrackState {
+    Tracking,
+    Lost,
+}
+
+impl fmt::Display for TrackState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrackState::Tracking => write!(f, "tracking"),
+            TrackState::Lost => write!(f, "lost"),
+        }
+    }
+}
+
+/// What to send while the track is lost.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnLost {
+    /// Send nothing.
+    Skip,
+    /// Send a sample whose values are all NaN.
+    Nan,
+}
+
+impl FromStr for OnLost {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OnLost, String> {
+        match s {
+            "skip" => Ok(OnLost::Skip),
+            "nan" => Ok(OnLost::Nan),
+            _ => Err(format!("unknown on-lost behavior `{}`", s)),
+        }
+    }
+}
+
+/// Thresholds of the `LossDetector`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LossConfig {
+    /// Confidence below which a frame counts as "bad".
+    pub min_confidence: f64,
+    /// Number of consecutive bad frames before the track is lost.
+    pub patience: u32,
+    /// Search windows narrower or shorter than this (in pixels) are
+    /// degenerate.
+    pub min_size: i32,
+    pub on_lost: OnLost,
+    /// Search the whole frame for the target while it is lost.
+    pub reacquire: bool,
+    /// Confidence a candidate must reach to be re-acquired. Higher than
+    /// `min_confidence` so we don't jump onto the noise we just lost to.
+    pub reacquire_confidence: f64,
+}
+
+impl Default for LossConfig {
+    fn default() -> LossConfig {
+        LossConfig {
+            min_confidence: 0.1,
+            patience: 5,
+            min_size: 4,
+            on_lost: OnLost::Skip,
+            reacquire: false,
+            reacquire_confidence: 0.3,
+        }
+    }
+}
+
+/// `LossDetector` turns per-frame confidences into a `TrackState`.
+pub struct LossDetector {
+    config: LossConfig,
+    state: TrackState,
+    bad_frames: u32,
+}
+
+impl LossDetector {
+    pub fn new(config: LossConfig) -> LossDetector {
+        LossDetector {
+            config,
+            state: TrackState::Tracking,
+            bad_frames: 0,
+        }
+    }
+
+    /// Starts over in the `Tracking` state, e.g. after a new selection.
+    pub fn reset(&mut self) {
+        self.state = TrackState::Tracking;
+        self.bad_frames = 0;
+    }
+
+    pub fn state(&self) -> TrackState {
+        self.state
+    }
+
+    /// Feeds the result of one frame and returns the new state.
+    pub fn update(&mut self, track: &Track) -> TrackState {
+        let degenerate = track.window.width < self.config.min_size ||
+                         track.window.height < self.config.min_size;
+        if degenerate || track.confidence < self.config.min_confidence {
+            self.bad_frames += 1;
+            debug!("Bad frame {} of {} (confidence {:.2})",
+                   self.bad_frames,
+                   self.config.patience,
+                   track.confidence);
+        } else {
+            self.bad_frames = 0;
+        }
+
+        let state = if degenerate || self.bad_frames >= self.config.patience {
+            TrackState::Lost
+        } else if self.bad_frames == 0 {
+            TrackState::Tracking
+        } else {
+            // Too early to call it; keep whatever we had.
+            self.state
+        };
+        if state != self.state {
+            info!("Track {} (confidence {:.2})", state, track.confidence);
+        }
+        self.state = state;
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::Region;
+    use crate::tracker::Track;
+    use rust_vision::Rect;
+    use super::{LossConfig, LossDetector};
+    use super::TrackState::{Lost, Tracking};
+
+    fn track(confidence: f64, size: i32) -> Track {
+        let window = Rect::new(10, 10, size, size);
+        Track {
+            region: Region::from_rect(window),
+            window,
+            confidence,
+            points: Vec::new(),
+        }
+    }
+
+    fn detector() -> LossDetector {
+        LossDetector::new(LossConfig {
+            min_confidence: 0.2,
+            patience: 3,
+            ..LossConfig::default()
+        })
+    }
+
+    #[test]
+    fn loses_the_track_after_enough_bad_frames_in_a_row() {
+        let mut loss = detector();
+        let states: Vec<_> = [0.1, 0.1, 0.5, 0.1, 0.1, 0.1, 0.1, 0.5]
+            .iter()
+            .map(|&c| loss.update(&track(c, 20)))
+            .collect();
+        assert_eq!(states,
+                   [Tracking, Tracking, Tracking, Tracking, Tracking, Lost,
+                    Lost, Tracking]);
+    }
+
+    #[test]
+    fn loses_a_degenerate_window_at_once() {
+        let mut loss = detector();
+        assert_eq!(loss.update(&track(1.0, 2)), Lost);
+        loss.reset();
+        assert_eq!(loss.state(), Tracking);
+    }
+}
@@ -0,0 +1,191 @@
+//! The dimensions of the samples, described for the other end.
+//!
+//! With several targets and features, a sample is a long row of numbers
+//! that has to be kept in line with the labels of the ESP pipeline by hand.
+//! The manifest lists the dimensions in the order they are sent, with their
+//! names and units (`px`, `unit`, `signed`, `aspect` or `letterbox`, `^2`
+//! for areas, and nothing for values that don't depend on the frame),
+//! generated from the options. ESP receives the time and sequence number of
+//! `--ascii-stamps` as two more dimensions, `t` and `seq`, which the
+//! manifest lists too.
+//!
+//! `--print-manifest` prints it and the lines declaring the stream in the
+//! ESP example, ready to paste, and exits:
+//!
+//! ```text
+//! esp-vision --targets 2 --target-names hand,ball --print-manifest
+//! ```
+//!
+//! ```c++
+//! TcpInputStream stream(8001, 4);
+//! stream.setLabelsForAllDimensions({"hand_x", "hand_y", "ball_x", "ball_y"});
+//! ```
+//!
+//! `--target-names` names the dimensions of every target after it rather
+//! than numbering them (`x1`, `y1`, `x2`, ...).
+//!
+//! `--send-manifest` starts the stream with the manifest, in a line before
+//! the first sample, for consumers that check what they receive:
+//! `# hand_x:px hand_y:px ...` in the ASCII format, and
+//! `{"dimensions":[{"name":"hand_x","unit":"px"},...]}` in JSON. ESP's
+//! `TcpInputStream` doesn't expect it. The framed format always starts with
+//! a header of its own.
+use crate::config::Config;
+use crate::framed::Dimension;
+use crate::sample::Sample;
+use crate::serialize::{Format, Serializer};
+use std::fmt::Write;
+
+/// Port of the `TcpInputStream` when `--server` has none.
+const DEFAULT_PORT: &str = "8001";
+
+/// `Manifest` lists the dimensions ESP receives.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+    dimensions: Vec<Dimension>,
+}
+
+impl Manifest {
+    /// The dimensions ESP receives with `config`.
+    pub fn new(config: &Config) -> Manifest {
+        let mut manifest = Manifest::from_names(config.dimension_names(),
+                                                config.dimension_labels());
+        if config.esp_dims() > config.dims() {
+            manifest.push("t", "s");
+            manifest.push("seq", "");
+        }
+        manifest
+    }
+
+    /// Dimensions called `names` and measured in `units`.
+    pub fn from_names(names: Vec<String>, units: Vec<String>) -> Manifest {
+        let dimensions = names.into_iter()
+            .zip(units)
+            .map(|(name, unit)| Dimension { name, unit })
+            .collect();
+        Manifest { dimensions }
+    }
+
+    fn push(&mut self, name: &str, unit: &str) {
+        self.dimensions.push(Dimension {
+            name: name.to_string(),
+            unit: unit.to_string(),
+        });
+    }
+
+    /// The line starting a stream in `format`, if it has room for one.
+    pub fn line(&self, format: Format) -> Option<String> {
+        match format {
+            Format::Ascii => {
+                let mut line = String::from("#");
+                for d in &self.dimensions {
+                    write!(line, " {}:{}", d.name, d.unit).unwrap();
+                }
+                Some(line)
+            }
+            Format::Json => {
+                let dimensions: Vec<String> = self.dimensions
+                    .iter()
+                    .map(|d| {
+                        format!("{{\"name\":\"{}\",\"unit\":\"{}\"}}",
+                                d.name,
+                                d.unit)
+                    })
+                    .collect();
+                Some(format!("{{\"dimensions\":[{}]}}", dimensions.join(",")))
+            }
+            Format::Binary | Format::Framed => None,
+        }
+    }
+
+    /// The dimensions, one per line, numbered from 1.
+    pub fn table(&self) -> String {
+        let mut table = String::new();
+        for (i, d) in self.dimensions.iter().enumerate() {
+            writeln!(table, "{:>4}  {:<16} {}", i + 1, d.name, d.unit)
+                .unwrap();
+        }
+        table
+    }
+
+    /// The declaration of the ESP stream receiving from `server`.
+    pub fn esp_snippet(&self, server: &str) -> String {
+        let port = match server.rfind(':') {
+            Some(i) => &server[i + 1..],
+            None => DEFAULT_PORT,
+        };
+        let labels: Vec<String> = self.dimensions
+            .iter()
+            .map(|d| format!("\"{}\"", d.name))
+            .collect();
+        format!("TcpInputStream stream({}, {});\n\
+                 stream.setLabelsForAllDimensions({{{}}});\n",
+                port,
+                self.dimensions.len(),
+                labels.join(", "))
+    }
+}
+
+/// `Announced` starts the stream of another serializer with a line.
+pub struct Announced {
+    line: String,
+    inner: Box<dyn Serializer>,
+}
+
+impl Announced {
+    pub fn new(line: String, inner: Box<dyn Serializer>) -> Announced {
+        Announced { line, inner }
+    }
+}
+
+impl Serializer for Announced {
+    fn serialize(&self, sample: &Sample, out: &mut Vec<u8>) {
+        self.inner.serialize(sample, out);
+    }
+
+    fn header(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.line.as_bytes());
+        out.push(b'\n');
+        self.inner.header(out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample::Sample;
+    use crate::serialize::{Format, Serializer};
+    use super::{Announced, Manifest};
+
+    fn manifest() -> Manifest {
+        Manifest::from_names(vec!["hand_x".to_string(),
+                                  "angle".to_string()],
+                             vec!["px".to_string(), String::new()])
+    }
+
+    #[test]
+    fn describes_the_dimensions() {
+        let manifest = manifest();
+        assert_eq!(manifest.line(Format::Ascii).unwrap(),
+                   "# hand_x:px angle:");
+        assert_eq!(manifest.line(Format::Json).unwrap(),
+                   "{\"dimensions\":[{\"name\":\"hand_x\",\"unit\":\"px\"},\
+                    {\"name\":\"angle\",\"unit\":\"\"}]}");
+        assert_eq!(manifest.line(Format::Framed), None);
+        assert_eq!(manifest.esp_snippet("192.168.1.10:9000"),
+                   "TcpInputStream stream(9000, 2);\n\
+                    stream.setLabelsForAllDimensions({\"hand_x\", \
+                    \"angle\"});\n");
+    }
+
+    #[test]
+    fn announces_the_stream() {
+        let line = manifest().line(Format::Ascii).unwrap();
+        let inner = Format::Ascii.serializer(Vec::new(), Vec::new(), false);
+        let serializer = Announced::new(line, inner);
+        let mut out = Vec::new();
+        serializer.header(&mut out);
+        serializer.serialize(&Sample::new(0, 0.0, vec![1.0, 2.0]), &mut out);
+        assert_eq!(String::from_utf8(out).unwrap(),
+                   "# hand_x:px angle:\n1 2 \n");
+    }
+}
@@ -0,0 +1,131 @@
+//! Colored markers: fixed HSV colors found with blob detection.
+//!
+//! Stickers in distinct colors can be tracked without any selection. Every
+//! marker has a name and a color range; each frame, the largest blob of each
+//! color becomes that marker's region.
+use crate::features::Region;
+use rust_vision::*;
+use std::str::FromStr;
+
+/// Size of the kernel used to clean up the color masks.
+const KERNEL: i32 = 5;
+/// Blobs smaller than this (in pixels) are noise.
+const MIN_AREA: f32 = 50.0;
+
+/// `MarkerSpec` names a marker and its color.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MarkerSpec {
+    /// Prefix of the marker's dimension names, e.g. `red` for `red_x`.
+    pub name: String,
+    /// Lower bound of the color, as `[hue, saturation, value]`. A lower hue
+    /// above the upper one wraps around, which is needed for red.
+    pub hsv_low: [i32; 3],
+    /// Upper bound of the color, as `[hue, saturation, value]`.
+    pub hsv_high: [i32; 3],
+}
+
+impl FromStr for MarkerSpec {
+    type Err = String;
+
+    /// Parses `name:h,s,v:h,s,v`, e.g. `red:170,120,70:10,255,255`.
+    fn from_str(s: &str) -> Result<MarkerSpec, String> {
+        let parts: Vec<&str> = s.split(':').collect();
+        if parts.len() != 3 || parts[0].is_empty() {
+            return Err(format!("marker `{}` is not name:h,s,v:h,s,v", s));
+        }
+        Ok(MarkerSpec {
+            name: parts[0].to_string(),
+            hsv_low: hsv(parts[1])?,
+            hsv_high: hsv(parts[2])?,
+        })
+    }
+}
+
+fn hsv(s: &str) -> Result<[i32; 3], String> {
+    let v = s.split(',')
+        .map(|c| c.trim().parse::<i32>())
+        .collect::<Result<Vec<i32>, _>>()
+        .map_err(|e| format!("invalid color `{}`: {}", s, e))?;
+    if v.len() != 3 {
+        return Err(format!("color `{}` needs three components", s));
+    }
+    Ok([v[0], v[1], v[2]])
+}
+
+/// `Markers` finds every configured marker in a frame.
+pub struct Markers {
+    specs: Vec<MarkerSpec>,
+    detector: SimpleBlobDetector,
+    kernel: Mat,
+}
+
+impl Markers {
+    pub fn new(specs: Vec<MarkerSpec>) -> Markers {
+        let params = SimpleBlobDetectorParams {
+            min_threshold: 127.0,
+            max_threshold: 255.0,
+            // The masks are white on black.
+            filter_by_color: true,
+            blob_color: 255,
+            filter_by_area: true,
+            min_area: MIN_AREA,
+            max_area: 1e7,
+            filter_by_circularity: false,
+            filter_by_inertia: false,
+            filter_by_convexity: false,
+        };
+        Markers {
+            specs,
+            detector: SimpleBlobDetector::new(params),
+            kernel: get_structuring_element(MorphShapes::Ellipse,
+                                            Size2i::new(KERNEL, KERNEL)),
+        }
+    }
+
+    pub fn specs(&self) -> &[MarkerSpec] {
+        &self.specs
+    }
+
+    /// The region of every marker in the (BGR) `frame`, in the configured
+    /// order; `None` for markers that are not visible.
+    pub fn update(&self, frame: &Mat) -> Vec<Option<Region>> {
+        let hsv = frame.cvt_color(ColorConversionCodes::BGR2HSV);
+        self.specs
+            .iter()
+            .map(|spec| {
+                let mask = color_mask(&hsv, spec)
+                    .morphology_ex(MorphTypes::Open, &self.kernel, 1);
+                self.detector
+                    .detect(&mask)
+                    .into_iter()
+                    .max_by(|a, b| a.size.partial_cmp(&b.size).unwrap())
+                    .map(|blob| {
+                        let size = blob.size as f64;
+                        Region {
+                            cx: blob.pt.x as f64,
+                            cy: blob.pt.y as f64,
+                            width: size,
+                            height: size,
+                            angle: 0.0,
+                            aspect: 1.0,
+                            axes: [size, size],
+                        }
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Pixels of `hsv` within the color of `spec`.
+fn color_mask(hsv: &Mat, spec: &MarkerSpec) -> Mat {
+    let (low, high) = (spec.hsv_low, spec.hsv_high);
+    let scalar = |c: [i32; 3]| Scalar::new(c[0], c[1], c[2], 0);
+    if low[0] <= high[0] {
+        return hsv.in_range(scalar(low), scalar(high));
+    }
+    // The hue range wraps around 180.
+    let mut mask = hsv.in_range(scalar(low), scalar([180, high[1], high[2]]));
+    mask.logic_or(hsv.in_range(scalar([0, low[1], low[2]]), scalar(high)));
+    mask
+}
@@ -0,0 +1,177 @@
+//! Prometheus metrics, with `--metrics ADDR`.
+//!
+//! `GET /metrics` on `ADDR` returns the counters and gauges below in the
+//! Prometheus text format, so that an installation that stopped tracking
+//! overnight can be alerted on:
+//!
+//! - `esp_vision_frames_total`: frames processed.
+//! - `esp_vision_frames_dropped_total`: live frames dropped, see
+//!   `pipeline`.
+//! - `esp_vision_samples_sent_total`: samples sent to ESP.
+//! - `esp_vision_reconnects_total`: reconnections to network cameras.
+//! - `esp_vision_targets_lost_total`: times a target was lost.
+//! - `esp_vision_stage_seconds{stage="..."}`: the time the capture,
+//!   process, render and send stages took on the last frame.
+//!
+//! Builds without the `net` feature only count, for the `--report`.
+use crate::net::Net;
+use crate::pipeline::StageTimes;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Reconnections to network cameras, counted where they happen.
+pub static RECONNECTS: AtomicU64 = AtomicU64::new(0);
+
+/// `Metrics` is shared between the main loop, which updates it, and the
+/// metrics endpoint.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    frames: AtomicU64,
+    dropped: AtomicU64,
+    sent: AtomicU64,
+    lost: AtomicU64,
+    stages: Mutex<StageTimes>,
+}
+
+impl Metrics {
+    /// Serves `/metrics` on `addr` from `net`.
+    pub fn serve(net: &Net, addr: &str) -> io::Result<Arc<Metrics>> {
+        let metrics = Arc::new(Metrics::default());
+        server::spawn(net, addr, metrics.clone())?;
+        Ok(metrics)
+    }
+
+    /// Takes the measurements of a processed frame: the totals of live
+    /// frames `dropped` and samples `sent`, and the stage `times`.
+    pub fn frame(&self, dropped: u64, sent: u64, times: StageTimes) {
+        self.frames.fetch_add(1, Ordering::SeqCst);
+        self.dropped.store(dropped, Ordering::SeqCst);
+        self.sent.store(sent, Ordering::SeqCst);
+        *self.stages.lock().unwrap() = times;
+    }
+
+    /// Counts a target that was just lost.
+    pub fn lost(&self) {
+        self.lost.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(not(feature = "net"))]
+mod server {
+    use crate::net::{self, Net};
+    use std::io;
+    use std::sync::Arc;
+    use super::Metrics;
+
+    pub fn spawn(_net: &Net,
+                 _addr: &str,
+                 _metrics: Arc<Metrics>)
+                 -> io::Result<()> {
+        Err(net::unavailable("metrics endpoint"))
+    }
+}
+
+#[cfg(feature = "net")]
+mod server {
+    use crate::net::Net;
+    use std::fmt::Write;
+    use std::io;
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use super::{Metrics, RECONNECTS};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    pub fn spawn(net: &Net,
+                 addr: &str,
+                 metrics: Arc<Metrics>)
+                 -> io::Result<()> {
+        let listener = net.block_on(TcpListener::bind(addr))?;
+        net.spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        debug!("Metrics requested by {}", peer);
+                        let metrics = metrics.clone();
+                        tokio::spawn(async move {
+                            respond(stream, &metrics).await.ok()
+                        });
+                    }
+                    Err(e) => warn!("Metrics endpoint error: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// The metrics in the Prometheus text format.
+    fn render(metrics: &Metrics) -> String {
+        let mut out = String::new();
+        let counters = [("frames_total",
+                         "Frames processed.",
+                         &metrics.frames),
+                        ("frames_dropped_total",
+                         "Live frames dropped.",
+                         &metrics.dropped),
+                        ("samples_sent_total",
+                         "Samples sent to ESP.",
+                         &metrics.sent),
+                        ("reconnects_total",
+                         "Reconnections to network cameras.",
+                         &RECONNECTS),
+                        ("targets_lost_total",
+                         "Times a target was lost.",
+                         &metrics.lost)];
+        for &(name, help, value) in &counters {
+            writeln!(out, "# HELP esp_vision_{} {}", name, help).unwrap();
+            writeln!(out, "# TYPE esp_vision_{} counter", name).unwrap();
+            writeln!(out,
+                     "esp_vision_{} {}",
+                     name,
+                     value.load(Ordering::SeqCst))
+                .unwrap();
+        }
+        let times = *metrics.stages.lock().unwrap();
+        writeln!(out,
+                 "# HELP esp_vision_stage_seconds Time of each stage on the \
+                  last frame.")
+            .unwrap();
+        writeln!(out, "# TYPE esp_vision_stage_seconds gauge").unwrap();
+        let stages = [("capture", times.capture),
+                      ("process", times.process),
+                      ("render", times.render),
+                      ("send", times.send)];
+        for &(stage, time) in &stages {
+            writeln!(out,
+                     "esp_vision_stage_seconds{{stage=\"{}\"}} {}",
+                     stage,
+                     time.as_secs_f64())
+                .unwrap();
+        }
+        out
+    }
+
+    /// Answers one HTTP request: the metrics for `GET /metrics`, 404
+    /// otherwise.
+    async fn respond(mut stream: TcpStream,
+                     metrics: &Metrics)
+                     -> io::Result<()> {
+        let mut buf = [0; 1024];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let response = if request.starts_with("GET /metrics ") {
+            let body = render(metrics);
+            format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; \
+                     version=0.0.4\r\nContent-Length: {}\r\nConnection: \
+                     close\r\n\r\n{}",
+                    body.len(),
+                    body)
+        } else {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: \
+             close\r\n\r\n"
+                .to_string()
+        };
+        stream.write_all(response.as_bytes()).await
+    }
+}
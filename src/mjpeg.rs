@@ -0,0 +1,170 @@
+//! Reading of MJPEG streams over HTTP, such as those of ESP32-CAM boards.
+//!
+//! The stream is a `multipart/x-mixed-replace` response whose parts are JPEG
+//! images. Rather than parsing the part headers, which differ between
+//! servers, the images are cut out of the body at their start and end
+//! markers.
+use crate::metrics;
+use rust_vision::{Mat, imdecode};
+use std::io::prelude::*;
+use std::net::TcpStream;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Time between attempts to reconnect to a dropped stream.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+/// A stream that sends nothing for this long is reconnected.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Bytes buffered without finding a complete image before giving up on them.
+const MAX_FRAME_BYTES: usize = 4 << 20;
+
+const JPEG_START: [u8; 2] = [0xff, 0xd8];
+const JPEG_END: [u8; 2] = [0xff, 0xd9];
+
+/// `MjpegStream` receives the images of an MJPEG stream on a background
+/// thread, and decodes the latest one on `read`.
+pub struct MjpegStream {
+    frames: Receiver<Vec<u8>>,
+}
+
+impl MjpegStream {
+    /// Connects to `url`, of the form `http://host[:port]/path`.
+    pub fn open(url: &str) -> Result<MjpegStream, String> {
+        let (host, path) = parse_url(url)?;
+        let stream = connect(&host, &path)
+            .map_err(|e| format!("cannot open {}: {}", url, e))?;
+        let (sender, frames) = mpsc::channel();
+        let url = url.to_string();
+        thread::spawn(move || {
+            let mut stream = Some(stream);
+            loop {
+                if let Some(stream) = stream.take() {
+                    match receive(stream, &sender) {
+                        Ok(()) => return,
+                        Err(e) => {
+                            warn!("Lost the stream {}: {}; reconnecting",
+                                  url,
+                                  e)
+                        }
+                    }
+                }
+                thread::sleep(RECONNECT_DELAY);
+                match connect(&host, &path) {
+                    Ok(s) => {
+                        info!("Reconnected to {}", url);
+                        metrics::RECONNECTS.fetch_add(1, Ordering::SeqCst);
+                        stream = Some(s);
+                    }
+                    Err(e) => warn!("Cannot reconnect to {}: {}", url, e),
+                }
+            }
+        });
+        Ok(MjpegStream { frames })
+    }
+
+    /// Decodes the newest image into `frame`, waiting for one if none came
+    /// since the last call. Older images are dropped, so that a slow
+    /// pipeline doesn't fall behind the camera.
+    pub fn read(&self, frame: &Mat) -> bool {
+        loop {
+            let mut data = match self.frames.recv() {
+                Ok(data) => data,
+                Err(_) => return false,
+            };
+            while let Ok(newer) = self.frames.try_recv() {
+                data = newer;
+            }
+            let image = imdecode(&data);
+            if image.cols > 0 {
+                image.copy_to(frame);
+                return true;
+            }
+        }
+    }
+}
+
+/// The `host:port` and path of an `http://` URL.
+fn parse_url(url: &str) -> Result<(String, String), String> {
+    if !url.starts_with("http://") {
+        return Err(format!("MJPEG streams need an http:// URL, not `{}`",
+                           url));
+    }
+    let rest = &url["http://".len()..];
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err(format!("no host in `{}`", url));
+    }
+    let host = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    Ok((host, path.to_string()))
+}
+
+/// Requests `path` from `host` and checks that the server agreed to send it.
+fn connect(host: &str, path: &str) -> Result<TcpStream, String> {
+    let mut stream = TcpStream::connect(host).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(READ_TIMEOUT)).map_err(|e| e.to_string())?;
+    write!(stream,
+           "GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+           path,
+           host)
+        .map_err(|e| e.to_string())?;
+    let mut status = Vec::new();
+    let mut byte = [0; 1];
+    while !status.ends_with(b"\r\n") {
+        if stream.read(&mut byte).map_err(|e| e.to_string())? == 0 {
+            return Err("the server closed the connection".to_string());
+        }
+        status.push(byte[0]);
+    }
+    let status = String::from_utf8_lossy(&status);
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(stream),
+        _ => Err(format!("the server answered `{}`", status.trim())),
+    }
+}
+
+/// Sends the images of `stream` to `sender` until the stream fails, or
+/// until nobody receives them anymore, which returns `Ok`.
+fn receive(mut stream: TcpStream,
+           sender: &Sender<Vec<u8>>)
+           -> Result<(), String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0; 16 * 1024];
+    loop {
+        let n = stream.read(&mut chunk).map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("the server closed the connection".to_string());
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+        while let Some(image) = next_image(&mut buffer) {
+            if sender.send(image).is_err() {
+                return Ok(());
+            }
+        }
+        if buffer.len() > MAX_FRAME_BYTES {
+            buffer.clear();
+        }
+    }
+}
+
+/// Removes the first complete JPEG image from `buffer`, with whatever came
+/// before it.
+fn next_image(buffer: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let start = find(buffer, &JPEG_START)?;
+    let end = find(&buffer[start..], &JPEG_END)? + start + JPEG_END.len();
+    let image = buffer[start..end].to_vec();
+    buffer.drain(..end);
+    Some(image)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
@@ -0,0 +1,46 @@
+//! A stand-in for ESP's `TcpInputStream`, for tests.
+//!
+//! `MockEsp` listens on a free local port, accepts one connection and keeps
+//! every byte it receives, so that tests can check exactly what ESP would
+//! have parsed.
+use std::io::Read;
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+
+pub struct MockEsp {
+    addr: String,
+    received: JoinHandle<Vec<u8>>,
+}
+
+impl MockEsp {
+    pub fn start() -> MockEsp {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let received = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut bytes = Vec::new();
+            stream.read_to_end(&mut bytes).unwrap();
+            bytes
+        });
+        MockEsp { addr, received }
+    }
+
+    /// The address to pass to `--server`.
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// Waits for the connection to close, and returns what was received.
+    pub fn finish(self) -> Vec<u8> {
+        self.received.join().unwrap()
+    }
+
+    /// What was received, as lines of text.
+    pub fn finish_lines(self) -> Vec<String> {
+        String::from_utf8(self.finish())
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+}
@@ -0,0 +1,227 @@
+//! Mouse input of the windows.
+//!
+//! OpenCV reports mouse events through a C callback with a pointer to
+//! user data. `Mouse` keeps that pointer to itself: the callback only
+//! queues typed `MouseEvent`s, which the main loop takes with
+//! `Mouse::events` and feeds to a `SelectionStatus`. Selecting a region is
+//! then plain code that can be tested without a window.
+use crate::keys::Command;
+use crate::window;
+use rust_vision::*;
+use std::ptr;
+use std::sync::mpsc::{self, Receiver, Sender, TryIter};
+
+/// A press and release of the button closer than this is a click.
+const CLICK_PIXELS: i32 = 3;
+/// How far the arrow keys move a selection, and `+` and `-` grow or
+/// shrink each of its sides.
+const STEP_PIXELS: i32 = 2;
+
+/// What the mouse did, and where in the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseEvent {
+    /// The left button was pressed.
+    Down(Point2i),
+    Move(Point2i),
+    /// The left button was released.
+    Up(Point2i),
+}
+
+impl MouseEvent {
+    /// The event of an OpenCV event code, if it is one we handle.
+    pub fn from_code(code: i32, x: i32, y: i32) -> Option<MouseEvent> {
+        let at = Point2i::new(x, y);
+        if code == MouseEventTypes::LButtonDown as i32 {
+            Some(MouseEvent::Down(at))
+        } else if code == MouseEventTypes::MouseMove as i32 {
+            Some(MouseEvent::Move(at))
+        } else if code == MouseEventTypes::LButtonUp as i32 {
+            Some(MouseEvent::Up(at))
+        } else {
+            None
+        }
+    }
+}
+
+/// `Mouse` receives the mouse events of a window.
+pub struct Mouse {
+    window: String,
+    events: Receiver<MouseEvent>,
+    /// The end of the channel the callback points at; boxed so that it
+    /// stays put.
+    _sender: Box<Sender<MouseEvent>>,
+}
+
+impl Mouse {
+    /// Starts receiving the events of `window`, which must exist.
+    pub fn attach(window: &str) -> Mouse {
+        let (sender, events) = mpsc::channel();
+        let sender = Box::new(sender);
+        let data = &*sender as *const Sender<MouseEvent>;
+        window::set_mouse_callback(window,
+                                   on_mouse,
+                                   data as MouseCallbackData);
+        Mouse {
+            window: window.to_string(),
+            events,
+            _sender: sender,
+        }
+    }
+
+    /// The events received since the last call.
+    pub fn events(&self) -> TryIter<MouseEvent> {
+        self.events.try_iter()
+    }
+}
+
+impl Drop for Mouse {
+    /// Stops the callback from pointing at the sender about to be freed.
+    fn drop(&mut self) {
+        window::set_mouse_callback(&self.window,
+                                   ignore,
+                                   ptr::null_mut::<Sender<MouseEvent>>() as
+                                   MouseCallbackData);
+    }
+}
+
+/// Queues the event for the `Mouse` that `data` points at.
+fn on_mouse(code: i32, x: i32, y: i32, _: i32, data: MouseCallbackData) {
+    // `data` is the sender of a `Mouse`, which unregisters it before it is
+    // dropped. OpenCV calls back from `wait_key`, on the thread that owns
+    // the `Mouse`, so the sender is never used from two threads.
+    let sender = unsafe { &*(data as *const Sender<MouseEvent>) };
+    if let Some(event) = MouseEvent::from_code(code, x, y) {
+        sender.send(event).ok();
+    }
+}
+
+fn ignore(_: i32, _: i32, _: i32, _: i32, _: MouseCallbackData) {}
+
+/// `SelectionStatus` tracks the region that users have selected for tracking.
+#[derive(Default)]
+pub struct SelectionStatus {
+    pub selection: Rect,
+    pub status: bool,
+    /// The region being dragged out, while the button is down.
+    pub dragging: Option<Rect>,
+    /// Where the button was last clicked without dragging.
+    pub click: Option<Point2i>,
+}
+
+impl SelectionStatus {
+    /// Follows the mouse: dragging with the button down selects a region,
+    /// and pressing and releasing it in place is a click.
+    pub fn handle(&mut self, event: MouseEvent) {
+        match event {
+            MouseEvent::Down(at) => {
+                self.selection.x = at.x;
+                self.selection.y = at.y;
+                self.dragging = Some(Rect::new(at.x, at.y, 0, 0));
+            }
+            MouseEvent::Move(at) => {
+                if self.dragging.is_some() {
+                    let start = &self.selection;
+                    self.dragging = Some(Rect::new(start.x.min(at.x),
+                                                   start.y.min(at.y),
+                                                   (at.x - start.x).abs(),
+                                                   (at.y - start.y).abs()));
+                }
+            }
+            MouseEvent::Up(at) => {
+                self.selection.width = at.x - self.selection.x;
+                self.selection.height = at.y - self.selection.y;
+                self.dragging = None;
+                // A click selects nothing.
+                if self.selection.width.abs() < CLICK_PIXELS &&
+                   self.selection.height.abs() < CLICK_PIXELS {
+                    self.click = Some(at);
+                } else if self.selection.width > 0 &&
+                          self.selection.height > 0 {
+                    self.status = true;
+                }
+            }
+        }
+    }
+}
+
+/// `selection` moved or resized by `command`, for fine adjustments after
+/// an imprecise drag. Other commands leave it as it is, and it never
+/// shrinks to nothing.
+pub fn adjust(selection: Rect, command: Command) -> Rect {
+    let mut r = selection;
+    match command {
+        Command::Nudge(dx, dy) => {
+            r.x += dx * STEP_PIXELS;
+            r.y += dy * STEP_PIXELS;
+        }
+        Command::Grow(d) => {
+            let d = d * STEP_PIXELS;
+            if r.width + 2 * d > 0 && r.height + 2 * d > 0 {
+                r.x -= d;
+                r.y -= d;
+                r.width += 2 * d;
+                r.height += 2 * d;
+            }
+        }
+        _ => {}
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keys::Command;
+    use rust_vision::{Point2i, Rect};
+    use super::{MouseEvent, SelectionStatus, adjust};
+
+    fn drag(status: &mut SelectionStatus, from: (i32, i32), to: (i32, i32)) {
+        status.handle(MouseEvent::Down(Point2i::new(from.0, from.1)));
+        status.handle(MouseEvent::Move(Point2i::new(to.0, to.1)));
+        status.handle(MouseEvent::Up(Point2i::new(to.0, to.1)));
+    }
+
+    #[test]
+    fn selects_the_dragged_region() {
+        let mut status = SelectionStatus::default();
+        drag(&mut status, (10, 20), (50, 80));
+        assert!(status.status);
+        assert_eq!(status.selection, Rect::new(10, 20, 40, 60));
+        assert_eq!(status.dragging, None);
+        assert_eq!(status.click, None);
+    }
+
+    #[test]
+    fn shows_the_region_while_dragging() {
+        let mut status = SelectionStatus::default();
+        status.handle(MouseEvent::Down(Point2i::new(50, 80)));
+        status.handle(MouseEvent::Move(Point2i::new(10, 20)));
+        assert_eq!(status.dragging, Some(Rect::new(10, 20, 40, 60)));
+        assert!(!status.status);
+    }
+
+    #[test]
+    fn reports_clicks_in_place() {
+        let mut status = SelectionStatus::default();
+        drag(&mut status, (30, 30), (31, 32));
+        assert_eq!(status.click, Some(Point2i::new(31, 32)));
+        assert!(!status.status);
+    }
+
+    #[test]
+    fn nudges_and_resizes_the_selection() {
+        let r = Rect::new(10, 20, 40, 60);
+        assert_eq!(adjust(r, Command::Nudge(-1, 0)), Rect::new(8, 20, 40, 60));
+        assert_eq!(adjust(r, Command::Nudge(0, 1)), Rect::new(10, 22, 40, 60));
+        assert_eq!(adjust(r, Command::Grow(1)), Rect::new(8, 18, 44, 64));
+        assert_eq!(adjust(r, Command::Grow(-1)), Rect::new(12, 22, 36, 56));
+        let tiny = Rect::new(10, 10, 4, 8);
+        assert_eq!(adjust(tiny, Command::Grow(-1)), tiny);
+    }
+
+    #[test]
+    fn ignores_moves_without_the_button() {
+        let mut status = SelectionStatus::default();
+        status.handle(MouseEvent::Move(Point2i::new(10, 10)));
+        assert_eq!(status.dragging, None);
+    }
+}
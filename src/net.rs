@@ -0,0 +1,59 @@
+//! The network runtime.
+//!
+//! Every connection, to ESP as well as on the control and prediction
+//! channels, is served by a single tokio runtime on a background thread.
+//! The vision loop only makes synchronous calls that don't wait on the
+//! network: samples are queued for the runtime to write, and what arrives
+//! is read from shared state.
+use std::future::Future;
+use std::io;
+use tokio::runtime::{Builder, Handle, Runtime};
+use tokio::task::JoinHandle;
+
+/// Whether this build has the `net` feature: the servers, the discovery of
+/// ESP and the network outputs, besides the connection to ESP.
+pub const BUILT: bool = cfg!(feature = "net");
+/// Threads of the runtime; the network needs little CPU.
+const WORKER_THREADS: usize = 1;
+
+/// `Net` owns the runtime. Connections are closed when it is dropped.
+pub struct Net {
+    runtime: Runtime,
+}
+
+impl Net {
+    pub fn new() -> io::Result<Net> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(WORKER_THREADS)
+            .thread_name("net")
+            .enable_all()
+            .build()?;
+        Ok(Net { runtime })
+    }
+
+    /// Runs `task` on the runtime, in the background.
+    pub fn spawn<F>(&self, task: F) -> JoinHandle<F::Output>
+        where F: Future + Send + 'static,
+              F::Output: Send + 'static
+    {
+        self.runtime.spawn(task)
+    }
+
+    /// Waits for `future` on the runtime, e.g. to connect before going on.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// A handle to the runtime, for what outlives the call that spawned it.
+    pub fn handle(&self) -> Handle {
+        self.runtime.handle().clone()
+    }
+}
+
+/// The error of what needs the `net` feature, in builds without it.
+#[cfg(not(feature = "net"))]
+pub fn unavailable(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other,
+                   format!("this build has no {}; build with `--features net`",
+                           what))
+}
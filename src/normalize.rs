@@ -0,0 +1,460 @@
+//! Resolution-independent coordinates.
+//!
+//! Raw pixel coordinates tie an ESP model to the camera resolution it was
+//! trained with. A `Normalizer` rescales every spatial dimension of a sample
+//! before it is sent anywhere.
+//!
+//! With a calibrated plane, positions are mapped onto the plane first, and
+//! normalized against its size rather than that of the frame; see the
+//! [`homography`](../homography/index.html) module.
+//!
+//! A `Convention` then moves the origin and orients the axes the way the
+//! consumer expects. The frame is mirrored as soon as it is captured, so that
+//! the preview behaves like a mirror, and positions are those of the preview
+//! unless `--unmirror` sends them as the camera sees them. `--y-up` points
+//! the y axis up, and `--origin center` or `--origin corner` moves the
+//! origin to the center or to the top left corner of the frame, the bottom
+//! left one with `--y-up`, whatever the normalization. The flips also turn
+//! the angles of the tracked ellipse, the direction of the optical flow and
+//! the offsets of the gaze, so that they stay those of the positions. Values
+//! outside the frame, e.g. predicted or mapped onto a plane, are never
+//! clamped.
+use crate::homography::Homography;
+use std::str::FromStr;
+
+/// How a dimension scales with the frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    /// A horizontal position.
+    X,
+    /// A vertical position.
+    Y,
+    /// A horizontal displacement (or its derivative); scaled but not offset.
+    DeltaX,
+    /// A vertical displacement (or its derivative); scaled but not offset.
+    DeltaY,
+    /// A length that is not bound to an axis.
+    Length,
+    /// An area.
+    Area,
+    /// An angle in `[0, 180)` degrees, as of a rotated rectangle; not
+    /// scaled, but mirrored with the axes.
+    Angle,
+    /// A direction in `[0, 360)` degrees, from the positive x axis towards
+    /// the negative y one; not scaled, but mirrored with the axes.
+    Direction,
+    /// A horizontal offset in a unit of its own, e.g. the size of an eye;
+    /// not scaled, but mirrored with the axis.
+    OffsetX,
+    /// A vertical offset in a unit of its own; not scaled, but mirrored with
+    /// the axis.
+    OffsetY,
+    /// Not a spatial quantity (ratios, counts); never touched.
+    None,
+}
+
+/// The coordinate space samples are expressed in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+    /// Raw pixels, as produced by the tracker.
+    Pixel,
+    /// `[0, 1]` on both axes.
+    Unit,
+    /// `[-1, 1]` on both axes, origin at the center of the frame.
+    Signed,
+    /// Origin at the center, `[-1, 1]` vertically and the same scale
+    /// horizontally, so circles stay circles on non-square frames.
+    Aspect,
+    /// `[0, 1]` along the longer side of the frame and the same scale along
+    /// the other, centered in the square as if letterboxed.
+    Letterbox,
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Normalization, String> {
+        match s {
+            "pixel" => Ok(Normalization::Pixel),
+            "unit" => Ok(Normalization::Unit),
+            "signed" => Ok(Normalization::Signed),
+            "aspect" => Ok(Normalization::Aspect),
+            "letterbox" => Ok(Normalization::Letterbox),
+            _ => Err(format!("unknown normalization `{}`", s)),
+        }
+    }
+}
+
+/// Per-frame scale factors: `x' = x * sx + ox`, `y' = y * sy + oy`, and
+/// lengths scale by `sl`.
+struct Transform {
+    sx: f64,
+    ox: f64,
+    sy: f64,
+    oy: f64,
+    sl: f64,
+}
+
+impl Normalization {
+    /// What values of `unit` are measured in: `px`, `unit`, `signed`,
+    /// `aspect` or `letterbox`, with `^2` for areas, and nothing when not
+    /// normalized.
+    pub fn label(&self, unit: Unit) -> String {
+        let space = match *self {
+            Normalization::Pixel => "px",
+            Normalization::Unit => "unit",
+            Normalization::Signed => "signed",
+            Normalization::Aspect => "aspect",
+            Normalization::Letterbox => "letterbox",
+        };
+        match unit {
+            Unit::Angle | Unit::Direction | Unit::OffsetX |
+            Unit::OffsetY | Unit::None => String::new(),
+            Unit::Area => format!("{}^2", space),
+            _ => space.to_string(),
+        }
+    }
+
+    fn transform(&self, width: f64, height: f64) -> Transform {
+        match *self {
+            Normalization::Pixel => {
+                Transform {
+                    sx: 1.0,
+                    ox: 0.0,
+                    sy: 1.0,
+                    oy: 0.0,
+                    sl: 1.0,
+                }
+            }
+            Normalization::Unit => {
+                Transform {
+                    sx: 1.0 / width,
+                    ox: 0.0,
+                    sy: 1.0 / height,
+                    oy: 0.0,
+                    sl: 1.0 / (width * height).sqrt(),
+                }
+            }
+            Normalization::Signed => {
+                Transform {
+                    sx: 2.0 / width,
+                    ox: -1.0,
+                    sy: 2.0 / height,
+                    oy: -1.0,
+                    sl: 2.0 / (width * height).sqrt(),
+                }
+            }
+            Normalization::Aspect => {
+                Transform {
+                    sx: 2.0 / height,
+                    ox: -width / height,
+                    sy: 2.0 / height,
+                    oy: -1.0,
+                    sl: 2.0 / height,
+                }
+            }
+            Normalization::Letterbox => {
+                let side = width.max(height);
+                Transform {
+                    sx: 1.0 / side,
+                    ox: (side - width) / (2.0 * side),
+                    sy: 1.0 / side,
+                    oy: (side - height) / (2.0 * side),
+                    sl: 1.0 / side,
+                }
+            }
+        }
+    }
+}
+
+/// Where the origin of the positions is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Origin {
+    /// The top left corner of the frame, or the bottom left one with the y
+    /// axis up.
+    Corner,
+    /// The center of the frame.
+    Center,
+}
+
+impl FromStr for Origin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Origin, String> {
+        match s {
+            "corner" => Ok(Origin::Corner),
+            "center" => Ok(Origin::Center),
+            _ => Err(format!("unknown origin `{}`; expected corner or center",
+                             s)),
+        }
+    }
+}
+
+/// The origin and the orientation of the axes, applied after the
+/// normalization.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Convention {
+    /// The origin, if not that of the normalization.
+    pub origin: Option<Origin>,
+    /// Whether the y axis points up rather than down.
+    pub y_up: bool,
+    /// Whether x is sent as the camera sees it rather than as the mirrored
+    /// preview shows it.
+    pub unmirror: bool,
+}
+
+impl Convention {
+    /// Applies the convention to `sample`, whose positions were normalized
+    /// by `p` in a frame of `size`.
+    fn apply(&self,
+             sample: &mut [f64],
+             units: &[Unit],
+             p: &Transform,
+             size: [f64; 2]) {
+        let (left, right) = (p.ox, size[0] * p.sx + p.ox);
+        let (top, bottom) = (p.oy, size[1] * p.sy + p.oy);
+        let origin = match self.origin {
+            Some(Origin::Corner) => [left, top],
+            Some(Origin::Center) => {
+                [(left + right) / 2.0, (top + bottom) / 2.0]
+            }
+            None => [0.0, 0.0],
+        };
+        for (v, unit) in sample.iter_mut().zip(units.iter()) {
+            *v = match *unit {
+                Unit::X if self.unmirror => left + right - *v - origin[0],
+                Unit::X => *v - origin[0],
+                Unit::Y if self.y_up => top + bottom - *v - origin[1],
+                Unit::Y => *v - origin[1],
+                Unit::DeltaX | Unit::OffsetX if self.unmirror => -*v,
+                Unit::DeltaY | Unit::OffsetY if self.y_up => -*v,
+                Unit::Angle if self.unmirror != self.y_up => {
+                    (180.0 - *v).rem_euclid(180.0)
+                }
+                Unit::Direction if self.unmirror || self.y_up => {
+                    let d = if self.unmirror { 180.0 - *v } else { *v };
+                    let d = if self.y_up { -d } else { d };
+                    d.rem_euclid(360.0)
+                }
+                _ => *v,
+            };
+        }
+    }
+}
+
+/// `Normalizer` applies a `Normalization` to samples whose dimensions are
+/// described by `units`.
+pub struct Normalizer {
+    mode: Normalization,
+    units: Vec<Unit>,
+    /// The mapping onto a plane, and the width and height of the plane.
+    plane: Option<(Homography, [f64; 2])>,
+    convention: Convention,
+}
+
+impl Normalizer {
+    pub fn new(mode: Normalization, units: Vec<Unit>) -> Normalizer {
+        Normalizer {
+            mode,
+            units,
+            plane: None,
+            convention: Convention::default(),
+        }
+    }
+
+    /// Applies `convention` to the samples once normalized.
+    pub fn set_convention(&mut self, convention: Convention) {
+        self.convention = convention;
+    }
+
+    /// Maps the positions of the samples onto a plane of `size` from now
+    /// on.
+    pub fn set_plane(&mut self, homography: Homography, size: [f64; 2]) {
+        self.plane = Some((homography, size));
+    }
+
+    /// Rescales `sample` in place for a frame of `width` x `height` pixels.
+    pub fn apply(&self, sample: &mut [f64], width: i32, height: i32) {
+        let plane = self.plane.as_ref().map(|&(ref homography, size)| {
+            self.map_positions(sample, homography);
+            (self.mode.transform(size[0], size[1]), size)
+        });
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let frame = [width as f64, height as f64];
+        let t = self.mode.transform(frame[0], frame[1]);
+        let (p, size) = match plane {
+            Some((ref p, size)) => (p, size),
+            None => (&t, frame),
+        };
+        if self.mode != Normalization::Pixel {
+            for (v, unit) in sample.iter_mut().zip(self.units.iter()) {
+                *v = match *unit {
+                    Unit::X => *v * p.sx + p.ox,
+                    Unit::Y => *v * p.sy + p.oy,
+                    Unit::DeltaX => *v * t.sx,
+                    Unit::DeltaY => *v * t.sy,
+                    Unit::Length => *v * t.sl,
+                    Unit::Area => *v * t.sl * t.sl,
+                    Unit::Angle | Unit::Direction | Unit::OffsetX |
+                    Unit::OffsetY | Unit::None => *v,
+                };
+            }
+        }
+        if self.convention != Convention::default() {
+            self.convention.apply(sample, &self.units, p, size);
+        }
+    }
+
+    /// Maps every position, an `X` followed by a `Y`, through `homography`.
+    fn map_positions(&self, sample: &mut [f64], homography: &Homography) {
+        for i in 1..sample.len().min(self.units.len()) {
+            if self.units[i - 1] == Unit::X && self.units[i] == Unit::Y {
+                let (x, y) = homography.map(sample[i - 1], sample[i]);
+                sample[i - 1] = x;
+                sample[i] = y;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flow;
+    use super::{Convention, Normalization, Normalizer, Origin, Unit};
+
+    const UNITS: [Unit; 4] = [Unit::X, Unit::Y, Unit::DeltaX, Unit::Angle];
+
+    /// `sample` normalized by `mode` and `convention` in a 640x480 frame.
+    fn apply(mode: Normalization,
+             convention: Convention,
+             sample: [f64; 4])
+             -> Vec<f64> {
+        let mut normalizer = Normalizer::new(mode, UNITS.to_vec());
+        normalizer.set_convention(convention);
+        let mut sample = sample.to_vec();
+        normalizer.apply(&mut sample, 640, 480);
+        sample
+    }
+
+    fn assert_near(actual: &[f64], expected: &[f64]) {
+        let near = actual.iter()
+            .zip(expected)
+            .all(|(a, e)| (a - e).abs() < 1e-9);
+        assert!(near && actual.len() == expected.len(), "{:?}", actual);
+    }
+
+    #[test]
+    fn letterboxes_landscape_and_portrait_frames() {
+        let normalizer = Normalizer::new(Normalization::Letterbox,
+                                         vec![Unit::X, Unit::Y]);
+        let mut corners = [0.0, 0.0, 640.0, 480.0];
+        normalizer.apply(&mut corners[..2], 640, 480);
+        normalizer.apply(&mut corners[2..], 640, 480);
+        assert_near(&corners, &[0.0, 0.125, 1.0, 0.875]);
+        let mut corners = [0.0, 0.0, 480.0, 640.0];
+        normalizer.apply(&mut corners[..2], 480, 640);
+        normalizer.apply(&mut corners[2..], 480, 640);
+        assert_near(&corners, &[0.125, 0.0, 0.875, 1.0]);
+    }
+
+    #[test]
+    fn unmirrors_and_points_y_up_in_pixels() {
+        let pixel = Normalization::Pixel;
+        let sample = [100.0, 100.0, 5.0, 30.0];
+        let unmirror = Convention {
+            unmirror: true,
+            ..Convention::default()
+        };
+        assert_near(&apply(pixel, unmirror, sample),
+                    &[540.0, 100.0, -5.0, 150.0]);
+        let y_up = Convention {
+            y_up: true,
+            ..Convention::default()
+        };
+        assert_near(&apply(pixel, y_up, sample),
+                    &[100.0, 380.0, 5.0, 150.0]);
+        let both = Convention {
+            unmirror: true,
+            y_up: true,
+            origin: Some(Origin::Center),
+        };
+        assert_near(&apply(pixel, both, sample),
+                    &[220.0, 140.0, -5.0, 30.0]);
+        let center = Convention {
+            origin: Some(Origin::Center),
+            ..Convention::default()
+        };
+        assert_near(&apply(pixel, center, sample),
+                    &[-220.0, -140.0, 5.0, 30.0]);
+    }
+
+    #[test]
+    fn unmirrors_and_points_y_up_in_normalized_spaces() {
+        let unit = Normalization::Unit;
+        let sample = [160.0, 120.0, 64.0, 0.0];
+        assert_near(&apply(unit, Convention::default(), sample),
+                    &[0.25, 0.25, 0.1, 0.0]);
+        let flipped = Convention {
+            unmirror: true,
+            y_up: true,
+            origin: Some(Origin::Corner),
+        };
+        assert_near(&apply(unit, flipped, sample),
+                    &[0.75, 0.75, -0.1, 0.0]);
+        let center = Convention {
+            origin: Some(Origin::Center),
+            ..Convention::default()
+        };
+        assert_near(&apply(unit, center, sample),
+                    &[-0.25, -0.25, 0.1, 0.0]);
+        // The corner of the signed space is at -1.
+        let corner = Convention {
+            origin: Some(Origin::Corner),
+            y_up: true,
+            ..Convention::default()
+        };
+        assert_near(&apply(Normalization::Signed, corner, sample),
+                    &[0.5, 1.5, 0.2, 0.0]);
+    }
+
+    #[test]
+    fn turns_the_flow_direction_with_the_flow() {
+        let units = flow::UNITS.to_vec();
+        for &(unmirror, y_up) in &[(false, false),
+                                   (true, false),
+                                   (false, true),
+                                   (true, true)] {
+            let mut normalizer = Normalizer::new(Normalization::Pixel,
+                                                 units.clone());
+            normalizer.set_convention(Convention {
+                unmirror,
+                y_up,
+                ..Convention::default()
+            });
+            // Right and up on screen.
+            let mut sample = [3.0, -3.0, 4.0, 45.0];
+            normalizer.apply(&mut sample, 640, 480);
+            let direction = (-sample[1]).atan2(sample[0]).to_degrees();
+            assert!((direction.rem_euclid(360.0) - sample[3]).abs() < 1e-9,
+                    "{:?}",
+                    sample);
+        }
+    }
+
+    #[test]
+    fn mirrors_the_gaze_without_scaling_it() {
+        let mut normalizer = Normalizer::new(Normalization::Unit,
+                                             vec![Unit::OffsetX,
+                                                  Unit::OffsetY]);
+        normalizer.set_convention(Convention {
+            unmirror: true,
+            y_up: true,
+            ..Convention::default()
+        });
+        let mut sample = [0.5, -0.25];
+        normalizer.apply(&mut sample, 640, 480);
+        assert_near(&sample, &[-0.5, 0.25]);
+    }
+}
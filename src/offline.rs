@@ -0,0 +1,194 @@
+//! `esp-vision process --input SOURCE --out FILE [options]`: tracks a
+//! recording as fast as it goes, without a window, and writes the features
+//! of every frame to a CSV file, to build datasets from existing clips.
+//!
+//! The options are those of the application, e.g.
+//!
+//! ```text
+//! esp-vision process --input clip.mp4 --out features.csv --roi 200,150,80,80
+//! ```
+//!
+//! A bare path is read as `video:PATH`. The file has the columns of
+//! `--log-features`: `time`, `seq`, the dimensions, then the `confidence`
+//! and `state` of the target, with one line per frame from the second on;
+//! the first starts the tracker. Times are those of the frames in the
+//! recording, whatever the speed of the machine, so that the derivatives
+//! and the smoothing filters behave as they would live. The dimensions are
+//! `NaN` while the target is lost.
+use crate::config::{Config, Mode};
+use crate::feature_log::FeatureLog;
+use crate::input::{Input, InputSpec};
+use crate::loss::TrackState;
+use crate::normalize::Normalizer;
+use crate::preprocess::Converter;
+use crate::profile::{self, Profile};
+use crate::sample::Sample;
+use crate::sink::Sink;
+use crate::target::Target;
+use rust_vision::{Mat, Rect};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Name of the subcommand.
+pub const COMMAND: &str = "process";
+
+/// Runs the subcommand; `args` is the whole command line.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let (rest, out) = split_args(args);
+    let mut config = Config::from_args(&rest)?;
+    let out = out.ok_or_else(|| {
+        format!("{} needs --out FILE for the features", COMMAND)
+    })?;
+    if config.mode != Mode::Track {
+        return Err(format!("{} tracks in the track mode, not --mode {}",
+                           COMMAND,
+                           config.mode));
+    }
+    if config.inputs.len() != 1 || config.targets != 1 {
+        return Err(format!("{} reads a single --input with a single target",
+                           COMMAND));
+    }
+    // Every frame, once, as fast as it goes.
+    config.playback.paced = false;
+    config.playback.looping = false;
+    let mut input = Input::open(&config.inputs[0],
+                                &config.playback,
+                                &config.capture)?;
+    let interval = match input.interval() {
+        Some(interval) => interval.mul_f64(config.playback.speed),
+        None => {
+            return Err(format!("{} needs recorded --input, e.g. a video",
+                               COMMAND))
+        }
+    };
+    let (mut profile, params) =
+        profile::initial(&config).map_err(|e| e.to_string())?;
+    // A profile starts where it was saved, as it does live.
+    let window = profile.as_ref().map(Profile::window);
+    let selection = match window.or(config.start_region) {
+        Some(region) => Some(region),
+        None if !config.tracker.needs_selection() => None,
+        None => {
+            return Err(format!("--tracker {} needs a selection; give --roi \
+                                or --region",
+                               config.tracker))
+        }
+    };
+    let names = config.tracked_names();
+    let status = Rc::new(RefCell::new(vec![None]));
+    let mut log = FeatureLog::create(&out, &names, 1, status.clone())
+        .map_err(|e| format!("cannot create {}: {}", out, e))?;
+    let mut normalizer = Normalizer::new(config.normalization,
+                                         config.tracked_units());
+    normalizer.set_convention(config.convention);
+    let mut converter = Converter::new(&config)?;
+
+    let mut target: Option<Target> = None;
+    let start = Instant::now();
+    let mut frames = 0;
+    let mut lost = 0;
+    let mut seq = 0;
+    loop {
+        let mut frame = Mat::new();
+        if !input.read(&frame) {
+            break;
+        }
+        let captured = start + interval * frames;
+        frames += 1;
+
+        converter.convert(&mut frame);
+
+        let target = match target {
+            Some(ref mut target) => target,
+            None => {
+                let region = selection.unwrap_or_else(|| {
+                    Rect::new(0, 0, frame.cols, frame.rows)
+                });
+                let mut started = Target::new(&config, &frame, region, &params);
+                if let Some(profile) = profile.take() {
+                    started.restore(&profile.model)?;
+                }
+                target = Some(started);
+                continue;
+            }
+        };
+        let o = target.update(&config, &frame, &params, captured, true);
+        let mut values = o.values;
+        if o.state == TrackState::Lost {
+            lost += 1;
+            values.iter_mut().for_each(|v| *v = f64::NAN);
+        }
+        normalizer.apply(&mut values, frame.cols, frame.rows);
+        status.borrow_mut()[0] = Some((o.track.confidence, o.state));
+        let time = (captured - start).as_secs_f64();
+        log.send(&Sample::new(seq, time, values))
+            .map_err(|e| format!("cannot write {}: {}", out, e))?;
+        seq += 1;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    if frames == 0 {
+        return Err("the input has no frames".to_string());
+    }
+    println!("{} frames in {:.2} s, {:.1} fps; {} samples written to {}, \
+              {} of them lost",
+             frames,
+             elapsed,
+             f64::from(frames) / elapsed,
+             seq,
+             out,
+             lost);
+    Ok(())
+}
+
+/// The command line without the subcommand and `--out`, for
+/// `Config::from_args`, and the value of `--out`. Bare paths given to
+/// `--input` are videos.
+fn split_args(args: &[String]) -> (Vec<String>, Option<String>) {
+    let mut rest = vec![format!("{} {}", args[0], COMMAND)];
+    let mut out = None;
+    let mut iter = args[2..].iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--out" {
+            out = iter.next().cloned();
+        } else if let Some(path) = arg.strip_prefix("--out=") {
+            out = Some(path.to_string());
+        } else if arg == "--input" {
+            rest.push(arg.clone());
+            if let Some(spec) = iter.next() {
+                rest.push(video(spec));
+            }
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+    (rest, out)
+}
+
+/// `spec`, or `video:spec` if it is a file rather than an input.
+fn video(spec: &str) -> String {
+    if spec.parse::<InputSpec>().is_err() && Path::new(spec).is_file() {
+        format!("video:{}", spec)
+    } else {
+        spec.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split_args;
+
+    #[test]
+    fn takes_out_the_output_file() {
+        let args: Vec<String> = ["esp-vision", "process", "--out", "a.csv",
+                                 "--roi", "1,2,3,4", "--out=b.csv"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (rest, out) = split_args(&args);
+        assert_eq!(rest, ["esp-vision process", "--roi", "1,2,3,4"]);
+        assert_eq!(out, Some("b.csv".to_string()));
+    }
+}
@@ -0,0 +1,376 @@
+//! More destinations for the samples, besides ESP.
+//!
+//! Every sample sent to ESP can go to other outputs as well, e.g. to an OSC
+//! visualizer and a CSV log at the same time. Each output has its own format
+//! and rate, and fails on its own, without affecting ESP or the others: one
+//! that cannot be opened is logged and opened again every few seconds, `tcp`
+//! outputs reconnect whenever their connection is lost, and the others are
+//! retried with every sample. Only changes are logged.
+//! Outputs are given with `--sink KIND:TARGET[,format=F][,rate=HZ]`,
+//! repeated, or as `[[sinks]]` tables in the config file:
+//!
+//! ```toml
+//! [[sinks]]
+//! type = "osc"
+//! address = "127.0.0.1:9000"
+//! pattern = "/hand"
+//! rate = 30
+//!
+//! [[sinks]]
+//! type = "csv"
+//! path = "session.csv"
+//! ```
+//!
+//! - `tcp` and `udp` send to `address` in `format`: `ascii` (the default),
+//!   `json`, `binary` or `framed`, see the
+//!   [`serialize`](../serialize/index.html) module.
+//! - `osc` sends an OSC message per sample to `address`, with the values as
+//!   arguments, at the address `pattern` (`/esp-vision` by default).
+//! - `csv` writes the samples to the file at `path`, as `--log-features`.
+//!
+//! `rate` limits an output to that many samples per second, as `--send-rate`
+//! does for ESP. Builds without the `net` feature only have `csv` outputs.
+use crate::feature_log::{FeatureLog, Status};
+use crate::net::{self, Net};
+use crate::rate::{self, Throttle};
+use crate::sample::Sample;
+use crate::serialize::{Format, Osc, Serializer};
+use crate::sink::{Sink, TcpSink, UdpSink};
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// The OSC address of the samples unless `pattern` says otherwise.
+const DEFAULT_PATTERN: &str = "/esp-vision";
+/// Time between attempts to open an output that cannot be opened.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// The kinds of outputs.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Tcp,
+    Udp,
+    Osc,
+    Csv,
+}
+
+/// The configuration of an output, as written in the config file.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SinkSpec {
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    /// `host:port` of `tcp`, `udp` and `osc` outputs.
+    pub address: Option<String>,
+    /// The file of `csv` outputs.
+    pub path: Option<String>,
+    /// The wire format of `tcp` and `udp` outputs.
+    pub format: Option<String>,
+    /// The OSC address of `osc` outputs.
+    pub pattern: Option<String>,
+    /// Samples per second, at most.
+    pub rate: Option<f64>,
+}
+
+impl SinkSpec {
+    /// Checks that the fields fit the kind of output.
+    pub fn validate(&self) -> Result<(), String> {
+        let network = self.kind != Kind::Csv;
+        if network && !net::BUILT {
+            return Err(format!("{:?} sinks need a build with `--features \
+                                net`",
+                               self.kind)
+                .to_lowercase());
+        }
+        if network && self.address.is_none() {
+            return Err(format!("{:?} sinks need an address", self.kind)
+                .to_lowercase());
+        }
+        if !network && self.path.is_none() {
+            return Err("csv sinks need a path".to_string());
+        }
+        match self.format {
+            Some(ref format) if self.kind == Kind::Tcp ||
+                                self.kind == Kind::Udp => {
+                format.parse::<Format>()?;
+            }
+            Some(_) => {
+                return Err("only tcp and udp sinks have a format".to_string())
+            }
+            None => {}
+        }
+        if self.pattern.is_some() && self.kind != Kind::Osc {
+            return Err("only osc sinks have a pattern".to_string());
+        }
+        if let Some(ref pattern) = self.pattern {
+            if !pattern.starts_with('/') {
+                return Err(format!("OSC pattern `{}` doesn't start with /",
+                                   pattern));
+            }
+        }
+        if let Some(rate) = self.rate {
+            rate::check("sink rate", rate)?;
+        }
+        Ok(())
+    }
+
+    /// Where the output goes, for messages.
+    fn target(&self) -> &str {
+        self.address.as_ref().or_else(|| self.path.as_ref()).unwrap()
+    }
+
+    /// Opens the output for `stream`. `tcp` outputs always open, and
+    /// connect in the background.
+    fn open(&self, net: &Net, stream: &Stream) -> io::Result<Box<dyn Sink>> {
+        match self.kind {
+            Kind::Tcp => {
+                let sink = TcpSink::reconnecting(net,
+                                                 self.target(),
+                                                 self.serializer(stream));
+                Ok(self.limited(Box::new(sink)))
+            }
+            _ => self.open_local(stream),
+        }
+    }
+
+    /// Opens an output other than `tcp`, which needs no network runtime.
+    fn open_local(&self, stream: &Stream) -> io::Result<Box<dyn Sink>> {
+        let target = self.target();
+        let sink: Box<dyn Sink> = match self.kind {
+            Kind::Tcp => unreachable!("tcp outputs open on the runtime"),
+            Kind::Udp => {
+                Box::new(UdpSink::connect(target, self.serializer(stream))?)
+            }
+            Kind::Osc => {
+                let address = self.pattern
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_PATTERN.to_string());
+                Box::new(UdpSink::connect(target,
+                                          Box::new(Osc { address }))?)
+            }
+            Kind::Csv => {
+                Box::new(FeatureLog::create(target,
+                                            &stream.names,
+                                            stream.targets,
+                                            stream.status.clone())?)
+            }
+        };
+        Ok(self.limited(sink))
+    }
+
+    /// The serializer of `tcp` and `udp` outputs.
+    fn serializer(&self, stream: &Stream) -> Box<dyn Serializer> {
+        let format = self.format
+            .as_ref()
+            .map_or(Format::Ascii, |f| f.parse().unwrap());
+        format.serializer(stream.names.clone(),
+                          stream.units.clone(),
+                          stream.stamps)
+    }
+
+    /// `sink`, at `rate` samples per second if given.
+    fn limited(&self, sink: Box<dyn Sink>) -> Box<dyn Sink> {
+        match self.rate {
+            Some(hz) => Box::new(Throttle::new(sink, hz)),
+            None => sink,
+        }
+    }
+}
+
+/// What the outputs are opened for: samples whose dimensions are called
+/// `names` and measured in `units`, stamped if `stamps` in ASCII, and the
+/// `status` of `targets` targets for `csv` outputs.
+struct Stream {
+    names: Vec<String>,
+    units: Vec<String>,
+    stamps: bool,
+    targets: usize,
+    status: Status,
+}
+
+/// Parses `KIND:TARGET[,format=F][,rate=HZ][,pattern=P]`, as given to
+/// `--sink`.
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SinkSpec, String> {
+        let mut parts = s.split(',');
+        let first = parts.next().unwrap_or("");
+        let i = first.find(':')
+            .ok_or_else(|| format!("sink `{}` is not KIND:TARGET", s))?;
+        let (kind, target) = (&first[..i], &first[i + 1..]);
+        let kind = match kind {
+            "tcp" => Kind::Tcp,
+            "udp" => Kind::Udp,
+            "osc" => Kind::Osc,
+            "csv" => Kind::Csv,
+            _ => {
+                return Err(format!("unknown sink `{}`; expected tcp, udp, \
+                                    osc or csv",
+                                   kind))
+            }
+        };
+        let mut spec = SinkSpec {
+            kind,
+            address: None,
+            path: None,
+            format: None,
+            pattern: None,
+            rate: None,
+        };
+        if kind == Kind::Csv {
+            spec.path = Some(target.to_string());
+        } else {
+            spec.address = Some(target.to_string());
+        }
+        for option in parts {
+            let (key, value) = match option.find('=') {
+                Some(i) => (&option[..i], option[i + 1..].to_string()),
+                None => return Err(format!("invalid sink option `{}`", option)),
+            };
+            match key {
+                "format" => spec.format = Some(value),
+                "pattern" => spec.pattern = Some(value),
+                "rate" => {
+                    spec.rate = Some(value.parse()
+                        .map_err(|_| format!("invalid sink rate `{}`", value))?)
+                }
+                _ => return Err(format!("unknown sink option `{}`", key)),
+            }
+        }
+        spec.validate()?;
+        Ok(spec)
+    }
+}
+
+/// An output, whether its last sample failed, and when to open it again if
+/// it couldn't be opened.
+struct Output {
+    name: String,
+    spec: SinkSpec,
+    sink: Option<Box<dyn Sink>>,
+    failing: bool,
+    retry: Instant,
+}
+
+impl Output {
+    /// Sends `sample` to the output, opening it first if it is due.
+    fn send(&mut self, sample: &Sample, stream: &Stream) -> io::Result<()> {
+        if self.sink.is_none() && Instant::now() >= self.retry {
+            self.retry = Instant::now() + RETRY_DELAY;
+            self.sink = Some(self.spec.open_local(stream)?);
+        }
+        match self.sink {
+            Some(ref mut sink) => sink.send(sample),
+            None => {
+                Err(io::Error::new(io::ErrorKind::NotConnected,
+                                   "the output is not open"))
+            }
+        }
+    }
+}
+
+/// `Fanout` sends every sample to all the outputs, and never fails itself.
+pub struct Fanout {
+    outputs: Vec<Output>,
+    stream: Stream,
+}
+
+impl Fanout {
+    /// Opens the outputs of `specs`, for samples whose dimensions are
+    /// called `names` and measured in `units`; `csv` outputs log the
+    /// `status` of `targets` targets. Outputs that cannot be opened are
+    /// logged, and opened again later.
+    pub fn open(specs: &[SinkSpec],
+                net: &Net,
+                names: &[String],
+                units: &[String],
+                stamps: bool,
+                targets: usize,
+                status: &Status)
+                -> Fanout {
+        let stream = Stream {
+            names: names.to_vec(),
+            units: units.to_vec(),
+            stamps,
+            targets,
+            status: status.clone(),
+        };
+        let now = Instant::now();
+        let mut outputs = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let name = format!("{:?} sink {}", spec.kind, spec.target())
+                .to_lowercase();
+            let (sink, failing) = match spec.open(net, &stream) {
+                Ok(sink) => {
+                    info!("Sending to the {}", name);
+                    (Some(sink), false)
+                }
+                Err(e) => {
+                    warn!("Cannot open the {}: {}; retrying every {} s",
+                          name,
+                          e,
+                          RETRY_DELAY.as_secs());
+                    (None, true)
+                }
+            };
+            outputs.push(Output {
+                name,
+                spec: spec.clone(),
+                sink,
+                failing,
+                retry: now + RETRY_DELAY,
+            });
+        }
+        Fanout { outputs, stream }
+    }
+}
+
+impl Sink for Fanout {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        for output in &mut self.outputs {
+            let result = output.send(sample, &self.stream);
+            // Only changes are logged, not every failed sample.
+            if output.failing != result.is_err() {
+                output.failing = result.is_err();
+                match result {
+                    Ok(()) => info!("Sending to the {} again", output.name),
+                    Err(e) => {
+                        warn!("Cannot send to the {}: {}", output.name, e)
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Kind, SinkSpec};
+
+    #[test]
+    fn parses_sinks_from_the_command_line() {
+        let spec: SinkSpec = "osc:127.0.0.1:9000,rate=30,pattern=/hand"
+            .parse()
+            .unwrap();
+        assert_eq!(spec.kind, Kind::Osc);
+        assert_eq!(spec.address, Some("127.0.0.1:9000".to_string()));
+        assert_eq!(spec.pattern, Some("/hand".to_string()));
+        assert_eq!(spec.rate, Some(30.0));
+        let spec: SinkSpec = "csv:session.csv".parse().unwrap();
+        assert_eq!(spec.path, Some("session.csv".to_string()));
+        assert_eq!(spec.address, None);
+    }
+
+    #[test]
+    fn rejects_options_that_dont_fit() {
+        assert!("osc:127.0.0.1:9000,format=json".parse::<SinkSpec>().is_err());
+        assert!("tcp:127.0.0.1:9000,pattern=/x".parse::<SinkSpec>().is_err());
+        assert!("udp:127.0.0.1:9000,rate=0".parse::<SinkSpec>().is_err());
+        assert!("udp:127.0.0.1:9000,format=xml".parse::<SinkSpec>().is_err());
+        assert!("serial:/dev/ttyUSB0".parse::<SinkSpec>().is_err());
+        assert!("udp".parse::<SinkSpec>().is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Overlays drawn on the displayed frame.
+use crate::features::Region;
+use crate::pipeline::StageTimes;
+use crate::sink::SinkStats;
+use crate::tracker::clip;
+use rust_vision::*;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+/// Margin between the overlays and the border of the frame, in pixels.
+const MARGIN: i32 = 10;
+
+/// Line segments the outline of an ellipse is drawn with.
+const ELLIPSE_SEGMENTS: usize = 36;
+
+/// Draws the rotated ellipse of `region` on `frame`.
+pub fn ellipse(frame: &Mat, region: &Region, color: Scalar) {
+    let (a, b) = (region.axes[0] / 2.0, region.axes[1] / 2.0);
+    let (sin, cos) = region.angle.to_radians().sin_cos();
+    let point = |i: usize| {
+        let t = 2.0 * PI * i as f64 / ELLIPSE_SEGMENTS as f64;
+        let (x, y) = (a * t.cos(), b * t.sin());
+        Point2i::new((region.cx + x * cos - y * sin).round() as i32,
+                     (region.cy + x * sin + y * cos).round() as i32)
+    };
+    for i in 0..ELLIPSE_SEGMENTS {
+        frame.line(point(i), point(i + 1), color, 2);
+    }
+}
+
+/// Color of the newest segment of the trails.
+const TRAIL_COLOR: (i32, i32, i32) = (255, 0, 255);
+
+/// `Trail` keeps the last centroids of a target, to show the shape of the
+/// gesture just made.
+pub struct Trail {
+    points: VecDeque<Point2i>,
+    length: usize,
+}
+
+impl Trail {
+    /// A trail of the last `length` centroids, drawing nothing if 0.
+    pub fn new(length: usize) -> Trail {
+        Trail {
+            points: VecDeque::with_capacity(length),
+            length,
+        }
+    }
+
+    /// Adds the centroid of `region`, forgetting the oldest beyond the
+    /// length of the trail.
+    pub fn push(&mut self, region: &Region) {
+        if self.length == 0 {
+            return;
+        }
+        if self.points.len() == self.length {
+            self.points.pop_front();
+        }
+        self.points.push_back(Point2i::new(region.cx.round() as i32,
+                                           region.cy.round() as i32));
+    }
+
+    /// Forgets the centroids, once the target is lost or deselected.
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// Draws the trail on `frame`, fading to black and thinning with age.
+    pub fn draw(&self, frame: &Mat) {
+        let count = self.points.len();
+        for (i, (&a, &b)) in
+            self.points.iter().zip(self.points.iter().skip(1)).enumerate() {
+            let weight = (i + 1) as f64 / (count - 1) as f64;
+            let faded = |c: i32| (f64::from(c) * weight) as i32;
+            let color = Scalar::new(faded(TRAIL_COLOR.0),
+                                    faded(TRAIL_COLOR.1),
+                                    faded(TRAIL_COLOR.2),
+                                    0);
+            frame.line(a, b, color, 1 + (2.0 * weight).round() as i32);
+        }
+    }
+}
+
+/// The `rect` region of `frame`, magnified to fit a quarter of the frame's
+/// width and height. `None` if the region is outside the frame.
+pub fn zoom(frame: &Mat, rect: Rect) -> Option<Mat> {
+    let rect = clip(rect, frame.cols, frame.rows);
+    if rect.width == 0 || rect.height == 0 {
+        return None;
+    }
+    let scale = f64::min(f64::from(frame.cols / 4) / f64::from(rect.width),
+                         f64::from(frame.rows / 4) / f64::from(rect.height));
+    let size = Size2i::new((f64::from(rect.width) * scale) as i32,
+                           (f64::from(rect.height) * scale) as i32);
+    if size.width == 0 || size.height == 0 {
+        return None;
+    }
+    Some(frame.roi(rect).resize(size))
+}
+
+/// Draws `inset` in the top right corner of `frame`, with a border.
+pub fn picture_in_picture(frame: &Mat, inset: &Mat) {
+    let corner = Rect::new(frame.cols - inset.cols - MARGIN,
+                           MARGIN,
+                           inset.cols,
+                           inset.rows);
+    if corner.x < 0 || corner.y + corner.height > frame.rows {
+        return;
+    }
+    inset.copy_to(&frame.roi(corner));
+    frame.rectangle_custom(corner,
+                           Scalar::new(255, 255, 255, 0),
+                           1,
+                           LineTypes::Line8);
+}
+
+/// Dims `frame` outside of `rect`, the selection being dragged out, and
+/// outlines it.
+pub fn rubber_band(frame: &Mat, rect: Rect) {
+    let rect = clip(rect, frame.cols, frame.rows);
+    if rect.width == 0 || rect.height == 0 {
+        return;
+    }
+    let inside = frame.roi(rect).clone();
+    frame.add_weighted(0.5, frame, 0.0, 0.0).copy_to(frame);
+    inside.copy_to(&frame.roi(rect));
+    frame.rectangle_custom(rect,
+                           Scalar::new(255, 255, 255, 0),
+                           1,
+                           LineTypes::Line8);
+}
+
+/// Dims `frame` and writes on it that the camera is `lost` since that long.
+pub fn outage(frame: &Mat, lost: Duration) {
+    frame.add_weighted(0.4, frame, 0.0, 0.0).copy_to(frame);
+    frame.put_text(&format!("camera lost for {} s, reconnecting",
+                            lost.as_secs()),
+                   Point2i::new(MARGIN, frame.rows / 2),
+                   HersheyFonts::HersheySimplex,
+                   0.8,
+                   Scalar::new(0, 0, 255, 0));
+}
+
+/// Weight of the newest frame in the smoothed rate and times.
+const SMOOTHING: f64 = 0.1;
+
+/// `Hud` measures the pipeline and draws the measurements on the frame.
+pub struct Hud {
+    last_frame: Option<Instant>,
+    /// Smoothed capture rate, in frames per second.
+    fps: f64,
+    /// Smoothed time from capture to display, in seconds.
+    latency: f64,
+    /// Samples sent at the start of the current second, and its start.
+    sent: (u64, Instant),
+    /// Samples sent during the last complete second.
+    send_rate: u64,
+    /// Smoothed capture, processing, rendering and send times, in seconds.
+    stages: [f64; 4],
+    /// Live frames dropped so far.
+    dropped: u64,
+}
+
+impl Hud {
+    pub fn new() -> Hud {
+        Hud {
+            last_frame: None,
+            fps: 0.0,
+            latency: 0.0,
+            sent: (0, Instant::now()),
+            send_rate: 0,
+            stages: [0.0; 4],
+            dropped: 0,
+        }
+    }
+
+    /// Takes the measurements of a frame captured at `captured` and
+    /// processed until now, with the number of live frames `dropped` so far.
+    pub fn update(&mut self,
+                  captured: Instant,
+                  stats: SinkStats,
+                  times: StageTimes,
+                  dropped: u64) {
+        self.dropped = dropped;
+        if let Some(last) = self.last_frame {
+            let dt = (captured - last).as_secs_f64();
+            if dt > 0.0 {
+                self.fps += SMOOTHING * (1.0 / dt - self.fps);
+            }
+        }
+        self.last_frame = Some(captured);
+        let latency = captured.elapsed().as_secs_f64();
+        self.latency += SMOOTHING * (latency - self.latency);
+        let times = [times.capture, times.process, times.render, times.send];
+        for (stage, time) in self.stages.iter_mut().zip(&times) {
+            *stage += SMOOTHING * (time.as_secs_f64() - *stage);
+        }
+        if self.sent.1.elapsed() >= Duration::from_secs(1) {
+            self.send_rate = stats.sent - self.sent.0;
+            self.sent = (stats.sent, Instant::now());
+        }
+    }
+
+    /// Smoothed capture rate, in frames per second.
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    /// Samples sent during the last complete second.
+    pub fn send_rate(&self) -> u64 {
+        self.send_rate
+    }
+
+    /// Draws the measurements, the connection state and the `confidence` of
+    /// the first target, if any, in the top left corner of `frame`.
+    pub fn draw(&self,
+                frame: &Mat,
+                stats: SinkStats,
+                confidence: Option<f64>) {
+        let connection = if stats.connected {
+            "connected"
+        } else {
+            "disconnected"
+        };
+        let confidence = match confidence {
+            Some(c) => format!("{:.2}", c),
+            None => "-".to_string(),
+        };
+        let lines = [format!("{:.1} fps", self.fps),
+                     format!("latency {:.0} ms", self.latency * 1000.0),
+                     format!("sent {}/s", self.send_rate),
+                     format!("ESP {}", connection),
+                     format!("confidence {}", confidence),
+                     format!("capture {:.1} ms, process {:.1} ms",
+                             self.stages[0] * 1000.0,
+                             self.stages[1] * 1000.0),
+                     format!("render {:.1} ms, send {:.1} ms",
+                             self.stages[2] * 1000.0,
+                             self.stages[3] * 1000.0),
+                     format!("dropped {} frames", self.dropped)];
+        for (i, line) in lines.iter().enumerate() {
+            frame.put_text(line,
+                           Point2i::new(MARGIN, 60 + 20 * i as i32),
+                           HersheyFonts::HersheySimplex,
+                           0.5,
+                           Scalar::new(255, 255, 255, 0));
+        }
+    }
+}
+
+impl Default for Hud {
+    fn default() -> Hud {
+        Hud::new()
+    }
+}
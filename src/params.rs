@@ -0,0 +1,136 @@
+//! Vision parameters that can be tuned while the application is running.
+use rust_vision::Scalar;
+
+/// Names of all parameters, as accepted by `VisionParams::set`.
+pub const NAMES: [&str; 9] = ["h_min",
+                              "s_min",
+                              "v_min",
+                              "h_max",
+                              "s_max",
+                              "v_max",
+                              "hist_bins",
+                              "term_iterations",
+                              "learning_rate"];
+
+/// `VisionParams` holds the knobs of the tracking pipelines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VisionParams {
+    /// Lower bound of the HSV mask, as `[hue, saturation, value]`.
+    pub hsv_low: [i32; 3],
+    /// Upper bound of the HSV mask, as `[hue, saturation, value]`.
+    pub hsv_high: [i32; 3],
+    /// Number of bins of the hue histogram.
+    pub hist_bins: i32,
+    /// Maximum number of CAMShift iterations per frame.
+    pub term_iterations: i32,
+    /// How fast the background model of the `mog2` and `knn` trackers
+    /// adapts, in `[0, 1]`; negative picks the rate automatically.
+    pub learning_rate: f64,
+}
+
+impl Default for VisionParams {
+    fn default() -> VisionParams {
+        VisionParams {
+            hsv_low: [0, 30, 10],
+            hsv_high: [180, 256, 256],
+            hist_bins: 16,
+            term_iterations: 10,
+            learning_rate: -1.0,
+        }
+    }
+}
+
+impl VisionParams {
+    /// Updates the parameter called `name` (`h_min`, `s_min`, `v_min`,
+    /// `h_max`, `s_max`, `v_max`, `hist_bins`, `term_iterations` or
+    /// `learning_rate`). Values are validated so a typo from the other end of
+    /// the control channel can't crash the pipeline.
+    pub fn set(&mut self, name: &str, value: f64) -> Result<(), String> {
+        if name == "learning_rate" {
+            if value > 1.0 || value.is_nan() {
+                return Err(format!("`{}` must be at most 1, got {}",
+                                   name,
+                                   value));
+            }
+            self.learning_rate = value;
+            return Ok(());
+        }
+        if !value.is_finite() {
+            return Err(format!("`{}` must be a number, got {}", name, value));
+        }
+        let v = value.round() as i32;
+        match name {
+            "h_min" => self.hsv_low[0] = check(name, v, 0, 180)?,
+            "s_min" => self.hsv_low[1] = check(name, v, 0, 256)?,
+            "v_min" => self.hsv_low[2] = check(name, v, 0, 256)?,
+            "h_max" => self.hsv_high[0] = check(name, v, 0, 180)?,
+            "s_max" => self.hsv_high[1] = check(name, v, 0, 256)?,
+            "v_max" => self.hsv_high[2] = check(name, v, 0, 256)?,
+            "hist_bins" => self.hist_bins = check(name, v, 1, 180)?,
+            "term_iterations" => {
+                self.term_iterations = check(name, v, 1, 1000)?
+            }
+            _ => return Err(format!("unknown parameter `{}`", name)),
+        }
+        Ok(())
+    }
+
+    /// The integer parameter called `name`, as accepted by `set`.
+    pub fn get(&self, name: &str) -> Option<i32> {
+        match name {
+            "h_min" => Some(self.hsv_low[0]),
+            "s_min" => Some(self.hsv_low[1]),
+            "v_min" => Some(self.hsv_low[2]),
+            "h_max" => Some(self.hsv_high[0]),
+            "s_max" => Some(self.hsv_high[1]),
+            "v_max" => Some(self.hsv_high[2]),
+            "hist_bins" => Some(self.hist_bins),
+            "term_iterations" => Some(self.term_iterations),
+            _ => None,
+        }
+    }
+
+    /// The parameter called `name`, integer or not.
+    pub fn value(&self, name: &str) -> Option<f64> {
+        if name == "learning_rate" {
+            Some(self.learning_rate)
+        } else {
+            self.get(name).map(f64::from)
+        }
+    }
+
+    /// Lower bound of the HSV mask, ready for `in_range`.
+    pub fn lower(&self) -> Scalar {
+        Scalar::new(self.hsv_low[0], self.hsv_low[1], self.hsv_low[2], 0)
+    }
+
+    /// Upper bound of the HSV mask, ready for `in_range`.
+    pub fn upper(&self) -> Scalar {
+        Scalar::new(self.hsv_high[0], self.hsv_high[1], self.hsv_high[2], 0)
+    }
+}
+
+fn check(name: &str, v: i32, min: i32, max: i32) -> Result<i32, String> {
+    if v < min || v > max {
+        Err(format!("`{}` must be within [{}, {}], got {}", name, min, max, v))
+    } else {
+        Ok(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VisionParams;
+
+    #[test]
+    fn rejects_values_that_are_not_numbers() {
+        let mut params = VisionParams::default();
+        assert!(params.set("h_min", f64::NAN).is_err());
+        assert!(params.set("s_max", f64::INFINITY).is_err());
+        assert!(params.set("term_iterations", f64::NEG_INFINITY).is_err());
+        assert!(params.set("learning_rate", f64::NAN).is_err());
+        assert_eq!(params, VisionParams::default());
+        params.set("h_min", 20.4).unwrap();
+        assert_eq!(params.get("h_min"), Some(20));
+    }
+}
@@ -0,0 +1,383 @@
+//! The stages of the main loop that run on threads of their own.
+//!
+//! Every input is captured on its own thread, and samples are written to
+//! ESP by the network runtime (see `net`), so that neither a slow display
+//! nor a slow connection holds up the camera. Tracking and rendering stay
+//! on the main thread, which HighGUI needs.
+//!
+//! Frames of recorded input are handed over through a bounded channel and
+//! wait for tracking, so that a replay sees every frame. Live input must not
+//! fall behind instead: tracking always takes the newest frame, frames it
+//! couldn't keep up with are dropped, and so are frames that are older than
+//! the latency budget by the time tracking gets to them. Both are counted,
+//! for the HUD.
+//!
+//! Cameras that fail are reopened by the capture thread; see the
+//! [`watchdog`](../watchdog/index.html) module.
+use crate::input::{CaptureSettings, Input, InputSpec, Playback};
+use crate::metrics;
+use crate::watchdog::{Action, Watchdog};
+use rust_vision::Mat;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Frames of recorded input waiting to be tracked.
+const FRAME_QUEUE: usize = 2;
+/// How often `Capture::read` returns during an outage, so that the window
+/// stays responsive.
+const OUTAGE_POLL: Duration = Duration::from_millis(100);
+
+/// The default latency budget, from capture to the start of tracking.
+pub const DEFAULT_LATENCY_BUDGET: Duration = Duration::from_millis(100);
+
+/// How long each stage took on its last frame or sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StageTimes {
+    /// Reading a frame, including waiting for it.
+    pub capture: Duration,
+    /// Everything done with a frame before it is displayed.
+    pub process: Duration,
+    /// Displaying the frame and polling the keyboard.
+    pub render: Duration,
+    /// Writing a sample to ESP.
+    pub send: Duration,
+}
+
+/// The `StageTimes` shared by the threads.
+pub type Timings = Arc<Mutex<StageTimes>>;
+
+/// What `Capture::read` got.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Read {
+    /// A frame, captured at this time.
+    Frame(Instant),
+    /// No frame: the camera fails since this time, and is being reopened.
+    Lost(Instant),
+    /// The input ended.
+    Ended,
+}
+
+/// A captured frame and when it was captured.
+struct Frame(Mat, Instant);
+
+// Each frame is allocated by the capture thread and handed over whole, so
+// it is never used by two threads at once.
+unsafe impl Send for Frame {}
+
+/// `Newest` holds the newest frame of a live input until it is tracked.
+struct Newest {
+    slot: Mutex<Slot>,
+    ready: Condvar,
+    /// Wakes the capture thread while it waits to reopen the camera.
+    stopping: Condvar,
+}
+
+#[derive(Default)]
+struct Slot {
+    frame: Option<Frame>,
+    /// The input ended.
+    closed: bool,
+    /// When the camera failed, while it is reopened.
+    lost: Option<Instant>,
+    /// Nobody takes the frames anymore.
+    stopped: bool,
+}
+
+impl Newest {
+    fn new() -> Newest {
+        Newest {
+            slot: Mutex::new(Slot::default()),
+            ready: Condvar::new(),
+            stopping: Condvar::new(),
+        }
+    }
+
+    /// Replaces the frame waiting, if any. Returns whether one was.
+    fn put(&self, frame: Frame) -> bool {
+        let replaced = self.slot.lock().unwrap().frame.replace(frame);
+        self.ready.notify_one();
+        replaced.is_some()
+    }
+
+    fn close(&self) {
+        self.slot.lock().unwrap().closed = true;
+        self.ready.notify_one();
+    }
+
+    /// Tells the capture thread to stop, even while it waits.
+    fn stop(&self) {
+        self.slot.lock().unwrap().stopped = true;
+        self.stopping.notify_all();
+    }
+
+    /// Waits `delay`, or less if told to stop. Returns whether it was.
+    fn wait(&self, delay: Duration) -> bool {
+        let slot = self.slot.lock().unwrap();
+        let (slot, _) = self.stopping
+            .wait_timeout_while(slot, delay, |slot| !slot.stopped)
+            .unwrap();
+        slot.stopped
+    }
+
+    /// Notes that the camera is out since `since`, or back with `None`.
+    fn lose(&self, since: Option<Instant>) {
+        self.slot.lock().unwrap().lost = since;
+        self.ready.notify_one();
+    }
+
+    /// Waits for a frame, or returns why there is none: the input ended,
+    /// or is out since a time, for `OUTAGE_POLL`.
+    fn take(&self) -> Result<Frame, Read> {
+        let mut slot = self.slot.lock().unwrap();
+        loop {
+            if let Some(frame) = slot.frame.take() {
+                return Ok(frame);
+            }
+            if slot.closed {
+                return Err(Read::Ended);
+            }
+            slot = match slot.lost {
+                Some(since) => {
+                    let (slot, waited) =
+                        self.ready.wait_timeout(slot, OUTAGE_POLL).unwrap();
+                    if waited.timed_out() && slot.frame.is_none() {
+                        return Err(Read::Lost(since));
+                    }
+                    slot
+                }
+                None => self.ready.wait(slot).unwrap(),
+            };
+        }
+    }
+}
+
+/// How frames get from the capture thread to tracking.
+enum Frames {
+    /// Every frame, in order, for recorded input.
+    Queue(Receiver<Frame>),
+    /// Only the newest, for live input.
+    Newest(Arc<Newest>),
+}
+
+/// `Capture` reads an `Input` on a background thread.
+pub struct Capture {
+    frames: Frames,
+    relock: Arc<AtomicBool>,
+    /// Live frames older than this when tracking gets to them are dropped.
+    budget: Option<Duration>,
+    dropped: Arc<AtomicU64>,
+    thread: JoinHandle<()>,
+}
+
+impl Capture {
+    /// Opens the input of `spec` on a new thread, and starts reading it.
+    /// Frames of live input older than `budget` are dropped.
+    pub fn spawn(spec: &InputSpec,
+                 playback: &Playback,
+                 settings: &CaptureSettings,
+                 budget: Option<Duration>,
+                 timings: Timings)
+                 -> Result<Capture, String> {
+        let (spec, playback, settings) =
+            (spec.clone(), playback.clone(), settings.clone());
+        let (sender, queue) = mpsc::sync_channel(FRAME_QUEUE);
+        let newest = Arc::new(Newest::new());
+        let slot = newest.clone();
+        let dropped = Arc::new(AtomicU64::new(0));
+        let overwritten = dropped.clone();
+        let (opened, result) = mpsc::channel();
+        let relock = Arc::new(AtomicBool::new(false));
+        let relock_requested = relock.clone();
+        let thread = thread::spawn(move || {
+            // Cameras are opened on the thread that reads them, as capture
+            // backends are not all happy to be used from another one.
+            let mut input = match Input::open(&spec, &playback, &settings) {
+                Ok(input) => input,
+                Err(e) => {
+                    opened.send(Err(e)).ok();
+                    return;
+                }
+            };
+            let live = input.is_live();
+            opened.send(Ok(live)).ok();
+            let reopens = matches!(spec,
+                                   InputSpec::Camera(_) |
+                                   InputSpec::V4l2(_) |
+                                   InputSpec::Realsense(_));
+            let mut watchdog = Watchdog::new(settings.timeout);
+            loop {
+                if relock_requested.swap(false, Ordering::SeqCst) {
+                    input.relock();
+                }
+                let start = Instant::now();
+                let frame = Mat::new();
+                // Failing cameras may deliver empty frames rather than
+                // none.
+                let read = input.read(&frame) && (!reopens || frame.cols > 0);
+                if !read && !reopens {
+                    slot.close();
+                    return;
+                }
+                if !read {
+                    let delay = match watchdog.failed(Instant::now()) {
+                        Action::Retry => continue,
+                        Action::Reopen(delay) => delay,
+                        Action::GiveUp => {
+                            error!("Giving up on the input {}", spec);
+                            slot.close();
+                            return;
+                        }
+                    };
+                    slot.lose(watchdog.outage());
+                    // The camera is released before it is opened again.
+                    drop(input);
+                    input = match reopen(&spec,
+                                         &playback,
+                                         &settings,
+                                         &mut watchdog,
+                                         delay,
+                                         &slot) {
+                        Some(input) => input,
+                        None => {
+                            slot.close();
+                            return;
+                        }
+                    };
+                    continue;
+                }
+                if let Some(outage) = watchdog.read(Instant::now()) {
+                    info!("The input {} is back after {:.1} s",
+                          spec,
+                          outage.as_secs_f64());
+                    metrics::RECONNECTS.fetch_add(1, Ordering::SeqCst);
+                    slot.lose(None);
+                }
+                let captured = Instant::now();
+                timings.lock().unwrap().capture = captured - start;
+                let frame = Frame(frame, captured);
+                if live {
+                    // Nobody else holds the slot once `Capture` is dropped.
+                    if Arc::strong_count(&slot) == 1 {
+                        return;
+                    }
+                    if slot.put(frame) {
+                        overwritten.fetch_add(1, Ordering::SeqCst);
+                    }
+                } else if sender.send(frame).is_err() {
+                    return;
+                }
+            }
+        });
+        let live = result.recv()
+            .map_err(|_| "the capture thread failed".to_string())??;
+        let frames = if live {
+            Frames::Newest(newest)
+        } else {
+            Frames::Queue(queue)
+        };
+        Ok(Capture {
+            frames,
+            relock,
+            budget,
+            dropped,
+            thread,
+        })
+    }
+
+    /// Waits for the next frame and moves it into `frame`. Returns when it
+    /// was captured; during an outage of the camera, it returns now and
+    /// then without a frame.
+    pub fn read(&self, frame: &mut Mat) -> Read {
+        let Frame(next, captured) = match self.frames {
+            Frames::Queue(ref queue) => {
+                match queue.recv() {
+                    Ok(next) => next,
+                    Err(_) => return Read::Ended,
+                }
+            }
+            Frames::Newest(ref newest) => {
+                loop {
+                    let next = match newest.take() {
+                        Ok(next) => next,
+                        Err(read) => return read,
+                    };
+                    match self.budget {
+                        Some(budget) if next.1.elapsed() > budget => {
+                            self.dropped.fetch_add(1, Ordering::SeqCst);
+                        }
+                        _ => break next,
+                    }
+                }
+            }
+        };
+        *frame = next;
+        Read::Frame(captured)
+    }
+
+    /// Whether the input is live, so that it should not end.
+    pub fn is_live(&self) -> bool {
+        match self.frames {
+            Frames::Newest(_) => true,
+            Frames::Queue(_) => false,
+        }
+    }
+
+    /// Number of live frames dropped so far, because tracking couldn't keep
+    /// up or they were older than the latency budget.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::SeqCst)
+    }
+
+    /// Relocks the camera controls, as `Input::relock`.
+    pub fn relock(&self) {
+        self.relock.store(true, Ordering::SeqCst);
+    }
+
+    /// Stops reading and waits for the input to be released, so that the
+    /// camera is free when the application exits.
+    pub fn close(self) {
+        let Capture { frames, thread, .. } = self;
+        // The thread stops at its next frame, when nobody takes it, or
+        // right away if it waits to reopen the camera.
+        if let Frames::Newest(ref newest) = frames {
+            newest.stop();
+        }
+        drop(frames);
+        thread.join().ok();
+    }
+}
+
+/// Waits `delay` and opens the input of `spec` again, as long as the
+/// `watchdog` lets it. `None` if it gave up, or if nobody takes the frames
+/// of the `slot` anymore; closing the capture ends the wait.
+fn reopen(spec: &InputSpec,
+          playback: &Playback,
+          settings: &CaptureSettings,
+          watchdog: &mut Watchdog,
+          mut delay: Duration,
+          slot: &Arc<Newest>)
+          -> Option<Input> {
+    loop {
+        warn!("The input {} failed; reopening it in {:.1} s",
+              spec,
+              delay.as_secs_f64());
+        if slot.wait(delay) || Arc::strong_count(slot) == 1 {
+            return None;
+        }
+        match Input::open(spec, playback, settings) {
+            Ok(input) => return Some(input),
+            Err(e) => debug!("{}", e),
+        }
+        delay = match watchdog.failed(Instant::now()) {
+            Action::Reopen(delay) => delay,
+            Action::Retry => delay,
+            Action::GiveUp => {
+                error!("Giving up on the input {}", spec);
+                return None;
+            }
+        };
+    }
+}
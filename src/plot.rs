@@ -0,0 +1,167 @@
+//! A live plot of the samples, shown with `g`.
+//!
+//! Jitter, dropouts and the effect of the smoothing filters are easier to
+//! see than to read. Pressing `g` opens a window with the last
+//! `--plot-seconds` (10) of every dimension sent, one trace per dimension
+//! scrolling from right to left, each scaled to its own range over that
+//! time. The traces are the samples as sent, after filtering and
+//! normalization, so `k` shows the smoothing at work; NaN values, e.g. of
+//! lost targets, leave gaps. With `--segment`, only the gestures are sent,
+//! with their markers, and so only they are plotted.
+use crate::sample::Sample;
+use crate::sink::Sink;
+use rust_vision::*;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io;
+use std::rc::Rc;
+
+/// Name of the window.
+pub const WINDOW: &str = "Plot";
+/// How many seconds are shown unless `--plot-seconds` says otherwise.
+pub const DEFAULT_SECONDS: f64 = 10.0;
+/// Size of the window: its width and the height of every trace.
+const WIDTH: i32 = 640;
+const LANE: i32 = 60;
+/// Room for the name and range of every trace, in pixels.
+const LABEL: i32 = 14;
+/// Gray level of the lines between the traces.
+const DIVIDER: i32 = 60;
+
+/// `Plot` keeps the samples of the last seconds, and draws them. Clones
+/// share the samples, so that one can be given to the sinks.
+#[derive(Clone)]
+pub struct Plot {
+    names: Vec<String>,
+    seconds: f64,
+    samples: Rc<RefCell<VecDeque<Sample>>>,
+}
+
+impl Plot {
+    pub fn new(names: Vec<String>, seconds: f64) -> Plot {
+        Plot {
+            names,
+            seconds,
+            samples: Rc::new(RefCell::new(VecDeque::new())),
+        }
+    }
+
+    /// Draws the traces.
+    pub fn render(&self) -> Mat {
+        let lanes = self.names.len().max(1) as i32;
+        let height = lanes * LANE;
+        let gray = vec![0; (WIDTH * height) as usize];
+        let plot = Mat::from_slice_u8(height, WIDTH, &gray)
+            .cvt_color(ColorConversionCodes::GRAY2BGR);
+        for (i, name) in self.names.iter().enumerate() {
+            let top = i as i32 * LANE;
+            if i > 0 {
+                plot.line(Point2i::new(0, top),
+                          Point2i::new(WIDTH, top),
+                          Scalar::new(DIVIDER, DIVIDER, DIVIDER, 0),
+                          1);
+            }
+            let (points, range) = self.trace(i, top);
+            let label = match range {
+                Some((low, high)) => {
+                    format!("{} [{:.3}, {:.3}]", name, low, high)
+                }
+                None => name.clone(),
+            };
+            plot.put_text(&label,
+                          Point2i::new(4, top + LABEL - 3),
+                          HersheyFonts::HersheySimplex,
+                          0.4,
+                          Scalar::new(200, 200, 200, 0));
+            for pair in points.windows(2) {
+                if let (Some(a), Some(b)) = (pair[0], pair[1]) {
+                    plot.line(a, b, Scalar::new(0, 255, 0, 0), 1);
+                }
+            }
+        }
+        plot
+    }
+
+    /// The points of dimension `dim` in the trace whose top is at `top`,
+    /// `None` for NaN, and the range of its values.
+    fn trace(&self,
+             dim: usize,
+             top: i32)
+             -> (Vec<Option<Point2i>>, Option<(f64, f64)>) {
+        let samples = self.samples.borrow();
+        let end = match samples.back() {
+            Some(last) => last.time,
+            None => return (Vec::new(), None),
+        };
+        let values = || {
+            samples.iter()
+                .filter_map(|s| s.values.get(dim))
+                .cloned()
+                .filter(|v| v.is_finite())
+        };
+        let low = values().fold(f64::INFINITY, f64::min);
+        let high = values().fold(f64::NEG_INFINITY, f64::max);
+        if low > high {
+            return (vec![None; samples.len()], None);
+        }
+        // A flat trace goes through the middle.
+        let (low_edge, high_edge) = if high > low {
+            (low, high)
+        } else {
+            (low - 1.0, high + 1.0)
+        };
+        let bottom = f64::from(top + LANE - 2);
+        let span = f64::from(LANE - LABEL - 4);
+        let points = samples.iter()
+            .map(|s| {
+                let v = s.values.get(dim).cloned().unwrap_or(f64::NAN);
+                if !v.is_finite() {
+                    return None;
+                }
+                let x = (1.0 - (end - s.time) / self.seconds) *
+                        f64::from(WIDTH - 1);
+                let y = bottom - (v - low_edge) / (high_edge - low_edge) * span;
+                Some(Point2i::new(x.round() as i32, y.round() as i32))
+            })
+            .collect();
+        (points, Some((low, high)))
+    }
+}
+
+impl Sink for Plot {
+    /// Keeps `sample`, and forgets those older than the plot shows.
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let mut samples = self.samples.borrow_mut();
+        samples.push_back(sample.clone());
+        while samples.front()
+            .map_or(false, |s| sample.time - s.time > self.seconds) {
+            samples.pop_front();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample::Sample;
+    use crate::sink::Sink;
+    use rust_vision::Point2i;
+    use super::{LANE, Plot, WIDTH};
+
+    #[test]
+    fn scrolls_and_scales_each_trace() {
+        let mut plot = Plot::new(vec!["x".to_string()], 2.0);
+        for (i, &x) in [5.0, 10.0, f64::NAN, 15.0].iter().enumerate() {
+            plot.send(&Sample::new(i as u64, i as f64, vec![x])).unwrap();
+        }
+        // The first sample is more than two seconds old.
+        let (points, range) = plot.trace(0, 0);
+        assert_eq!(range, Some((10.0, 15.0)));
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[1], None);
+        let (first, last) = (points[0].unwrap(), points[2].unwrap());
+        assert_eq!(first.x, 0);
+        assert_eq!(last, Point2i::new(WIDTH - 1, 16));
+        assert_eq!(first.y, LANE - 2);
+    }
+}
@@ -0,0 +1,133 @@
+//! Human pose: selected joints of an OpenPose network.
+//!
+//! The network (e.g. OpenPose's COCO model, `pose_iter_440000.caffemodel`
+//! with `pose_deploy_linevec.prototxt`) produces one heatmap per joint. The
+//! peak of a joint's heatmap is its position, and the height of the peak is
+//! the confidence the joint is gated by. Left and right are the person's.
+use rust_vision::Point2f;
+
+pub use self::network::Pose;
+
+/// The joints of the COCO model, by heatmap.
+pub const JOINTS: [&str; 18] =
+    ["nose", "neck", "right_shoulder", "right_elbow", "right_wrist",
+     "left_shoulder", "left_elbow", "left_wrist", "right_hip", "right_knee",
+     "right_ankle", "left_hip", "left_knee", "left_ankle", "right_eye",
+     "left_eye", "right_ear", "left_ear"];
+
+/// Parses a comma-separated list of joint names into heatmap indices.
+pub fn parse_joints(s: &str) -> Result<Vec<usize>, String> {
+    s.split(',')
+        .map(|j| {
+            let j = j.trim();
+            JOINTS.iter()
+                .position(|&name| name == j)
+                .ok_or_else(|| format!("unknown joint `{}`", j))
+        })
+        .collect()
+}
+
+/// `x y` of every joint, NaN for joints that were not found.
+pub fn values(joints: &[Option<Point2f>]) -> Vec<f64> {
+    joints.iter()
+        .flat_map(|j| match *j {
+            Some(p) => vec![p.x as f64, p.y as f64],
+            None => vec![f64::NAN, f64::NAN],
+        })
+        .collect()
+}
+
+#[cfg(feature = "dnn")]
+mod network {
+    use crate::camera;
+    use crate::cuda;
+    use rust_vision::*;
+
+    /// Size of the network input.
+    const INPUT: i32 = 368;
+
+    /// `Pose` finds the selected joints. It is meant for a single person: with
+    /// several in view, each joint is the strongest response of any of them.
+    pub struct Pose {
+        net: Net,
+        joints: Vec<usize>,
+        min_confidence: f64,
+    }
+
+    impl Pose {
+        /// Loads the network, to be run on CUDA if `cuda` is set.
+        pub fn new(model: &str,
+                   config: &str,
+                   joints: Vec<usize>,
+                   min_confidence: f64,
+                   cuda: bool)
+                   -> Result<Pose, String> {
+            let mut net = Net::read(model, config);
+            if net.empty() {
+                return Err(format!("cannot load the pose network {}", model));
+            }
+            if cuda {
+                cuda::use_for(&mut net);
+            }
+            Ok(Pose {
+                net,
+                joints,
+                min_confidence,
+            })
+        }
+
+        /// The position of every selected joint in the displayed `frame`, or
+        /// `None` if its confidence is too low.
+        pub fn update(&mut self, frame: &Mat) -> Vec<Option<Point2f>> {
+            let raw = camera::unmirrored(frame);
+            let blob = blob_from_image(&raw,
+                                       1.0 / 255.0,
+                                       Size2i::new(INPUT, INPUT),
+                                       Scalar::new(0, 0, 0, 0),
+                                       false,
+                                       false);
+            self.net.set_input(&blob);
+            let heatmaps = self.net.forward();
+            let (rows, cols) = (heatmaps.size_of(2), heatmaps.size_of(3));
+            let sx = frame.cols as f32 / cols as f32;
+            let sy = frame.rows as f32 / rows as f32;
+            let mirror = (frame.cols - 1) as f32;
+            self.joints
+                .iter()
+                .map(|&j| {
+                    let (_, peak, _, at) = heatmaps.plane(0, j as i32)
+                        .min_max_loc();
+                    if peak < self.min_confidence {
+                        return None;
+                    }
+                    Some(Point2f::new(mirror - (at.x as f32 + 0.5) * sx,
+                                      (at.y as f32 + 0.5) * sy))
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(not(feature = "dnn"))]
+mod network {
+    use rust_vision::{Mat, Point2f};
+
+    /// Without the `dnn` feature, there is no network to run.
+    pub enum Pose {}
+
+    impl Pose {
+        pub fn new(_model: &str,
+                   _config: &str,
+                   _joints: Vec<usize>,
+                   _min_confidence: f64,
+                   _cuda: bool)
+                   -> Result<Pose, String> {
+            Err("the pose mode needs a build with `--features dnn`"
+                .to_string())
+        }
+
+        pub fn update(&mut self, _frame: &Mat) -> Vec<Option<Point2f>> {
+            match *self {}
+        }
+    }
+}
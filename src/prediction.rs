@@ -0,0 +1,78 @@
+//! Return channel for ESP predictions.
+//!
+//! ESP can forward the recognized class label to a TCP server through a
+//! `TcpOStream`. We run that server here so the label can be displayed on top
+//! of the video without switching to the ESP GUI.
+use crate::net::Net;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A prediction received from ESP.
+#[derive(Clone, Debug)]
+pub struct Prediction {
+    /// The class label (or whatever string ESP was configured to send).
+    pub label: String,
+    /// When the prediction arrived.
+    pub received: Instant,
+}
+
+impl Prediction {
+    /// Whether the prediction was received within `timeout`. Old predictions
+    /// are no longer interesting to display.
+    pub fn is_fresh(&self, timeout: Duration) -> bool {
+        self.received.elapsed() < timeout
+    }
+}
+
+/// `PredictionListener` accepts connections from ESP on the network runtime
+/// and keeps the most recent prediction around.
+pub struct PredictionListener {
+    latest: Arc<Mutex<Option<Prediction>>>,
+}
+
+impl PredictionListener {
+    /// Binds to `addr` and starts listening on `net`. Only one ESP
+    /// connection is served at a time; when it closes we wait for the next
+    /// one.
+    pub fn spawn(net: &Net, addr: &str) -> io::Result<PredictionListener> {
+        let listener = net.block_on(TcpListener::bind(addr))?;
+        let latest = Arc::new(Mutex::new(None));
+        let shared = latest.clone();
+        net.spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        info!("Receiving predictions from {}", peer);
+                        serve(stream, &shared).await;
+                        info!("Predictions from {} ended", peer);
+                    }
+                    Err(e) => warn!("Prediction channel error: {}", e),
+                }
+            }
+        });
+        Ok(PredictionListener { latest })
+    }
+
+    /// Returns the most recent prediction, if any.
+    pub fn latest(&self) -> Option<Prediction> {
+        self.latest.lock().unwrap().clone()
+    }
+}
+
+/// Reads newline-separated labels until ESP hangs up.
+async fn serve(stream: TcpStream, latest: &Mutex<Option<Prediction>>) {
+    let mut lines = BufReader::new(stream).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        let label = line.trim();
+        if label.is_empty() {
+            continue;
+        }
+        *latest.lock().unwrap() = Some(Prediction {
+            label: label.to_string(),
+            received: Instant::now(),
+        });
+    }
+}
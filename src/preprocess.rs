@@ -0,0 +1,244 @@
+//! Preprocessing of the captured frames, before anything else sees them.
+//!
+//! The steps are configured from the `[[preprocess]]` tables of the config
+//! file, and run in order:
+//!
+//! ```toml
+//! [[preprocess]]
+//! type = "crop"
+//! rect = [80, 0, 480, 480]
+//!
+//! [[preprocess]]
+//! type = "resize"
+//! scale = 0.5
+//!
+//! [[preprocess]]
+//! type = "blur"
+//! kernel = 5
+//!
+//! [[preprocess]]
+//! type = "gamma"
+//! gamma = 1.5
+//! brightness = 10
+//! ```
+//!
+//! Downscaling trades resolution for speed: every later stage handles fewer
+//! pixels. Coordinates, including selections and sent features, are those of
+//! the preprocessed frame; what needs those of the camera, such as depth,
+//! maps them back with `Converter::captured`.
+use crate::camera::Undistorter;
+use crate::config::Config;
+use crate::tracker::clip;
+use rust_vision::*;
+
+/// The configuration of a step, as written in the config file.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum StepSpec {
+    /// Scales the frame by `scale`, e.g. 0.5 for half the width and height.
+    Resize { scale: f64 },
+    /// Gaussian blur with an odd `kernel` size, against sensor noise.
+    /// `sigma` 0 derives it from the kernel size.
+    Blur {
+        kernel: i32,
+        #[serde(default)]
+        sigma: f64,
+    },
+    /// Keeps the `rect` region, as `[x, y, width, height]`.
+    Crop { rect: [i32; 4] },
+    /// Gamma correction, above 1 to brighten the shadows, then `brightness`
+    /// added to every channel.
+    Gamma {
+        gamma: f64,
+        #[serde(default)]
+        brightness: f64,
+    },
+}
+
+/// A validated step.
+enum Step {
+    Resize(f64),
+    Blur(Size2i, f64),
+    Crop(Rect),
+    /// The lookup table of the gamma and brightness adjustment.
+    Lut(Mat),
+}
+
+impl StepSpec {
+    /// Validates the parameters and builds the step.
+    fn build(&self) -> Result<Step, String> {
+        match *self {
+            StepSpec::Resize { scale } => {
+                if scale.is_nan() || scale <= 0.0 {
+                    return Err(format!("resize: scale must be positive, got \
+                                        {}",
+                                       scale));
+                }
+                Ok(Step::Resize(scale))
+            }
+            StepSpec::Blur { kernel, sigma } => {
+                if kernel <= 0 || kernel % 2 == 0 {
+                    return Err(format!("blur: kernel must be odd and \
+                                        positive, got {}",
+                                       kernel));
+                }
+                if !(sigma >= 0.0 && sigma.is_finite()) {
+                    return Err(format!("blur: sigma must be 0 or positive, \
+                                        got {}",
+                                       sigma));
+                }
+                Ok(Step::Blur(Size2i::new(kernel, kernel), sigma))
+            }
+            StepSpec::Crop { rect } => {
+                if rect[0] < 0 || rect[1] < 0 || rect[2] <= 0 || rect[3] <= 0 {
+                    return Err("crop: rect needs a non-negative corner and a \
+                                positive size"
+                        .to_string());
+                }
+                Ok(Step::Crop(Rect::new(rect[0], rect[1], rect[2], rect[3])))
+            }
+            StepSpec::Gamma { gamma, brightness } => {
+                if gamma.is_nan() || gamma <= 0.0 {
+                    return Err(format!("gamma: gamma must be positive, got \
+                                        {}",
+                                       gamma));
+                }
+                let table: Vec<u8> = (0..256)
+                    .map(|i| {
+                        let v = f64::from(i) / 255.0;
+                        let v = 255.0 * v.powf(1.0 / gamma) + brightness;
+                        v.round().clamp(0.0, 255.0) as u8
+                    })
+                    .collect();
+                Ok(Step::Lut(Mat::from_slice_u8(1, 256, &table)))
+            }
+        }
+    }
+}
+
+/// `Pipeline` runs the preprocessing steps one after the other.
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    pub fn new(specs: &[StepSpec]) -> Result<Pipeline, String> {
+        let steps = specs.iter()
+            .map(|s| s.build())
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Pipeline { steps })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// The preprocessed `frame`.
+    pub fn apply(&self, frame: &Mat) -> Mat {
+        let mut out = frame.clone();
+        for step in &self.steps {
+            out = match *step {
+                Step::Resize(scale) => {
+                    let (cols, rows) = resized((out.cols, out.rows), scale);
+                    out.resize(Size2i::new(cols, rows))
+                }
+                Step::Blur(size, sigma) => out.gaussian_blur(size, sigma),
+                Step::Crop(rect) => {
+                    let rect = clip(rect, out.cols, out.rows);
+                    if rect.width == 0 || rect.height == 0 {
+                        continue;
+                    }
+                    out.roi(rect).clone()
+                }
+                Step::Lut(ref table) => out.lut(table),
+            };
+        }
+        out
+    }
+
+    /// Where the point `p` of a preprocessed frame was in the frame of
+    /// `size`, as `(cols, rows)`, that it was preprocessed from.
+    pub fn unmap(&self, size: (i32, i32), p: [f64; 2]) -> [f64; 2] {
+        // Each axis maps as `scale * from + shift`.
+        let mut size = size;
+        let mut axes = [(1.0, 0.0), (1.0, 0.0)];
+        for step in &self.steps {
+            match *step {
+                Step::Resize(factor) => {
+                    let to = resized(size, factor);
+                    let factors = [f64::from(to.0) / f64::from(size.0),
+                                   f64::from(to.1) / f64::from(size.1)];
+                    for (axis, &s) in axes.iter_mut().zip(&factors) {
+                        // Pixel centers, as `cv::resize` maps them.
+                        *axis = (axis.0 * s, axis.1 * s + 0.5 * s - 0.5);
+                    }
+                    size = to;
+                }
+                Step::Crop(rect) => {
+                    let rect = clip(rect, size.0, size.1);
+                    if rect.width == 0 || rect.height == 0 {
+                        continue;
+                    }
+                    axes[0].1 -= f64::from(rect.x);
+                    axes[1].1 -= f64::from(rect.y);
+                    size = (rect.width, rect.height);
+                }
+                Step::Blur(..) | Step::Lut(_) => {}
+            }
+        }
+        [(p[0] - axes[0].1) / axes[0].0, (p[1] - axes[1].1) / axes[1].0]
+    }
+}
+
+/// The size of a frame of `size` resized by `scale`.
+fn resized(size: (i32, i32), scale: f64) -> (i32, i32) {
+    (((f64::from(size.0) * scale) as i32).max(1),
+     ((f64::from(size.1) * scale) as i32).max(1))
+}
+
+/// `Converter` turns captured frames into those every later stage sees:
+/// undistorted with `--undistort`, mirrored, then preprocessed. The
+/// application, `bench` and `process` all convert frames with it.
+pub struct Converter {
+    undistorter: Option<Undistorter>,
+    pipeline: Pipeline,
+}
+
+impl Converter {
+    pub fn new(config: &Config) -> Result<Converter, String> {
+        let undistorter = if config.undistort {
+            config.intrinsics.as_ref().map(Undistorter::new)
+        } else {
+            None
+        };
+        Ok(Converter {
+            undistorter,
+            pipeline: Pipeline::new(&config.preprocess)?,
+        })
+    }
+
+    /// Converts a frame of the first input in place.
+    pub fn convert(&mut self, frame: &mut Mat) {
+        if let Some(ref mut undistorter) = self.undistorter {
+            *frame = undistorter.apply(frame);
+        }
+        self.convert_other(frame);
+    }
+
+    /// Converts a frame of another input in place; only the first is
+    /// undistorted.
+    pub fn convert_other(&self, frame: &mut Mat) {
+        frame.flip(FlipCode::YAxis);
+        if !self.pipeline.is_empty() {
+            *frame = self.pipeline.apply(frame);
+        }
+    }
+
+    /// Where the point `p` of a converted frame was in the captured frame of
+    /// `size`, as `(cols, rows)`: before the preprocessing and the mirror.
+    /// Undistortion is not undone.
+    pub fn captured(&self, size: (i32, i32), p: [f64; 2]) -> [f64; 2] {
+        let [x, y] = self.pipeline.unmap(size, p);
+        [f64::from(size.0 - 1) - x, y]
+    }
+}
@@ -0,0 +1,65 @@
+//! Tracking without showing the video, with `--privacy MODE`.
+//!
+//! Some venues, and most ethics boards, don't allow the video of the people
+//! being tracked to be shown on shared screens or to leave the process. In
+//! privacy mode, the targets are tracked in the frames as usual, but what is
+//! shown is masked before the overlays are drawn over it: `--privacy blur`
+//! blurs the preview beyond recognition, enough to still find one's way in
+//! the scene, and `--privacy black` blacks it out, leaving only the
+//! regions, the labels, the zones and the HUD. With `--headless`, nothing is
+//! shown at all.
+//!
+//! Only derived coordinates are streamed either way. Everything else that
+//! shows frames follows: the recording of `--record`, the frames of
+//! `--dashboard` and the snapshots of `s` are masked too, the zoom of `z`
+//! and the back projection of `b` are unavailable, and the options that
+//! keep raw frames, `--session` and several `--input`, are refused.
+//!
+//! Privacy mode needs the track mode. With `black`, targets are best
+//! selected without seeing them: with `--roi`, `--region`, a face or a hand.
+use rust_vision::*;
+use std::str::FromStr;
+
+/// Pixels of the frame averaged into one by `blur`.
+const BLOCK: i32 = 24;
+
+/// How the preview is masked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Privacy {
+    /// Blurred beyond recognition.
+    Blur,
+    /// Black.
+    Black,
+}
+
+impl Privacy {
+    /// Masks `frame` in place.
+    pub fn apply(self, frame: &Mat) {
+        match self {
+            Privacy::Blur => {
+                let small = Size2i::new((frame.cols / BLOCK).max(1),
+                                        (frame.rows / BLOCK).max(1));
+                frame.resize(small)
+                    .resize(Size2i::new(frame.cols, frame.rows))
+                    .copy_to(frame);
+            }
+            Privacy::Black => {
+                frame.add_weighted(0.0, frame, 0.0, 0.0).copy_to(frame);
+            }
+        }
+    }
+}
+
+impl FromStr for Privacy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Privacy, String> {
+        match s {
+            "blur" => Ok(Privacy::Blur),
+            "black" => Ok(Privacy::Black),
+            _ => Err(format!("unknown privacy mode `{}`; expected blur or \
+                              black",
+                             s)),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Tracking profiles, to resume tracking a target in a later session without
+//! selecting it again.
+//!
+//! A profile is a TOML file holding the mask thresholds, the last track
+//! window and, for backends that have one, the model of the target, such as
+//! CAMShift's normalized histogram. Restoring the model rather than building
+//! it again from the first frame means tracking behaves exactly as it did
+//! when the profile was saved.
+use crate::config::Config;
+use crate::error::Error;
+use crate::params::VisionParams;
+use rust_vision::Rect;
+use std::fs::File;
+use std::io::prelude::*;
+
+/// `Profile` is the saved state of one target.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// The backend the profile was saved with, as given to `--tracker`.
+    pub tracker: String,
+    pub hsv_low: [i32; 3],
+    pub hsv_high: [i32; 3],
+    pub hist_bins: i32,
+    /// The last track window, as `[x, y, width, height]`.
+    pub window: [i32; 4],
+    /// The model of the target; empty for backends without one.
+    #[serde(default)]
+    pub model: Vec<f32>,
+}
+
+impl Profile {
+    pub fn new(tracker: &str,
+               params: &VisionParams,
+               window: Rect,
+               model: Vec<f32>)
+               -> Profile {
+        Profile {
+            tracker: tracker.to_string(),
+            hsv_low: params.hsv_low,
+            hsv_high: params.hsv_high,
+            hist_bins: params.hist_bins,
+            window: [window.x, window.y, window.width, window.height],
+            model,
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Profile, String> {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| format!("cannot read profile {}: {}", path, e))?;
+        toml::from_str(&text)
+            .map_err(|e| format!("invalid profile {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = toml::to_string(self)
+            .map_err(|e| format!("cannot serialize profile: {}", e))?;
+        File::create(path)
+            .and_then(|mut f| f.write_all(text.as_bytes()))
+            .map_err(|e| format!("cannot write profile {}: {}", path, e))
+    }
+
+    pub fn window(&self) -> Rect {
+        let w = self.window;
+        Rect::new(w[0], w[1], w[2], w[3])
+    }
+
+    /// Sets the thresholds of `params` to the saved ones.
+    pub fn apply(&self, params: &mut VisionParams) {
+        params.hsv_low = self.hsv_low;
+        params.hsv_high = self.hsv_high;
+        params.hist_bins = self.hist_bins;
+    }
+}
+
+/// The profile of `--load-profile`, if any, and the parameters to start
+/// tracking with: the defaults, then `[params]`, then the thresholds of the
+/// profile. The application, `bench` and `process` all start this way.
+pub fn initial(config: &Config)
+               -> Result<(Option<Profile>, VisionParams), Error> {
+    let profile = match config.load_profile {
+        Some(ref path) => {
+            let profile = Profile::load(path).map_err(Error::Data)?;
+            if profile.tracker != config.tracker.to_string() {
+                return Err(Error::Usage(format!("the profile {} was saved \
+                                                 with --tracker {}",
+                                                path,
+                                                profile.tracker)));
+            }
+            Some(profile)
+        }
+        None => None,
+    };
+    let mut params = VisionParams::default();
+    // The parameters were checked when the config was read.
+    for &(ref name, value) in &config.params {
+        params.set(name, value).unwrap();
+    }
+    if let Some(ref profile) = profile {
+        profile.apply(&mut params);
+    }
+    Ok((profile, params))
+}
@@ -0,0 +1,69 @@
+//! QR codes: the payload of a code names the channel it is sent on.
+//!
+//! Tagging objects with QR codes distinguishes them without any color
+//! calibration. Every configured channel is a payload; the centroid of the
+//! code carrying it is sent as `<payload>_x <payload>_y`.
+use crate::camera;
+use rust_vision::{Mat, Point2f, QRCodeDetector};
+
+/// A decoded code.
+pub struct Code {
+    pub payload: String,
+    /// Corners in the displayed (mirrored) frame.
+    pub corners: Vec<Point2f>,
+}
+
+/// `QrCodes` finds the codes of the configured channels.
+pub struct QrCodes {
+    detector: QRCodeDetector,
+    channels: Vec<String>,
+}
+
+impl QrCodes {
+    pub fn new(channels: Vec<String>) -> QrCodes {
+        QrCodes {
+            detector: QRCodeDetector::new(),
+            channels,
+        }
+    }
+
+    /// All codes in the displayed `frame`, known or not.
+    pub fn detect(&self, frame: &Mat) -> Vec<Code> {
+        let raw = camera::unmirrored(frame);
+        let mirror = (frame.cols - 1) as f32;
+        self.detector
+            .detect_and_decode_multi(&raw)
+            .into_iter()
+            .filter(|code| !code.0.is_empty())
+            .map(|(payload, corners)| {
+                Code {
+                    payload,
+                    corners: corners.iter()
+                        .map(|p| Point2f::new(mirror - p.x, p.y))
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+
+    /// `x y` of the centroid of every channel's code, or NaN if it is not
+    /// visible.
+    pub fn values(&self, codes: &[Code]) -> Vec<f64> {
+        let mut values = Vec::with_capacity(2 * self.channels.len());
+        for channel in &self.channels {
+            let code = codes.iter()
+                .find(|c| c.payload == *channel && !c.corners.is_empty());
+            match code {
+                Some(code) => {
+                    let n = code.corners.len() as f64;
+                    let x: f64 = code.corners.iter().map(|p| p.x as f64).sum();
+                    let y: f64 = code.corners.iter().map(|p| p.y as f64).sum();
+                    values.push(x / n);
+                    values.push(y / n);
+                }
+                None => values.extend_from_slice(&[f64::NAN, f64::NAN]),
+            }
+        }
+        values
+    }
+}
@@ -0,0 +1,242 @@
+//! Output rate limiting, independent of the camera frame rate.
+use crate::sample::Sample;
+use crate::sink::Sink;
+use std::cell::Cell;
+use std::io;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// The lowest rate of a clock, a tick an hour. The interval between the
+/// ticks of much lower rates doesn't fit in a `Duration`.
+pub const MIN_RATE: f64 = 1.0 / 3600.0;
+
+/// Checks `hz`, the rate of `what` per second, as given by the user.
+pub fn check(what: &str, hz: f64) -> Result<f64, String> {
+    if hz >= MIN_RATE && hz.is_finite() {
+        Ok(hz)
+    } else {
+        Err(format!("{} must be at least one per hour, got {}", what, hz))
+    }
+}
+
+/// `RateLimiter` decides whether a tick of a fixed-rate clock has passed.
+pub struct RateLimiter {
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// A limiter that lets `hz` events per second through.
+    pub fn new(hz: f64) -> RateLimiter {
+        RateLimiter {
+            interval: Duration::from_secs_f64(1.0 / hz),
+            next: None,
+        }
+    }
+
+    /// Changes the rate from the next tick on.
+    pub fn set_rate(&mut self, hz: f64) {
+        self.interval = Duration::from_secs_f64(1.0 / hz);
+    }
+
+    /// Returns `true` at most once per tick. Never blocks.
+    pub fn ready(&mut self, now: Instant) -> bool {
+        match self.next {
+            Some(next) if now < next => false,
+            Some(next) => {
+                // Stay on the fixed clock, unless we fell behind by more
+                // than a tick; catching up would send a burst.
+                let after = next + self.interval;
+                self.next = Some(if after > now {
+                    after
+                } else {
+                    now + self.interval
+                });
+                true
+            }
+            None => {
+                self.next = Some(now + self.interval);
+                true
+            }
+        }
+    }
+}
+
+/// `Throttle` forwards at most one sample per tick to the wrapped sink. The
+/// first sample produced after a tick is the most recent one at that time;
+/// the ones in between are dropped rather than queued.
+pub struct Throttle<S> {
+    inner: S,
+    /// The rate, which may change between samples; `None` forwards every
+    /// sample.
+    rate: Rc<Cell<Option<f64>>>,
+    /// The limiter and the rate it was last set to.
+    limiter: Option<(RateLimiter, f64)>,
+}
+
+impl<S: Sink> Throttle<S> {
+    pub fn new(inner: S, hz: f64) -> Throttle<S> {
+        Throttle::with_rate(inner, Rc::new(Cell::new(Some(hz))))
+    }
+
+    /// A throttle whose rate can be changed through `rate`.
+    pub fn with_rate(inner: S, rate: Rc<Cell<Option<f64>>>) -> Throttle<S> {
+        Throttle {
+            inner,
+            rate,
+            limiter: None,
+        }
+    }
+}
+
+impl<S: Sink> Sink for Throttle<S> {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let hz = match self.rate.get() {
+            Some(hz) => hz,
+            None => return self.inner.send(sample),
+        };
+        let (limiter, rate) = self.limiter
+            .get_or_insert_with(|| (RateLimiter::new(hz), hz));
+        if *rate != hz {
+            limiter.set_rate(hz);
+            *rate = hz;
+        }
+        if limiter.ready(Instant::now()) {
+            self.inner.send(sample)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Gaps between samples longer than this are not bridged: the output clock
+/// restarts at the next sample instead of making up the positions between.
+const MAX_GAP: f64 = 0.25;
+
+/// `Resampler` sends samples on a fixed clock, interpolating linearly
+/// between the samples it is sent, so that their spacing is exactly uniform
+/// despite capture jitter and dropped frames. Every output sample is due
+/// between two input samples, so output lags by up to a frame. Output
+/// samples are numbered by the ticks of the clock.
+pub struct Resampler<S> {
+    inner: S,
+    interval: f64,
+    /// The last sample sent to the resampler.
+    last: Option<Sample>,
+    /// When the clock started, and the number of ticks since.
+    start: f64,
+    ticks: u64,
+    seq: u64,
+}
+
+impl<S: Sink> Resampler<S> {
+    pub fn new(inner: S, hz: f64) -> Resampler<S> {
+        Resampler {
+            inner,
+            interval: 1.0 / hz,
+            last: None,
+            start: 0.0,
+            ticks: 0,
+            seq: 0,
+        }
+    }
+
+    /// The time of the next tick.
+    fn next(&self) -> f64 {
+        self.start + self.ticks as f64 * self.interval
+    }
+
+    fn tick(&mut self, values: Vec<f64>) -> io::Result<()> {
+        let sample = Sample::new(self.seq, self.next(), values);
+        self.seq += 1;
+        self.ticks += 1;
+        self.inner.send(&sample)
+    }
+}
+
+impl<S: Sink> Sink for Resampler<S> {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let last = match self.last.replace(sample.clone()) {
+            Some(last) if sample.time - last.time <= MAX_GAP => last,
+            _ => {
+                self.start = sample.time;
+                self.ticks = 0;
+                return self.tick(sample.values.clone());
+            }
+        };
+        let span = sample.time - last.time;
+        let mut result = Ok(());
+        while self.next() <= sample.time {
+            let t = if span > 0.0 {
+                (self.next() - last.time) / span
+            } else {
+                1.0
+            };
+            let values = last.values
+                .iter()
+                .zip(&sample.values)
+                .map(|(a, b)| a + (b - a) * t)
+                .collect();
+            result = result.and(self.tick(values));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample::Sample;
+    use crate::sink::Sink;
+    use std::time::{Duration, Instant};
+    use super::{RateLimiter, Resampler};
+
+    #[test]
+    fn lets_a_sample_through_per_tick() {
+        let mut limiter = RateLimiter::new(10.0);
+        let start = Instant::now();
+        let ms = |ms: u64| start + Duration::from_millis(ms);
+        // Frames every 30 ms, the fourth of them late.
+        let ready: Vec<bool> = [0, 30, 60, 90, 150, 180, 210, 240, 270]
+            .iter()
+            .map(|&t| limiter.ready(ms(t)))
+            .collect();
+        assert_eq!(ready,
+                   [true, false, false, false, true, false, true, false,
+                    false]);
+        // After falling behind by more than a tick, the clock restarts
+        // rather than sending a burst.
+        assert!(limiter.ready(ms(600)));
+        assert!(!limiter.ready(ms(650)));
+        assert!(limiter.ready(ms(700)));
+    }
+
+    #[test]
+    fn interpolates_onto_a_fixed_clock() {
+        let mut resampler = Resampler::new(Vec::new(), 10.0);
+        // Jittery input, with a frame dropped between 0.23 and 0.41.
+        for &(time, x) in &[(1.0, 0.0), (1.12, 12.0), (1.23, 23.0),
+                            (1.41, 41.0)] {
+            resampler.send(&Sample::new(0, time, vec![x])).unwrap();
+        }
+        let sent = resampler.inner;
+        assert_eq!(sent.len(), 5);
+        for (i, s) in sent.iter().enumerate() {
+            assert_eq!(s.seq, i as u64);
+            assert!((s.time - (1.0 + 0.1 * i as f64)).abs() < 1e-9);
+            assert!((s.values[0] - 10.0 * i as f64).abs() < 1e-9,
+                    "{:?}",
+                    s);
+        }
+    }
+
+    #[test]
+    fn restarts_the_clock_after_a_long_gap() {
+        let mut resampler = Resampler::new(Vec::new(), 10.0);
+        resampler.send(&Sample::new(0, 0.0, vec![0.0])).unwrap();
+        resampler.send(&Sample::new(1, 2.05, vec![5.0])).unwrap();
+        let sent = resampler.inner;
+        assert_eq!(sent.len(), 2);
+        assert_eq!(sent[1].time, 2.05);
+        assert_eq!(sent[1].values, vec![5.0]);
+    }
+}
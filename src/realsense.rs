@@ -0,0 +1,351 @@
+//! Intel RealSense depth cameras, with `--input realsense` in builds with the
+//! `realsense` feature.
+//!
+//! The camera streams color and depth, aligned so that both have the same
+//! pixels. Targets are tracked in color as with any camera, and with
+//! `--mode track` every target also sends `z` after its other dimensions:
+//! the distance at its centroid in millimeters. Depth cameras leave holes
+//! where they can't see, such as at the edges of objects and on black or
+//! shiny surfaces; a centroid in a hole takes the median of the valid
+//! depths in the smallest square around it that has any, up to `HOLE_RADIUS`
+//! pixels away, and is NaN beyond. `realsense:SERIAL` picks a camera by its
+//! serial number when several are connected; `--resolution` and `--fps` are
+//! asked of both streams.
+//!
+//! ```text
+//! cargo build --release --features realsense
+//! esp-vision --input realsense --features x,y
+//! ```
+//!
+//! The depth is the one of the latest frame read, which is the frame being
+//! tracked unless the pipeline falls behind. It is read where the camera saw
+//! the centroid, in the frame as captured: unmirrored, and before
+//! `[[preprocess]]` crops or scales it.
+use crate::input::CaptureSettings;
+use crate::v4l2;
+use rust_vision::{Mat, imdecode};
+use std::sync::Mutex;
+
+/// Whether this build has the `realsense` feature.
+pub const BUILT: bool = cfg!(feature = "realsense");
+/// How far from a hole valid depths are looked for, in pixels.
+const HOLE_RADIUS: i64 = 12;
+/// Size of the streams unless `--resolution` says otherwise.
+const DEFAULT_SIZE: (i32, i32) = (640, 480);
+const DEFAULT_FPS: f64 = 30.0;
+
+/// The depth of the latest frame, shared with the tracking loop.
+static LATEST: Mutex<Option<DepthMap>> = Mutex::new(None);
+
+/// `DepthMap` is the depth of every pixel of a frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthMap {
+    width: usize,
+    height: usize,
+    /// Millimeters, row by row; 0 where the depth is unknown.
+    depth: Vec<u16>,
+}
+
+impl DepthMap {
+    pub fn new(width: usize, height: usize, depth: Vec<u16>) -> DepthMap {
+        DepthMap {
+            width,
+            height,
+            depth,
+        }
+    }
+
+    /// The depth at `(x, y)` in a frame of `cols` by `rows` pixels, in
+    /// millimeters, filling holes. `None` if there is no valid depth
+    /// nearby.
+    pub fn at(&self, x: f64, y: f64, cols: i32, rows: i32) -> Option<f64> {
+        if cols <= 0 || rows <= 0 || !x.is_finite() || !y.is_finite() {
+            return None;
+        }
+        let x = (x * self.width as f64 / f64::from(cols)) as i64;
+        let y = (y * self.height as f64 / f64::from(rows)) as i64;
+        let mut valid = Vec::new();
+        for radius in 0..HOLE_RADIUS + 1 {
+            valid.clear();
+            for j in y - radius..y + radius + 1 {
+                for i in x - radius..x + radius + 1 {
+                    match self.get(i, j) {
+                        Some(d) if d > 0 => valid.push(d),
+                        _ => {}
+                    }
+                }
+            }
+            if !valid.is_empty() {
+                valid.sort_unstable();
+                return Some(f64::from(valid[valid.len() / 2]));
+            }
+        }
+        None
+    }
+
+    fn get(&self, x: i64, y: i64) -> Option<u16> {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return None;
+        }
+        self.depth.get(y as usize * self.width + x as usize).cloned()
+    }
+}
+
+/// The depth of the latest frame of the RealSense input, if any.
+pub fn latest() -> Option<DepthMap> {
+    LATEST.lock().unwrap().clone()
+}
+
+/// `RealSense` streams the color and depth of a RealSense camera.
+pub struct RealSense {
+    camera: device::Camera,
+}
+
+impl RealSense {
+    /// Opens the camera with the serial number `serial`, or the first one
+    /// if empty, and starts streaming with `settings`.
+    pub fn open(serial: &str,
+                settings: &CaptureSettings)
+                -> Result<RealSense, String> {
+        if settings.fourcc.is_some() || settings.exposure.is_some() ||
+           settings.gain.is_some() ||
+           settings.white_balance.is_some() || settings.lock {
+            warn!("The realsense input ignores the pixel format, exposure, \
+                   gain and white balance settings");
+        }
+        let (width, height) = settings.size.unwrap_or(DEFAULT_SIZE);
+        let fps = settings.fps.unwrap_or(DEFAULT_FPS);
+        let camera = device::Camera::open(serial,
+                                          width as usize,
+                                          height as usize,
+                                          fps.round() as usize)?;
+        info!("Streaming color and depth from the RealSense camera {} at \
+               {}x{}",
+              camera.serial(),
+              width,
+              height);
+        Ok(RealSense { camera })
+    }
+
+    /// Reads the next frame into `frame`, and keeps its depth for `latest`.
+    /// Returns `false` if the camera fails.
+    pub fn read(&mut self, frame: &Mat) -> bool {
+        let (width, height, bgr, depth) = match self.camera.next() {
+            Ok(frames) => frames,
+            Err(e) => {
+                warn!("Cannot read from the RealSense camera: {}", e);
+                return false;
+            }
+        };
+        let image = imdecode(&v4l2::bmp(width, height, &bgr));
+        if image.cols <= 0 {
+            return false;
+        }
+        image.copy_to(frame);
+        *LATEST.lock().unwrap() = Some(DepthMap::new(width, height, depth));
+        true
+    }
+}
+
+#[cfg(not(feature = "realsense"))]
+mod device {
+    /// Cameras can't be opened without the `realsense` feature.
+    pub enum Camera {}
+
+    impl Camera {
+        pub fn open(_serial: &str,
+                    _width: usize,
+                    _height: usize,
+                    _fps: usize)
+                    -> Result<Camera, String> {
+            Err("this build has no realsense input; build with `--features \
+                 realsense`"
+                .to_string())
+        }
+
+        pub fn serial(&self) -> String {
+            match *self {}
+        }
+
+        pub fn next(&mut self)
+                    -> Result<(usize, usize, Vec<u8>, Vec<u16>), String> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(feature = "realsense")]
+mod device {
+    use realsense_rust::config::Config;
+    use realsense_rust::context::Context;
+    use realsense_rust::frame::{ColorFrame, DepthFrame, FrameEx, PixelKind};
+    use realsense_rust::kind::{Rs2CameraInfo, Rs2Format, Rs2StreamKind};
+    use realsense_rust::pipeline::{ActivePipeline, InactivePipeline};
+    use realsense_rust::processing_blocks::align::Align;
+    use std::collections::HashSet;
+    use std::ffi::CString;
+    use std::fmt::Display;
+    use std::time::Duration;
+
+    /// How long a frame may take before the camera counts as failed.
+    const TIMEOUT: Duration = Duration::from_secs(2);
+    /// Frames the aligner holds.
+    const ALIGN_QUEUE: usize = 2;
+
+    fn failed<E: Display>(e: E) -> String {
+        format!("cannot open the RealSense camera: {}", e)
+    }
+
+    pub struct Camera {
+        pipeline: ActivePipeline,
+        align: Align,
+        serial: String,
+    }
+
+    impl Camera {
+        pub fn open(serial: &str,
+                    width: usize,
+                    height: usize,
+                    fps: usize)
+                    -> Result<Camera, String> {
+            let context = Context::new().map_err(failed)?;
+            let devices = context.query_devices(HashSet::new());
+            let serial_of = |d: &realsense_rust::device::Device| {
+                d.info(Rs2CameraInfo::SerialNumber)
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            };
+            let device = devices.iter()
+                .find(|d| serial.is_empty() || serial_of(d) == serial)
+                .ok_or_else(|| if serial.is_empty() {
+                    "no RealSense camera is connected".to_string()
+                } else {
+                    format!("no RealSense camera has the serial number {}",
+                            serial)
+                })?;
+            let serial = serial_of(device);
+            let mut config = Config::new();
+            let id = CString::new(serial.clone()).map_err(failed)?;
+            config.enable_device_from_serial(&id)
+                .and_then(|c| c.disable_all_streams())
+                .and_then(|c| {
+                    c.enable_stream(Rs2StreamKind::Color,
+                                    None,
+                                    width,
+                                    height,
+                                    Rs2Format::Bgr8,
+                                    fps)
+                })
+                .and_then(|c| {
+                    c.enable_stream(Rs2StreamKind::Depth,
+                                    None,
+                                    width,
+                                    height,
+                                    Rs2Format::Z16,
+                                    fps)
+                })
+                .map_err(failed)?;
+            let pipeline = InactivePipeline::try_from(&context)
+                .map_err(failed)?
+                .start(Some(config))
+                .map_err(failed)?;
+            let align = Align::new(Rs2StreamKind::Color, ALIGN_QUEUE)
+                .map_err(failed)?;
+            Ok(Camera {
+                pipeline,
+                align,
+                serial,
+            })
+        }
+
+        pub fn serial(&self) -> String {
+            self.serial.clone()
+        }
+
+        /// The width and height of the next frame, its blue, green and red
+        /// pixels and its depth in millimeters, waiting for it.
+        pub fn next(&mut self)
+                    -> Result<(usize, usize, Vec<u8>, Vec<u16>), String> {
+            let frames = self.pipeline
+                .wait(Some(TIMEOUT))
+                .map_err(|e| e.to_string())?;
+            self.align.queue(frames).map_err(|e| e.to_string())?;
+            let aligned = self.align
+                .wait(TIMEOUT)
+                .map_err(|e| e.to_string())?;
+            let color = aligned.frames_of_type::<ColorFrame>()
+                .pop()
+                .ok_or("no color in the frame")?;
+            let depth = aligned.frames_of_type::<DepthFrame>()
+                .pop()
+                .ok_or("no depth in the frame")?;
+            let (width, height) = (color.width(), color.height());
+            let mut bgr = Vec::with_capacity(width * height * 3);
+            for pixel in color.iter() {
+                if let PixelKind::Bgr8 { b, g, r } = pixel {
+                    bgr.extend_from_slice(&[*b, *g, *r]);
+                }
+            }
+            let scale = depth.depth_units().map_err(|e| e.to_string())?;
+            let millimeters = depth.iter()
+                .map(|pixel| match pixel {
+                    PixelKind::Z16 { depth } => {
+                        (f32::from(*depth) * scale * 1000.0)
+                            .round()
+                            .min(f32::from(u16::MAX)) as u16
+                    }
+                    _ => 0,
+                })
+                .collect();
+            Ok((width, height, bgr, millimeters))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::preprocess::{Converter, StepSpec};
+    use super::DepthMap;
+
+    #[test]
+    fn reads_the_depth_at_the_centroid() {
+        let map = DepthMap::new(4, 2, vec![0, 0, 0, 0, 900, 1000, 1100, 0]);
+        assert_eq!(map.at(1.0, 1.0, 4, 2), Some(1000.0));
+        // In a frame downscaled by half.
+        assert_eq!(map.at(1.0, 0.5, 2, 1), Some(1100.0));
+    }
+
+    #[test]
+    fn reads_the_depth_where_the_camera_saw_the_centroid() {
+        let mut depth = vec![1000; 32];
+        depth[8 + 3] = 1500;
+        let map = DepthMap::new(8, 4, depth);
+        let mut config = Config::default();
+        config.preprocess = vec![StepSpec::Crop { rect: [2, 0, 6, 4] },
+                                 StepSpec::Resize { scale: 0.5 }];
+        let converter = Converter::new(&config).unwrap();
+        // The pixel (3, 1) of the 8x4 frame is shown at (4, 1) mirrored,
+        // (2, 1) cropped, and between pixels halved.
+        let [x, y] = converter.captured((8, 4), [0.75, 0.25]);
+        assert_eq!(map.at(x, y, 8, 4), Some(1500.0));
+    }
+
+    #[test]
+    fn fills_holes_with_the_median_around_them() {
+        let mut depth = vec![0; 25];
+        depth[0] = 500;
+        depth[6] = 700;
+        depth[8] = 900;
+        depth[16] = 800;
+        let map = DepthMap::new(5, 5, depth);
+        // The 3x3 square around the center holds 700, 900 and 800.
+        assert_eq!(map.at(2.0, 2.0, 5, 5), Some(800.0));
+    }
+
+    #[test]
+    fn gives_up_on_large_holes() {
+        let map = DepthMap::new(40, 1, vec![0; 40]);
+        assert_eq!(map.at(20.0, 0.0, 40, 1), None);
+    }
+}
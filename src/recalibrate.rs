@@ -0,0 +1,92 @@
+//! Recalibrating the colors when the lighting changes, with `c`.
+//!
+//! The lighting of a show is rarely that of the rehearsal, and the HSV mask
+//! and histograms tuned for one lose the target in the other. Pressing `c`
+//! samples a reference patch: the region given with `--reference-patch
+//! X,Y,W,H`, such as a card of the color of the target that stays in view,
+//! or else the region of the first target. The bounds of the HSV mask are
+//! set to the colors of the patch, with margins: the hue between its 5th
+//! and 95th percentiles, or any hue for reds, which wrap around, and the
+//! saturation and value above their 5th percentiles. Every tracked target
+//! then learns its histogram again from where it is, under the new mask,
+//! and goes on; its filters, derivatives and selection slot are kept.
+//!
+//! The trackbars follow the new bounds, and `t` prints them, to keep them in
+//! the `[params]` of the config file for the next show.
+use rust_vision::*;
+
+/// Share of the pixels of the patch left out at either end of a channel.
+const PERCENTILE: f64 = 0.05;
+/// Hue added on either side of the range of the patch.
+const HUE_MARGIN: i32 = 5;
+/// Saturation and value taken below the lowest of the patch.
+const MARGIN: i32 = 20;
+/// Hues this close to 0 or 180 are reds.
+const RED: i32 = 15;
+
+/// The lower and upper bounds of an HSV mask that holds the colors of the
+/// region `patch` of the (BGR) `frame`, as `[hue, saturation, value]`.
+/// `None` if the patch is outside the frame.
+pub fn bounds(frame: &Mat, patch: Rect) -> Option<([i32; 3], [i32; 3])> {
+    let patch = crate::tracker::clip(patch, frame.cols, frame.rows);
+    if patch.width == 0 || patch.height == 0 {
+        return None;
+    }
+    let hsv = frame.roi(patch).cvt_color(ColorConversionCodes::BGR2HSV);
+    let total = patch.width * patch.height;
+    // The pixels whose `channel` is at most `t`.
+    let at_most = |channel: usize, t: i32| {
+        let mut high = [255; 3];
+        high[channel] = t;
+        hsv.in_range(Scalar::new(0, 0, 0, 0),
+                      Scalar::new(high[0], high[1], high[2], 0))
+            .count_non_zero()
+    };
+    let low = |channel: usize| {
+        percentile(|t| at_most(channel, t), total, PERCENTILE, 255)
+    };
+    let reds = at_most(0, RED) + total - at_most(0, 180 - RED - 1);
+    let (h_low, h_high) = if f64::from(reds) > PERCENTILE * f64::from(total) {
+        (0, 180)
+    } else {
+        let high = percentile(|t| at_most(0, t), total, 1.0 - PERCENTILE, 180);
+        ((low(0) - HUE_MARGIN).max(0), (high + HUE_MARGIN).min(180))
+    };
+    Some(([h_low, (low(1) - MARGIN).max(0), (low(2) - MARGIN).max(0)],
+          [h_high, 256, 256]))
+}
+
+/// The smallest value up to `max` that at least the share `p` of `total`
+/// values are at most, given how many values `at_most` every value.
+fn percentile<F>(at_most: F, total: i32, p: f64, max: i32) -> i32
+    where F: Fn(i32) -> i32
+{
+    let wanted = (p * f64::from(total)).ceil() as i32;
+    let (mut low, mut high) = (0, max);
+    while low < high {
+        let mid = (low + high) / 2;
+        if at_most(mid) >= wanted {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    low
+}
+
+#[cfg(test)]
+mod tests {
+    use super::percentile;
+
+    #[test]
+    fn finds_percentiles_by_counting() {
+        // The values 0 to 99, once each.
+        let values: Vec<i32> = (0..100).collect();
+        let at_most =
+            |t: i32| values.iter().filter(|&&v| v <= t).count() as i32;
+        assert_eq!(percentile(&at_most, 100, 0.05, 255), 4);
+        assert_eq!(percentile(&at_most, 100, 0.95, 255), 94);
+        assert_eq!(percentile(&at_most, 100, 1.0, 255), 99);
+        assert_eq!(percentile(|_| 0, 100, 0.5, 180), 180);
+    }
+}
@@ -0,0 +1,50 @@
+//! Recording of the displayed frames to a video file.
+use rust_vision::{Mat, Size2i, VideoWriter};
+
+/// Frame rate written to the file. Webcams deliver about 30 frames per
+/// second; a slower pipeline plays back faster than it ran.
+const FPS: f64 = 30.0;
+
+/// `Recorder` writes frames, annotations included, to a video file. The file
+/// is opened on the first frame, once its size is known.
+pub struct Recorder {
+    path: String,
+    writer: Option<VideoWriter>,
+}
+
+impl Recorder {
+    pub fn new(path: &str) -> Recorder {
+        Recorder {
+            path: path.to_string(),
+            writer: None,
+        }
+    }
+
+    pub fn write(&mut self, frame: &Mat) -> Result<(), String> {
+        if self.writer.is_none() {
+            let writer = VideoWriter::new(&self.path,
+                                          fourcc(&self.path),
+                                          FPS,
+                                          Size2i::new(frame.cols, frame.rows));
+            if !writer.is_open() {
+                return Err(format!("cannot record to {}", self.path));
+            }
+            info!("Recording to {}", self.path);
+            self.writer = Some(writer);
+        }
+        if let Some(ref mut writer) = self.writer {
+            writer.write(frame);
+        }
+        Ok(())
+    }
+}
+
+/// The codec for the container of `path`: MPEG-4 for `.mp4`, Motion JPEG
+/// otherwise, which every OpenCV build can write to `.avi`.
+fn fourcc(path: &str) -> [char; 4] {
+    if path.to_lowercase().ends_with(".mp4") {
+        ['m', 'p', '4', 'v']
+    } else {
+        ['M', 'J', 'P', 'G']
+    }
+}
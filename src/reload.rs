@@ -0,0 +1,248 @@
+//! Reloading the config file while running.
+//!
+//! The file given with `--config` is read again every second. When it
+//! changes, `[params]`, `send_rate` and `[[filters]]` take their new values
+//! right away: the parameters as if sent on the control channel, the rate
+//! from the next sample on, and the filters for the targets tracked and
+//! the ones started later. Parameters removed from the file go back to
+//! their defaults. Settings that the command line or the environment also
+//! set keep that value. Changes to anything else are logged as needing a
+//! restart, and a file that no longer reads as valid settings is reported
+//! and ignored until it is fixed.
+use crate::config::Live;
+use crate::filter::FilterSpec;
+use crate::params::VisionParams;
+use std::collections::BTreeSet;
+use std::fs;
+use std::time::{Duration, Instant};
+use toml::value::Table;
+
+/// How often the file is read.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The live settings that changed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    /// The parameters to set, by name.
+    pub params: Vec<(String, f64)>,
+    /// The new send rate, if it changed; `None` sends every sample.
+    pub send_rate: Option<Option<f64>>,
+    /// The new smoothing filters, if they changed.
+    pub filters: Option<Vec<FilterSpec>>,
+}
+
+/// `Reloader` watches the config file.
+pub struct Reloader {
+    path: String,
+    /// Keys of `Live::KEYS` that the command line or environment set.
+    overridden: Vec<&'static str>,
+    /// The contents last read, valid or not.
+    text: String,
+    /// The last valid contents, and their live settings.
+    table: Table,
+    live: Live,
+    /// Whether the file could not be read last time.
+    unreadable: bool,
+    next_check: Instant,
+}
+
+impl Reloader {
+    /// Watches the config file at `path`, which was just read.
+    pub fn new(path: &str, overridden: Vec<&'static str>) -> Reloader {
+        let text = fs::read_to_string(path).unwrap_or_default();
+        Reloader::with_text(path, overridden, text)
+    }
+
+    fn with_text(path: &str,
+                 overridden: Vec<&'static str>,
+                 text: String)
+                 -> Reloader {
+        Reloader {
+            path: path.to_string(),
+            overridden,
+            table: toml::from_str(&text).unwrap_or_else(|_| Table::new()),
+            live: Live::parse(&text).unwrap_or_default(),
+            text,
+            unreadable: false,
+            next_check: Instant::now() + CHECK_INTERVAL,
+        }
+    }
+
+    /// Reads the file if it is time to, and returns what changed in the
+    /// live settings, if anything.
+    pub fn poll(&mut self, now: Instant) -> Option<Changes> {
+        if now < self.next_check {
+            return None;
+        }
+        self.next_check = now + CHECK_INTERVAL;
+        match fs::read_to_string(&self.path) {
+            Ok(text) => {
+                self.unreadable = false;
+                self.reload(text)
+            }
+            Err(e) => {
+                if !self.unreadable {
+                    warn!("Cannot read config file {}: {}", self.path, e);
+                    self.unreadable = true;
+                }
+                None
+            }
+        }
+    }
+
+    fn reload(&mut self, text: String) -> Option<Changes> {
+        if text == self.text {
+            return None;
+        }
+        self.text = text;
+        let parsed = toml::from_str::<Table>(&self.text)
+            .map_err(|e| e.to_string())
+            .and_then(|table| Ok((table, Live::parse(&self.text)?)));
+        let (table, live) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("Ignoring the changes to config file {}: {}",
+                      self.path,
+                      e);
+                return None;
+            }
+        };
+        let keys: BTreeSet<&String> =
+            self.table.keys().chain(table.keys()).collect();
+        let mut reloaded = Vec::new();
+        for key in keys {
+            if self.table.get(key) == table.get(key) {
+                continue;
+            }
+            match Live::KEYS.iter().find(|&&k| k == key) {
+                Some(&key) if self.overridden.contains(&key) => {
+                    info!("{} changed in {}, but the command line or the \
+                           environment sets it",
+                          key,
+                          self.path);
+                }
+                Some(&key) => reloaded.push(key),
+                None => {
+                    warn!("{} changed in {}; restart to apply it",
+                          key,
+                          self.path);
+                }
+            }
+        }
+        let mut changes = Changes::default();
+        for &key in &reloaded {
+            match key {
+                "params" => {
+                    changes.params = changed_params(&self.live.params,
+                                                    &live.params)
+                }
+                "send_rate" => changes.send_rate = Some(live.send_rate),
+                _ => changes.filters = Some(live.filters.clone()),
+            }
+        }
+        self.table = table;
+        self.live = live;
+        if reloaded.is_empty() {
+            return None;
+        }
+        info!("Reloaded {} from {}", reloaded.join(", "), self.path);
+        Some(changes)
+    }
+}
+
+/// The parameters to set to go from `old` to `new`: the new and changed
+/// ones, and the removed ones at their defaults.
+fn changed_params(old: &[(String, f64)],
+                  new: &[(String, f64)])
+                  -> Vec<(String, f64)> {
+    let mut changed: Vec<_> =
+        new.iter().filter(|p| !old.contains(p)).cloned().collect();
+    let defaults = VisionParams::default();
+    for &(ref name, _) in old {
+        if !new.iter().any(|&(ref n, _)| n == name) {
+            if let Some(value) = defaults.value(name) {
+                changed.push((name.clone(), value));
+            }
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::FilterSpec;
+    use super::{Changes, Reloader};
+
+    const TEXT: &str = r#"
+server = "127.0.0.1:8001"
+send_rate = 30
+
+[params]
+h_min = 10
+"#;
+
+    fn reloader(overridden: Vec<&'static str>) -> Reloader {
+        Reloader::with_text("esp.toml", overridden, TEXT.to_string())
+    }
+
+    #[test]
+    fn applies_the_changed_live_settings() {
+        let mut reloader = reloader(Vec::new());
+        let text = r#"
+server = "127.0.0.1:8001"
+send_rate = 15
+
+[params]
+h_min = 20
+v_max = 200
+
+[[filters]]
+type = "median"
+window = 5
+"#;
+        assert_eq!(reloader.reload(text.to_string()),
+                   Some(Changes {
+                       params: vec![("h_min".to_string(), 20.0),
+                                    ("v_max".to_string(), 200.0)],
+                       send_rate: Some(Some(15.0)),
+                       filters: Some(vec![FilterSpec::Median { window: 5 }]),
+                   }));
+        // Removed settings go back to their defaults.
+        let text = "server = \"127.0.0.1:8001\"";
+        assert_eq!(reloader.reload(text.to_string()),
+                   Some(Changes {
+                       params: vec![("h_min".to_string(), 0.0),
+                                    ("v_max".to_string(), 256.0)],
+                       send_rate: Some(None),
+                       filters: Some(Vec::new()),
+                   }));
+    }
+
+    #[test]
+    fn leaves_other_settings_to_a_restart() {
+        let mut reloader = reloader(Vec::new());
+        let text = TEXT.replace("127.0.0.1", "10.0.0.2");
+        assert_eq!(reloader.reload(text), None);
+    }
+
+    #[test]
+    fn keeps_settings_from_the_command_line() {
+        let mut reloader = reloader(vec!["send_rate"]);
+        let text = TEXT.replace("send_rate = 30", "send_rate = 15");
+        assert_eq!(reloader.reload(text), None);
+    }
+
+    #[test]
+    fn ignores_invalid_files_until_fixed() {
+        let mut reloader = reloader(Vec::new());
+        let invalid = TEXT.replace("send_rate = 30", "send_rate = -1");
+        assert_eq!(reloader.reload(invalid), None);
+        let typo = TEXT.replace("h_min", "hmin");
+        assert_eq!(reloader.reload(typo), None);
+        // Compared with the last valid file.
+        let fixed = TEXT.replace("h_min = 10", "h_min = 12");
+        let changes = reloader.reload(fixed).unwrap();
+        assert_eq!(changes.params, vec![("h_min".to_string(), 12.0)]);
+        assert_eq!(changes.send_rate, None);
+    }
+}
@@ -0,0 +1,227 @@
+//! The quality report of a session, with `--report FILE` or `i`.
+//!
+//! When an installation "felt laggy yesterday", the log rarely says why. The
+//! report sums a session up: the frames processed and the live frames
+//! dropped, the intervals during which a target was lost, the average
+//! confidence of the tracked targets, the samples sent per second with their
+//! spread and extremes, and how many times the connection to ESP and the
+//! network cameras were made again.
+//!
+//! `--report FILE` writes it when the application stops, on `q`, SIGINT or
+//! SIGTERM, as JSON if `FILE` ends with `.json` and as text otherwise.
+//! Pressing `i` writes the report of the session so far, to `FILE` or else to
+//! `report-SEQ.txt`.
+use crate::loss::TrackState;
+use crate::metrics;
+use crate::sink::SinkStats;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::io;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// `Report` follows the quality of a session, frame by frame.
+pub struct Report {
+    frames: u64,
+    /// The sum and the number of the confidences of tracked targets.
+    confidence: (f64, u64),
+    /// The start and end of every interval during which a target was lost,
+    /// in seconds since the start; the last one has no end while lost.
+    lost: Vec<(f64, Option<f64>)>,
+    /// Samples sent during every complete second.
+    rates: Vec<u64>,
+    /// Samples sent at the start of the current second, and its start.
+    second: (u64, Instant),
+    /// Whether the last sample reached ESP.
+    connected: bool,
+    /// Reconnections to ESP.
+    reconnects: u64,
+}
+
+impl Report {
+    pub fn new() -> Report {
+        Report {
+            frames: 0,
+            confidence: (0.0, 0),
+            lost: Vec::new(),
+            rates: Vec::new(),
+            second: (0, Instant::now()),
+            connected: true,
+            reconnects: 0,
+        }
+    }
+
+    /// Takes a processed frame, with the statistics of the sink to ESP.
+    pub fn frame(&mut self, stats: SinkStats) {
+        self.frames += 1;
+        if stats.connected && !self.connected {
+            self.reconnects += 1;
+        }
+        self.connected = stats.connected;
+        if self.second.1.elapsed() >= Duration::from_secs(1) {
+            self.rates.push(stats.sent - self.second.0);
+            self.second = (stats.sent, Instant::now());
+        }
+    }
+
+    /// Takes the confidence and state of the selected targets at `time`.
+    pub fn targets(&mut self,
+                   time: f64,
+                   statuses: &[Option<(f64, TrackState)>]) {
+        let mut lost = false;
+        for &(confidence, state) in statuses.iter().flatten() {
+            match state {
+                TrackState::Tracking => {
+                    self.confidence.0 += confidence;
+                    self.confidence.1 += 1;
+                }
+                TrackState::Lost => lost = true,
+            }
+        }
+        let open = matches!(self.lost.last(), Some(&(_, None)));
+        if lost && !open {
+            self.lost.push((time, None));
+        } else if !lost && open {
+            self.lost.last_mut().unwrap().1 = Some(time);
+        }
+    }
+
+    /// Writes the report of a session that lasted `elapsed` and dropped
+    /// `dropped` live frames to `path`.
+    pub fn write(&self,
+                 path: &str,
+                 elapsed: Duration,
+                 dropped: u64)
+                 -> io::Result<()> {
+        let report = if path.ends_with(".json") {
+            self.json(elapsed, dropped)
+        } else {
+            self.text(elapsed, dropped)
+        };
+        fs::write(path, report)
+    }
+
+    /// The average confidence of the tracked targets, if any was.
+    fn average_confidence(&self) -> Option<f64> {
+        match self.confidence {
+            (_, 0) => None,
+            (sum, n) => Some(sum / n as f64),
+        }
+    }
+
+    /// The mean, standard deviation, minimum and maximum of the samples
+    /// sent per second.
+    fn send_rate(&self) -> (f64, f64, u64, u64) {
+        if self.rates.is_empty() {
+            return (0.0, 0.0, 0, 0);
+        }
+        let n = self.rates.len() as f64;
+        let mean = self.rates.iter().sum::<u64>() as f64 / n;
+        let variance = self.rates
+            .iter()
+            .map(|&r| (r as f64 - mean) * (r as f64 - mean))
+            .sum::<f64>() / n;
+        (mean,
+         variance.sqrt(),
+         *self.rates.iter().min().unwrap(),
+         *self.rates.iter().max().unwrap())
+    }
+
+    fn text(&self, elapsed: Duration, dropped: u64) -> String {
+        let mut out = String::new();
+        let secs = elapsed.as_secs_f64();
+        writeln!(out, "Duration          {:.1} s", secs).unwrap();
+        writeln!(out,
+                 "Frames            {} ({:.1} fps)",
+                 self.frames,
+                 self.frames as f64 / secs)
+            .unwrap();
+        writeln!(out, "Dropped frames    {}", dropped).unwrap();
+        match self.average_confidence() {
+            Some(c) => writeln!(out, "Confidence        {:.3}", c).unwrap(),
+            None => writeln!(out, "Confidence        -").unwrap(),
+        }
+        let (mean, sd, min, max) = self.send_rate();
+        writeln!(out,
+                 "Send rate         {:.1} ± {:.1} samples/s, {} to {}",
+                 mean,
+                 sd,
+                 min,
+                 max)
+            .unwrap();
+        writeln!(out, "ESP reconnects    {}", self.reconnects).unwrap();
+        writeln!(out,
+                 "Camera reconnects {}",
+                 metrics::RECONNECTS.load(Ordering::SeqCst))
+            .unwrap();
+        writeln!(out, "Lost intervals    {}", self.lost.len()).unwrap();
+        for &(start, end) in &self.lost {
+            let end = end.unwrap_or(secs);
+            writeln!(out,
+                     "  {:.2} s to {:.2} s ({:.2} s)",
+                     start,
+                     end,
+                     end - start)
+                .unwrap();
+        }
+        out
+    }
+
+    fn json(&self, elapsed: Duration, dropped: u64) -> String {
+        let secs = elapsed.as_secs_f64();
+        let (mean, sd, min, max) = self.send_rate();
+        let lost: Vec<String> = self.lost
+            .iter()
+            .map(|&(start, end)| {
+                format!("[{:.3},{:.3}]", start, end.unwrap_or(secs))
+            })
+            .collect();
+        let confidence = match self.average_confidence() {
+            Some(c) => format!("{:.4}", c),
+            None => "null".to_string(),
+        };
+        format!("{{\"duration\":{:.3},\"frames\":{},\"dropped\":{},\
+                 \"confidence\":{},\"send_rate\":{{\"mean\":{:.3},\
+                 \"sd\":{:.3},\"min\":{},\"max\":{}}},\
+                 \"esp_reconnects\":{},\"camera_reconnects\":{},\
+                 \"lost\":[{}]}}\n",
+                secs,
+                self.frames,
+                dropped,
+                confidence,
+                mean,
+                sd,
+                min,
+                max,
+                self.reconnects,
+                metrics::RECONNECTS.load(Ordering::SeqCst),
+                lost.join(","))
+    }
+}
+
+impl Default for Report {
+    fn default() -> Report {
+        Report::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loss::TrackState;
+    use super::Report;
+
+    #[test]
+    fn follows_lost_intervals_and_confidence() {
+        let mut report = Report::new();
+        let tracking = Some((0.8, TrackState::Tracking));
+        let lost = Some((0.1, TrackState::Lost));
+        report.targets(0.0, &[tracking, None]);
+        report.targets(1.0, &[lost, None]);
+        report.targets(1.5, &[lost, None]);
+        report.targets(2.0, &[tracking, Some((0.6, TrackState::Tracking))]);
+        report.targets(3.0, &[tracking, lost]);
+        assert_eq!(report.lost, [(1.0, Some(2.0)), (3.0, None)]);
+        assert_eq!(report.confidence.1, 4);
+        assert!((report.average_confidence().unwrap() - 0.75).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,19 @@
+//! The unit of data flowing from the tracker to ESP.
+
+/// `Sample` is one feature vector together with its capture time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// Incremented for every sample produced, so gaps reveal drops.
+    pub seq: u64,
+    /// Seconds since the start of the session, taken from a monotonic clock
+    /// right after the frame was captured.
+    pub time: f64,
+    /// The feature values, in the order of the dimension names.
+    pub values: Vec<f64>,
+}
+
+impl Sample {
+    pub fn new(seq: u64, time: f64, values: Vec<f64>) -> Sample {
+        Sample { seq, time, values }
+    }
+}
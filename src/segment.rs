@@ -0,0 +1,223 @@
+//! Gesture segmentation by motion, with `--segment START:STOP`.
+//!
+//! Streaming continuously makes ESP null-reject everything in between
+//! gestures. The `Segmenter` measures how fast the positions of every sample
+//! move, in the units of the samples per second: a gesture starts when the
+//! speed reaches `START`, and ends once it stayed below `STOP` (lower, for
+//! hysteresis) for a fifth of a second. Only the samples of gestures are
+//! sent. With `--segment-markers`, every sample is sent with an extra
+//! `segment` dimension instead: `1` on the first sample of a gesture, `-1`
+//! on its last and `0` otherwise.
+use crate::normalize::Unit;
+use crate::sample::Sample;
+use crate::sink::Sink;
+use std::io;
+use std::str::FromStr;
+
+/// The name of the dimension of the markers.
+pub const NAME: &str = "segment";
+/// How long the speed must stay below `stop` to end a gesture, in seconds.
+const HOLD: f64 = 0.2;
+
+/// The thresholds of the segmentation, and what it sends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segmentation {
+    /// The speed at which gestures start.
+    pub start: f64,
+    /// The speed below which gestures end.
+    pub stop: f64,
+    /// Send every sample with markers rather than only the gestures.
+    pub markers: bool,
+}
+
+/// Parses `START:STOP`, without markers.
+impl FromStr for Segmentation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Segmentation, String> {
+        let invalid = || format!("invalid segmentation `{}`; expected \
+                                  START:STOP speeds",
+                                 s);
+        let speeds: Vec<_> =
+            s.split(':').map(|v| v.trim().parse::<f64>()).collect();
+        let (start, stop) = match speeds[..] {
+            [Ok(start), Ok(stop)] => (start, stop),
+            _ => return Err(invalid()),
+        };
+        if !(stop > 0.0 && start >= stop && start.is_finite()) {
+            return Err(format!("segmentation needs START >= STOP > 0, got \
+                                {}:{}",
+                               start,
+                               stop));
+        }
+        Ok(Segmentation {
+            start,
+            stop,
+            markers: false,
+        })
+    }
+}
+
+/// Where a sample is relative to a gesture.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Marker {
+    Start,
+    Inside,
+    End,
+    Outside,
+}
+
+/// `Segmenter` forwards the samples of gestures to the wrapped sink, or all
+/// samples with markers.
+pub struct Segmenter<S> {
+    inner: S,
+    segmentation: Segmentation,
+    /// The indices of the positions in the samples.
+    positions: Vec<usize>,
+    last: Option<Sample>,
+    moving: bool,
+    /// When the speed fell below `stop` during the gesture, if it did.
+    slow_since: Option<f64>,
+}
+
+impl<S: Sink> Segmenter<S> {
+    /// Segments samples whose dimensions are measured in `units`.
+    pub fn new(inner: S,
+               segmentation: Segmentation,
+               units: &[Unit])
+               -> Segmenter<S> {
+        let positions = units.iter()
+            .enumerate()
+            .filter(|&(_, &u)| u == Unit::X || u == Unit::Y)
+            .map(|(i, _)| i)
+            .collect();
+        Segmenter {
+            inner,
+            segmentation,
+            positions,
+            last: None,
+            moving: false,
+            slow_since: None,
+        }
+    }
+
+    /// How fast the positions moved from `last` to `sample`. Positions that
+    /// are lost don't move.
+    fn speed(&self, last: &Sample, sample: &Sample) -> f64 {
+        let dt = sample.time - last.time;
+        if dt <= 0.0 {
+            return 0.0;
+        }
+        let distance = self.positions
+            .iter()
+            .filter_map(|&i| Some(sample.values.get(i)? - last.values.get(i)?))
+            .filter(|d| d.is_finite())
+            .map(|d| d * d)
+            .sum::<f64>()
+            .sqrt();
+        distance / dt
+    }
+
+    fn update(&mut self, speed: f64, time: f64) -> Marker {
+        if !self.moving {
+            if speed >= self.segmentation.start {
+                self.moving = true;
+                self.slow_since = None;
+                debug!("Gesture started at {:.2} s", time);
+                return Marker::Start;
+            }
+            return Marker::Outside;
+        }
+        if speed >= self.segmentation.stop {
+            self.slow_since = None;
+            return Marker::Inside;
+        }
+        let since = *self.slow_since.get_or_insert(time);
+        if time - since < HOLD {
+            return Marker::Inside;
+        }
+        self.moving = false;
+        debug!("Gesture ended at {:.2} s", time);
+        Marker::End
+    }
+}
+
+impl<S: Sink> Sink for Segmenter<S> {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let speed = match self.last {
+            Some(ref last) => self.speed(last, sample),
+            None => 0.0,
+        };
+        self.last = Some(sample.clone());
+        let marker = self.update(speed, sample.time);
+        if self.segmentation.markers {
+            let mut marked = sample.clone();
+            marked.values.push(match marker {
+                Marker::Start => 1.0,
+                Marker::End => -1.0,
+                Marker::Inside | Marker::Outside => 0.0,
+            });
+            return self.inner.send(&marked);
+        }
+        match marker {
+            Marker::Outside => Ok(()),
+            _ => self.inner.send(sample),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::Unit;
+    use crate::sample::Sample;
+    use crate::sink::Sink;
+    use super::{Segmentation, Segmenter};
+
+    /// Samples at 8 Hz of a point resting, moving right at `speeds` and
+    /// resting again.
+    fn send<S: Sink>(sink: &mut S, speeds: &[f64]) {
+        let mut x = 0.0;
+        let moves = speeds.iter().chain(&[0.0; 5]);
+        for (seq, speed) in moves.enumerate() {
+            x += speed / 8.0;
+            let sample = Sample::new(seq as u64, seq as f64 / 8.0, vec![x]);
+            sink.send(&sample).unwrap();
+        }
+    }
+
+    fn segmenter(markers: bool) -> Segmenter<Vec<Sample>> {
+        let segmentation = Segmentation {
+            markers,
+            .."100:20".parse::<Segmentation>().unwrap()
+        };
+        Segmenter::new(Vec::new(), segmentation, &[Unit::X])
+    }
+
+    #[test]
+    fn sends_only_gestures() {
+        let mut segmenter = segmenter(false);
+        // Drifting slower than `start` is no gesture; a short pause below
+        // `stop` doesn't end one.
+        send(&mut segmenter, &[50.0, 50.0, 200.0, 50.0, 10.0, 100.0, 10.0]);
+        let seqs: Vec<u64> = segmenter.inner.iter().map(|s| s.seq).collect();
+        // The sample 0.2 s after the speed fell ends it.
+        assert_eq!(seqs, [2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn marks_the_start_and_end_of_gestures() {
+        let mut segmenter = segmenter(true);
+        send(&mut segmenter, &[0.0, 150.0, 150.0]);
+        let markers: Vec<f64> =
+            segmenter.inner.iter().map(|s| s.values[1]).collect();
+        assert_eq!(markers, [0.0, 1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn needs_start_above_stop() {
+        assert!("20:100".parse::<Segmentation>().is_err());
+        assert!("100".parse::<Segmentation>().is_err());
+        assert!("100:0".parse::<Segmentation>().is_err());
+        assert_eq!("100:100".parse::<Segmentation>().unwrap().stop, 100.0);
+    }
+}
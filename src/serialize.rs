@@ -0,0 +1,263 @@
+//! Wire formats for samples.
+//!
+//! - `ascii`: space-separated values terminated by a newline, which is what
+//!   ESP's `TcpInputStream` parses. This is the default. The time and the
+//!   sequence number can optionally be appended as two extra values.
+//! - `json`: one JSON object per line, e.g.
+//!   `{"x":320,"y":240,"t":1.25,"seq":37}`.
+//! - `binary`: the magic bytes `EV`, the payload length as a little-endian
+//!   `u16`, then the payload: the sequence number as `u64`, the time as `f64`
+//!   and every value as `f32`, all little-endian.
+//! - `framed`: a header naming the dimensions and their units, then a frame
+//!   with a CRC per sample; see the [`framed`](../framed/index.html) module.
+//!
+//! Besides these, `osc` sinks (see `outputs`) send every sample as an OSC
+//! message whose arguments are the values, as big-endian `f32`.
+use crate::framed::{self, Dimension};
+use crate::sample::Sample;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Magic bytes at the start of every binary frame.
+pub const BINARY_MAGIC: &[u8; 2] = b"EV";
+
+/// `Serializer` turns a sample into bytes for a sink.
+pub trait Serializer: Send {
+    /// Appends the encoding of `sample` to `out`.
+    fn serialize(&self, sample: &Sample, out: &mut Vec<u8>);
+
+    /// Appends what starts a stream, before any sample, to `out`.
+    fn header(&self, _out: &mut Vec<u8>) {}
+}
+
+/// The available wire formats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Ascii,
+    Json,
+    Binary,
+    Framed,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "ascii" => Ok(Format::Ascii),
+            "json" => Ok(Format::Json),
+            "binary" => Ok(Format::Binary),
+            "framed" => Ok(Format::Framed),
+            _ => Err(format!("unknown format `{}`", s)),
+        }
+    }
+}
+
+impl Format {
+    /// Creates a serializer for samples whose dimensions are called `names`
+    /// and measured in `units`, which are empty when unknown. `stamps` only
+    /// affects the ASCII format, the others are always stamped.
+    pub fn serializer(&self,
+                      names: Vec<String>,
+                      units: Vec<String>,
+                      stamps: bool)
+                      -> Box<dyn Serializer> {
+        match *self {
+            Format::Ascii => Box::new(Ascii { stamps }),
+            Format::Json => Box::new(JsonLines { names }),
+            Format::Binary => Box::new(Binary),
+            Format::Framed => {
+                let units = units.into_iter()
+                    .chain(std::iter::repeat(String::new()));
+                let dimensions = names.into_iter()
+                    .zip(units)
+                    .map(|(name, unit)| Dimension { name, unit })
+                    .collect();
+                Box::new(Framed { dimensions })
+            }
+        }
+    }
+}
+
+/// Space-separated values, one sample per line.
+pub struct Ascii {
+    /// Append the time and the sequence number after the values.
+    pub stamps: bool,
+}
+
+impl Serializer for Ascii {
+    fn serialize(&self, sample: &Sample, out: &mut Vec<u8>) {
+        let mut line = String::new();
+        for v in &sample.values {
+            write!(line, "{} ", v).unwrap();
+        }
+        if self.stamps {
+            write!(line, "{} {} ", sample.time, sample.seq).unwrap();
+        }
+        line.push('\n');
+        out.extend_from_slice(line.as_bytes());
+    }
+}
+
+/// One JSON object per line, keyed by dimension name.
+pub struct JsonLines {
+    names: Vec<String>,
+}
+
+impl Serializer for JsonLines {
+    fn serialize(&self, sample: &Sample, out: &mut Vec<u8>) {
+        let mut line = String::from("{");
+        for (name, v) in self.names.iter().zip(sample.values.iter()) {
+            write!(line, "\"{}\":{},", name, json_number(*v)).unwrap();
+        }
+        writeln!(line,
+                 "\"t\":{},\"seq\":{}}}",
+                 json_number(sample.time),
+                 sample.seq)
+            .unwrap();
+        out.extend_from_slice(line.as_bytes());
+    }
+}
+
+/// JSON has no representation for NaN or infinities.
+fn json_number(v: f64) -> String {
+    if v.is_finite() {
+        v.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+/// Length-prefixed little-endian frames.
+pub struct Binary;
+
+impl Serializer for Binary {
+    fn serialize(&self, sample: &Sample, out: &mut Vec<u8>) {
+        let len = 8 + 8 + 4 * sample.values.len();
+        out.extend_from_slice(BINARY_MAGIC);
+        out.extend_from_slice(&(len as u16).to_le_bytes());
+        out.extend_from_slice(&sample.seq.to_le_bytes());
+        out.extend_from_slice(&sample.time.to_le_bytes());
+        for v in &sample.values {
+            out.extend_from_slice(&(*v as f32).to_le_bytes());
+        }
+    }
+}
+
+/// A header describing the dimensions, then fixed-size frames with a CRC.
+pub struct Framed {
+    dimensions: Vec<Dimension>,
+}
+
+impl Serializer for Framed {
+    fn serialize(&self, sample: &Sample, out: &mut Vec<u8>) {
+        framed::frame(sample, out);
+    }
+
+    fn header(&self, out: &mut Vec<u8>) {
+        framed::header(&self.dimensions, out);
+    }
+}
+
+/// An OSC message per sample, for visualizers such as Max, Pure Data or
+/// TouchDesigner: the values as 32-bit floats, sent to `address`.
+pub struct Osc {
+    pub address: String,
+}
+
+impl Serializer for Osc {
+    fn serialize(&self, sample: &Sample, out: &mut Vec<u8>) {
+        osc_string(&self.address, out);
+        let tags: String = Some(',')
+            .into_iter()
+            .chain(sample.values.iter().map(|_| 'f'))
+            .collect();
+        osc_string(&tags, out);
+        for v in &sample.values {
+            out.extend_from_slice(&(*v as f32).to_be_bytes());
+        }
+    }
+}
+
+/// OSC strings end with a NUL and are padded to a multiple of 4 bytes.
+fn osc_string(s: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(s.as_bytes());
+    let padding = 4 - s.len() % 4;
+    out.extend(std::iter::repeat(0).take(padding));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::framed::{Decoder, Frame};
+    use crate::sample::Sample;
+    use super::{Format, Osc, Serializer};
+
+    fn bytes(format: Format, stamps: bool, sample: &Sample) -> Vec<u8> {
+        let names = vec!["x".to_string(), "y".to_string()];
+        let mut out = Vec::new();
+        format.serializer(names, Vec::new(), stamps)
+            .serialize(sample, &mut out);
+        out
+    }
+
+    #[test]
+    fn writes_ascii_as_esp_parses_it() {
+        let sample = Sample::new(7, 1.5, vec![320.0, 240.5]);
+        assert_eq!(bytes(Format::Ascii, false, &sample),
+                   &b"320 240.5 \n"[..]);
+        assert_eq!(bytes(Format::Ascii, true, &sample),
+                   &b"320 240.5 1.5 7 \n"[..]);
+    }
+
+    #[test]
+    fn writes_json_lines_with_null_for_nan() {
+        let sample = Sample::new(3, 0.25, vec![1.0, f64::NAN]);
+        assert_eq!(bytes(Format::Json, false, &sample),
+                   &b"{\"x\":1,\"y\":null,\"t\":0.25,\"seq\":3}\n"[..]);
+    }
+
+    #[test]
+    fn writes_binary_frames() {
+        let sample = Sample::new(1, 2.0, vec![0.5, -1.0]);
+        let mut expected = b"EV".to_vec();
+        expected.extend_from_slice(&24u16.to_le_bytes());
+        expected.extend_from_slice(&1u64.to_le_bytes());
+        expected.extend_from_slice(&2.0f64.to_le_bytes());
+        expected.extend_from_slice(&0.5f32.to_le_bytes());
+        expected.extend_from_slice(&(-1.0f32).to_le_bytes());
+        assert_eq!(bytes(Format::Binary, false, &sample), expected);
+    }
+
+    #[test]
+    fn starts_framed_streams_with_the_dimensions() {
+        let serializer = Format::Framed.serializer(vec!["x".to_string(),
+                                                        "angle".to_string()],
+                                                   vec!["px".to_string()],
+                                                   false);
+        let mut out = Vec::new();
+        serializer.header(&mut out);
+        serializer.serialize(&Sample::new(1, 2.0, vec![0.5, 90.0]), &mut out);
+        let mut decoder = Decoder::new();
+        decoder.feed(&out);
+        let units: Vec<&str> = match decoder.decode() {
+            Some(Frame::Header(ref dimensions)) => {
+                dimensions.iter().map(|d| d.unit.as_str()).collect()
+            }
+            frame => panic!("{:?} instead of the header", frame),
+        };
+        assert_eq!(units, ["px", ""]);
+        assert_eq!(decoder.decode(),
+                   Some(Frame::Sample(Sample::new(1, 2.0, vec![0.5, 90.0]))));
+    }
+
+    #[test]
+    fn writes_osc_messages() {
+        let osc = Osc { address: "/esp".to_string() };
+        let mut out = Vec::new();
+        osc.serialize(&Sample::new(7, 1.5, vec![1.0, -2.5]), &mut out);
+        let mut expected = b"/esp\0\0\0\0,ff\0".to_vec();
+        expected.extend_from_slice(&1.0f32.to_be_bytes());
+        expected.extend_from_slice(&(-2.5f32).to_be_bytes());
+        assert_eq!(out, expected);
+    }
+}
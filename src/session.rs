@@ -0,0 +1,248 @@
+//! Recording of sessions, and their replay.
+//!
+//! `--session DIR` saves every captured frame to `DIR`, with what users did
+//! while it was on screen: selections, key presses and parameter changes.
+//! `esp-vision replay DIR` runs the session again with the same options,
+//! frames and events, and the recorded capture times, so that the tracker
+//! sees exactly what it saw then.
+//!
+//! `DIR/args` holds the command line, one argument per line. `DIR/events`
+//! holds one event per line, after the index of the frame it happened on:
+//!
+//! ```text
+//! 0 time 0.000
+//! 0 select 120 80 40 60
+//! 57 key 114
+//! 90 param s_min 60
+//! ```
+use crate::params::{self, VisionParams};
+use rust_vision::{Mat, Rect, imwrite};
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the subcommand.
+pub const REPLAY_COMMAND: &str = "replay";
+/// Options of the recorded command line that don't apply to a replay.
+const NOT_REPLAYED: [&str; 6] = ["session", "replay", "input", "speed",
+                                 "frame-delay", "loop"];
+
+/// Something users did.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A region selected with the mouse.
+    Select(Rect),
+    /// A key pressed, as returned by `show`.
+    Key(i32),
+    /// A parameter changed, by the control channel or a trackbar.
+    Param(String, f64),
+}
+
+/// `SessionRecorder` writes a session to a directory.
+pub struct SessionRecorder {
+    dir: PathBuf,
+    events: BufWriter<File>,
+    /// Number of frames saved so far.
+    frames: u64,
+    params: Option<VisionParams>,
+}
+
+impl SessionRecorder {
+    /// Creates `dir` and records `args`, the command line without the
+    /// program name, into it.
+    pub fn create(dir: &str,
+                  args: &[String])
+                  -> Result<SessionRecorder, String> {
+        let error = |e: ::std::io::Error| {
+            format!("cannot record the session to {}: {}", dir, e)
+        };
+        fs::create_dir_all(dir).map_err(&error)?;
+        let dir = PathBuf::from(dir);
+        let mut file = File::create(dir.join("args")).map_err(&error)?;
+        for arg in args {
+            writeln!(file, "{}", arg).map_err(&error)?;
+        }
+        let events = File::create(dir.join("events")).map_err(&error)?;
+        info!("Recording the session to {}", dir.display());
+        Ok(SessionRecorder {
+            dir,
+            events: BufWriter::new(events),
+            frames: 0,
+            params: None,
+        })
+    }
+
+    /// Saves `frame`, captured `time` after the start and processed with
+    /// `params`, which are recorded where they changed.
+    pub fn frame(&mut self,
+                 frame: &Mat,
+                 time: Duration,
+                 params: &VisionParams)
+                 -> Result<(), String> {
+        let path = frame_path(&self.dir, self.frames);
+        if !imwrite(&path.to_string_lossy(), frame) {
+            return Err(format!("cannot save {}", path.display()));
+        }
+        self.frames += 1;
+        self.write(&format!("time {:.6}", time.as_secs_f64()))?;
+        for name in &params::NAMES {
+            let value = params.value(name);
+            if self.params.as_ref().map(|p| p.value(name)) != Some(value) {
+                self.event(&Event::Param(name.to_string(), value.unwrap()))?;
+            }
+        }
+        self.params = Some(params.clone());
+        Ok(())
+    }
+
+    /// Records `event` as happening on the last saved frame.
+    pub fn event(&mut self, event: &Event) -> Result<(), String> {
+        let line = match *event {
+            Event::Select(r) => {
+                format!("select {} {} {} {}", r.x, r.y, r.width, r.height)
+            }
+            Event::Key(key) => format!("key {}", key),
+            Event::Param(ref name, value) => {
+                format!("param {} {}", name, value)
+            }
+        };
+        self.write(&line)
+    }
+
+    fn write(&mut self, line: &str) -> Result<(), String> {
+        // Events are flushed right away so that a crash, which may be what
+        // the session is recorded for, keeps them.
+        let frame = self.frames.saturating_sub(1);
+        writeln!(self.events, "{} {}", frame, line)
+            .and_then(|_| self.events.flush())
+            .map_err(|e| format!("cannot record the session: {}", e))
+    }
+}
+
+/// `Session` is a recorded session, to replay.
+pub struct Session {
+    /// The capture time of every frame.
+    times: Vec<Duration>,
+    /// The events, with the index of their frame, in order.
+    events: Vec<(u64, Event)>,
+}
+
+impl Session {
+    pub fn load(dir: &str) -> Result<Session, String> {
+        let path = Path::new(dir).join("events");
+        let mut text = String::new();
+        File::open(&path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let mut session = Session {
+            times: Vec::new(),
+            events: Vec::new(),
+        };
+        for (n, line) in text.lines().enumerate() {
+            session.parse(line).map_err(|e| {
+                format!("{}:{}: {}", path.display(), n + 1, e)
+            })?;
+        }
+        Ok(session)
+    }
+
+    fn parse(&mut self, line: &str) -> Result<(), String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| -> Result<f64, String> {
+            words.get(i)
+                .and_then(|w| w.parse().ok())
+                .ok_or_else(|| format!("invalid event `{}`", line))
+        };
+        let frame = number(0)? as u64;
+        let event = match words.get(1) {
+            Some(&"time") => {
+                self.times.push(Duration::from_secs_f64(number(2)?));
+                return Ok(());
+            }
+            Some(&"select") => {
+                Event::Select(Rect::new(number(2)? as i32,
+                                        number(3)? as i32,
+                                        number(4)? as i32,
+                                        number(5)? as i32))
+            }
+            Some(&"key") => Event::Key(number(2)? as i32),
+            Some(&"param") if words.len() == 4 => {
+                Event::Param(words[2].to_string(), number(3)?)
+            }
+            _ => return Err(format!("invalid event `{}`", line)),
+        };
+        self.events.push((frame, event));
+        Ok(())
+    }
+
+    /// When the frame at `index` was captured, counting from the start.
+    pub fn time(&self, index: u64) -> Option<Duration> {
+        self.times.get(index as usize).cloned()
+    }
+
+    /// The events that happened on the frame at `index`.
+    pub fn events(&self, index: u64) -> Vec<&Event> {
+        self.events
+            .iter()
+            .filter(|&&(frame, _)| frame == index)
+            .map(|(_, event)| event)
+            .collect()
+    }
+
+    /// The key pressed on the frame at `index`, if any.
+    pub fn key(&self, index: u64) -> Option<i32> {
+        self.events(index).into_iter().filter_map(|event| {
+            match *event {
+                Event::Key(key) => Some(key),
+                _ => None,
+            }
+        }).next()
+    }
+}
+
+/// The input spec reading back the frames of the session in `dir`.
+pub fn frames(dir: &str) -> String {
+    format!("images:{}/frame-*.png", dir)
+}
+
+/// The command line of `esp-vision replay DIR [options]`: that of the
+/// session, with `--replay DIR` and any further `options`.
+pub fn replay_args(args: &[String]) -> Result<Vec<String>, String> {
+    let dir = args.get(2)
+        .ok_or_else(|| format!("Usage: {} {} DIR [options]",
+                               args[0],
+                               REPLAY_COMMAND))?;
+    let path = Path::new(dir).join("args");
+    let mut text = String::new();
+    File::open(&path)
+        .and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    let mut replayed = vec![args[0].clone()];
+    let mut recorded = text.lines();
+    while let Some(arg) = recorded.next() {
+        if let Some(name) = arg.strip_prefix("--") {
+            if NOT_REPLAYED.iter()
+                .any(|n| name.starts_with(&format!("{}=", n))) {
+                continue;
+            }
+            if NOT_REPLAYED.contains(&name) {
+                // `--loop` is the only flag; the others take a value.
+                if name != "loop" {
+                    recorded.next();
+                }
+                continue;
+            }
+        }
+        replayed.push(arg.to_string());
+    }
+    replayed.push("--replay".to_string());
+    replayed.push(dir.clone());
+    replayed.extend_from_slice(&args[3..]);
+    Ok(replayed)
+}
+
+fn frame_path(dir: &Path, index: u64) -> PathBuf {
+    dir.join(format!("frame-{:06}.png", index))
+}
@@ -0,0 +1,69 @@
+//! Stopping on SIGINT and SIGTERM.
+//!
+//! Rather than dying mid-write, the application finishes the frame at hand
+//! when it is asked to stop, then leaves the main loop as if `q` was
+//! pressed: the cameras are released, the samples queued for ESP are
+//! written, the recordings are closed and a summary is logged. A second
+//! signal exits right away, for when stopping hangs.
+use crate::net::Net;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Exit code of a process stopped by a second signal, as shells report
+/// SIGINT.
+const FORCED_EXIT: i32 = 130;
+
+/// Whether the application was asked to stop.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    /// Listens for SIGINT, and SIGTERM on Unix, on the network runtime.
+    pub fn listen(net: &Net) -> Shutdown {
+        let shutdown = Shutdown::default();
+        let requested = shutdown.requested.clone();
+        net.spawn(async move {
+            loop {
+                let signal = wait().await;
+                if requested.swap(true, Ordering::SeqCst) {
+                    warn!("Stopping now on {}", signal);
+                    process::exit(FORCED_EXIT);
+                }
+                info!("Stopping on {}; press Ctrl-C again to force it",
+                      signal);
+            }
+        });
+        shutdown
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+/// Waits for the next signal to stop, and returns its name.
+#[cfg(unix)]
+async fn wait() -> &'static str {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut term = match signal(SignalKind::terminate()) {
+        Ok(term) => term,
+        Err(e) => {
+            warn!("Cannot handle SIGTERM: {}", e);
+            tokio::signal::ctrl_c().await.ok();
+            return "SIGINT";
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+        _ = term.recv() => "SIGTERM",
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait() -> &'static str {
+    tokio::signal::ctrl_c().await.ok();
+    "Ctrl-C"
+}
@@ -0,0 +1,348 @@
+//! Destinations for samples.
+use crate::latency::Latency;
+use crate::net::Net;
+use crate::pipeline::Timings;
+use crate::sample::Sample;
+use crate::serialize::Serializer;
+use std::cell::Cell;
+use std::io;
+use std::net::UdpSocket;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// Samples waiting to be written by a `TcpSink`.
+const SEND_QUEUE: usize = 64;
+/// How long dropping a `TcpSink` waits for the queued samples to be written.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+/// Time between attempts of a reconnecting `TcpSink` to connect.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// `Sink` consumes the feature stream.
+pub trait Sink {
+    fn send(&mut self, sample: &Sample) -> io::Result<()>;
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        (**self).send(sample)
+    }
+}
+
+/// Tests collect what is sent in a vector.
+#[cfg(test)]
+impl Sink for Vec<Sample> {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        self.push(sample.clone());
+        Ok(())
+    }
+}
+
+/// `TcpSink` writes samples to a TCP connection, e.g. ESP's
+/// `TcpInputStream`. Sending only serializes the sample and queues it for
+/// the network runtime; a full queue drops it.
+pub struct TcpSink {
+    serializer: Box<dyn Serializer>,
+    /// The serialized samples and their times. `None` once closed, when
+    /// dropped.
+    queue: Option<Sender<(Vec<u8>, f64)>>,
+    /// Whether the last sample written failed.
+    failed: Arc<AtomicBool>,
+    writer: Option<JoinHandle<()>>,
+    runtime: Handle,
+}
+
+impl TcpSink {
+    /// Connects to `addr` on `net`, which writes the samples from then on,
+    /// and reports the time each write takes to `timings`. With `latency`,
+    /// the latency of every sample is measured, and so is the round trip
+    /// of what the server echoes.
+    pub fn connect(net: &Net,
+                   addr: &str,
+                   serializer: Box<dyn Serializer>,
+                   timings: Timings,
+                   latency: Option<Arc<Latency>>)
+                   -> io::Result<TcpSink> {
+        let mut stream = net.block_on(TcpStream::connect(addr))?;
+        info!("Connected to ESP at {}", addr);
+        let mut header = Vec::new();
+        serializer.header(&mut header);
+        net.block_on(stream.write_all(&header))?;
+        let (mut reader, mut stream) = stream.into_split();
+        if let Some(latency) = latency.clone() {
+            net.spawn(async move {
+                let mut buf = [0; 4096];
+                while let Ok(n) = reader.read(&mut buf).await {
+                    if n == 0 {
+                        break;
+                    }
+                    latency.echoed(n);
+                }
+            });
+        }
+        let (queue, mut samples) = mpsc::channel::<(Vec<u8>, f64)>(SEND_QUEUE);
+        let failed = Arc::new(AtomicBool::new(false));
+        let failures = failed.clone();
+        let writer = net.spawn(async move {
+            while let Some((buf, time)) = samples.recv().await {
+                let start = Instant::now();
+                let result = stream.write_all(&buf).await;
+                timings.lock().unwrap().send = start.elapsed();
+                if let (Some(latency), true) = (&latency, result.is_ok()) {
+                    latency.written(time, buf.len());
+                }
+                // Only changes are logged, not every failed sample.
+                if failures.swap(result.is_err(), Ordering::SeqCst) !=
+                   result.is_err() {
+                    match result {
+                        Ok(()) => info!("Sending to ESP again"),
+                        Err(e) => warn!("Cannot send to ESP: {}", e),
+                    }
+                }
+            }
+        });
+        Ok(TcpSink {
+            serializer,
+            queue: Some(queue),
+            failed,
+            writer: Some(writer),
+            runtime: net.handle(),
+        })
+    }
+
+    /// Like `connect`, but never fails: the connection is made by the
+    /// network runtime, and made again whenever it is lost, at most every
+    /// `RECONNECT_DELAY`. Samples sent while disconnected are dropped, and
+    /// fail as a write error would.
+    pub fn reconnecting(net: &Net,
+                        addr: &str,
+                        serializer: Box<dyn Serializer>)
+                        -> TcpSink {
+        let mut header = Vec::new();
+        serializer.header(&mut header);
+        let addr = addr.to_string();
+        let (queue, mut samples) = mpsc::channel::<(Vec<u8>, f64)>(SEND_QUEUE);
+        // Failed until connected.
+        let failed = Arc::new(AtomicBool::new(true));
+        let failures = failed.clone();
+        let writer = net.spawn(async move {
+            let mut stream: Option<TcpStream> = None;
+            let mut next = time::Instant::now();
+            let mut down = false;
+            while let Some((buf, _)) = samples.recv().await {
+                if stream.is_none() && time::Instant::now() >= next {
+                    next = time::Instant::now() + RECONNECT_DELAY;
+                    match open(&addr, &header).await {
+                        Ok(s) => {
+                            info!("Connected to {}", addr);
+                            stream = Some(s);
+                            down = false;
+                        }
+                        // Only the first of the failed attempts is logged.
+                        Err(e) if !down => {
+                            warn!("Cannot connect to {}: {}; retrying every \
+                                   {} s",
+                                  addr,
+                                  e,
+                                  RECONNECT_DELAY.as_secs());
+                            down = true;
+                        }
+                        Err(_) => {}
+                    }
+                }
+                if let Some(ref mut s) = stream {
+                    if let Err(e) = s.write_all(&buf).await {
+                        warn!("Lost the connection to {}: {}", addr, e);
+                        stream = None;
+                        down = true;
+                    }
+                }
+                failures.store(stream.is_none(), Ordering::SeqCst);
+            }
+        });
+        TcpSink {
+            serializer,
+            queue: Some(queue),
+            failed,
+            writer: Some(writer),
+            runtime: net.handle(),
+        }
+    }
+}
+
+/// A connection to `addr` that has been sent `header`, or an error once
+/// `RECONNECT_DELAY` has passed.
+async fn open(addr: &str, header: &[u8]) -> io::Result<TcpStream> {
+    let connect = async {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(header).await?;
+        Ok(stream)
+    };
+    time::timeout(RECONNECT_DELAY, connect)
+        .await
+        .unwrap_or_else(|_| {
+            Err(io::Error::new(io::ErrorKind::TimedOut,
+                               "the connection timed out"))
+        })
+}
+
+impl Sink for TcpSink {
+    /// Queues `sample`, and fails if the last one written did, so that
+    /// errors still reach the caller, one sample late.
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let mut buf = Vec::new();
+        self.serializer.serialize(sample, &mut buf);
+        let queue = self.queue.as_ref().unwrap();
+        let queued = match queue.try_send((buf, sample.time)) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                Err(io::Error::new(io::ErrorKind::WouldBlock,
+                                   "the send queue is full"))
+            }
+            Err(TrySendError::Closed(_)) => {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe,
+                                   "the connection is closed"))
+            }
+        };
+        if self.failed.load(Ordering::SeqCst) {
+            return Err(io::Error::new(io::ErrorKind::BrokenPipe,
+                                      "the last sample could not be sent"));
+        }
+        queued
+    }
+}
+
+impl Drop for TcpSink {
+    /// Waits a little for the queued samples to be written.
+    fn drop(&mut self) {
+        // Closing the queue lets the writer finish once it is empty.
+        self.queue = None;
+        if let Some(writer) = self.writer.take() {
+            self.runtime
+                .block_on(time::timeout(FLUSH_TIMEOUT, writer))
+                .ok();
+        }
+    }
+}
+
+/// What a `Metered` sink has seen so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SinkStats {
+    /// Number of samples sent successfully.
+    pub sent: u64,
+    /// Whether the last send succeeded.
+    pub connected: bool,
+}
+
+/// `Metered` keeps `SinkStats` of the wrapped sink, for display.
+pub struct Metered<S> {
+    inner: S,
+    stats: Rc<Cell<SinkStats>>,
+}
+
+impl<S: Sink> Metered<S> {
+    /// Wraps `inner`, which is expected to be connected already.
+    pub fn new(inner: S, stats: Rc<Cell<SinkStats>>) -> Metered<S> {
+        stats.set(SinkStats {
+            sent: 0,
+            connected: true,
+        });
+        Metered { inner, stats }
+    }
+}
+
+impl<S: Sink> Sink for Metered<S> {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let result = self.inner.send(sample);
+        let mut stats = self.stats.get();
+        stats.connected = result.is_ok();
+        if result.is_ok() {
+            stats.sent += 1;
+        }
+        self.stats.set(stats);
+        result
+    }
+}
+
+/// `Gate` drops the samples sent while it is paused, so gestures can be
+/// left out of the stream while tracking goes on.
+pub struct Gate<S> {
+    inner: S,
+    paused: Arc<AtomicBool>,
+}
+
+impl<S: Sink> Gate<S> {
+    pub fn new(inner: S, paused: Arc<AtomicBool>) -> Gate<S> {
+        Gate { inner, paused }
+    }
+}
+
+impl<S: Sink> Sink for Gate<S> {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        if self.paused.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.inner.send(sample)
+    }
+}
+
+/// `UdpSink` sends every sample as a datagram of its own. Nothing is queued:
+/// a sample that can't be sent right away is dropped.
+pub struct UdpSink {
+    socket: UdpSocket,
+    serializer: Box<dyn Serializer>,
+}
+
+impl UdpSink {
+    /// Sends to `addr` from an ephemeral port.
+    pub fn connect(addr: &str,
+                   serializer: Box<dyn Serializer>)
+                   -> io::Result<UdpSink> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        let mut header = Vec::new();
+        serializer.header(&mut header);
+        if !header.is_empty() {
+            socket.send(&header)?;
+        }
+        socket.set_nonblocking(true)?;
+        Ok(UdpSink { socket, serializer })
+    }
+}
+
+impl Sink for UdpSink {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let mut buf = Vec::new();
+        self.serializer.serialize(sample, &mut buf);
+        self.socket.send(&buf).map(|_| ())
+    }
+}
+
+/// `Tee` sends every sample to two sinks, e.g. to ESP and to a log.
+pub struct Tee<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: Sink, B: Sink> Tee<A, B> {
+    pub fn new(first: A, second: B) -> Tee<A, B> {
+        Tee { first, second }
+    }
+}
+
+impl<A: Sink, B: Sink> Sink for Tee<A, B> {
+    /// Sends to both sinks, even if the first fails.
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let first = self.first.send(sample);
+        let second = self.second.send(sample);
+        first.and(second)
+    }
+}
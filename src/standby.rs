@@ -0,0 +1,169 @@
+//! Motion-triggered start and stop, for unattended installations.
+//!
+//! A kiosk spends most of its time with nobody in front of it. With
+//! `--arm-zone X,Y,W,H`, tracking waits, armed, until something moves in
+//! that zone of the frame: meanwhile only a small copy of the zone is
+//! compared to its background, and nothing is tracked or sent. Motion
+//! starts tracking the way an unattended start would, from the largest face
+//! with `--face-cascade` or a hand with `--skin-zone`, and otherwise from the
+//! moving blob itself, whose colors CAMShift learns. Trackers that need no
+//! selection start on the whole frame. Once nothing moved in the zone for
+//! `--idle-after SECS` (30), tracking stops and the zone is armed again.
+use rust_vision::*;
+use std::time::{Duration, Instant};
+
+/// Width the zone is downscaled to before looking for motion.
+const WIDTH: i32 = 80;
+/// Number of frames the background model of the zone remembers.
+const HISTORY: i32 = 200;
+/// Frames the background model learns from before motion counts.
+const WARMUP: u32 = 10;
+/// Share of the zone that has to move to start tracking.
+const MIN_MOTION: f64 = 0.02;
+/// Foreground pixels are 255.
+const FOREGROUND: f64 = 200.0;
+/// How long nothing has to move in the zone to stop, unless
+/// `--idle-after` says otherwise.
+pub const DEFAULT_IDLE_AFTER: Duration = Duration::from_secs(30);
+
+/// What changed with a frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    /// Something moved in the armed zone; start tracking, from the bounding
+    /// box of the motion unless a detector finds better.
+    Wake(Rect),
+    /// Nothing moved for a while; stop tracking and arm again.
+    Sleep,
+}
+
+/// `Standby` watches the zone and decides when to track.
+pub struct Standby {
+    zone: Rect,
+    idle_after: Duration,
+    model: BackgroundSubtractorMOG2,
+    kernel: Mat,
+    /// Frames the model has seen, up to `WARMUP`.
+    seen: u32,
+    armed: bool,
+    last_motion: Option<Instant>,
+}
+
+impl Standby {
+    pub fn new(zone: Rect, idle_after: Duration) -> Standby {
+        Standby {
+            zone,
+            idle_after,
+            model: BackgroundSubtractorMOG2::new(HISTORY, 16.0, false),
+            kernel: get_structuring_element(MorphShapes::Ellipse,
+                                            Size2i::new(3, 3)),
+            seen: 0,
+            armed: true,
+            last_motion: None,
+        }
+    }
+
+    /// Whether tracking waits for motion.
+    pub fn armed(&self) -> bool {
+        self.armed
+    }
+
+    pub fn zone(&self) -> Rect {
+        self.zone
+    }
+
+    /// Feeds the (BGR) `frame` captured at `now`.
+    pub fn update(&mut self, frame: &Mat, now: Instant) -> Option<Transition> {
+        let motion = self.motion(frame);
+        self.step(motion, now)
+    }
+
+    /// Moves on with the bounding box of the `motion` in the zone, if
+    /// anything moved, at `now`.
+    fn step(&mut self,
+            motion: Option<Rect>,
+            now: Instant)
+            -> Option<Transition> {
+        if let Some(rect) = motion {
+            self.last_motion = Some(now);
+            if self.armed {
+                self.armed = false;
+                return Some(Transition::Wake(rect));
+            }
+            return None;
+        }
+        let idle_after = self.idle_after;
+        let still = self.last_motion
+            .map_or(true, |t| now.saturating_duration_since(t) >= idle_after);
+        if !self.armed && still {
+            self.armed = true;
+            return Some(Transition::Sleep);
+        }
+        None
+    }
+
+    /// The bounding box of what moved in the zone of `frame`, in frame
+    /// coordinates, if enough of it did.
+    fn motion(&mut self, frame: &Mat) -> Option<Rect> {
+        let zone = crate::tracker::clip(self.zone, frame.cols, frame.rows);
+        if zone.width == 0 || zone.height == 0 {
+            return None;
+        }
+        let height = (zone.height * WIDTH / zone.width).max(1);
+        let small = frame.roi(zone).resize(Size2i::new(WIDTH, height));
+        // OpenCV picks the learning rate from the history.
+        let mask = self.model
+            .apply(&small, -1.0)
+            .threshold(FOREGROUND, 255.0, ThresholdTypes::Binary)
+            .morphology_ex(MorphTypes::Open, &self.kernel, 1);
+        if self.seen < WARMUP {
+            self.seen += 1;
+            return None;
+        }
+        let moving = mask.count_non_zero() as f64 /
+                     f64::from(WIDTH * height);
+        if moving < MIN_MOTION {
+            return None;
+        }
+        let blob = mask.find_contours(RetrievalModes::External,
+                                      ContourApproximationModes::Simple)
+            .iter()
+            .map(|c| (contour_area(c), bounding_rect(c)))
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .map(|(_, r)| r)?;
+        // Back to the full frame.
+        let scale = f64::from(zone.width) / f64::from(WIDTH);
+        let s = |v: i32| (f64::from(v) * scale).round() as i32;
+        Some(Rect::new(zone.x + s(blob.x),
+                       zone.y + s(blob.y),
+                       s(blob.width).max(1),
+                       s(blob.height).max(1)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_vision::Rect;
+    use std::time::{Duration, Instant};
+    use super::{Standby, Transition};
+
+    #[test]
+    fn wakes_on_motion_and_sleeps_when_still() {
+        let zone = Rect::new(0, 0, 100, 100);
+        let mut standby = Standby::new(zone, Duration::from_secs(5));
+        let now = Instant::now();
+        let blob = Rect::new(10, 10, 20, 20);
+        assert_eq!(standby.step(None, now), None);
+        assert!(standby.armed());
+        assert_eq!(standby.step(Some(blob), now),
+                   Some(Transition::Wake(blob)));
+        assert!(!standby.armed());
+        // More motion keeps it awake.
+        let later = now + Duration::from_secs(4);
+        assert_eq!(standby.step(Some(blob), later), None);
+        assert_eq!(standby.step(None, later + Duration::from_secs(4)), None);
+        assert_eq!(standby.step(None, later + Duration::from_secs(5)),
+                   Some(Transition::Sleep));
+        assert!(standby.armed());
+        assert_eq!(standby.step(None, later + Duration::from_secs(6)), None);
+    }
+}
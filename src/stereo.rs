@@ -0,0 +1,253 @@
+//! Metric depth from a pair of calibrated cameras.
+//!
+//! `--stereo rig.toml` takes two `--input`s, the left camera first, with the
+//! target selected in both. Instead of the features of both views, it sends
+//! those of the left view followed by `z`: the distance of the target from
+//! the left camera along its optical axis, triangulated from the centroids
+//! in both views, in the unit of the translation between the cameras. The
+//! centroids are taken back to the frames as the cameras captured them,
+//! unmirrored and before `[[preprocess]]`, which the intrinsics describe.
+//! While either view has lost the target, `z` is NaN. The rig is described
+//! by the intrinsics of both cameras and their relative pose:
+//!
+//! ```toml
+//! left = "left.toml"
+//! right = "right.toml"
+//! rotation = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+//! translation = [-0.12, 0.0, 0.0]
+//! ```
+//!
+//! The intrinsics are read relative to the rig file; see the
+//! [`camera`](../camera/index.html) module. `rotation` (row by row, the
+//! identity by default) and `translation` take points from the frame of the
+//! left camera to that of the right one, as `cv::stereoCalibrate` returns
+//! them: for parallel cameras 12 cm apart, the translation above gives `z`
+//! in meters.
+use crate::camera::Intrinsics;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+/// Iterations removing the lens distortion from a point.
+const UNDISTORT_ITERATIONS: usize = 5;
+
+/// The rig file as written.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RigFile {
+    left: String,
+    right: String,
+    #[serde(default = "identity")]
+    rotation: [f64; 9],
+    translation: [f64; 3],
+}
+
+fn identity() -> [f64; 9] {
+    [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+}
+
+/// `Rig` triangulates points seen by both cameras.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rig {
+    left: Intrinsics,
+    right: Intrinsics,
+    rotation: [f64; 9],
+    translation: [f64; 3],
+}
+
+impl Rig {
+    pub fn load(path: &str) -> Result<Rig, String> {
+        let mut text = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| format!("cannot read stereo rig {}: {}", path, e))?;
+        let file: RigFile = toml::from_str(&text)
+            .map_err(|e| format!("invalid stereo rig {}: {}", path, e))?;
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let intrinsics = |name: &str| {
+            Intrinsics::load(&dir.join(name).to_string_lossy())
+        };
+        Ok(Rig {
+            left: intrinsics(&file.left)?,
+            right: intrinsics(&file.right)?,
+            rotation: file.rotation,
+            translation: file.translation,
+        })
+    }
+
+    /// The depth of the point at pixel `left` in the left view and `right`
+    /// in the right one, or `None` if the rays don't meet in front of the
+    /// cameras.
+    pub fn depth(&self, left: [f64; 2], right: [f64; 2]) -> Option<f64> {
+        self.triangulate(left, right).map(|p| p[2])
+    }
+
+    /// The point at pixel `left` in the left view and `right` in the right
+    /// one, in the frame of the left camera: the midpoint of the shortest
+    /// segment between the two rays.
+    fn triangulate(&self, left: [f64; 2], right: [f64; 2]) -> Option<[f64; 3]> {
+        let d1 = ray(&self.left, left);
+        // The right camera's center and ray, in the frame of the left one.
+        let o2 = scale(transpose_mul(&self.rotation, self.translation), -1.0);
+        let d2 = transpose_mul(&self.rotation, ray(&self.right, right));
+        let w = scale(o2, -1.0);
+        let (a, b, c) = (dot(d1, d1), dot(d1, d2), dot(d2, d2));
+        let (d, e) = (dot(d1, w), dot(d2, w));
+        let denominator = a * c - b * b;
+        if denominator.abs() < 1e-12 {
+            // Parallel rays.
+            return None;
+        }
+        let s = (b * e - c * d) / denominator;
+        let t = (a * e - b * d) / denominator;
+        let p1 = scale(d1, s);
+        let p2 = add(o2, scale(d2, t));
+        let p = scale(add(p1, p2), 0.5);
+        if s > 0.0 && t > 0.0 && p[2] > 0.0 {
+            Some(p)
+        } else {
+            None
+        }
+    }
+}
+
+/// The direction of the ray through the pixel `p` of the camera `k`, in
+/// its frame, with the lens distortion removed the way OpenCV's
+/// `undistortPoints` does.
+fn ray(k: &Intrinsics, p: [f64; 2]) -> [f64; 3] {
+    let (x0, y0) = ((p[0] - k.cx) / k.fx, (p[1] - k.cy) / k.fy);
+    let d = |i: usize| k.distortion.get(i).cloned().unwrap_or(0.0);
+    let (k1, k2, p1, p2, k3) = (d(0), d(1), d(2), d(3), d(4));
+    let (mut x, mut y) = (x0, y0);
+    for _ in 0..UNDISTORT_ITERATIONS {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+        let dx = 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+        let dy = p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+        x = (x0 - dx) / radial;
+        y = (y0 - dy) / radial;
+    }
+    [x, y, 1.0]
+}
+
+/// `mᵀ v`, with the 3x3 matrix `m` given row by row.
+fn transpose_mul(m: &[f64; 9], v: [f64; 3]) -> [f64; 3] {
+    [m[0] * v[0] + m[3] * v[1] + m[6] * v[2],
+     m[1] * v[0] + m[4] * v[1] + m[7] * v[2],
+     m[2] * v[0] + m[5] * v[1] + m[8] * v[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn scale(a: [f64; 3], s: f64) -> [f64; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::camera::Intrinsics;
+    use crate::config::Config;
+    use crate::preprocess::{Converter, StepSpec};
+    use super::{Rig, identity};
+
+    fn camera() -> Intrinsics {
+        Intrinsics {
+            fx: 600.0,
+            fy: 600.0,
+            cx: 320.0,
+            cy: 240.0,
+            distortion: Vec::new(),
+        }
+    }
+
+    fn project(k: &Intrinsics, p: [f64; 3]) -> [f64; 2] {
+        [k.fx * p[0] / p[2] + k.cx, k.fy * p[1] / p[2] + k.cy]
+    }
+
+    #[test]
+    fn triangulates_with_parallel_cameras() {
+        let rig = Rig {
+            left: camera(),
+            right: camera(),
+            rotation: identity(),
+            translation: [-0.12, 0.0, 0.0],
+        };
+        let point = [0.1, -0.05, 1.5];
+        let right = [point[0] - 0.12, point[1], point[2]];
+        let p = rig.triangulate(project(&rig.left, point),
+                                project(&rig.right, right))
+            .unwrap();
+        for i in 0..3 {
+            assert!((p[i] - point[i]).abs() < 1e-9, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn triangulates_with_converging_cameras() {
+        // The right camera is turned 10 degrees towards the left one.
+        let (sin, cos) = 10f64.to_radians().sin_cos();
+        let rotation = [cos, 0.0, sin, 0.0, 1.0, 0.0, -sin, 0.0, cos];
+        let rig = Rig {
+            left: camera(),
+            right: camera(),
+            rotation,
+            translation: [-0.2, 0.0, 0.0],
+        };
+        let point = [0.05, 0.1, 2.0];
+        let r = rotation;
+        let right = [r[0] * point[0] + r[1] * point[1] + r[2] * point[2] -
+                     0.2,
+                     r[3] * point[0] + r[4] * point[1] + r[5] * point[2],
+                     r[6] * point[0] + r[7] * point[1] + r[8] * point[2]];
+        let z = rig.depth(project(&rig.left, point),
+                          project(&rig.right, right))
+            .unwrap();
+        assert!((z - 2.0).abs() < 1e-9, "{}", z);
+    }
+
+    #[test]
+    fn triangulates_points_of_the_displayed_frames() {
+        let rig = Rig {
+            left: camera(),
+            right: camera(),
+            rotation: identity(),
+            translation: [-0.12, 0.0, 0.0],
+        };
+        let mut config = Config::default();
+        config.preprocess = vec![StepSpec::Crop { rect: [80, 0, 480, 480] },
+                                 StepSpec::Resize { scale: 0.5 }];
+        let converter = Converter::new(&config).unwrap();
+        // Where the 640x480 frames show a pixel: mirrored, cropped, halved.
+        let shown = |p: [f64; 2]| {
+            [(639.0 - p[0] - 80.0 + 0.5) * 0.5 - 0.5, (p[1] + 0.5) * 0.5 - 0.5]
+        };
+        let point = [0.1, -0.05, 1.5];
+        let right = [point[0] - 0.12, point[1], point[2]];
+        let left = shown(project(&rig.left, point));
+        let right = shown(project(&rig.right, right));
+        let z = rig.depth(converter.captured((640, 480), left),
+                          converter.captured((640, 480), right))
+            .unwrap();
+        assert!((z - 1.5).abs() < 1e-9, "{}", z);
+    }
+
+    #[test]
+    fn rejects_points_behind_the_cameras() {
+        let rig = Rig {
+            left: camera(),
+            right: camera(),
+            rotation: identity(),
+            translation: [-0.12, 0.0, 0.0],
+        };
+        // Disparity of the wrong sign.
+        assert_eq!(rig.depth([300.0, 240.0], [340.0, 240.0]), None);
+        // No disparity: the rays are parallel.
+        assert_eq!(rig.depth([300.0, 240.0], [300.0, 240.0]), None);
+    }
+}
@@ -0,0 +1,144 @@
+//! Aligning the samples ESP records with the frames they were measured in,
+//! with `--sync-log FILE` and `--sync-beacons SECS`.
+//!
+//! `--sync-log` writes a CSV line per sample sent: its `seq` and `time`, the
+//! wall-clock time the frame was captured at, as `unix` seconds, and the
+//! number of the `frame` since the start, which is that of the frame in the
+//! `--record` video. Keepalive samples repeat the `seq` and `frame` of the
+//! last sample.
+//!
+//! ESP keeps the values of the samples it records, but not always their
+//! sequence numbers. `--sync-beacons SECS` sends a `sync` dimension, after
+//! all others, that is `0` except in a sample every `SECS` seconds, the
+//! beacon, where it is the number of the beacon: `1`, `2`, ... Beacon
+//! numbers are exact in every format. The `beacon` column of the sync log
+//! gives the sample, the capture time and the frame of every beacon, from
+//! which the samples in between are counted:
+//!
+//! ```text
+//! seq,time,unix,frame,beacon
+//! 299,9.967102,1791020711.402265,301,0
+//! 300,10.000431,1791020711.435594,302,11
+//! ```
+use crate::sample::Sample;
+use crate::sink::Sink;
+use std::cell::Cell;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, BufWriter};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Name of the dimension of the beacons.
+pub const NAME: &str = "sync";
+
+/// The number of the frame the next sample is measured in.
+pub type Frame = Rc<Cell<u64>>;
+
+/// `Beacons` appends the `sync` dimension to the samples it forwards.
+pub struct Beacons<S> {
+    inner: S,
+    interval: f64,
+    /// The time of the next beacon.
+    next: f64,
+    count: u64,
+}
+
+impl<S: Sink> Beacons<S> {
+    /// Sends a beacon every `interval`, starting with the first sample.
+    pub fn new(inner: S, interval: Duration) -> Beacons<S> {
+        Beacons {
+            inner,
+            interval: interval.as_secs_f64(),
+            next: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl<S: Sink> Sink for Beacons<S> {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let mut marked = sample.clone();
+        if sample.time >= self.next {
+            self.count += 1;
+            // Skipped beacons are not caught up with.
+            self.next = (sample.time / self.interval).floor() * self.interval +
+                        self.interval;
+            marked.values.push(self.count as f64);
+        } else {
+            marked.values.push(0.0);
+        }
+        self.inner.send(&marked)
+    }
+}
+
+/// `SyncLog` writes the capture time and frame of the samples it is sent to
+/// a CSV file.
+pub struct SyncLog {
+    writer: BufWriter<File>,
+    /// The wall-clock time of the start, in seconds since the epoch.
+    epoch: f64,
+    frame: Frame,
+    /// The index of the beacons in the samples, if they have any.
+    beacon: Option<usize>,
+}
+
+impl SyncLog {
+    /// Creates `path` for samples whose times count from `start`, measured
+    /// in the frame `frame`, and whose value at `beacon`, if any, is the
+    /// beacon.
+    pub fn create(path: &str,
+                  start: Instant,
+                  frame: Frame,
+                  beacon: Option<usize>)
+                  -> io::Result<SyncLog> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "seq,time,unix,frame,beacon")?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(SyncLog {
+            writer,
+            epoch: now.as_secs_f64() - start.elapsed().as_secs_f64(),
+            frame,
+            beacon,
+        })
+    }
+}
+
+impl Sink for SyncLog {
+    fn send(&mut self, sample: &Sample) -> io::Result<()> {
+        let beacon = self.beacon
+            .and_then(|i| sample.values.get(i))
+            .cloned()
+            .unwrap_or(0.0);
+        writeln!(self.writer,
+                 "{},{:.6},{:.6},{},{}",
+                 sample.seq,
+                 sample.time,
+                 self.epoch + sample.time,
+                 self.frame.get(),
+                 beacon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample::Sample;
+    use crate::sink::Sink;
+    use std::time::Duration;
+    use super::Beacons;
+
+    #[test]
+    fn numbers_a_beacon_per_interval() {
+        let mut beacons = Beacons::new(Vec::new(), Duration::from_secs(1));
+        for (seq, &time) in [0.0, 0.4, 0.8, 1.2, 1.6, 3.5, 3.9, 4.0]
+            .iter()
+            .enumerate() {
+            beacons.send(&Sample::new(seq as u64, time, vec![7.0])).unwrap();
+        }
+        let sync: Vec<f64> =
+            beacons.inner.iter().map(|s| s.values[1]).collect();
+        assert_eq!(sync, [1.0, 0.0, 0.0, 2.0, 0.0, 3.0, 0.0, 4.0]);
+    }
+}
@@ -0,0 +1,316 @@
+//! A synthetic input for testing without a camera.
+//!
+//! `--input synthetic:TRAJECTORY` renders a saturated colored disc moving
+//! over a gray background, which is easy for every tracker to follow, along
+//! one of these trajectories:
+//!
+//! - `circle`: once around the center of the frame.
+//! - `line`: from the left edge to the right one and back.
+//! - `bounce`: a ball bouncing off the edges of the frame, the trajectory of
+//!   `--demo`.
+//! - `X,Y;X,Y;...`: through the given points, in pixels, in order.
+//!
+//! The disc moves at a fixed number of frames between points, so that the
+//! same trajectory always renders the same frames, and its position in
+//! every frame is known: tests track it and compare with
+//! `Synthetic::position`.
+use rust_vision::*;
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+/// Size of the frames.
+pub const WIDTH: i32 = 640;
+pub const HEIGHT: i32 = 480;
+/// Radius of the disc, in pixels.
+pub const RADIUS: i32 = 30;
+/// Gray level of the background.
+const BACKGROUND: u8 = 90;
+/// The disc is pure red, in BGR.
+const COLOR: (i32, i32, i32) = (0, 0, 255);
+/// Points on the `circle` trajectory, and frames between two of them.
+const CIRCLE_POINTS: usize = 36;
+const CIRCLE_STEPS: usize = 4;
+/// Frames between two points of the `line` and scripted trajectories.
+const LINE_STEPS: usize = 60;
+const SCRIPT_STEPS: usize = 30;
+/// Frames of one period of `bounce`, after which the ball is back where it
+/// started; it crosses the frame once horizontally and twice vertically.
+const BOUNCE_FRAMES: usize = 200;
+/// Where the ball of `bounce` starts, from the top left of the area its
+/// center can reach.
+const BOUNCE_START: [f64; 2] = [120.0, 60.0];
+
+/// The trajectory of `--demo`.
+pub const DEMO: &str = "bounce";
+
+/// The path the disc follows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trajectory {
+    /// The points passed through, in order.
+    points: Vec<[f64; 2]>,
+    /// Frames from one point to the next.
+    steps: usize,
+}
+
+impl Trajectory {
+    /// Number of frames of one pass along the trajectory.
+    pub fn frames(&self) -> usize {
+        (self.points.len() - 1) * self.steps + 1
+    }
+
+    /// Center of the disc in `frame`, or `None` after the last one.
+    pub fn position(&self, frame: usize) -> Option<[f64; 2]> {
+        if frame >= self.frames() {
+            return None;
+        }
+        let segment = frame / self.steps;
+        let t = (frame % self.steps) as f64 / self.steps as f64;
+        let from = self.points[segment];
+        let to = self.points.get(segment + 1).unwrap_or(&from);
+        Some([from[0] + (to[0] - from[0]) * t,
+              from[1] + (to[1] - from[1]) * t])
+    }
+}
+
+impl FromStr for Trajectory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Trajectory, String> {
+        let (w, h) = (WIDTH as f64, HEIGHT as f64);
+        match s {
+            "circle" => {
+                let r = h / 3.0;
+                let points = (0..=CIRCLE_POINTS)
+                    .map(|i| {
+                        let a = 2.0 * PI * i as f64 / CIRCLE_POINTS as f64;
+                        [w / 2.0 + r * a.cos(), h / 2.0 + r * a.sin()]
+                    })
+                    .collect();
+                Ok(Trajectory {
+                    points,
+                    steps: CIRCLE_STEPS,
+                })
+            }
+            "bounce" => {
+                let range = [w - 2.0 * RADIUS as f64, h - 2.0 * RADIUS as f64];
+                let n = BOUNCE_FRAMES as f64;
+                let speed = [2.0 * range[0] / n, 4.0 * range[1] / n];
+                let points = (0..=BOUNCE_FRAMES)
+                    .map(|i| {
+                        let t = i as f64;
+                        [RADIUS as f64 +
+                         fold(BOUNCE_START[0] + speed[0] * t, range[0]),
+                         RADIUS as f64 +
+                         fold(BOUNCE_START[1] + speed[1] * t, range[1])]
+                    })
+                    .collect();
+                Ok(Trajectory { points, steps: 1 })
+            }
+            "line" => {
+                let margin = 2.0 * RADIUS as f64;
+                Ok(Trajectory {
+                    points: vec![[margin, h / 2.0],
+                                 [w - margin, h / 2.0],
+                                 [margin, h / 2.0]],
+                    steps: LINE_STEPS,
+                })
+            }
+            _ => {
+                let points = s.split(';')
+                    .map(point)
+                    .collect::<Result<Vec<_>, String>>()?;
+                if points.len() < 2 {
+                    return Err(format!("a trajectory needs two points at \
+                                        least, got `{}`",
+                                       s));
+                }
+                Ok(Trajectory {
+                    points,
+                    steps: SCRIPT_STEPS,
+                })
+            }
+        }
+    }
+}
+
+/// Folds `v` into `[0, len]`, as a ball bouncing between `0` and `len`.
+fn fold(v: f64, len: f64) -> f64 {
+    let v = v.rem_euclid(2.0 * len);
+    if v > len {
+        2.0 * len - v
+    } else {
+        v
+    }
+}
+
+/// The selection that starts tracking the disc of `trajectory` in its first
+/// frame, as tracking sees it: mirrored.
+pub fn selection(trajectory: &Trajectory) -> Rect {
+    let [x, y] = trajectory.points[0];
+    Rect::new(WIDTH - x.round() as i32 - RADIUS,
+              y.round() as i32 - RADIUS,
+              2 * RADIUS,
+              2 * RADIUS)
+}
+
+/// Parses `X,Y`, within the frame.
+fn point(s: &str) -> Result<[f64; 2], String> {
+    let error = || {
+        format!("invalid trajectory point `{}`; expected X,Y within {}x{}",
+                s,
+                WIDTH,
+                HEIGHT)
+    };
+    let v = s.split(',')
+        .map(|n| n.trim().parse::<f64>().map_err(|_| error()))
+        .collect::<Result<Vec<f64>, String>>()?;
+    match v[..] {
+        [x, y] if (0.0..=WIDTH as f64).contains(&x) &&
+                  (0.0..=HEIGHT as f64).contains(&y) => Ok([x, y]),
+        _ => Err(error()),
+    }
+}
+
+/// `Synthetic` renders the frames of a `Trajectory`.
+pub struct Synthetic {
+    trajectory: Trajectory,
+    /// Index of the next frame.
+    next: usize,
+    background: Mat,
+}
+
+impl Synthetic {
+    pub fn new(trajectory: Trajectory) -> Synthetic {
+        let gray = vec![BACKGROUND; (WIDTH * HEIGHT) as usize];
+        let background = Mat::from_slice_u8(HEIGHT, WIDTH, &gray)
+            .cvt_color(ColorConversionCodes::GRAY2BGR);
+        Synthetic {
+            trajectory,
+            next: 0,
+            background,
+        }
+    }
+
+    /// Center of the disc in the frame with index `frame`, counting from 0
+    /// at the start of the trajectory.
+    pub fn position(&self, frame: usize) -> Option<[f64; 2]> {
+        self.trajectory.position(frame)
+    }
+
+    /// Renders the next frame into `frame`. Returns `false` after the last
+    /// one; with `looping`, the trajectory starts over instead.
+    pub fn read(&mut self, frame: &Mat, looping: bool) -> bool {
+        if self.next == self.trajectory.frames() && looping {
+            self.next = 0;
+        }
+        let [x, y] = match self.trajectory.position(self.next) {
+            Some(p) => p,
+            None => return false,
+        };
+        self.next += 1;
+        self.background.copy_to(frame);
+        frame.circle(Point2i::new(x.round() as i32, y.round() as i32),
+                     RADIUS,
+                     Scalar::new(COLOR.0, COLOR.1, COLOR.2, 0),
+                     -1);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::params::VisionParams;
+    use crate::tracker::{Backend, TrackerOptions};
+    use rust_vision::{Mat, Rect};
+    use super::{HEIGHT, RADIUS, Synthetic, Trajectory, WIDTH};
+
+    /// How far the tracked center may be from the disc's, in pixels.
+    const TOLERANCE: f64 = 8.0;
+
+    #[test]
+    fn passes_through_the_points() {
+        let t: Trajectory = "100,100;300,100;300,200".parse().unwrap();
+        assert_eq!(t.frames(), 61);
+        assert_eq!(t.position(0), Some([100.0, 100.0]));
+        assert_eq!(t.position(15), Some([200.0, 100.0]));
+        assert_eq!(t.position(30), Some([300.0, 100.0]));
+        assert_eq!(t.position(60), Some([300.0, 200.0]));
+        assert_eq!(t.position(61), None);
+    }
+
+    #[test]
+    fn rejects_invalid_trajectories() {
+        assert!("".parse::<Trajectory>().is_err());
+        assert!("100,100".parse::<Trajectory>().is_err());
+        assert!("100,100;x,3".parse::<Trajectory>().is_err());
+        assert!("100,100;900,100".parse::<Trajectory>().is_err());
+    }
+
+    #[test]
+    fn bounces_inside_the_frame_and_back_to_the_start() {
+        let t: Trajectory = "bounce".parse().unwrap();
+        for i in 0..t.frames() {
+            let [x, y] = t.position(i).unwrap();
+            assert!(x >= RADIUS as f64 && x <= (WIDTH - RADIUS) as f64);
+            assert!(y >= RADIUS as f64 && y <= (HEIGHT - RADIUS) as f64);
+        }
+        let last = t.position(t.frames() - 1).unwrap();
+        let first = t.position(0).unwrap();
+        assert!((first[0] - last[0]).abs() < 1e-9);
+        assert!((first[1] - last[1]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn circles_back_to_the_start() {
+        let t: Trajectory = "circle".parse().unwrap();
+        let first = t.position(0).unwrap();
+        let last = t.position(t.frames() - 1).unwrap();
+        assert!((first[0] - last[0]).abs() < 1e-9);
+        assert!((first[1] - last[1]).abs() < 1e-9);
+    }
+
+    fn follows(backend: Backend, trajectory: &str) {
+        let mut source = Synthetic::new(trajectory.parse().unwrap());
+        let params = VisionParams::default();
+        let mut tracker = backend.create(&TrackerOptions::default());
+        let frame = Mat::new();
+        assert!(source.read(&frame, false));
+        let [x, y] = source.position(0).unwrap();
+        let selection = Rect::new(x as i32 - RADIUS,
+                                  y as i32 - RADIUS,
+                                  2 * RADIUS,
+                                  2 * RADIUS);
+        tracker.init(&frame, selection, &params);
+        let mut n = 1;
+        while source.read(&frame, false) {
+            let track = tracker.update(&frame, &params);
+            let [x, y] = source.position(n).unwrap();
+            assert!((track.region.cx - x).abs() < TOLERANCE &&
+                    (track.region.cy - y).abs() < TOLERANCE,
+                    "{} lost the disc in frame {}: ({}, {}) instead of \
+                     ({}, {})",
+                    backend,
+                    n,
+                    track.region.cx,
+                    track.region.cy,
+                    x,
+                    y);
+            n += 1;
+        }
+    }
+
+    #[test]
+    fn camshift_follows_a_circle() {
+        follows(Backend::Camshift, "circle");
+    }
+
+    #[test]
+    fn camshift_follows_a_line() {
+        follows(Backend::Camshift, "line");
+    }
+
+    #[test]
+    fn ensemble_follows_a_circle() {
+        follows(Backend::Ensemble, "circle");
+    }
+}
@@ -0,0 +1,130 @@
+//! A tracked target: a tracker with its own loss detection and history.
+use crate::config::Config;
+use crate::derive::Differentiator;
+use crate::features::{self, Region};
+use crate::filter::Chain;
+use crate::loss::{LossConfig, LossDetector, TrackState};
+use crate::params::VisionParams;
+use crate::tracker::{Track, Tracker};
+use rust_vision::{Mat, Rect};
+use std::time::Instant;
+
+/// What a target reports for one frame.
+pub struct Observation {
+    pub track: Track,
+    pub state: TrackState,
+    /// The target was lost on this frame.
+    pub lost: bool,
+    /// The region after the smoothing filters.
+    pub filtered: Region,
+    /// The target's dimensions, before normalization.
+    pub values: Vec<f64>,
+}
+
+/// `Target` follows one selected region. Every target has its own loss
+/// detection, filters and derivatives, so several can be tracked at once.
+pub struct Target {
+    tracker: Box<dyn Tracker>,
+    loss: LossDetector,
+    loss_config: LossConfig,
+    differentiator: Differentiator,
+    filters: Chain,
+}
+
+impl Target {
+    /// Starts tracking `selection` in `frame`.
+    pub fn new(config: &Config,
+               frame: &Mat,
+               selection: Rect,
+               params: &VisionParams)
+               -> Target {
+        let mut tracker = config.tracker.create(&config.tracking);
+        tracker.init(frame, selection, params);
+        Target {
+            tracker,
+            loss: LossDetector::new(config.loss.clone()),
+            loss_config: config.loss.clone(),
+            differentiator: Differentiator::new(config.derive),
+            filters: Chain::new(&config.filters).unwrap(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.tracker.name()
+    }
+
+    /// See `Tracker::back_project`.
+    pub fn back_project(&self) -> Option<&Mat> {
+        self.tracker.back_project()
+    }
+
+    /// See `Tracker::model`.
+    pub fn model(&self) -> Vec<f32> {
+        self.tracker.model()
+    }
+
+    /// See `Tracker::restore`.
+    pub fn restore(&mut self, model: &[f32]) -> Result<(), String> {
+        self.tracker.restore(model)
+    }
+
+    /// Learns the target again from `window` in `frame`, e.g. under new
+    /// lighting, keeping its filters and history.
+    pub fn recalibrate(&mut self,
+                       frame: &Mat,
+                       window: Rect,
+                       params: &VisionParams) {
+        self.tracker.init(frame, window, params);
+        self.loss.reset();
+    }
+
+    /// Smooths the region with `filters` from the next frame on.
+    pub fn set_filters(&mut self, filters: Chain) {
+        self.filters = filters;
+    }
+
+    /// Tracks the target in the next frame, captured at `captured`.
+    /// `smoothing` picks whether the values are computed from the filtered
+    /// or the raw region.
+    pub fn update(&mut self,
+                  config: &Config,
+                  frame: &Mat,
+                  params: &VisionParams,
+                  captured: Instant,
+                  smoothing: bool)
+                  -> Observation {
+        let mut track = self.tracker.update(frame, params);
+        let before = self.loss.state();
+        let mut state = self.loss.update(&track);
+        if state == TrackState::Lost && self.loss_config.reacquire {
+            let found = self.tracker
+                .reacquire(params, self.loss_config.reacquire_confidence);
+            if let Some(found) = found {
+                info!("Target re-acquired (confidence {:.2})",
+                      found.confidence);
+                track = found;
+                self.loss.reset();
+                state = TrackState::Tracking;
+                self.differentiator.reset();
+                self.filters.reset();
+            }
+        }
+
+        // Filters keep running while turned off so that switching back
+        // doesn't start from a stale state.
+        let filtered = self.filters.filter(captured, track.region);
+        let region = if smoothing { filtered } else { track.region };
+        let mut values = match config.points {
+            Some(n) => features::point_values(&track.points, n),
+            None => config.features.extract(&region),
+        };
+        self.differentiator.update(captured, region.centroid(), &mut values);
+        Observation {
+            track,
+            state,
+            lost: before == TrackState::Tracking && state == TrackState::Lost,
+            filtered,
+            values,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Trackbars for tuning the mask thresholds and the histogram while watching
+//! the tracker.
+//!
+//! The trackbars and the control channel edit the same parameters: moving a
+//! trackbar takes effect on the next frame, and updates from the control
+//! channel move the trackbars.
+use crate::control::ControlServer;
+use crate::params::VisionParams;
+use crate::window;
+
+/// Name of the window holding the trackbars.
+const WINDOW: &str = "Controls";
+/// The parameters with a trackbar, and their maximum.
+const BARS: [(&str, i32); 7] = [("h_min", 180),
+                                ("s_min", 256),
+                                ("v_min", 256),
+                                ("h_max", 180),
+                                ("s_max", 256),
+                                ("v_max", 256),
+                                ("hist_bins", 180)];
+
+/// `Trackbars` keeps a window of trackbars in sync with the parameters.
+pub struct Trackbars {
+    /// The parameters as of the last `sync`.
+    last: VisionParams,
+}
+
+impl Trackbars {
+    /// Opens the trackbar window, starting from `params`.
+    pub fn new(params: VisionParams) -> Trackbars {
+        window::open(WINDOW);
+        for &(name, max) in &BARS {
+            let value = params.get(name).unwrap();
+            window::create_trackbar(name, WINDOW, value, max);
+        }
+        Trackbars { last: params }
+    }
+
+    /// Applies the trackbars that were moved since the last call, and moves
+    /// the others to follow the control channel.
+    pub fn sync(&mut self, control: &ControlServer) {
+        let current = control.params();
+        for &(name, _) in &BARS {
+            let pos = window::trackbar_pos(name, WINDOW);
+            let last = self.last.get(name).unwrap();
+            if pos != last {
+                if let Err(e) = control.set(name, f64::from(pos)) {
+                    warn!("{}", e);
+                    window::set_trackbar_pos(name, WINDOW, last);
+                }
+            } else if current.get(name) != Some(last) {
+                window::set_trackbar_pos(name,
+                                         WINDOW,
+                                         current.get(name).unwrap());
+            }
+        }
+        self.last = control.params();
+    }
+}
+
+/// Prints `params` as control messages, so tuned values can be replayed to
+/// the control channel.
+pub fn print(params: &VisionParams) {
+    for &(name, _) in &BARS {
+        println!("{} {}", name, params.get(name).unwrap());
+    }
+}
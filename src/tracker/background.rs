@@ -0,0 +1,148 @@
+//! Background subtraction: the largest moving blob is the target.
+use crate::features::Region;
+use crate::params::VisionParams;
+use rust_vision::*;
+use super::{Track, Tracker, clip};
+
+/// Number of frames the background model remembers.
+const HISTORY: i32 = 500;
+/// Foreground pixels are 255; shadows are marked 127 and dropped.
+const FOREGROUND: f64 = 200.0;
+/// Size of the kernel used to clean up the foreground mask.
+const KERNEL: i32 = 5;
+/// Blobs smaller than this (in pixels) are noise.
+const MIN_AREA: f64 = 100.0;
+
+/// The background models OpenCV offers.
+enum Model {
+    Mog2(BackgroundSubtractorMOG2),
+    Knn(BackgroundSubtractorKNN),
+}
+
+impl Model {
+    fn apply(&mut self, frame: &Mat, learning_rate: f64) -> Mat {
+        match *self {
+            Model::Mog2(ref mut m) => m.apply(frame, learning_rate),
+            Model::Knn(ref mut m) => m.apply(frame, learning_rate),
+        }
+    }
+}
+
+/// `Background` learns the static background of a fixed camera and tracks
+/// the largest foreground blob, so it works regardless of the target's
+/// color. It needs no selection; if one is given, only blobs inside it are
+/// considered.
+pub struct Background {
+    name: &'static str,
+    knn: bool,
+    model: Option<Model>,
+    area: Option<Rect>,
+    kernel: Mat,
+}
+
+impl Background {
+    /// Uses the Gaussian mixture model (MOG2).
+    pub fn mog2() -> Background {
+        Background::with_model("mog2", false)
+    }
+
+    /// Uses the k-nearest-neighbours model (KNN).
+    pub fn knn() -> Background {
+        Background::with_model("knn", true)
+    }
+
+    fn with_model(name: &'static str, knn: bool) -> Background {
+        Background {
+            name,
+            knn,
+            model: None,
+            area: None,
+            kernel: get_structuring_element(MorphShapes::Ellipse,
+                                            Size2i::new(KERNEL, KERNEL)),
+        }
+    }
+
+    /// The foreground mask of `frame`, without shadows and specks.
+    fn foreground(&mut self, frame: &Mat, learning_rate: f64) -> Mat {
+        let raw = match self.model {
+            Some(ref mut model) => model.apply(frame, learning_rate),
+            None => return Mat::new(),
+        };
+        raw.threshold(FOREGROUND, 255.0, ThresholdTypes::Binary)
+            .morphology_ex(MorphTypes::Open, &self.kernel, 1)
+            .morphology_ex(MorphTypes::Close, &self.kernel, 2)
+    }
+}
+
+impl Tracker for Background {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn init(&mut self, frame: &Mat, selection: Rect, _: &VisionParams) {
+        // Starting over also forgets the background, which is what we want
+        // when the camera was moved.
+        self.model = Some(if self.knn {
+            Model::Knn(BackgroundSubtractorKNN::new(HISTORY, 400.0, true))
+        } else {
+            Model::Mog2(BackgroundSubtractorMOG2::new(HISTORY, 16.0, true))
+        });
+        let whole = selection.x <= 0 && selection.y <= 0 &&
+                    selection.width >= frame.cols &&
+                    selection.height >= frame.rows;
+        self.area = if whole { None } else { Some(selection) };
+    }
+
+    fn update(&mut self, frame: &Mat, params: &VisionParams) -> Track {
+        let mask = self.foreground(frame, params.learning_rate);
+        let (offset, contours) = match self.area {
+            Some(area) => {
+                let area = clip(area, mask.cols, mask.rows);
+                let contours = mask.roi(area)
+                    .find_contours(RetrievalModes::External,
+                                   ContourApproximationModes::Simple);
+                ((area.x, area.y), contours)
+            }
+            None => {
+                ((0, 0),
+                 mask.find_contours(RetrievalModes::External,
+                                    ContourApproximationModes::Simple))
+            }
+        };
+
+        let largest = contours.iter()
+            .map(|c| (contour_area(c), c))
+            .filter(|&(area, _)| area >= MIN_AREA)
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        match largest {
+            Some((_, contour)) => {
+                let mut bbox = bounding_rect(contour);
+                bbox.x += offset.0;
+                bbox.y += offset.1;
+                let mut region = Region::from_rect(bbox);
+                // The centroid of the blob itself is steadier than the
+                // center of its bounding box.
+                let m = moments(contour);
+                if m.m00 > 0.0 {
+                    region.cx = m.m10 / m.m00 + offset.0 as f64;
+                    region.cy = m.m01 / m.m00 + offset.1 as f64;
+                }
+                Track {
+                    region,
+                    window: bbox,
+                    confidence: 1.0,
+                    points: Vec::new(),
+                }
+            }
+            // Nothing is moving.
+            None => {
+                Track {
+                    region: Region::from_rect(Rect::default()),
+                    window: Rect::default(),
+                    confidence: 0.0,
+                    points: Vec::new(),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,392 @@
+//! The CAMShift tracker.
+use crate::features::Region;
+use crate::params::VisionParams;
+use rust_vision::*;
+use std::cmp::Reverse;
+use std::str::FromStr;
+use super::{Track, Tracker, clip, sanitize};
+
+/// Range of the hue channel in OpenCV's 8-bit HSV.
+const HUE_RANGE: [f32; 2] = [0_f32, 180_f32];
+/// Range of the saturation channel in OpenCV's 8-bit HSV.
+const SAT_RANGE: [f32; 2] = [0_f32, 256_f32];
+/// Channels of the histogram; only the first is used by hue histograms.
+const CHANNELS: [i32; 2] = [0, 1];
+
+/// The channels the target is described by.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Histogram {
+    /// Hue only. Robust to lighting, but can't tell a red target from skin.
+    Hue,
+    /// Hue and saturation. Separates colors of the same hue, such as a
+    /// saturated red and skin.
+    HueSaturation,
+}
+
+impl FromStr for Histogram {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Histogram, String> {
+        match s {
+            "hue" => Ok(Histogram::Hue),
+            "hs" => Ok(Histogram::HueSaturation),
+            _ => Err(format!("unknown histogram `{}`", s)),
+        }
+    }
+}
+
+/// Settings of the `camshift` tracker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CamshiftOptions {
+    pub histogram: Histogram,
+    /// Number of saturation bins of the `HueSaturation` histogram; the hue
+    /// bins are the `hist_bins` parameter.
+    pub saturation_bins: i32,
+    /// Weight of the histogram of the current window when it is blended into
+    /// the stored one, every frame. Zero keeps the histogram of the
+    /// selection.
+    pub adapt_rate: f64,
+    /// Confidence a frame needs to be blended in, so the histogram doesn't
+    /// learn the background while the track is going astray.
+    pub adapt_confidence: f64,
+    /// The search window never shrinks below this many pixels on each side,
+    /// so that CAMShift can find the target again after it got small or
+    /// went to the edge of the frame.
+    pub min_window: i32,
+    /// Convert to HSV, mask and backproject through OpenCL (as `UMat`s),
+    /// which takes these per-pixel steps off the CPU.
+    pub opencl: bool,
+}
+
+impl Default for CamshiftOptions {
+    fn default() -> CamshiftOptions {
+        CamshiftOptions {
+            histogram: Histogram::Hue,
+            saturation_bins: 8,
+            adapt_rate: 0.0,
+            adapt_confidence: 0.5,
+            min_window: 8,
+            opencl: false,
+        }
+    }
+}
+
+/// `Camshift` tracks a region by the histogram of its hue, and optionally
+/// its saturation.
+pub struct Camshift {
+    options: CamshiftOptions,
+    hist: Mat,
+    bins: i32,
+    window: Rect,
+    /// Size of the original selection, used for re-acquisition.
+    size: (i32, i32),
+    /// Backprojection of the last frame, kept for re-acquisition.
+    back_project: Option<Mat>,
+}
+
+impl Camshift {
+    pub fn new(options: CamshiftOptions) -> Camshift {
+        Camshift {
+            options,
+            hist: Mat::new(),
+            bins: 0,
+            window: Rect::default(),
+            size: (0, 0),
+            back_project: None,
+        }
+    }
+
+    /// Builds the histogram of `selection`, restricted to `mask`.
+    fn build_hist(&mut self,
+                  planes: &Mat,
+                  mask: &Mat,
+                  selection: Rect,
+                  bins: i32) {
+        self.hist = self.hist_of(planes, mask, selection, bins);
+        self.bins = bins;
+    }
+
+    /// The normalized histogram of `rect`, restricted to `mask`.
+    fn hist_of(&self, planes: &Mat, mask: &Mat, rect: Rect, bins: i32) -> Mat {
+        let roi = planes.roi(rect);
+        let maskroi = mask.roi(rect);
+        let sizes = [bins, self.options.saturation_bins];
+        let phranges = ranges();
+        let raw_hist = roi.calc_hist(&CHANNELS[0] as *const i32,
+                                     maskroi,
+                                     self.dims(),
+                                     &sizes[0] as *const i32,
+                                     &phranges[0] as *const *const f32);
+        raw_hist.normalize(0_f64, 255_f64, NormTypes::NormMinMax)
+    }
+
+    fn dims(&self) -> i32 {
+        match self.options.histogram {
+            Histogram::Hue => 1,
+            Histogram::HueSaturation => 2,
+        }
+    }
+
+    /// The channels of `frame` the histogram is built from, and the mask of
+    /// pixels within the HSV bounds.
+    fn planes_and_mask(&self,
+                       frame: &Mat,
+                       params: &VisionParams)
+                       -> (Mat, Mat) {
+        #[cfg(feature = "opencl")]
+        if self.options.opencl {
+            let (planes, mask) = self.planes_and_mask_ocl(frame, params);
+            return (planes.get_mat(), mask.get_mat());
+        }
+        let hsv = frame.cvt_color(ColorConversionCodes::BGR2HSV);
+        let mask = hsv.in_range(params.lower(), params.upper());
+        let planes = match self.options.histogram {
+            Histogram::Hue => {
+                let ch = [0, 0];
+                hsv.mix_channels(1, 1, &ch[0] as *const i32, 1)
+            }
+            Histogram::HueSaturation => hsv,
+        };
+        (planes, mask)
+    }
+
+    /// `planes_and_mask`, computed by OpenCL and left on the device.
+    #[cfg(feature = "opencl")]
+    fn planes_and_mask_ocl(&self,
+                           frame: &Mat,
+                           params: &VisionParams)
+                           -> (UMat, UMat) {
+        let hsv = frame.get_umat(AccessFlag::Read)
+            .cvt_color(ColorConversionCodes::BGR2HSV);
+        let mask = hsv.in_range(params.lower(), params.upper());
+        let planes = match self.options.histogram {
+            Histogram::Hue => hsv.extract_channel(0),
+            Histogram::HueSaturation => hsv,
+        };
+        (planes, mask)
+    }
+
+    /// The masked backprojection of the histogram onto `frame`. With OpenCL
+    /// only the result is brought back from the device; the planes and mask
+    /// are downloaded only when the histogram needs them.
+    fn back_project_frame(&mut self,
+                          frame: &Mat,
+                          params: &VisionParams)
+                          -> Mat {
+        let phranges = ranges();
+        #[cfg(feature = "opencl")]
+        if self.options.opencl {
+            let (planes, mask) = self.planes_and_mask_ocl(frame, params);
+            if self.bins != params.hist_bins {
+                let window = self.window;
+                self.build_hist(&planes.get_mat(),
+                                &mask.get_mat(),
+                                window,
+                                params.hist_bins);
+            }
+            let hist = self.hist.get_umat(AccessFlag::Read);
+            let back_project =
+                planes.calc_back_project(&CHANNELS[0] as *const i32,
+                                         &hist,
+                                         &phranges[0] as *const *const f32);
+            return back_project.bitwise_and(&mask).get_mat();
+        }
+        let (planes, mask) = self.planes_and_mask(frame, params);
+        // A new number of bins invalidates the histogram; rebuild it from the
+        // region we are currently tracking.
+        if self.bins != params.hist_bins {
+            let window = self.window;
+            self.build_hist(&planes, &mask, window, params.hist_bins);
+        }
+        let mut back_project =
+            planes.calc_back_project(&CHANNELS[0] as *const i32,
+                                     &self.hist,
+                                     &phranges[0] as *const *const f32);
+        back_project.logic_and(mask);
+        back_project
+    }
+}
+
+/// Whether this build has the `opencl` feature.
+pub const OPENCL_BUILT: bool = cfg!(feature = "opencl");
+
+/// Whether OpenCV can run on an OpenCL device here. Enables OpenCL in
+/// OpenCV's transparent API if so.
+#[cfg(feature = "opencl")]
+pub fn enable_opencl() -> bool {
+    if !ocl::have_opencl() {
+        return false;
+    }
+    ocl::set_use_opencl(true);
+    ocl::use_opencl()
+}
+
+#[cfg(not(feature = "opencl"))]
+pub fn enable_opencl() -> bool {
+    false
+}
+
+impl Default for Camshift {
+    fn default() -> Camshift {
+        Camshift::new(CamshiftOptions::default())
+    }
+}
+
+/// The ranges of the hue and saturation axes, as `calc_hist` wants them.
+fn ranges() -> [*const f32; 2] {
+    [&HUE_RANGE[0] as *const f32, &SAT_RANGE[0] as *const f32]
+}
+
+impl Tracker for Camshift {
+    fn name(&self) -> &'static str {
+        "camshift"
+    }
+
+    fn init(&mut self, frame: &Mat, selection: Rect, params: &VisionParams) {
+        let selection = sanitize(selection,
+                                 frame.cols,
+                                 frame.rows,
+                                 self.options.min_window);
+        let (planes, mask) = self.planes_and_mask(frame, params);
+        self.build_hist(&planes, &mask, selection, params.hist_bins);
+        self.window = selection;
+        self.size = (selection.width, selection.height);
+        self.back_project = None;
+    }
+
+    fn update(&mut self, frame: &Mat, params: &VisionParams) -> Track {
+        let back_project = self.back_project_frame(frame, params);
+
+        let (track_box, window, confidence) =
+            camshift(&back_project, self.window, params.term_iterations);
+        // CAMShift moves its search window along with the target; the
+        // wrapper takes the window by value, so carry it over ourselves. A
+        // window that collapsed keeps the last one, which is never too
+        // small to search.
+        if window.width > 0 && window.height > 0 {
+            self.window = sanitize(window,
+                                   frame.cols,
+                                   frame.rows,
+                                   self.options.min_window);
+            // Follow slow changes of the target's appearance, such as the
+            // lighting, while we are sure we are on it.
+            let rate = self.options.adapt_rate;
+            if rate > 0.0 && confidence >= self.options.adapt_confidence {
+                let (planes, mask) = self.planes_and_mask(frame, params);
+                let fresh = self.hist_of(&planes, &mask, window, self.bins);
+                self.hist =
+                    self.hist.add_weighted(1.0 - rate, &fresh, rate, 0.0);
+            }
+        }
+        self.back_project = Some(back_project);
+        Track {
+            region: Region::from_box(&track_box),
+            // What was found rather than what is searched next, so that the
+            // target can be lost for shrinking below `--min-size`.
+            window,
+            confidence,
+            points: Vec::new(),
+        }
+    }
+
+    /// The frame is scanned with windows the size of the original
+    /// selection; CAMShift is started from the `SEEDS` windows holding the
+    /// most backprojection mass, and the best result is accepted if its
+    /// confidence reaches `min_confidence`.
+    fn reacquire(&mut self,
+                 params: &VisionParams,
+                 min_confidence: f64)
+                 -> Option<Track> {
+        const SEEDS: usize = 3;
+        let back_project = match self.back_project {
+            Some(ref bp) => bp,
+            None => return None,
+        };
+        let (w, h) = (self.size.0.max(1), self.size.1.max(1));
+        let (cols, rows) = (back_project.cols, back_project.rows);
+
+        let mut cells = Vec::new();
+        let mut y = 0;
+        while y < rows {
+            let mut x = 0;
+            while x < cols {
+                let cell = clip(Rect::new(x, y, w, h), cols, rows);
+                if cell.width > 0 && cell.height > 0 {
+                    cells.push((back_project.roi(cell).count_non_zero(), cell));
+                }
+                x += w;
+            }
+            y += h;
+        }
+        cells.sort_by_key(|&(mass, _)| Reverse(mass));
+
+        let iterations = params.term_iterations;
+        let best = cells.iter()
+            .take(SEEDS)
+            .filter(|&&(mass, _)| mass > 0)
+            .map(|&(_, seed)| camshift(back_project, seed, iterations))
+            .filter(|&(_, window, _)| window.width > 0 && window.height > 0)
+            .max_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
+        match best {
+            Some((track_box, window, confidence))
+                if confidence >= min_confidence => {
+                self.window = sanitize(window,
+                                       back_project.cols,
+                                       back_project.rows,
+                                       self.options.min_window);
+                Some(Track {
+                    region: Region::from_box(&track_box),
+                    window,
+                    confidence,
+                    points: Vec::new(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn back_project(&self) -> Option<&Mat> {
+        self.back_project.as_ref()
+    }
+
+    fn model(&self) -> Vec<f32> {
+        self.hist.data_f32()
+    }
+
+    fn restore(&mut self, model: &[f32]) -> Result<(), String> {
+        let cols = match self.options.histogram {
+            Histogram::Hue => 1,
+            Histogram::HueSaturation => self.options.saturation_bins,
+        };
+        if model.len() != (self.bins * cols) as usize {
+            return Err(format!("the saved histogram has {} bins, expected \
+                                {}; was it saved with other --histogram or \
+                                --saturation-bins?",
+                               model.len(),
+                               self.bins * cols));
+        }
+        self.hist = Mat::from_slice_f32(self.bins, cols, model);
+        Ok(())
+    }
+}
+
+/// Runs CAMShift from `window` and returns the box, the next search window
+/// and its confidence: the fraction of the window covered by the (masked)
+/// backprojection. It is close to zero when CAMShift has latched onto noise.
+fn camshift(back_project: &Mat,
+            window: Rect,
+            iterations: i32)
+            -> (RotatedRect, Rect, f64) {
+    let criteria = TermCriteria::new(TermType::Count, iterations, 1_f64);
+    let track_box = back_project.camshift(window, &criteria);
+    let window = clip(track_box.bounding_rect(),
+                      back_project.cols,
+                      back_project.rows);
+    let area = window.width * window.height;
+    let confidence = if area > 0 {
+        back_project.roi(window).count_non_zero() as f64 / area as f64
+    } else {
+        0.0
+    };
+    (track_box, window, confidence)
+}
@@ -0,0 +1,248 @@
+//! Object detection with OpenCV's DNN module, bridged by a fast tracker.
+//!
+//! Builds without the `dnn` feature have the tracker, but it detects nothing.
+use crate::features::Region;
+use crate::params::VisionParams;
+use rust_vision::{Mat, Rect};
+use std::str::FromStr;
+use self::detector::Detector;
+use super::{Mosse, Track, Tracker};
+
+/// Whether this build has the `dnn` feature.
+pub const BUILT: bool = cfg!(feature = "dnn");
+
+/// The family of the network, which decides how frames are fed to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetKind {
+    /// MobileNet-SSD (Caffe): 300x300, centered and scaled to `[-1, 1]`.
+    Ssd,
+    /// YOLO (Darknet): 416x416 RGB in `[0, 1]`.
+    Yolo,
+}
+
+impl FromStr for NetKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<NetKind, String> {
+        match s {
+            "ssd" => Ok(NetKind::Ssd),
+            "yolo" => Ok(NetKind::Yolo),
+            _ => Err(format!("unknown network kind `{}`", s)),
+        }
+    }
+}
+
+/// The labels of the VOC-trained MobileNet-SSD, by class id.
+pub const VOC_LABELS: [&str; 21] =
+    ["background", "aeroplane", "bicycle", "bird", "boat", "bottle", "bus",
+     "car", "cat", "chair", "cow", "diningtable", "dog", "horse", "motorbike",
+     "person", "pottedplant", "sheep", "sofa", "train", "tvmonitor"];
+
+/// Settings of the `dnn` tracker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DnnOptions {
+    /// The weights, e.g. `MobileNetSSD_deploy.caffemodel`.
+    pub model: String,
+    /// The network description, e.g. `MobileNetSSD_deploy.prototxt`.
+    pub config: String,
+    pub kind: NetKind,
+    /// Id of the class to follow.
+    pub class: i32,
+    /// Detections scoring less are ignored.
+    pub confidence: f32,
+    /// Frames between detections; a MOSSE tracker bridges the gaps.
+    pub every: u32,
+    /// Run the detector on CUDA.
+    pub cuda: bool,
+}
+
+impl Default for DnnOptions {
+    fn default() -> DnnOptions {
+        DnnOptions {
+            model: String::new(),
+            config: String::new(),
+            kind: NetKind::Ssd,
+            class: 15,
+            confidence: 0.5,
+            every: 10,
+            cuda: false,
+        }
+    }
+}
+
+/// `Dnn` detects objects of one class and follows the most confident one,
+/// the one closest to the current target once tracking. It needs no
+/// selection.
+pub struct Dnn {
+    options: DnnOptions,
+    detector: Detector,
+    bridge: Mosse,
+    target: Option<(Rect, f64)>,
+    since_detection: u32,
+}
+
+impl Dnn {
+    pub fn new(options: DnnOptions) -> Dnn {
+        Dnn {
+            detector: Detector::new(&options),
+            options,
+            bridge: Mosse::new(),
+            target: None,
+            since_detection: 0,
+        }
+    }
+
+    /// The best detection of our class, with its score.
+    fn detect(&mut self, frame: &Mat) -> Option<(Rect, f64)> {
+        let detections =
+            self.detector.detect(frame, self.options.confidence);
+        let candidates = detections.into_iter()
+            .filter(|&(class, _, _)| class == self.options.class)
+            .map(|(_, score, bbox)| (bbox, score as f64));
+        match self.target {
+            // Stay on the same object rather than jumping to a better one.
+            Some((current, _)) => {
+                candidates.min_by_key(|&(bbox, _)| distance(bbox, current))
+            }
+            None => {
+                candidates.max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            }
+        }
+    }
+}
+
+impl Tracker for Dnn {
+    fn name(&self) -> &'static str {
+        "dnn"
+    }
+
+    fn init(&mut self, frame: &Mat, selection: Rect, params: &VisionParams) {
+        let whole = selection.x <= 0 && selection.y <= 0 &&
+                    selection.width >= frame.cols &&
+                    selection.height >= frame.rows;
+        if whole {
+            self.target = None;
+        } else {
+            // A selection points at the object to follow.
+            self.bridge.init(frame, selection, params);
+            self.target = Some((selection, 1.0));
+        }
+        // Detect on the next frame.
+        self.since_detection = self.options.every;
+    }
+
+    fn update(&mut self, frame: &Mat, params: &VisionParams) -> Track {
+        self.since_detection += 1;
+        if self.target.is_none() || self.since_detection >= self.options.every {
+            self.since_detection = 0;
+            if let Some((bbox, score)) = self.detect(frame) {
+                self.bridge.init(frame, bbox, params);
+                self.target = Some((bbox, score));
+                return Track {
+                    region: Region::from_rect(bbox),
+                    window: bbox,
+                    confidence: score,
+                    points: Vec::new(),
+                };
+            }
+        }
+
+        let score = match self.target {
+            Some((_, score)) => score,
+            None => 0.0,
+        };
+        let mut track = self.bridge.update(frame, params);
+        track.confidence *= score;
+        // If the bridge failed, detect again on the next frame.
+        self.target = if track.confidence > 0.0 {
+            Some((track.window, score))
+        } else {
+            None
+        };
+        track
+    }
+}
+
+/// Squared distance between the centers of `a` and `b`.
+fn distance(a: Rect, b: Rect) -> i64 {
+    let dx = (2 * a.x + a.width - 2 * b.x - b.width) as i64;
+    let dy = (2 * a.y + a.height - 2 * b.y - b.height) as i64;
+    dx * dx + dy * dy
+}
+
+#[cfg(feature = "dnn")]
+mod detector {
+    use crate::cuda;
+    use rust_vision::{DetectionModel, Mat, Rect, Scalar, Size2i};
+    use super::{DnnOptions, NetKind};
+
+    /// Overlapping detections above this IoU are merged.
+    const NMS_THRESHOLD: f32 = 0.4;
+
+    /// `Detector` runs the network.
+    pub struct Detector {
+        model: DetectionModel,
+    }
+
+    impl Detector {
+        pub fn new(options: &DnnOptions) -> Detector {
+            let mut model = DetectionModel::new(&options.model,
+                                                &options.config);
+            if options.cuda {
+                cuda::use_for(model.net_mut());
+            }
+            match options.kind {
+                NetKind::Ssd => {
+                    model.set_input_params(1.0 / 127.5,
+                                           Size2i::new(300, 300),
+                                           Scalar::new(127, 127, 127, 0),
+                                           false)
+                }
+                NetKind::Yolo => {
+                    model.set_input_params(1.0 / 255.0,
+                                           Size2i::new(416, 416),
+                                           Scalar::new(0, 0, 0, 0),
+                                           true)
+                }
+            }
+            Detector { model }
+        }
+
+        /// The class, score and box of every detection scoring at least
+        /// `confidence`.
+        pub fn detect(&mut self,
+                      frame: &Mat,
+                      confidence: f32)
+                      -> Vec<(i32, f32, Rect)> {
+            let (classes, scores, boxes) =
+                self.model.detect(frame, confidence, NMS_THRESHOLD);
+            classes.into_iter()
+                .zip(scores)
+                .zip(boxes)
+                .map(|((class, score), bbox)| (class, score, bbox))
+                .collect()
+        }
+    }
+}
+
+#[cfg(not(feature = "dnn"))]
+mod detector {
+    use rust_vision::{Mat, Rect};
+    use super::DnnOptions;
+
+    /// Without the `dnn` feature, there is no network to run.
+    pub struct Detector;
+
+    impl Detector {
+        pub fn new(_options: &DnnOptions) -> Detector {
+            Detector
+        }
+
+        pub fn detect(&mut self,
+                      _frame: &Mat,
+                      _confidence: f32)
+                      -> Vec<(i32, f32, Rect)> {
+            Vec::new()
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! CAMShift and CSRT together, for long unattended runs.
+//!
+//! Both trackers follow the selection side by side. CAMShift follows the
+//! color of the target, whatever its shape, and CSRT its appearance,
+//! whatever the lighting does to its color, so they rarely lose it at the
+//! same time. While both have lock and agree, the region is their average,
+//! weighted by confidence. When one loses lock, or they part ways, the more
+//! confident one is followed, and the other one is restarted where it is:
+//! CSRT learns the target afresh, while CAMShift keeps the histogram of the
+//! selection, so that it doesn't learn the background.
+use crate::features::Region;
+use crate::params::VisionParams;
+use rust_vision::{Mat, Rect};
+use super::{Camshift, CamshiftOptions, Csrt, Track, Tracker};
+
+/// Confidence below which a tracker has lost the target.
+const LOCK: f64 = 0.2;
+
+/// `Ensemble` fuses a CAMShift and a CSRT tracker.
+pub struct Ensemble {
+    color: Camshift,
+    appearance: Csrt,
+}
+
+impl Ensemble {
+    pub fn new(options: CamshiftOptions) -> Ensemble {
+        Ensemble {
+            color: Camshift::new(options),
+            appearance: Csrt::new(),
+        }
+    }
+
+    /// Moves CAMShift to `window`, keeping its histogram.
+    fn reseat_color(&mut self,
+                    frame: &Mat,
+                    window: Rect,
+                    params: &VisionParams) {
+        let model = self.color.model();
+        self.color.init(frame, window, params);
+        if let Err(e) = self.color.restore(&model) {
+            warn!("Failed to keep the histogram of the ensemble: {}", e);
+        }
+    }
+}
+
+impl Tracker for Ensemble {
+    fn name(&self) -> &'static str {
+        "ensemble"
+    }
+
+    fn init(&mut self, frame: &Mat, selection: Rect, params: &VisionParams) {
+        self.color.init(frame, selection, params);
+        self.appearance.init(frame, selection, params);
+    }
+
+    fn update(&mut self, frame: &Mat, params: &VisionParams) -> Track {
+        let color = self.color.update(frame, params);
+        let appearance = self.appearance.update(frame, params);
+        let color_surer = color.confidence >= appearance.confidence;
+        match (color.confidence >= LOCK, appearance.confidence >= LOCK) {
+            (true, true) if overlap(color.window, appearance.window) => {
+                fuse(color, &appearance)
+            }
+            // Neither can restart the other.
+            (false, false) if color_surer => color,
+            (false, false) => appearance,
+            _ if color_surer => {
+                self.appearance.init(frame, color.window, params);
+                color
+            }
+            _ => {
+                self.reseat_color(frame, appearance.window, params);
+                appearance
+            }
+        }
+    }
+
+    fn reacquire(&mut self,
+                 params: &VisionParams,
+                 min_confidence: f64)
+                 -> Option<Track> {
+        // CSRT is restarted from CAMShift on the next frame.
+        self.color.reacquire(params, min_confidence)
+    }
+
+    fn back_project(&self) -> Option<&Mat> {
+        self.color.back_project()
+    }
+
+    fn model(&self) -> Vec<f32> {
+        self.color.model()
+    }
+
+    fn restore(&mut self, model: &[f32]) -> Result<(), String> {
+        self.color.restore(model)
+    }
+}
+
+/// Whether `a` and `b` share any pixel.
+fn overlap(a: Rect, b: Rect) -> bool {
+    a.x < b.x + b.width && b.x < a.x + a.width && a.y < b.y + b.height &&
+    b.y < a.y + a.height
+}
+
+/// The average of the CAMShift track `color` and the CSRT track
+/// `appearance`, weighted by their confidence, as sure as the surer one.
+/// Only CAMShift measures the rotation.
+fn fuse(color: Track, appearance: &Track) -> Track {
+    let weight = color.confidence /
+                 (color.confidence + appearance.confidence);
+    let mix = |a: f64, b: f64| weight * a + (1.0 - weight) * b;
+    let (a, b) = (&color.region, &appearance.region);
+    let region = Region {
+        cx: mix(a.cx, b.cx),
+        cy: mix(a.cy, b.cy),
+        width: mix(a.width, b.width),
+        height: mix(a.height, b.height),
+        ..color.region
+    };
+    let window = Rect::new((region.cx - region.width / 2.0).round() as i32,
+                           (region.cy - region.height / 2.0).round() as i32,
+                           region.width.round() as i32,
+                           region.height.round() as i32);
+    Track {
+        region,
+        window,
+        confidence: color.confidence.max(appearance.confidence),
+        points: color.points,
+    }
+}
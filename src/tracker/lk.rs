@@ -0,0 +1,134 @@
+//! Sparse optical flow: pyramidal Lucas-Kanade on corners of the selection.
+use crate::features::Region;
+use crate::params::VisionParams;
+use rust_vision::{ColorConversionCodes, Mat, Point2f, Rect, Size2i};
+use super::{Track, Tracker};
+
+/// Most corners picked inside the selection.
+const MAX_POINTS: i32 = 16;
+/// Minimal corner quality, relative to the best corner.
+const QUALITY: f64 = 0.01;
+/// Minimal distance between corners, in pixels.
+const MIN_DISTANCE: f64 = 5.0;
+/// Size of the search window at each pyramid level.
+const WINDOW: i32 = 21;
+/// Number of pyramid levels above the frame.
+const LEVELS: i32 = 3;
+
+/// `LucasKanade` tracks corners found inside the selection with pyramidal
+/// Lucas-Kanade optical flow. The region is centered on the mean of the
+/// points that are still tracked and spans their bounding box.
+pub struct LucasKanade {
+    prev: Mat,
+    points: Vec<Point2f>,
+    /// Number of points found at initialization.
+    initial: usize,
+}
+
+impl LucasKanade {
+    pub fn new() -> LucasKanade {
+        LucasKanade {
+            prev: Mat::new(),
+            points: Vec::new(),
+            initial: 0,
+        }
+    }
+
+    fn track(&self) -> Track {
+        let (region, window) = region_of(&self.points);
+        let confidence = if self.initial > 0 {
+            self.points.len() as f64 / self.initial as f64
+        } else {
+            0.0
+        };
+        Track {
+            region,
+            window,
+            confidence,
+            points: self.points.clone(),
+        }
+    }
+}
+
+impl Default for LucasKanade {
+    fn default() -> LucasKanade {
+        LucasKanade::new()
+    }
+}
+
+impl Tracker for LucasKanade {
+    fn name(&self) -> &'static str {
+        "lk"
+    }
+
+    fn init(&mut self, frame: &Mat, selection: Rect, _: &VisionParams) {
+        let gray = frame.cvt_color(ColorConversionCodes::BGR2GRAY);
+        let corners = gray.roi(selection)
+            .good_features_to_track(MAX_POINTS, QUALITY, MIN_DISTANCE);
+        self.points = corners.iter()
+            .map(|p| {
+                Point2f::new(p.x + selection.x as f32, p.y + selection.y as f32)
+            })
+            .collect();
+        if self.points.is_empty() {
+            // A flat selection has no corners; follow its center instead.
+            info!("No corners in the selection, tracking its center");
+            let x = selection.x + selection.width / 2;
+            let y = selection.y + selection.height / 2;
+            self.points.push(Point2f::new(x as f32, y as f32));
+        }
+        self.initial = self.points.len();
+        self.prev = gray;
+    }
+
+    fn update(&mut self, frame: &Mat, _: &VisionParams) -> Track {
+        let gray = frame.cvt_color(ColorConversionCodes::BGR2GRAY);
+        if !self.points.is_empty() {
+            let (next, found) =
+                self.prev.calc_optical_flow_pyr_lk(&gray,
+                                                   &self.points,
+                                                   Size2i::new(WINDOW, WINDOW),
+                                                   LEVELS);
+            self.points = next.into_iter()
+                .zip(found)
+                .filter(|&(_, ok)| ok)
+                .map(|(p, _)| p)
+                .collect();
+        }
+        self.prev = gray;
+        self.track()
+    }
+}
+
+/// The region around `points`, centered on their mean and spanning their
+/// bounding box, and the area searched for them in the next frame. Both are
+/// empty if there are no points.
+fn region_of(points: &[Point2f]) -> (Region, Rect) {
+    if points.is_empty() {
+        return (Region::from_rect(Rect::default()), Rect::default());
+    }
+    let n = points.len() as f64;
+    let cx = points.iter().map(|p| p.x as f64).sum::<f64>() / n;
+    let cy = points.iter().map(|p| p.y as f64).sum::<f64>() / n;
+    let min_x = points.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+    let max_x = points.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+    let min_y = points.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+    let max_y = points.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+    // A single point still gets a one pixel box.
+    let width = (max_x - min_x).max(1.0) as f64;
+    let height = (max_y - min_y).max(1.0) as f64;
+    let region = Region {
+        cx,
+        cy,
+        width,
+        height,
+        angle: 0.0,
+        aspect: width / height,
+        axes: [width, height],
+    };
+    let window = Rect::new(min_x as i32 - WINDOW / 2,
+                           min_y as i32 - WINDOW / 2,
+                           (max_x - min_x) as i32 + WINDOW,
+                           (max_y - min_y) as i32 + WINDOW);
+    (region, window)
+}
@@ -0,0 +1,381 @@
+//! Tracker backends.
+//!
+//! Every backend implements `Tracker`: it is initialized from a selected
+//! rectangle and then reports a `Region` for every frame, from which the same
+//! output features are extracted no matter which backend is used.
+//!
+//! - `camshift` (default): CAMShift on the hue histogram of the selection.
+//!   Fast and rotation-aware, but needs a target with a distinctive color.
+//!   A hue-saturation histogram tells apart colors of the same hue. With
+//!   `--opencl`, in builds with the `opencl` feature, the per-pixel work
+//!   runs on the GPU, or on the CPU as usual if there is no OpenCL device.
+//! - `kcf`: OpenCV's kernelized correlation filter tracker.
+//! - `csrt`: OpenCV's discriminative correlation filter tracker with channel
+//!   and spatial reliability. Slower than KCF but more accurate.
+//! - `mosse`: OpenCV's MOSSE tracker. A single correlation filter on the
+//!   gray frame; much cheaper than the others, which makes it the choice for
+//!   low-power machines such as a Raspberry Pi.
+//! - `lk`: pyramidal Lucas-Kanade optical flow on corners found inside the
+//!   selection. Precise enough for small targets such as finger tips; the
+//!   individual points are available in `Track::points`.
+//! - `mog2` and `knn`: background subtraction for fixed cameras. The largest
+//!   moving blob is tracked, whatever its color; no selection is needed, but
+//!   one restricts the search to it. The adaptation rate of the background
+//!   model is the `learning_rate` parameter.
+//! - `dnn`: an OpenCV DNN object detector (MobileNet-SSD or YOLO) looks for
+//!   objects of one class every few frames, and MOSSE follows the target in
+//!   between. No selection is needed.
+//! - `template`: normalized cross-correlation of the selected patch within a
+//!   window around its last position. Stable on rigid, textured targets.
+//! - `ensemble`: CAMShift and CSRT side by side, fused while both have lock,
+//!   each restarting the other when it loses it. Slower than CSRT alone, but
+//!   the most robust for long unattended runs.
+//!
+//! Other crates add backends of their own with `register_tracker`, which
+//! makes them selectable by name like the built-in ones.
+use crate::features::Region;
+use crate::params::VisionParams;
+use rust_vision::{Mat, Point2f, Rect};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+mod background;
+mod camshift;
+mod dnn;
+mod ensemble;
+mod lk;
+mod opencv;
+mod template;
+
+pub use self::background::Background;
+pub use self::camshift::{Camshift, CamshiftOptions, OPENCL_BUILT,
+                         enable_opencl};
+pub use self::dnn::{BUILT as DNN_BUILT, Dnn, DnnOptions, VOC_LABELS};
+pub use self::ensemble::Ensemble;
+pub use self::lk::LucasKanade;
+pub use self::opencv::{Csrt, Kcf, Mosse};
+pub use self::template::{TemplateMatch, TemplateOptions};
+
+/// `Track` is the result of tracking one frame.
+#[derive(Clone, Debug)]
+pub struct Track {
+    /// The tracked region.
+    pub region: Region,
+    /// The window the target was found in, around which the next frame is
+    /// searched.
+    pub window: Rect,
+    /// How sure the tracker is that it is locked onto the target, in
+    /// `[0, 1]`.
+    pub confidence: f64,
+    /// The individual points, for trackers that follow points rather than
+    /// a region. Empty otherwise.
+    pub points: Vec<Point2f>,
+}
+
+/// `Tracker` is implemented by every tracking backend.
+pub trait Tracker {
+    /// The backend's name, as given to `--tracker`.
+    fn name(&self) -> &'static str;
+
+    /// Starts tracking `selection` in the (BGR) `frame`.
+    fn init(&mut self, frame: &Mat, selection: Rect, params: &VisionParams);
+
+    /// Tracks the target in the next frame.
+    fn update(&mut self, frame: &Mat, params: &VisionParams) -> Track;
+
+    /// Searches the whole frame for the target after it was lost, using the
+    /// frame of the last `update`. Backends that can't do that return
+    /// `None`, which is the default.
+    fn reacquire(&mut self,
+                 _params: &VisionParams,
+                 _min_confidence: f64)
+                 -> Option<Track> {
+        None
+    }
+
+    /// The backprojection of the last frame, for backends that track one.
+    fn back_project(&self) -> Option<&Mat> {
+        None
+    }
+
+    /// The model of the target, to be saved in a profile. Empty for
+    /// backends whose model can't be saved, which is the default.
+    fn model(&self) -> Vec<f32> {
+        Vec::new()
+    }
+
+    /// Replaces the model built by `init` with a saved one.
+    fn restore(&mut self, _model: &[f32]) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The available backends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Camshift,
+    Kcf,
+    Csrt,
+    Mosse,
+    Lk,
+    Mog2,
+    Knn,
+    Template,
+    Dnn,
+    Ensemble,
+    /// A backend added with `register_tracker`.
+    Plugin(&'static str),
+}
+
+/// Creates an uninitialized tracker of a registered backend.
+pub type Factory = Box<dyn Fn() -> Box<dyn Tracker> + Send>;
+
+/// The backends added with `register_tracker`, by name.
+static PLUGINS: Mutex<Vec<(&'static str, Factory)>> = Mutex::new(Vec::new());
+
+/// Adds a backend, which `--tracker name` then selects, as do the `bench`
+/// and `evaluate` subcommands. `factory` is called for every tracker that
+/// is needed. Register backends before calling `esp_vision::main`.
+pub fn register_tracker<F>(name: &'static str, factory: F) -> Result<(), String>
+    where F: Fn() -> Box<dyn Tracker> + Send + 'static
+{
+    let separator = |c: char| c == ',' || c.is_whitespace();
+    if name.is_empty() || name.contains(separator) {
+        return Err(format!("invalid tracker name `{}`", name));
+    }
+    if name.parse::<Backend>().is_ok() {
+        return Err(format!("a tracker named `{}` already exists", name));
+    }
+    PLUGINS.lock().unwrap().push((name, Box::new(factory)));
+    Ok(())
+}
+
+/// Settings of the backends that have any.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackerOptions {
+    pub camshift: CamshiftOptions,
+    pub template: TemplateOptions,
+    pub dnn: DnnOptions,
+}
+
+impl Backend {
+    /// Creates an uninitialized tracker.
+    pub fn create(&self, options: &TrackerOptions) -> Box<dyn Tracker> {
+        match *self {
+            Backend::Camshift => {
+                Box::new(Camshift::new(options.camshift.clone()))
+            }
+            Backend::Kcf => Box::new(Kcf::new()),
+            Backend::Csrt => Box::new(Csrt::new()),
+            Backend::Mosse => Box::new(Mosse::new()),
+            Backend::Lk => Box::new(LucasKanade::new()),
+            Backend::Mog2 => Box::new(Background::mog2()),
+            Backend::Knn => Box::new(Background::knn()),
+            Backend::Template => {
+                Box::new(TemplateMatch::new(options.template.clone()))
+            }
+            Backend::Dnn => Box::new(Dnn::new(options.dnn.clone())),
+            Backend::Ensemble => {
+                Box::new(Ensemble::new(options.camshift.clone()))
+            }
+            Backend::Plugin(name) => {
+                let plugins = PLUGINS.lock().unwrap();
+                let &(_, ref factory) = plugins.iter()
+                    .find(|&&(n, _)| n == name)
+                    .expect("trackers are parsed from registered names");
+                factory()
+            }
+        }
+    }
+
+    /// Whether tracking has to wait for a selection. Backends that don't
+    /// need one start on the whole frame right away.
+    pub fn needs_selection(&self) -> bool {
+        ![Backend::Mog2, Backend::Knn, Backend::Dnn].contains(self)
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backend, String> {
+        match s {
+            "camshift" => Ok(Backend::Camshift),
+            "kcf" => Ok(Backend::Kcf),
+            "csrt" => Ok(Backend::Csrt),
+            "mosse" => Ok(Backend::Mosse),
+            "lk" => Ok(Backend::Lk),
+            "mog2" => Ok(Backend::Mog2),
+            "knn" => Ok(Backend::Knn),
+            "template" => Ok(Backend::Template),
+            "dnn" => Ok(Backend::Dnn),
+            "ensemble" => Ok(Backend::Ensemble),
+            _ => {
+                PLUGINS.lock()
+                    .unwrap()
+                    .iter()
+                    .find(|&&(name, _)| name == s)
+                    .map(|&(name, _)| Backend::Plugin(name))
+                    .ok_or_else(|| format!("unknown tracker `{}`", s))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Backend::Camshift => "camshift",
+            Backend::Kcf => "kcf",
+            Backend::Csrt => "csrt",
+            Backend::Mosse => "mosse",
+            Backend::Lk => "lk",
+            Backend::Mog2 => "mog2",
+            Backend::Knn => "knn",
+            Backend::Template => "template",
+            Backend::Dnn => "dnn",
+            Backend::Ensemble => "ensemble",
+            Backend::Plugin(name) => name,
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Intersection of `rect` with a `cols` x `rows` image. Empty if they don't
+/// overlap.
+pub fn clip(rect: Rect, cols: i32, rows: i32) -> Rect {
+    let x0 = rect.x.max(0);
+    let y0 = rect.y.max(0);
+    let x1 = (rect.x + rect.width).min(cols);
+    let y1 = (rect.y + rect.height).min(rows);
+    Rect::new(x0, y0, (x1 - x0).max(0), (y1 - y0).max(0))
+}
+
+/// A search window that is safe to track from in a `cols` x `rows` image:
+/// `rect` clipped to the image and grown around its center to at least
+/// `min_size` pixels on each side, as far as the image allows. A window
+/// that collapsed, or left the image, is re-inflated at the nearest point of
+/// the image. Empty only for an empty image.
+pub fn sanitize(rect: Rect, cols: i32, rows: i32, min_size: i32) -> Rect {
+    if cols <= 0 || rows <= 0 {
+        return Rect::default();
+    }
+    let clipped = clip(rect, cols, rows);
+    let (cx, cy) = if clipped.width > 0 && clipped.height > 0 {
+        (clipped.x + clipped.width / 2, clipped.y + clipped.height / 2)
+    } else {
+        ((rect.x + rect.width / 2).clamp(0, cols - 1),
+         (rect.y + rect.height / 2).clamp(0, rows - 1))
+    };
+    let width = clipped.width.max(min_size).clamp(1, cols);
+    let height = clipped.height.max(min_size).clamp(1, rows);
+    Rect::new((cx - width / 2).clamp(0, cols - width),
+              (cy - height / 2).clamp(0, rows - height),
+              width,
+              height)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::features::Region;
+    use crate::params::VisionParams;
+    use rust_vision::{Mat, Rect};
+    use super::{Backend, Track, Tracker, TrackerOptions, register_tracker,
+                sanitize};
+
+    /// A backend that stays where it was started.
+    struct Still(Rect);
+
+    impl Tracker for Still {
+        fn name(&self) -> &'static str {
+            "still"
+        }
+
+        fn init(&mut self, _: &Mat, selection: Rect, _: &VisionParams) {
+            self.0 = selection;
+        }
+
+        fn update(&mut self, _: &Mat, _: &VisionParams) -> Track {
+            Track {
+                region: Region::from_rect(self.0),
+                window: self.0,
+                confidence: 1.0,
+                points: Vec::new(),
+            }
+        }
+    }
+
+    #[test]
+    fn selects_registered_trackers_by_name() {
+        assert!("still".parse::<Backend>().is_err());
+        register_tracker("still", || Box::new(Still(Rect::default())))
+            .unwrap();
+        let backend: Backend = "still".parse().unwrap();
+        assert_eq!(backend, Backend::Plugin("still"));
+        assert_eq!(backend.to_string(), "still");
+        assert!(backend.needs_selection());
+        let mut tracker = backend.create(&TrackerOptions::default());
+        let selection = Rect::new(10, 20, 30, 40);
+        let params = VisionParams::default();
+        tracker.init(&Mat::new(), selection, &params);
+        assert_eq!(tracker.update(&Mat::new(), &params).window, selection);
+    }
+
+    #[test]
+    fn rejects_names_taken_or_unusable() {
+        assert!(register_tracker("kcf", || Box::new(Still(Rect::default())))
+            .is_err());
+        assert!(register_tracker("", || Box::new(Still(Rect::default())))
+            .is_err());
+        assert!(register_tracker("a,b", || Box::new(Still(Rect::default())))
+            .is_err());
+    }
+
+    #[test]
+    fn keeps_windows_inside_the_image() {
+        let rect = Rect::new(100, 50, 40, 30);
+        assert_eq!(sanitize(rect, 640, 480, 8), rect);
+    }
+
+    #[test]
+    fn clamps_windows_across_the_edge() {
+        assert_eq!(sanitize(Rect::new(-20, 460, 60, 40), 640, 480, 8),
+                   Rect::new(0, 460, 40, 20));
+        assert_eq!(sanitize(Rect::new(620, -5, 40, 40), 640, 480, 8),
+                   Rect::new(620, 0, 20, 35));
+    }
+
+    #[test]
+    fn grows_small_windows_to_the_minimum_size() {
+        assert_eq!(sanitize(Rect::new(100, 100, 2, 30), 640, 480, 10),
+                   Rect::new(96, 100, 10, 30));
+    }
+
+    #[test]
+    fn keeps_grown_windows_inside_the_image() {
+        assert_eq!(sanitize(Rect::new(638, 0, 2, 2), 640, 480, 10),
+                   Rect::new(630, 0, 10, 10));
+    }
+
+    #[test]
+    fn re_inflates_collapsed_windows() {
+        assert_eq!(sanitize(Rect::new(200, 100, 0, 0), 640, 480, 10),
+                   Rect::new(195, 95, 10, 10));
+    }
+
+    #[test]
+    fn brings_windows_outside_the_image_back() {
+        assert_eq!(sanitize(Rect::new(700, 500, 20, 20), 640, 480, 10),
+                   Rect::new(630, 470, 10, 10));
+        assert_eq!(sanitize(Rect::new(-50, 200, 10, 10), 640, 480, 10),
+                   Rect::new(0, 200, 10, 10));
+    }
+
+    #[test]
+    fn never_exceeds_the_image() {
+        assert_eq!(sanitize(Rect::new(0, 0, 4, 4), 6, 5, 10),
+                   Rect::new(0, 0, 6, 5));
+        assert_eq!(sanitize(Rect::new(5, 5, 1, 1), 0, 0, 10), Rect::default());
+    }
+}
@@ -0,0 +1,120 @@
+//! Correlation filter trackers from OpenCV's tracking module.
+use crate::features::Region;
+use crate::params::VisionParams;
+use rust_vision::{Mat, Rect, TrackerCSRT, TrackerKCF, TrackerMOSSE};
+use super::{Track, Tracker};
+
+/// The part of OpenCV's `cv::Tracker` interface we rely on.
+pub trait CvTracker {
+    fn create() -> Self;
+    fn init(&mut self, frame: &Mat, bbox: Rect) -> bool;
+    fn update(&mut self, frame: &Mat, bbox: &mut Rect) -> bool;
+}
+
+impl CvTracker for TrackerKCF {
+    fn create() -> TrackerKCF {
+        TrackerKCF::new()
+    }
+    fn init(&mut self, frame: &Mat, bbox: Rect) -> bool {
+        TrackerKCF::init(self, frame, bbox)
+    }
+    fn update(&mut self, frame: &Mat, bbox: &mut Rect) -> bool {
+        TrackerKCF::update(self, frame, bbox)
+    }
+}
+
+impl CvTracker for TrackerCSRT {
+    fn create() -> TrackerCSRT {
+        TrackerCSRT::new()
+    }
+    fn init(&mut self, frame: &Mat, bbox: Rect) -> bool {
+        TrackerCSRT::init(self, frame, bbox)
+    }
+    fn update(&mut self, frame: &Mat, bbox: &mut Rect) -> bool {
+        TrackerCSRT::update(self, frame, bbox)
+    }
+}
+
+impl CvTracker for TrackerMOSSE {
+    fn create() -> TrackerMOSSE {
+        TrackerMOSSE::new()
+    }
+    fn init(&mut self, frame: &Mat, bbox: Rect) -> bool {
+        TrackerMOSSE::init(self, frame, bbox)
+    }
+    fn update(&mut self, frame: &Mat, bbox: &mut Rect) -> bool {
+        TrackerMOSSE::update(self, frame, bbox)
+    }
+}
+
+/// `OpenCvTracker` adapts a `CvTracker` to our `Tracker`. OpenCV only tells
+/// us whether the update succeeded, so the confidence is either 1 or 0.
+pub struct OpenCvTracker<T> {
+    name: &'static str,
+    inner: Option<T>,
+    bbox: Rect,
+}
+
+/// OpenCV's KCF tracker.
+pub type Kcf = OpenCvTracker<TrackerKCF>;
+/// OpenCV's CSRT tracker.
+pub type Csrt = OpenCvTracker<TrackerCSRT>;
+/// OpenCV's MOSSE tracker.
+pub type Mosse = OpenCvTracker<TrackerMOSSE>;
+
+impl Kcf {
+    pub fn new() -> Kcf {
+        OpenCvTracker::with_name("kcf")
+    }
+}
+
+impl Csrt {
+    pub fn new() -> Csrt {
+        OpenCvTracker::with_name("csrt")
+    }
+}
+
+impl Mosse {
+    pub fn new() -> Mosse {
+        OpenCvTracker::with_name("mosse")
+    }
+}
+
+impl<T: CvTracker> OpenCvTracker<T> {
+    fn with_name(name: &'static str) -> OpenCvTracker<T> {
+        OpenCvTracker {
+            name,
+            inner: None,
+            bbox: Rect::default(),
+        }
+    }
+}
+
+impl<T: CvTracker> Tracker for OpenCvTracker<T> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn init(&mut self, frame: &Mat, selection: Rect, _: &VisionParams) {
+        // OpenCV trackers can't be re-initialized; start from a fresh one.
+        let mut inner = T::create();
+        if !inner.init(frame, selection) {
+            warn!("Failed to initialize the {} tracker", self.name);
+        }
+        self.inner = Some(inner);
+        self.bbox = selection;
+    }
+
+    fn update(&mut self, frame: &Mat, _: &VisionParams) -> Track {
+        let ok = match self.inner {
+            Some(ref mut inner) => inner.update(frame, &mut self.bbox),
+            None => false,
+        };
+        Track {
+            region: Region::from_rect(self.bbox),
+            window: self.bbox,
+            confidence: if ok { 1.0 } else { 0.0 },
+            points: Vec::new(),
+        }
+    }
+}
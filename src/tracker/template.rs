@@ -0,0 +1,130 @@
+//! Normalized cross-correlation template matching.
+use crate::features::Region;
+use crate::params::VisionParams;
+use rust_vision::{ColorConversionCodes, Mat, Rect, TemplateMatchModes};
+use std::str::FromStr;
+use super::{Track, Tracker, clip};
+
+/// When the template is replaced by the latest match.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UpdatePolicy {
+    /// Keep the selected template. Never drifts, but fails once the target
+    /// changes its appearance.
+    Never,
+    /// Take every match. Follows appearance changes, but drifts.
+    Always,
+    /// Take matches that score at least `update_confidence`.
+    Confident,
+}
+
+impl FromStr for UpdatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<UpdatePolicy, String> {
+        match s {
+            "never" => Ok(UpdatePolicy::Never),
+            "always" => Ok(UpdatePolicy::Always),
+            "confident" => Ok(UpdatePolicy::Confident),
+            _ => Err(format!("unknown template update policy `{}`", s)),
+        }
+    }
+}
+
+/// Settings of the `template` tracker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemplateOptions {
+    /// Size of the search window relative to the template, on each axis.
+    pub search_expansion: f64,
+    pub update: UpdatePolicy,
+    /// Score a match needs for the `Confident` policy.
+    pub update_confidence: f64,
+}
+
+impl Default for TemplateOptions {
+    fn default() -> TemplateOptions {
+        TemplateOptions {
+            search_expansion: 2.0,
+            update: UpdatePolicy::Confident,
+            update_confidence: 0.8,
+        }
+    }
+}
+
+/// `TemplateMatch` looks for the selected patch around its last position.
+/// It suits rigid, textured targets such as a printed card, whose hue
+/// histogram says little about them.
+pub struct TemplateMatch {
+    options: TemplateOptions,
+    template: Mat,
+    bbox: Rect,
+}
+
+impl TemplateMatch {
+    pub fn new(options: TemplateOptions) -> TemplateMatch {
+        TemplateMatch {
+            options,
+            template: Mat::new(),
+            bbox: Rect::default(),
+        }
+    }
+
+    /// The area searched around the last match.
+    fn search_window(&self, cols: i32, rows: i32) -> Rect {
+        let grow = self.options.search_expansion - 1.0;
+        let dx = (self.bbox.width as f64 * grow / 2.0).round() as i32;
+        let dy = (self.bbox.height as f64 * grow / 2.0).round() as i32;
+        clip(Rect::new(self.bbox.x - dx,
+                       self.bbox.y - dy,
+                       self.bbox.width + 2 * dx,
+                       self.bbox.height + 2 * dy),
+             cols,
+             rows)
+    }
+}
+
+impl Tracker for TemplateMatch {
+    fn name(&self) -> &'static str {
+        "template"
+    }
+
+    fn init(&mut self, frame: &Mat, selection: Rect, _: &VisionParams) {
+        let gray = frame.cvt_color(ColorConversionCodes::BGR2GRAY);
+        self.bbox = clip(selection, gray.cols, gray.rows);
+        self.template = gray.roi(self.bbox).clone();
+    }
+
+    fn update(&mut self, frame: &Mat, _: &VisionParams) -> Track {
+        let gray = frame.cvt_color(ColorConversionCodes::BGR2GRAY);
+        let search = self.search_window(gray.cols, gray.rows);
+        let fits = search.width >= self.bbox.width &&
+                   search.height >= self.bbox.height &&
+                   self.bbox.width > 0 && self.bbox.height > 0;
+        let mut score = 0.0;
+        if fits {
+            let scores = gray.roi(search)
+                .match_template(&self.template,
+                                TemplateMatchModes::CCoeffNormed);
+            let (_, best, _, at) = scores.min_max_loc();
+            self.bbox.x = search.x + at.x;
+            self.bbox.y = search.y + at.y;
+            score = best.max(0.0);
+
+            let update = match self.options.update {
+                UpdatePolicy::Never => false,
+                UpdatePolicy::Always => true,
+                UpdatePolicy::Confident => {
+                    score >= self.options.update_confidence
+                }
+            };
+            if update {
+                self.template = gray.roi(self.bbox).clone();
+            }
+        }
+        Track {
+            region: Region::from_rect(self.bbox),
+            window: self.bbox,
+            confidence: score,
+            points: Vec::new(),
+        }
+    }
+}
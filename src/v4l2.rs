@@ -0,0 +1,340 @@
+//! Cameras read through V4L2 directly, with `--input v4l2:DEVICE` in builds
+//! with the `rpi` feature.
+//!
+//! On the Raspberry Pi, OpenCV's capture of the CSI camera drops frames,
+//! stalls or fails to open depending on the backend it picks. The `v4l2`
+//! input skips OpenCV's backends: it streams the device, `/dev/videoN` for
+//! `v4l2:N` or any path, from memory-mapped buffers and converts the frames
+//! into images itself. It asks for `--pixel-format` (MJPG unless given),
+//! `--resolution` and `--fps`, with a warning for what the driver changes;
+//! MJPG, YUYV, BGR3 and RGB3 frames are understood. The exposure controls
+//! are not supported.
+//!
+//! The CSI camera is a V4L2 device with the legacy camera stack; on newer
+//! Raspberry Pi OS releases, run through `libcamerify` instead:
+//!
+//! ```text
+//! cargo build --release --features rpi
+//! libcamerify esp-vision --input v4l2:0
+//! ```
+use crate::input::CaptureSettings;
+use rust_vision::{Mat, imdecode};
+
+/// Whether this build has the `rpi` feature.
+pub const BUILT: bool = cfg!(feature = "rpi");
+/// The pixel format asked for unless `--pixel-format` says otherwise.
+const DEFAULT_FOURCC: [u8; 4] = *b"MJPG";
+/// Size of the headers of a BMP image.
+const BMP_HEADER: usize = 14 + 40;
+
+/// The device of `v4l2:DEVICE`: `/dev/videoN` for an index `N`, the first
+/// one if empty, or else a path.
+pub fn device_path(device: &str) -> String {
+    if device.is_empty() {
+        return "/dev/video0".to_string();
+    }
+    match device.parse::<u32>() {
+        Ok(index) => format!("/dev/video{}", index),
+        Err(_) => device.to_string(),
+    }
+}
+
+/// `V4l2Camera` streams the frames of a V4L2 device.
+pub struct V4l2Camera {
+    camera: device::Camera,
+}
+
+impl V4l2Camera {
+    /// Opens `device` and starts streaming with `settings`.
+    pub fn open(device: &str,
+                settings: &CaptureSettings)
+                -> Result<V4l2Camera, String> {
+        if settings.exposure.is_some() || settings.gain.is_some() ||
+           settings.white_balance.is_some() || settings.lock {
+            warn!("The v4l2 input ignores the exposure, gain and white \
+                   balance settings");
+        }
+        let fourcc = match settings.fourcc {
+            Some(fourcc) => {
+                let mut code = [0; 4];
+                for (b, c) in code.iter_mut().zip(&fourcc) {
+                    *b = *c as u8;
+                }
+                code
+            }
+            None => DEFAULT_FOURCC,
+        };
+        let path = device_path(device);
+        let camera = device::Camera::open(&path, fourcc, settings)?;
+        let (actual, width, height) = camera.format();
+        if actual != fourcc {
+            warn!("Asked for pixel format {}, the camera delivers {}",
+                  String::from_utf8_lossy(&fourcc),
+                  String::from_utf8_lossy(&actual));
+        }
+        if !supported(actual) {
+            return Err(format!("{} delivers {}, which the v4l2 input can't \
+                                convert; try --pixel-format MJPG or YUYV",
+                               path,
+                               String::from_utf8_lossy(&actual)));
+        }
+        info!("Streaming {} in {} at {}x{}",
+              path,
+              String::from_utf8_lossy(&actual),
+              width,
+              height);
+        Ok(V4l2Camera { camera })
+    }
+
+    /// Converts the next frame into `frame`. Returns `false` if the device
+    /// fails.
+    pub fn read(&mut self, frame: &Mat) -> bool {
+        let (fourcc, width, height) = self.camera.format();
+        loop {
+            let image = match self.camera.next() {
+                Ok(data) => encode(fourcc, width, height, data),
+                Err(e) => {
+                    warn!("Cannot read from the camera: {}", e);
+                    return false;
+                }
+            };
+            // Short frames and damaged JPEG images are skipped.
+            let image = match image {
+                Some(image) => imdecode(&image),
+                None => continue,
+            };
+            if image.cols > 0 {
+                image.copy_to(frame);
+                return true;
+            }
+        }
+    }
+}
+
+/// Whether `encode` converts frames in `fourcc`.
+fn supported(fourcc: [u8; 4]) -> bool {
+    matches!(&fourcc, b"MJPG" | b"JPEG" | b"YUYV" | b"BGR3" | b"RGB3")
+}
+
+/// The frame `data` of `width` by `height` pixels in `fourcc` as an image
+/// file `imdecode` reads: JPEG as it is, or else a BMP. `None` if the format
+/// is unknown or the frame too short.
+fn encode(fourcc: [u8; 4],
+          width: usize,
+          height: usize,
+          data: &[u8])
+          -> Option<Vec<u8>> {
+    let pixels = width * height;
+    match &fourcc {
+        b"MJPG" | b"JPEG" => Some(data.to_vec()),
+        b"YUYV" => {
+            let bgr = yuyv_to_bgr(data.get(..pixels * 2)?);
+            Some(bmp(width, height, &bgr))
+        }
+        b"BGR3" => Some(bmp(width, height, data.get(..pixels * 3)?)),
+        b"RGB3" => {
+            let mut bgr = data.get(..pixels * 3)?.to_vec();
+            for pixel in bgr.chunks_mut(3) {
+                pixel.swap(0, 2);
+            }
+            Some(bmp(width, height, &bgr))
+        }
+        _ => None,
+    }
+}
+
+/// Converts YUYV, where every two pixels share their U and V, to blue, green
+/// and red with the BT.601 coefficients.
+fn yuyv_to_bgr(yuyv: &[u8]) -> Vec<u8> {
+    let mut bgr = Vec::with_capacity(yuyv.len() / 2 * 3);
+    for pair in yuyv.chunks(4) {
+        if let [y0, u, y1, v] = *pair {
+            let d = i32::from(u) - 128;
+            let e = i32::from(v) - 128;
+            for &y in &[y0, y1] {
+                let c = 298 * (i32::from(y) - 16) + 128;
+                let clamp = |x: i32| (x >> 8).max(0).min(255) as u8;
+                bgr.push(clamp(c + 516 * d));
+                bgr.push(clamp(c - 100 * d - 208 * e));
+                bgr.push(clamp(c + 409 * e));
+            }
+        }
+    }
+    bgr
+}
+
+/// The BMP image of the rows of `bgr` pixels, from the top.
+pub fn bmp(width: usize, height: usize, bgr: &[u8]) -> Vec<u8> {
+    // Rows are padded to a multiple of 4 bytes.
+    let stride = (width * 3 + 3) / 4 * 4;
+    let size = BMP_HEADER + stride * height;
+    let mut out = Vec::with_capacity(size);
+    out.extend_from_slice(b"BM");
+    out.extend_from_slice(&(size as u32).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&(BMP_HEADER as u32).to_le_bytes());
+    out.extend_from_slice(&40u32.to_le_bytes());
+    out.extend_from_slice(&(width as i32).to_le_bytes());
+    // A negative height stores the rows from the top.
+    out.extend_from_slice(&(-(height as i32)).to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&24u16.to_le_bytes());
+    // No compression, the size of the pixels, 72 dpi and no palette.
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&((stride * height) as u32).to_le_bytes());
+    out.extend_from_slice(&2835u32.to_le_bytes());
+    out.extend_from_slice(&2835u32.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    for row in bgr.chunks(width * 3) {
+        out.extend_from_slice(row);
+        out.resize(out.len() + stride - row.len(), 0);
+    }
+    out
+}
+
+#[cfg(not(feature = "rpi"))]
+mod device {
+    use crate::input::CaptureSettings;
+    use std::io;
+
+    /// Cameras can't be opened without the `rpi` feature.
+    pub enum Camera {}
+
+    impl Camera {
+        pub fn open(_path: &str,
+                    _fourcc: [u8; 4],
+                    _settings: &CaptureSettings)
+                    -> Result<Camera, String> {
+            Err("this build has no v4l2 input; build with `--features rpi`"
+                .to_string())
+        }
+
+        pub fn format(&self) -> ([u8; 4], usize, usize) {
+            match *self {}
+        }
+
+        pub fn next(&mut self) -> io::Result<&[u8]> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(feature = "rpi")]
+mod device {
+    use crate::input::CaptureSettings;
+    use std::io;
+    use v4l::buffer::Type;
+    use v4l::io::mmap::Stream;
+    use v4l::io::traits::CaptureStream;
+    use v4l::video::Capture;
+    use v4l::video::capture::Parameters;
+    use v4l::{Device, FourCC};
+
+    /// Buffers the driver fills while the pipeline works on a frame.
+    const BUFFERS: u32 = 4;
+
+    pub struct Camera {
+        stream: Stream<'static>,
+        fourcc: [u8; 4],
+        width: usize,
+        height: usize,
+    }
+
+    impl Camera {
+        /// Opens `path` and asks for `fourcc` and the size and frame rate of
+        /// `settings`.
+        pub fn open(path: &str,
+                    fourcc: [u8; 4],
+                    settings: &CaptureSettings)
+                    -> Result<Camera, String> {
+            let failed = |e: io::Error| format!("cannot open {}: {}", path, e);
+            let device = Device::with_path(path).map_err(failed)?;
+            let mut format = device.format().map_err(failed)?;
+            format.fourcc = FourCC::new(&fourcc);
+            if let Some((width, height)) = settings.size {
+                format.width = width as u32;
+                format.height = height as u32;
+            }
+            let format = device.set_format(&format).map_err(failed)?;
+            if let Some((width, height)) = settings.size {
+                if (format.width, format.height) !=
+                   (width as u32, height as u32) {
+                    warn!("Asked for {}x{}, the camera delivers {}x{}",
+                          width,
+                          height,
+                          format.width,
+                          format.height);
+                }
+            }
+            if let Some(fps) = settings.fps {
+                let asked = Parameters::with_fps(fps.round() as u32);
+                let params = device.set_params(&asked).map_err(failed)?;
+                let actual = f64::from(params.interval.denominator) /
+                             f64::from(params.interval.numerator.max(1));
+                if (actual - fps).abs() > 0.5 {
+                    warn!("Asked for {} fps, the camera delivers {}",
+                          fps,
+                          actual);
+                }
+            }
+            let stream =
+                Stream::with_buffers(&device, Type::VideoCapture, BUFFERS)
+                    .map_err(failed)?;
+            Ok(Camera {
+                stream,
+                fourcc: format.fourcc.repr,
+                width: format.width as usize,
+                height: format.height as usize,
+            })
+        }
+
+        /// The pixel format, width and height of the frames.
+        pub fn format(&self) -> ([u8; 4], usize, usize) {
+            (self.fourcc, self.width, self.height)
+        }
+
+        /// The data of the next frame, waiting for it.
+        pub fn next(&mut self) -> io::Result<&[u8]> {
+            let (data, meta) = CaptureStream::next(&mut self.stream)?;
+            // Drivers that don't report the bytes used fill the buffer.
+            let used = match meta.bytesused as usize {
+                0 => data.len(),
+                used => used.min(data.len()),
+            };
+            Ok(&data[..used])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BMP_HEADER, bmp, device_path, encode, yuyv_to_bgr};
+
+    #[test]
+    fn names_devices_by_index_or_path() {
+        assert_eq!(device_path(""), "/dev/video0");
+        assert_eq!(device_path("2"), "/dev/video2");
+        assert_eq!(device_path("/dev/csi"), "/dev/csi");
+    }
+
+    #[test]
+    fn converts_yuyv_with_bt601() {
+        // Black and white, then pure red.
+        let bgr = yuyv_to_bgr(&[16, 128, 235, 128, 81, 90, 81, 240]);
+        assert_eq!(bgr[..6], [0, 0, 0, 255, 255, 255]);
+        let red = &bgr[6..9];
+        assert!(red[0] < 5 && red[1] < 5 && red[2] > 250, "{:?}", red);
+    }
+
+    #[test]
+    fn pads_the_rows_of_bmp_images() {
+        let image = bmp(1, 2, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(image.len(), BMP_HEADER + 2 * 4);
+        assert_eq!(image[BMP_HEADER..], [1, 2, 3, 0, 4, 5, 6, 0]);
+        assert_eq!(encode(*b"RGB3", 1, 1, &[1, 2, 3]).unwrap()[BMP_HEADER..],
+                   [3, 2, 1, 0]);
+        assert_eq!(encode(*b"YUYV", 2, 2, &[0; 4]), None);
+        assert_eq!(encode(*b"GREY", 1, 1, &[0]), None);
+    }
+}
@@ -0,0 +1,143 @@
+//! Recovery from camera failures.
+//!
+//! When a camera is unplugged or its driver hangs, reads fail or return
+//! empty frames. After a few in a row, the capture thread releases the
+//! camera and opens it again, waiting longer between attempts the longer
+//! the outage lasts, from half a second up to ten. Meanwhile nothing is
+//! tracked or sent, and the window shows the last frame with the length of
+//! the outage. `--camera-timeout SECS` gives up after that long, with the
+//! exit code of an unplugged camera; by default the camera is awaited
+//! forever. Network cameras reconnect on their own, and recorded input
+//! simply ends.
+use std::time::{Duration, Instant};
+
+/// Failed reads in a row that make an outage.
+const FAILURES: u32 = 5;
+/// The wait before the first attempt to reopen the camera, doubled after
+/// every failed attempt up to `MAX_DELAY`.
+const FIRST_DELAY: Duration = Duration::from_millis(500);
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+/// What to do after a failed read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Read again.
+    Retry,
+    /// Release the camera, wait this long and open it again.
+    Reopen(Duration),
+    /// Stop reading: the camera is gone for longer than the timeout.
+    GiveUp,
+}
+
+/// `Watchdog` counts failed reads and paces the attempts to reopen.
+#[derive(Debug)]
+pub struct Watchdog {
+    /// How long an outage may last before giving up; `None` never does.
+    timeout: Option<Duration>,
+    /// Failed reads in a row.
+    failures: u32,
+    /// When the reads started failing.
+    since: Option<Instant>,
+    delay: Duration,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Option<Duration>) -> Watchdog {
+        Watchdog {
+            timeout,
+            failures: 0,
+            since: None,
+            delay: FIRST_DELAY,
+        }
+    }
+
+    /// When the current outage started, if the input is out.
+    pub fn outage(&self) -> Option<Instant> {
+        match self.since {
+            Some(since) if self.failures >= FAILURES => Some(since),
+            _ => None,
+        }
+    }
+
+    /// Notes a frame read at `now`. Returns how long the outage it ends
+    /// lasted, if there was one.
+    pub fn read(&mut self, now: Instant) -> Option<Duration> {
+        let outage = self.outage();
+        self.failures = 0;
+        self.since = None;
+        self.delay = FIRST_DELAY;
+        outage.map(|since| now.saturating_duration_since(since))
+    }
+
+    /// Notes a failed read, or a failed attempt to reopen, at `now`.
+    pub fn failed(&mut self, now: Instant) -> Action {
+        self.failures = self.failures.saturating_add(1);
+        let since = *self.since.get_or_insert(now);
+        if self.failures < FAILURES {
+            return Action::Retry;
+        }
+        if let Some(timeout) = self.timeout {
+            if now.saturating_duration_since(since) >= timeout {
+                return Action::GiveUp;
+            }
+        }
+        let delay = self.delay;
+        self.delay = (delay * 2).min(MAX_DELAY);
+        Action::Reopen(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{Action, FAILURES, Watchdog};
+
+    #[test]
+    fn reopens_with_backoff_after_failures_in_a_row() {
+        let mut watchdog = Watchdog::new(None);
+        let now = Instant::now();
+        for _ in 1..FAILURES {
+            assert_eq!(watchdog.failed(now), Action::Retry);
+        }
+        assert_eq!(watchdog.outage(), None);
+        let delays: Vec<_> = (0..7)
+            .map(|_| match watchdog.failed(now) {
+                Action::Reopen(delay) => delay.as_millis(),
+                action => panic!("{:?}", action),
+            })
+            .collect();
+        assert_eq!(delays, [500, 1000, 2000, 4000, 8000, 10000, 10000]);
+        assert_eq!(watchdog.outage(), Some(now));
+        let back = now + Duration::from_secs(3);
+        assert_eq!(watchdog.read(back), Some(Duration::from_secs(3)));
+        assert_eq!(watchdog.outage(), None);
+        // The next outage starts over from the shortest wait.
+        for _ in 1..FAILURES {
+            watchdog.failed(back);
+        }
+        assert_eq!(watchdog.failed(back),
+                   Action::Reopen(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn forgets_isolated_failures() {
+        let mut watchdog = Watchdog::new(None);
+        let now = Instant::now();
+        watchdog.failed(now);
+        assert_eq!(watchdog.read(now), None);
+        for _ in 1..FAILURES {
+            assert_eq!(watchdog.failed(now), Action::Retry);
+        }
+    }
+
+    #[test]
+    fn gives_up_after_the_timeout() {
+        let mut watchdog = Watchdog::new(Some(Duration::from_secs(5)));
+        let now = Instant::now();
+        for _ in 0..FAILURES {
+            watchdog.failed(now);
+        }
+        let later = now + Duration::from_secs(5);
+        assert_eq!(watchdog.failed(later), Action::GiveUp);
+    }
+}
@@ -0,0 +1,101 @@
+//! Windows, trackbars and mouse callbacks, in builds with the `gui` feature.
+//!
+//! OpenCV's highgui is the largest dependency, and often unavailable when
+//! cross-compiling for small ARM boards. Builds without the `gui` feature
+//! only run `--headless`, and leave out `--trackbars` and `calibrate`:
+//!
+//! ```text
+//! cargo build --release --no-default-features
+//! ```
+//!
+//! All calls to highgui go through this module, which does nothing in such
+//! builds.
+use rust_vision::MouseCallbackData;
+
+/// Whether this build has the `gui` feature.
+pub const BUILT: bool = cfg!(feature = "gui");
+
+/// The signature of mouse callbacks: the event code, the position, the
+/// flags and the data given with the callback.
+pub type MouseCallback = fn(i32, i32, i32, i32, MouseCallbackData);
+
+pub use self::imp::*;
+
+#[cfg(feature = "gui")]
+mod imp {
+    use rust_vision::*;
+    use super::MouseCallback;
+
+    /// Opens the window `name`, sized to its frames.
+    pub fn open(name: &str) {
+        highgui_named_window(name, WindowFlags::WindowAutosize);
+    }
+
+    pub fn close(name: &str) {
+        highgui_destroy_window(name);
+    }
+
+    /// Shows `frame` in the window `name`.
+    pub fn show(name: &str, frame: &Mat) {
+        highgui_imshow(name, frame);
+    }
+
+    /// Shows `frame` in the window `name` and waits up to `delay`
+    /// milliseconds for a key. Returns the full code of the key, as
+    /// `waitKeyEx` does, or -1; see `keys::command`.
+    pub fn show_and_wait(name: &str, frame: &Mat, delay: i32) -> i32 {
+        highgui_imshow(name, frame);
+        highgui_wait_key_ex(delay)
+    }
+
+    pub fn set_mouse_callback(name: &str,
+                              callback: MouseCallback,
+                              data: MouseCallbackData) {
+        highgui_set_mouse_callback(name, callback, data);
+    }
+
+    pub fn create_trackbar(name: &str, window: &str, value: i32, max: i32) {
+        highgui_create_trackbar(name, window, value, max);
+    }
+
+    pub fn trackbar_pos(name: &str, window: &str) -> i32 {
+        highgui_get_trackbar_pos(name, window)
+    }
+
+    pub fn set_trackbar_pos(name: &str, window: &str, pos: i32) {
+        highgui_set_trackbar_pos(name, window, pos);
+    }
+}
+
+#[cfg(not(feature = "gui"))]
+mod imp {
+    use rust_vision::{Mat, MouseCallbackData};
+    use super::MouseCallback;
+
+    pub fn open(_name: &str) {}
+
+    pub fn close(_name: &str) {}
+
+    pub fn show(_name: &str, _frame: &Mat) {}
+
+    pub fn show_and_wait(_name: &str, _frame: &Mat, _delay: i32) -> i32 {
+        -1
+    }
+
+    pub fn set_mouse_callback(_name: &str,
+                              _callback: MouseCallback,
+                              _data: MouseCallbackData) {
+    }
+
+    pub fn create_trackbar(_name: &str,
+                           _window: &str,
+                           _value: i32,
+                           _max: i32) {
+    }
+
+    pub fn trackbar_pos(_name: &str, _window: &str) -> i32 {
+        0
+    }
+
+    pub fn set_trackbar_pos(_name: &str, _window: &str, _pos: i32) {}
+}
@@ -0,0 +1,230 @@
+//! Named zones of the frame, and the events of targets entering and leaving
+//! them.
+//!
+//! Many installations care less about where the target is than about which
+//! part of the scene it is in. Zones are rectangles or polygons, in pixels,
+//! given as `[[zones]]` tables in the config file:
+//!
+//! ```toml
+//! [[zones]]
+//! name = "door"
+//! rect = [40, 300, 120, 120]
+//!
+//! [[zones]]
+//! name = "stage"
+//! polygon = [[200, 100], [440, 100], [520, 400], [120, 400]]
+//! ```
+//!
+//! In the track mode, a zone is occupied while the centroid of any target
+//! is inside it. Zones are drawn in the preview, thicker while occupied, and
+//! their events are logged. They reach ESP and other consumers in two ways:
+//!
+//! - `--zone-dims` sends a dimension per zone after those of the targets,
+//!   `zone_NAME`, `1` while the zone is occupied and `0` otherwise.
+//! - `--zone-events ADDR` sends every event as a UDP datagram to `ADDR`,
+//!   one JSON object per event:
+//!   `{"zone":"door","event":"enter","t":12.5}`, then `"exit"`.
+use rust_vision::*;
+use std::io;
+use std::net::UdpSocket;
+
+/// Color of the zones.
+const COLOR: (i32, i32, i32) = (255, 128, 0);
+
+/// A zone as written in the config file: a `rect` or a `polygon`.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneSpec {
+    pub name: String,
+    /// The zone, as `[x, y, width, height]`.
+    pub rect: Option<[i32; 4]>,
+    /// The corners of the zone, as `[x, y]`, in order.
+    pub polygon: Option<Vec<[f64; 2]>>,
+}
+
+impl ZoneSpec {
+    /// The corners of the zone, checking that it has a shape.
+    pub fn corners(&self) -> Result<Vec<[f64; 2]>, String> {
+        match (self.rect, &self.polygon) {
+            (Some(r), None) if r[2] > 0 && r[3] > 0 => {
+                let (x, y) = (f64::from(r[0]), f64::from(r[1]));
+                let (w, h) = (f64::from(r[2]), f64::from(r[3]));
+                Ok(vec![[x, y], [x + w, y], [x + w, y + h], [x, y + h]])
+            }
+            (None, Some(p)) if p.len() >= 3 => Ok(p.clone()),
+            (Some(_), None) => {
+                Err(format!("zone `{}` needs a positive size", self.name))
+            }
+            (None, Some(_)) => {
+                Err(format!("zone `{}` needs at least three corners",
+                            self.name))
+            }
+            _ => {
+                Err(format!("zone `{}` needs either a rect or a polygon",
+                            self.name))
+            }
+        }
+    }
+}
+
+/// A target entering or leaving a zone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub zone: String,
+    pub entered: bool,
+}
+
+/// `Zones` follows which zones are occupied.
+pub struct Zones {
+    names: Vec<String>,
+    corners: Vec<Vec<[f64; 2]>>,
+    occupied: Vec<bool>,
+}
+
+impl Zones {
+    /// The zones of `specs`, which were checked with `ZoneSpec::corners`.
+    pub fn new(specs: &[ZoneSpec]) -> Zones {
+        Zones {
+            names: specs.iter().map(|s| s.name.clone()).collect(),
+            corners: specs.iter().map(|s| s.corners().unwrap()).collect(),
+            occupied: vec![false; specs.len()],
+        }
+    }
+
+    /// Moves on with the centroids of the tracked targets, and returns the
+    /// zones entered and left.
+    pub fn update(&mut self, centroids: &[[f64; 2]]) -> Vec<Event> {
+        let mut events = Vec::new();
+        for (i, corners) in self.corners.iter().enumerate() {
+            let occupied = centroids.iter().any(|&p| inside(corners, p));
+            if occupied != self.occupied[i] {
+                self.occupied[i] = occupied;
+                events.push(Event {
+                    zone: self.names[i].clone(),
+                    entered: occupied,
+                });
+            }
+        }
+        events
+    }
+
+    /// `1` for every occupied zone and `0` for the others, as sent by
+    /// `--zone-dims`.
+    pub fn values(&self) -> Vec<f64> {
+        self.occupied.iter().map(|&o| if o { 1.0 } else { 0.0 }).collect()
+    }
+
+    /// Draws the zones and their names on `frame`.
+    pub fn draw(&self, frame: &Mat) {
+        let color = Scalar::new(COLOR.0, COLOR.1, COLOR.2, 0);
+        for (i, corners) in self.corners.iter().enumerate() {
+            let point = |p: [f64; 2]| {
+                Point2i::new(p[0].round() as i32, p[1].round() as i32)
+            };
+            let thickness = if self.occupied[i] { 3 } else { 1 };
+            for (j, &corner) in corners.iter().enumerate() {
+                let next = corners[(j + 1) % corners.len()];
+                frame.line(point(corner), point(next), color, thickness);
+            }
+            let top_left = point(corners[0]);
+            frame.put_text(&self.names[i],
+                           Point2i::new(top_left.x + 4, top_left.y + 14),
+                           HersheyFonts::HersheySimplex,
+                           0.45,
+                           color);
+        }
+    }
+}
+
+/// Whether `p` is inside the polygon of `corners`, by counting the edges a
+/// ray from it to the right crosses.
+fn inside(corners: &[[f64; 2]], p: [f64; 2]) -> bool {
+    let mut inside = false;
+    for (i, a) in corners.iter().enumerate() {
+        let b = corners[(i + 1) % corners.len()];
+        if (a[1] > p[1]) != (b[1] > p[1]) {
+            let x = a[0] + (p[1] - a[1]) / (b[1] - a[1]) * (b[0] - a[0]);
+            if p[0] < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// `EventSender` sends the events of `--zone-events` as UDP datagrams.
+pub struct EventSender {
+    socket: UdpSocket,
+}
+
+impl EventSender {
+    /// Sends to `addr` from an ephemeral port.
+    pub fn connect(addr: &str) -> io::Result<EventSender> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.connect(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(EventSender { socket })
+    }
+
+    /// Sends `event`, which happened at `time`.
+    pub fn send(&self, event: &Event, time: f64) -> io::Result<()> {
+        self.socket.send(json(event, time).as_bytes()).map(|_| ())
+    }
+}
+
+fn json(event: &Event, time: f64) -> String {
+    format!("{{\"zone\":\"{}\",\"event\":\"{}\",\"t\":{}}}\n",
+            event.zone,
+            if event.entered { "enter" } else { "exit" },
+            time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, ZoneSpec, Zones, json};
+
+    fn zone(name: &str,
+            rect: Option<[i32; 4]>,
+            polygon: Option<Vec<[f64; 2]>>)
+            -> ZoneSpec {
+        ZoneSpec {
+            name: name.to_string(),
+            rect,
+            polygon,
+        }
+    }
+
+    #[test]
+    fn reports_entering_and_leaving() {
+        let triangle = vec![[200.0, 0.0], [300.0, 100.0], [100.0, 100.0]];
+        let mut zones = Zones::new(&[zone("door", Some([0, 0, 100, 100]), None),
+                                     zone("stage", None, Some(triangle))]);
+        assert_eq!(zones.update(&[[50.0, 50.0]]),
+                   vec![Event {
+                            zone: "door".to_string(),
+                            entered: true,
+                        }]);
+        assert_eq!(zones.values(), vec![1.0, 0.0]);
+        // Still in the door, and in the stage with a second target, but
+        // not in the corner of the triangle's bounding box.
+        assert_eq!(zones.update(&[[50.0, 50.0], [110.0, 20.0]]), vec![]);
+        let events = zones.update(&[[200.0, 80.0]]);
+        assert_eq!(events.len(), 2);
+        assert!(!events[0].entered && events[1].entered);
+        assert_eq!(zones.values(), vec![0.0, 1.0]);
+        assert_eq!(json(&events[1], 1.5),
+                   "{\"zone\":\"stage\",\"event\":\"enter\",\"t\":1.5}\n");
+    }
+
+    #[test]
+    fn needs_a_shape() {
+        assert!(zone("a", None, None).corners().is_err());
+        assert!(zone("a", Some([0, 0, 0, 10]), None).corners().is_err());
+        assert!(zone("a", None, Some(vec![[0.0, 0.0], [1.0, 1.0]]))
+            .corners()
+            .is_err());
+        assert!(zone("a", Some([0, 0, 1, 1]), Some(Vec::new()))
+            .corners()
+            .is_err());
+    }
+}